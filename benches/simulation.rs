@@ -1,17 +1,19 @@
+#[cfg(feature = "simd")]
+use aci_map::SimulationBackend;
 use aci_map::{
-    air::{AirLeveler, OxygenUser},
+    air::{AirLeveler, GasMask, OxygenUser},
     liquids::{LiquidData, LiquidLeveler},
     objects::environment_object::EnvironmentObject,
     Map, MapObject,
 };
-use criterion::{black_box, criterion_group, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 fn simulate_map(map: &mut dyn MapObject) {
     map.perform_simulation_tick(0.05);
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    let mut map: Map<500, 500> = Map::new_default();
+    let mut map = Map::new_default(500, 500);
 
     map.objects_mut()
         .push_object::<EnvironmentObject>(AirLeveler {
@@ -20,6 +22,10 @@ fn criterion_benchmark(c: &mut Criterion) {
             nitrogen: 0.79,
             oxygen: 0.00,
             fumes: 0.0,
+            gases: GasMask::ALL,
+            reservoir: None,
+            rate: None,
+            enabled: true,
         });
     map.objects_mut()
         .push_object::<EnvironmentObject>(AirLeveler {
@@ -28,12 +34,17 @@ fn criterion_benchmark(c: &mut Criterion) {
             nitrogen: 0.79,
             oxygen: 0.21,
             fumes: 0.00,
+            gases: GasMask::ALL,
+            reservoir: None,
+            rate: None,
+            enabled: true,
         });
     map.objects_mut()
         .push_object::<EnvironmentObject>(OxygenUser {
             x: 50,
             y: 50,
             change_per_sec: 0.001,
+            enabled: true,
         });
 
     map.objects_mut()
@@ -41,42 +52,39 @@ fn criterion_benchmark(c: &mut Criterion) {
             x: 99,
             y: 0,
             target: LiquidData::Water { level: 1.0 },
+            enabled: true,
+            solidify: false,
         });
     map.objects_mut()
         .push_object::<EnvironmentObject>(LiquidLeveler {
             x: 99,
             y: 9,
             target: LiquidData::Lava { level: 1.0 },
+            enabled: true,
+            solidify: false,
         });
 
     for (x, y) in map
         .all_tile_coords()
         .filter(|(x, y)| *x > 90 && *x < 120 && *y < 20)
     {
-        map.tiles[x][y].ground_level = -1.1;
+        map.tiles[(x, y)].ground_level = -1.1;
     }
 
     let mut g = c.benchmark_group("simulate");
     g.warm_up_time(std::time::Duration::from_secs(15));
     g.throughput(criterion::Throughput::Elements(1));
     g.bench_function("500x500", |b| b.iter(|| simulate_map(black_box(&mut map))));
+
+    // The default backend is the SIMD one when it's available, so compare it against the scalar one
+    #[cfg(feature = "simd")]
+    {
+        map.set_simulation_backend(SimulationBackend::Scalar);
+        g.bench_function("500x500 scalar", |b| {
+            b.iter(|| simulate_map(black_box(&mut map)))
+        });
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
-fn main() {
-    rayon::ThreadPoolBuilder::new()
-        .stack_size(64 * 1024 * 1024)
-        .build_global()
-        .unwrap();
-
-    std::thread::Builder::new()
-        .name("TestThread".into())
-        .stack_size(64 * 1024 * 1024)
-        .spawn(|| {
-            benches();
-            Criterion::default().configure_from_args().final_summary();
-        })
-        .unwrap()
-        .join()
-        .unwrap();
-}
+criterion_main!(benches);
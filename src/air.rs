@@ -1,175 +1,572 @@
-use std::ops::Add;
+use std::{
+    fmt::{Debug, Display},
+    ops::{Add, AddAssign, SubAssign},
+};
 
-use crate::{liquids::AnyLiquid, tiles::Tile, Facing, Map};
+use crate::{
+    grid::Grid,
+    heat::WATER_BOILING_TEMPERATURE,
+    liquids::{AnyLiquid, LiquidData, Water},
+    tiles::Tile,
+    Direction8, Facing, Map, SimulationBackend,
+};
 
-impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
-    pub(crate) fn calculate_air_diff(&self, delta_time: f32) -> [[AirDiff; HEIGHT]; WIDTH] {
-        let mut air_diff_result = [[AirDiff::default(); HEIGHT]; WIDTH];
+/// The amount of steam one level of water turns into when it evaporates, and back when it condenses
+pub const STEAM_PER_WATER_LEVEL: f32 = 1.0;
+/// The fraction of the difference to the saturation pressure of steam that evaporates or condenses per second
+pub const PHASE_CHANGE_RATE: f32 = 0.5;
+/// How much colder than the tile itself a wall next to it is, which makes steam condense on it sooner
+pub const WALL_CONDENSATION_COOLING: f32 = 10.0;
 
-        const PRESSURE_SPREAD_RATE: f32 = 0.01;
-        const DIFFUSION_SPREAD_RATE: f32 = 0.05;
+/// How steep the saturation pressure of steam rises with the temperature, in kelvin.
+/// This is the latent heat of water divided by the gas constant.
+const STEAM_PRESSURE_CURVE: f32 = 5120.0;
+const ZERO_CELSIUS_IN_KELVIN: f32 = 273.15;
+/// The fraction the pressure of air goes up for every degree it's warmer than [Tile::DEFAULT_TEMPERATURE].
+/// This is a lot less than for a real gas, so hot spots cause a draft instead of blowing their tile empty.
+pub const AIR_THERMAL_EXPANSION: f32 = 0.001;
+
+#[cfg(feature = "simd")]
+mod simd;
+
+impl Map {
+    /// Calculate the air diff of a simulation tick without applying it.
+    ///
+    /// This is the raw output of the diffusion and pressure kernel and is meant for tuning and analysis.
+    pub fn debug_air_diff(&self, delta_time: f32) -> Grid<AirDiff> {
+        let mut air_diff = Grid::new(self.width(), self.height(), AirDiff::default());
+        self.calculate_air_diff(delta_time, &mut air_diff);
+        air_diff
+    }
+
+    /// Move air between two tiles so their pressures get closer. The tiles don't need to be neighbours.
+    ///
+    /// `rate` is the fraction of the pressure difference that is equalized per second.
+    /// The air that is moved has the composition of the tile with the highest pressure, so no air is lost or created.
+    /// Nothing happens if one of the tiles has no air.
+    pub fn equalize_air(
+        &mut self,
+        a: (usize, usize),
+        b: (usize, usize),
+        rate: f32,
+        delta_time: f32,
+    ) {
+        let (Some((air_a, liquids_a)), Some((air_b, liquids_b))) = (
+            self.tiles[(a.0, a.1)].tile_type.get_ground(),
+            self.tiles[(b.0, b.1)].tile_type.get_ground(),
+        ) else {
+            return;
+        };
+
+        let pressure_a = air_a.air_pressure(liquids_a.get_level::<AnyLiquid>());
+        let pressure_b = air_b.air_pressure(liquids_b.get_level::<AnyLiquid>());
+
+        let ((high, high_air, high_liquids), (low, low_liquids)) = if pressure_a >= pressure_b {
+            ((a, *air_a, liquids_a), (b, liquids_b))
+        } else {
+            ((b, *air_b, liquids_b), (a, liquids_a))
+        };
+
+        // Moving air changes the pressure more in the tile with less room for air
+        let air_fraction_high = air_fraction(high_liquids.get_level::<AnyLiquid>()).max(0.001);
+        let air_fraction_low = air_fraction(low_liquids.get_level::<AnyLiquid>()).max(0.001);
+        let air_for_equal_pressure =
+            (pressure_a - pressure_b).abs() * air_fraction_high * air_fraction_low
+                / (air_fraction_high + air_fraction_low);
+        let air_moved = air_for_equal_pressure * (rate * delta_time).clamp(0.0, 1.0);
+
+        let total_air = high_air.total();
+        if total_air <= 0.0 || air_moved <= 0.0 {
+            return;
+        }
+
+        let moved = AirDiff {
+            nitrogen: air_moved * high_air.nitrogen / total_air,
+            oxygen: air_moved * high_air.oxygen / total_air,
+            fumes: air_moved * high_air.fumes / total_air,
+            steam: air_moved * high_air.steam / total_air,
+            carbon_dioxide: air_moved * high_air.carbon_dioxide / total_air,
+            heat: air_moved * high_air.temperature,
+            evaporated: 0.0,
+        };
+
+        let high_air = self.tiles[(high.0, high.1)]
+            .tile_type
+            .get_air_mut()
+            .unwrap();
+        high_air.nitrogen -= moved.nitrogen;
+        high_air.oxygen -= moved.oxygen;
+        high_air.fumes -= moved.fumes;
+        high_air.steam -= moved.steam;
+        high_air.carbon_dioxide -= moved.carbon_dioxide;
+
+        let low_air = self.tiles[(low.0, low.1)].tile_type.get_air_mut().unwrap();
+        low_air.mix_in(air_moved, moved.heat);
+        low_air.nitrogen += moved.nitrogen;
+        low_air.oxygen += moved.oxygen;
+        low_air.fumes += moved.fumes;
+        low_air.steam += moved.steam;
+        low_air.carbon_dioxide += moved.carbon_dioxide;
+
+        self.render_dirty[(a.0, a.1)] = true;
+        self.render_dirty[(b.0, b.1)] = true;
+    }
+
+    /// Calculate the air diff of a tick into the given grid. Its old values are overwritten.
+    pub(crate) fn calculate_air_diff(&self, delta_time: f32, air_diff: &mut Grid<AirDiff>) {
+        match self.simulation_backend {
+            SimulationBackend::Scalar => self.calculate_air_diff_scalar(delta_time, air_diff),
+            #[cfg(feature = "simd")]
+            SimulationBackend::Simd => self.calculate_air_diff_simd(delta_time, air_diff),
+        }
+    }
+
+    fn calculate_air_diff_scalar(&self, delta_time: f32, air_diff: &mut Grid<AirDiff>) {
+        let pressure_spread_rate = self.simulation_params.air_pressure_spread_rate;
+        let diffusion_spread_rate = self.simulation_params.air_diffusion_spread_rate;
 
         // In this model we will 'give away' air pressure and oxygen.
 
-        for (x, y) in self.all_tile_coords() {
-            let Some((air, liquids)) = self.tiles[x][y].tile_type.get_ground() else {
+        air_diff.accumulate_into(!self.deterministic, |rect, air_diff_result| {
+            for (x, y) in rect.coords() {
+                if self.settled[(x, y)] {
+                    continue;
+                }
+
+                let Some((air, liquids)) = self.tiles[(x, y)].get_simulated_air() else {
                     continue;
                 };
 
-            let air_pressure = air.air_pressure(liquids.get_level::<AnyLiquid>());
+                let liquid_level = liquids.get_level::<AnyLiquid>();
+                let air_pressure = air.air_pressure(liquid_level);
 
-            let neighbour_airs = self
-                // Get all neighbours
-                .neighbour_tiles(x, y)
-                // Get only the ones that are ground
-                .filter_map(|(x, y, tile)| {
-                    tile.tile_type
-                        .get_ground()
-                        .map(|(air, liquids)| (x, y, air, liquids))
-                });
+                let neighbour_airs = self
+                    // Get all neighbours
+                    .neighbour_tiles(x, y)
+                    // A trade is calculated from both sides, so a settled tile must be skipped by its neighbours too
+                    .filter(|(x, y, _)| !self.settled[(*x, *y)])
+                    // Get only the ones that are ground and take part in the air simulation
+                    .filter_map(|(x, y, tile)| {
+                        tile.get_simulated_air()
+                            .map(|(air, liquids)| (x, y, air, liquids))
+                    });
+
+                let nitrogen_fraction = air.nitrogen_fraction();
+                let oxygen_fraction = air.oxygen_fraction();
+                let fumes_fraction = air.fumes_fraction();
+                let steam_fraction = air.steam_fraction();
+                let carbon_dioxide_fraction = air.carbon_dioxide_fraction();
+
+                for (nx, ny, neighbour_air, neighbour_liquids) in neighbour_airs {
+                    let neighbour_liquid_level = neighbour_liquids.get_level::<AnyLiquid>();
+                    let neighbour_air_pressure = neighbour_air.air_pressure(neighbour_liquid_level);
+
+                    // Air can only diffuse through the part of the tiles that isn't flooded
+                    let diffusion_area =
+                        air_fraction(liquid_level).min(air_fraction(neighbour_liquid_level));
+
+                    // Move air due to diffusion. We trade air equally. We give some, we take some
+                    let nitrogen_needed_for_equal = nitrogen_fraction * neighbour_air_pressure;
+                    let oxygen_needed_for_equal = oxygen_fraction * neighbour_air_pressure;
+                    let fumes_needed_for_equal = fumes_fraction * neighbour_air_pressure;
+                    let steam_needed_for_equal = steam_fraction * neighbour_air_pressure;
+                    let carbon_dioxide_needed_for_equal =
+                        carbon_dioxide_fraction * neighbour_air_pressure;
 
-            let nitrogen_fraction = air.nitrogen_fraction();
-            let oxygen_fraction = air.oxygen_fraction();
-            let fumes_fraction = air.fumes_fraction();
-
-            for (nx, ny, neighbour_air, neighbour_liquids) in neighbour_airs {
-                let neighbour_air_pressure =
-                    neighbour_air.air_pressure(neighbour_liquids.get_level::<AnyLiquid>());
-
-                // Move air due to diffusion. We trade air equally. We give some, we take some
-                let nitrogen_needed_for_equal = nitrogen_fraction * neighbour_air_pressure;
-                let oxygen_needed_for_equal = oxygen_fraction * neighbour_air_pressure;
-                let fumes_needed_for_equal = fumes_fraction * neighbour_air_pressure;
-
-                let nitrogen_traded = nitrogen_needed_for_equal
-                    .clamp(-neighbour_air.nitrogen, air.nitrogen / 8.0)
-                    * DIFFUSION_SPREAD_RATE
-                    * delta_time;
-                let oxygen_traded = oxygen_needed_for_equal
-                    .clamp(-neighbour_air.oxygen, air.oxygen / 8.0)
-                    * DIFFUSION_SPREAD_RATE
-                    * delta_time;
-                let fumes_traded = fumes_needed_for_equal
-                    .clamp(-neighbour_air.fumes, air.fumes / 8.0)
-                    * DIFFUSION_SPREAD_RATE
-                    * delta_time;
-
-                air_diff_result[nx][ny].nitrogen += nitrogen_traded;
-                air_diff_result[nx][ny].oxygen += oxygen_traded;
-                air_diff_result[nx][ny].fumes += fumes_traded;
-
-                air_diff_result[x][y].nitrogen -= nitrogen_traded;
-                air_diff_result[x][y].oxygen -= oxygen_traded;
-                air_diff_result[x][y].fumes -= fumes_traded;
-
-                // Move air due to pressure difference
-                if neighbour_air_pressure < air_pressure {
-                    // It moves due to the total pressure difference, not the difference between each element separately
-                    let pressure_delta = air_pressure - neighbour_air_pressure;
-                    let applied_pressure_delta = ((pressure_delta * PRESSURE_SPREAD_RATE).sqrt()
-                        * delta_time)
-                        .min(air_pressure / 8.0);
-
-                    let nitrogen_delta = applied_pressure_delta * nitrogen_fraction;
-                    let oxygen_delta = applied_pressure_delta * oxygen_fraction;
-                    let fumes_delta = applied_pressure_delta * fumes_fraction;
-
-                    air_diff_result[nx][ny].nitrogen += nitrogen_delta;
-                    air_diff_result[nx][ny].oxygen += oxygen_delta;
-                    air_diff_result[nx][ny].fumes += fumes_delta;
-
-                    air_diff_result[x][y].nitrogen -= nitrogen_delta;
-                    air_diff_result[x][y].oxygen -= oxygen_delta;
-                    air_diff_result[x][y].fumes -= fumes_delta;
+                    let nitrogen_traded = nitrogen_needed_for_equal
+                        .clamp(-neighbour_air.nitrogen, air.nitrogen / 8.0)
+                        * diffusion_spread_rate
+                        * diffusion_area
+                        * delta_time;
+                    let oxygen_traded = oxygen_needed_for_equal
+                        .clamp(-neighbour_air.oxygen, air.oxygen / 8.0)
+                        * diffusion_spread_rate
+                        * diffusion_area
+                        * delta_time;
+                    let fumes_traded = fumes_needed_for_equal
+                        .clamp(-neighbour_air.fumes, air.fumes / 8.0)
+                        * diffusion_spread_rate
+                        * diffusion_area
+                        * delta_time;
+                    let steam_traded = steam_needed_for_equal
+                        .clamp(-neighbour_air.steam, air.steam / 8.0)
+                        * diffusion_spread_rate
+                        * diffusion_area
+                        * delta_time;
+                    let carbon_dioxide_traded = carbon_dioxide_needed_for_equal
+                        .clamp(-neighbour_air.carbon_dioxide, air.carbon_dioxide / 8.0)
+                        * diffusion_spread_rate
+                        * diffusion_area
+                        * delta_time;
+
+                    // The air that is given away has our temperature, the air that is taken that of the neighbour
+                    let total_traded = nitrogen_traded
+                        + oxygen_traded
+                        + fumes_traded
+                        + steam_traded
+                        + carbon_dioxide_traded;
+                    let traded = AirDiff {
+                        nitrogen: nitrogen_traded,
+                        oxygen: oxygen_traded,
+                        fumes: fumes_traded,
+                        steam: steam_traded,
+                        carbon_dioxide: carbon_dioxide_traded,
+                        heat: total_traded.max(0.0) * air.temperature
+                            + total_traded.min(0.0) * neighbour_air.temperature,
+                        evaporated: 0.0,
+                    };
+                    air_diff_result[(nx, ny)] += traded;
+                    air_diff_result[(x, y)] -= traded;
+
+                    // Move air due to pressure difference
+                    if neighbour_air_pressure < air_pressure {
+                        // It moves due to the total pressure difference, not the difference between each element separately
+                        let pressure_delta = air_pressure - neighbour_air_pressure;
+                        let applied_pressure_delta =
+                            ((pressure_delta * pressure_spread_rate).sqrt() * delta_time)
+                                .min(air_pressure / 8.0);
+
+                        let nitrogen_delta = applied_pressure_delta * nitrogen_fraction;
+                        let oxygen_delta = applied_pressure_delta * oxygen_fraction;
+                        let fumes_delta = applied_pressure_delta * fumes_fraction;
+                        let steam_delta = applied_pressure_delta * steam_fraction;
+                        let carbon_dioxide_delta = applied_pressure_delta * carbon_dioxide_fraction;
+
+                        let moved = AirDiff {
+                            nitrogen: nitrogen_delta,
+                            oxygen: oxygen_delta,
+                            fumes: fumes_delta,
+                            steam: steam_delta,
+                            carbon_dioxide: carbon_dioxide_delta,
+                            heat: (nitrogen_delta
+                                + oxygen_delta
+                                + fumes_delta
+                                + steam_delta
+                                + carbon_dioxide_delta)
+                                * air.temperature,
+                            evaporated: 0.0,
+                        };
+                        air_diff_result[(nx, ny)] += moved;
+                        air_diff_result[(x, y)] -= moved;
+                    }
                 }
+
+                let evaporated = self.calculate_evaporation(x, y, air, liquids, delta_time);
+                air_diff_result[(x, y)].steam += evaporated;
+                air_diff_result[(x, y)].evaporated += evaporated;
+                // Steam comes off the water at the temperature of the tile and condenses out of the air at its temperature
+                air_diff_result[(x, y)].heat += if evaporated > 0.0 {
+                    evaporated * self.tiles[(x, y)].temperature
+                } else {
+                    evaporated * air.temperature
+                };
             }
+        })
+    }
+
+    /// The amount of steam the water of the tile turns into. Negative when steam condenses into water.
+    ///
+    /// Water boils when the tile is at the boiling point, or before that when the air pressure is below
+    /// the saturation pressure of steam. Steam condenses when there's more of it than the air can hold
+    /// at the temperature of the tile, which is colder next to walls.
+    fn calculate_evaporation(
+        &self,
+        x: usize,
+        y: usize,
+        air: &AirData,
+        liquids: &LiquidData,
+        delta_time: f32,
+    ) -> f32 {
+        let temperature = self.tiles[(x, y)].temperature;
+        let liquid_level = liquids.get_level::<AnyLiquid>();
+        let water_level = liquids.get_level::<Water>();
+        let steam_pressure = air.steam / air_fraction(liquid_level).max(0.001);
+        let rate = (PHASE_CHANGE_RATE * delta_time).min(1.0);
+
+        let saturation_pressure = saturation_steam_pressure(temperature);
+        let boiling = temperature >= WATER_BOILING_TEMPERATURE
+            || saturation_pressure >= air.air_pressure(liquid_level);
+
+        if water_level > 0.0 && boiling && steam_pressure < saturation_pressure {
+            let evaporated =
+                (saturation_pressure - steam_pressure) * air_fraction(liquid_level) * rate;
+            return evaporated.min(water_level * STEAM_PER_WATER_LEVEL);
+        }
+
+        let next_to_wall = self
+            .neighbour_tiles(x, y)
+            .any(|(_, _, neighbour)| neighbour.tile_type.is_wall());
+        let condensation_temperature = if next_to_wall {
+            temperature - WALL_CONDENSATION_COOLING
+        } else {
+            temperature
+        };
+        let condensation_pressure = saturation_steam_pressure(condensation_temperature);
+
+        if steam_pressure > condensation_pressure {
+            let condensed =
+                (steam_pressure - condensation_pressure) * air_fraction(liquid_level) * rate;
+            return -condensed.min(air.steam);
         }
 
-        air_diff_result
+        0.0
     }
 
-    pub(crate) fn apply_air_diff(&mut self, air_diff: [[AirDiff; HEIGHT]; WIDTH], delta_time: f32) {
+    pub(crate) fn apply_air_diff(&mut self, air_diff: &Grid<AirDiff>, delta_time: f32) {
+        let heat_exchange_fraction =
+            (self.simulation_params.air_heat_exchange_rate * delta_time).min(1.0);
+
         for (x, y) in self.all_tile_coords() {
-            let Some(air) = self.tiles[x][y].tile_type.get_air_mut() else {
-                    continue;
-                };
+            let tile = &mut self.tiles[(x, y)];
+            let Some(air) = tile.tile_type.get_air_mut() else {
+                continue;
+            };
+
+            let old_air = *air;
+            let diff = air_diff[(x, y)];
 
-            air.nitrogen = air.nitrogen.add(air_diff[x][y].nitrogen).max(0.0);
-            air.oxygen = air.oxygen.add(air_diff[x][y].oxygen).max(0.0);
-            air.fumes = air.fumes.add(air_diff[x][y].fumes).max(0.0);
+            air.mix_in(
+                diff.nitrogen + diff.oxygen + diff.fumes + diff.steam + diff.carbon_dioxide,
+                diff.heat,
+            );
+            air.nitrogen = air.nitrogen.add(diff.nitrogen).max(0.0);
+            air.oxygen = air.oxygen.add(diff.oxygen).max(0.0);
+            air.fumes = air.fumes.add(diff.fumes).max(0.0);
+            air.steam = air.steam.add(diff.steam).max(0.0);
+            air.carbon_dioxide = air.carbon_dioxide.add(diff.carbon_dioxide).max(0.0);
+
+            // The air warms up or cools down to the temperature of the ground and walls around it
+            air.temperature += (tile.temperature - air.temperature) * heat_exchange_fraction;
+
+            if *air != old_air {
+                self.render_dirty[(x, y)] = true;
+            }
         }
 
-        for map_object in self.objects.read().unwrap().get_all_objects() {
-            for air_leveler in map_object.air_levelers() {
-                let Some(air) = self.tiles[air_leveler.x][air_leveler.y].tile_type.get_air_mut() else {
+        for mut map_object in self.objects.read().unwrap().get_all_objects_mut() {
+            for (index, air_leveler) in map_object
+                .air_levelers()
+                .into_iter()
+                .enumerate()
+                .filter(|(_, air_leveler)| air_leveler.enabled)
+            {
+                let Some(air) = self.tiles[(air_leveler.x, air_leveler.y)]
+                    .tile_type
+                    .get_air_mut()
+                else {
                     continue;
                 };
 
-                air.nitrogen = air_leveler.nitrogen;
-                air.oxygen = air_leveler.oxygen;
-                air.fumes = air_leveler.fumes;
+                let old_air = *air;
+
+                let rate_fraction = air_leveler
+                    .rate
+                    .map(|rate| (rate * delta_time).clamp(0.0, 1.0))
+                    .unwrap_or(1.0);
+                let delta = |leveled: bool, target: f32, current: f32| {
+                    if leveled {
+                        (target - current) * rate_fraction
+                    } else {
+                        0.0
+                    }
+                };
+                let nitrogen_delta = delta(
+                    air_leveler.gases.nitrogen,
+                    air_leveler.nitrogen,
+                    old_air.nitrogen,
+                );
+                let oxygen_delta =
+                    delta(air_leveler.gases.oxygen, air_leveler.oxygen, old_air.oxygen);
+                let fumes_delta = delta(air_leveler.gases.fumes, air_leveler.fumes, old_air.fumes);
+
+                // A leveler with a reservoir can only get part of the way if it doesn't have enough gas left
+                let (fraction, injected) = match air_leveler.reservoir {
+                    None => (1.0, 0.0),
+                    Some(reservoir) => {
+                        let needed =
+                            nitrogen_delta.max(0.0) + oxygen_delta.max(0.0) + fumes_delta.max(0.0);
+
+                        if needed <= 0.0 {
+                            (1.0, 0.0)
+                        } else {
+                            let fraction = (reservoir / needed).clamp(0.0, 1.0);
+                            (fraction, needed * fraction)
+                        }
+                    }
+                };
+
+                air.nitrogen += nitrogen_delta * fraction;
+                air.oxygen += oxygen_delta * fraction;
+                air.fumes += fumes_delta * fraction;
+
+                if injected > 0.0 {
+                    map_object.drain_air_reservoir(index, injected);
+                }
+
+                if *air != old_air {
+                    self.render_dirty[(air_leveler.x, air_leveler.y)] = true;
+                }
             }
 
-            for oxygen_user in map_object.oxygen_users() {
-                let Some(air) = self.tiles[oxygen_user.x][oxygen_user.y].tile_type.get_air_mut() else {
+            for oxygen_user in map_object
+                .oxygen_users()
+                .into_iter()
+                .filter(|oxygen_user| oxygen_user.enabled)
+            {
+                let Some(air) = self.tiles[(oxygen_user.x, oxygen_user.y)]
+                    .tile_type
+                    .get_air_mut()
+                else {
                     continue;
                 };
 
-                if air.oxygen < oxygen_user.change_per_sec * delta_time {
+                // Breathing turns oxygen into carbon dioxide, plants and scrubbers turn it back
+                let change = oxygen_user.change_per_sec * delta_time;
+                if air.oxygen < change || air.carbon_dioxide < -change {
                     continue;
                 }
 
-                air.oxygen -= oxygen_user.change_per_sec * delta_time;
-                air.fumes += oxygen_user.change_per_sec * delta_time;
+                air.oxygen -= change;
+                air.carbon_dioxide += change;
+
+                self.render_dirty[(oxygen_user.x, oxygen_user.y)] = true;
             }
+        }
 
-            for air_pusher in map_object.air_pushers() {
-                let Some((push_x, push_y)) = air_pusher.direction
-                    .move_coords_in_direction::<WIDTH, HEIGHT>(air_pusher.x, air_pusher.y) else {
-                        continue;
-                    };
+        let mut air_pushers = self
+            .objects
+            .read()
+            .unwrap()
+            .get_all_objects()
+            .flat_map(|map_object| map_object.air_pushers())
+            .filter(|air_pusher| air_pusher.enabled)
+            .filter_map(|air_pusher| air_pusher.as_pushing(self.width(), self.height()))
+            .collect::<Vec<_>>();
 
-                let Some(source_air) = self.tiles[air_pusher.x][air_pusher.y].tile_type.get_air() else {
-                    continue;
-                };
+        // Pushers that are lined up form a duct. By running the upstream pushers first,
+        // the air they push into the next pusher can be carried onward in the same tick.
+        // Every pusher still only runs once, so no air is moved twice by the same pusher.
+        air_pushers.sort_by_key(|air_pusher| air_pusher.duct_order());
 
-                let nitrogen_taken = source_air.nitrogen * air_pusher.amount * delta_time;
-                let oxygen_taken = source_air.oxygen * air_pusher.amount * delta_time;
-                let fumes_taken = source_air.fumes * air_pusher.amount * delta_time;
+        for air_pusher in air_pushers {
+            let Some((push_x, push_y)) = air_pusher.direction.move_coords_in_direction(
+                air_pusher.x,
+                air_pusher.y,
+                self.width(),
+                self.height(),
+            ) else {
+                continue;
+            };
 
-                let Some(target_air) = self.tiles[push_x][push_y].tile_type.get_air_mut() else {
-                    continue;
-                };
+            if self.tiles[(air_pusher.x, air_pusher.y)]
+                .get_simulated_air()
+                .is_none()
+                || self.tiles[(push_x, push_y)].get_simulated_air().is_none()
+            {
+                continue;
+            }
 
-                target_air.nitrogen += nitrogen_taken;
-                target_air.oxygen += oxygen_taken;
-                target_air.fumes += fumes_taken;
+            let Some(source_air) = self.tiles[(air_pusher.x, air_pusher.y)].tile_type.get_air()
+            else {
+                continue;
+            };
 
-                let source_air = self.tiles[air_pusher.x][air_pusher.y]
-                    .tile_type
-                    .get_air_mut()
-                    .unwrap();
+            let nitrogen_taken = source_air.nitrogen * air_pusher.amount * delta_time;
+            let oxygen_taken = source_air.oxygen * air_pusher.amount * delta_time;
+            let fumes_taken = source_air.fumes * air_pusher.amount * delta_time;
+            let steam_taken = source_air.steam * air_pusher.amount * delta_time;
+            let carbon_dioxide_taken = source_air.carbon_dioxide * air_pusher.amount * delta_time;
+            let source_temperature = source_air.temperature;
+
+            let Some(target_air) = self.tiles[(push_x, push_y)].tile_type.get_air_mut() else {
+                continue;
+            };
+
+            let total_taken =
+                nitrogen_taken + oxygen_taken + fumes_taken + steam_taken + carbon_dioxide_taken;
+            target_air.mix_in(total_taken, total_taken * source_temperature);
+            target_air.nitrogen += nitrogen_taken;
+            target_air.oxygen += oxygen_taken;
+            target_air.fumes += fumes_taken;
+            target_air.steam += steam_taken;
+            target_air.carbon_dioxide += carbon_dioxide_taken;
+
+            let source_air = self.tiles[(air_pusher.x, air_pusher.y)]
+                .tile_type
+                .get_air_mut()
+                .unwrap();
 
-                source_air.nitrogen -= nitrogen_taken;
-                source_air.oxygen -= oxygen_taken;
-                source_air.fumes -= fumes_taken;
+            source_air.nitrogen -= nitrogen_taken;
+            source_air.oxygen -= oxygen_taken;
+            source_air.fumes -= fumes_taken;
+            source_air.steam -= steam_taken;
+            source_air.carbon_dioxide -= carbon_dioxide_taken;
+
+            if total_taken != 0.0 {
+                self.render_dirty[(air_pusher.x, air_pusher.y)] = true;
+                self.render_dirty[(push_x, push_y)] = true;
             }
         }
     }
 }
 
+/// The change in air of a tile during a simulation tick
 #[derive(Default, Clone, Copy, Debug)]
-pub(crate) struct AirDiff {
-    nitrogen: f32,
-    oxygen: f32,
-    fumes: f32,
+pub struct AirDiff {
+    pub nitrogen: f32,
+    pub oxygen: f32,
+    pub fumes: f32,
+    pub steam: f32,
+    pub carbon_dioxide: f32,
+    /// The temperature that came along with the air, as the sum of every amount of air that came in times its temperature.
+    /// The air that went out counts negatively.
+    pub heat: f32,
+    /// The part of the steam that came from evaporating water, or went into water when negative.
+    /// The water level changes by this divided by [STEAM_PER_WATER_LEVEL].
+    pub evaporated: f32,
+}
+
+impl AddAssign for AirDiff {
+    fn add_assign(&mut self, rhs: Self) {
+        self.nitrogen += rhs.nitrogen;
+        self.oxygen += rhs.oxygen;
+        self.fumes += rhs.fumes;
+        self.steam += rhs.steam;
+        self.carbon_dioxide += rhs.carbon_dioxide;
+        self.heat += rhs.heat;
+        self.evaporated += rhs.evaporated;
+    }
+}
+
+impl SubAssign for AirDiff {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.nitrogen -= rhs.nitrogen;
+        self.oxygen -= rhs.oxygen;
+        self.fumes -= rhs.fumes;
+        self.steam -= rhs.steam;
+        self.carbon_dioxide -= rhs.carbon_dioxide;
+        self.heat -= rhs.heat;
+        self.evaporated -= rhs.evaporated;
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AirData {
     pub nitrogen: f32,
     pub oxygen: f32,
     pub fumes: f32,
+    /// Evaporated water. It condenses back into water on cold tiles.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub steam: f32,
+    /// Breathed out by the characters. Unlike the fumes, it's turned back into oxygen by plants and scrubbers,
+    /// see [OxygenUser].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub carbon_dioxide: f32,
+    /// The temperature of the air in degrees Celsius.
+    /// It's pulled towards the temperature of the tile and warmer air has a higher pressure, see [AIR_THERMAL_EXPANSION].
+    #[cfg_attr(feature = "serde", serde(default = "AirData::default_temperature"))]
+    pub temperature: f32,
 }
 
 impl AirData {
@@ -178,28 +575,124 @@ impl AirData {
             nitrogen: 0.79,
             oxygen: 0.21,
             fumes: 0.0,
+            steam: 0.0,
+            carbon_dioxide: 0.0,
+            temperature: Tile::DEFAULT_TEMPERATURE,
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn default_temperature() -> f32 {
+        Tile::DEFAULT_TEMPERATURE
+    }
+
+    /// Update the temperature for the given amount of air coming in with the given heat, see [AirDiff::heat].
+    /// Must be called before the gases are changed.
+    pub(crate) fn mix_in(&mut self, amount: f32, heat: f32) {
+        let new_total = self.total() + amount;
+        if new_total > f32::MIN_POSITIVE {
+            self.temperature =
+                ((self.total() * self.temperature + heat) / new_total).max(-ZERO_CELSIUS_IN_KELVIN);
         }
     }
 
+    /// The amount of all gases together.
+    /// The fractions are 0 instead of NaN when this is 0, so a vacuum doesn't poison its neighbours.
+    #[inline(always)]
+    pub(crate) fn total(&self) -> f32 {
+        self.nitrogen + self.oxygen + self.fumes + self.steam + self.carbon_dioxide
+    }
+
     #[inline(always)]
     pub(crate) fn nitrogen_fraction(&self) -> f32 {
-        self.nitrogen / (self.nitrogen + self.oxygen + self.fumes)
+        self.nitrogen / self.total().max(f32::MIN_POSITIVE)
     }
 
     #[inline(always)]
     pub(crate) fn oxygen_fraction(&self) -> f32 {
-        self.oxygen / (self.nitrogen + self.oxygen + self.fumes)
+        self.oxygen / self.total().max(f32::MIN_POSITIVE)
     }
 
     #[inline(always)]
     pub(crate) fn fumes_fraction(&self) -> f32 {
-        self.fumes / (self.nitrogen + self.oxygen + self.fumes)
+        self.fumes / self.total().max(f32::MIN_POSITIVE)
+    }
+
+    #[inline(always)]
+    pub(crate) fn steam_fraction(&self) -> f32 {
+        self.steam / self.total().max(f32::MIN_POSITIVE)
+    }
+
+    #[inline(always)]
+    pub(crate) fn carbon_dioxide_fraction(&self) -> f32 {
+        self.carbon_dioxide / self.total().max(f32::MIN_POSITIVE)
     }
 
     #[inline(always)]
     pub(crate) fn air_pressure(&self, liquid_level: f32) -> f32 {
-        (self.nitrogen + self.oxygen + self.fumes)
-            / (1.0 - liquid_level / Tile::TUNNEL_HEIGHT).max(0.001)
+        self.total() * thermal_pressure_factor(self.temperature)
+            / air_fraction(liquid_level).max(0.001)
+    }
+}
+
+/// The fraction of a tile that is taken up by air instead of liquid
+#[inline(always)]
+fn air_fraction(liquid_level: f32) -> f32 {
+    (1.0 - liquid_level / LiquidData::MAX_LEVEL).clamp(0.0, 1.0)
+}
+
+/// How much more pressure air has at the given temperature than at [Tile::DEFAULT_TEMPERATURE]
+#[inline(always)]
+fn thermal_pressure_factor(temperature: f32) -> f32 {
+    (1.0 + (temperature - Tile::DEFAULT_TEMPERATURE) * AIR_THERMAL_EXPANSION).max(0.1)
+}
+
+/// The pressure at which steam starts condensing at the given temperature in degrees Celsius.
+/// It's 1.0 at the boiling point of water.
+fn saturation_steam_pressure(temperature: f32) -> f32 {
+    let kelvin = (temperature + ZERO_CELSIUS_IN_KELVIN).max(1.0);
+    let boiling_kelvin = WATER_BOILING_TEMPERATURE + ZERO_CELSIUS_IN_KELVIN;
+    (STEAM_PRESSURE_CURVE * (1.0 / boiling_kelvin - 1.0 / kelvin)).exp()
+}
+
+impl Debug for AirData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AirData")
+            .field("nitrogen", &self.nitrogen)
+            .field("oxygen", &self.oxygen)
+            .field("fumes", &self.fumes)
+            .field("steam", &self.steam)
+            .field("carbon_dioxide", &self.carbon_dioxide)
+            .field("temperature", &self.temperature)
+            .field("nitrogen_fraction", &self.nitrogen_fraction())
+            .field("oxygen_fraction", &self.oxygen_fraction())
+            .field("fumes_fraction", &self.fumes_fraction())
+            .field("steam_fraction", &self.steam_fraction())
+            .field("carbon_dioxide_fraction", &self.carbon_dioxide_fraction())
+            .field("air_pressure", &self.air_pressure(0.0))
+            .finish()
+    }
+}
+
+impl Display for AirData {
+    /// Shows the components with their fractions, the pressure the air would have without any liquid and the temperature
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "N2: {:.3} ({:.1}%), O2: {:.3} ({:.1}%), fumes: {:.3} ({:.1}%), steam: {:.3} ({:.1}%), CO2: {:.3} ({:.1}%), pressure: {:.3}, temperature: {:.1}°C",
+            self.nitrogen,
+            self.nitrogen_fraction() * 100.0,
+            self.oxygen,
+            self.oxygen_fraction() * 100.0,
+            self.fumes,
+            self.fumes_fraction() * 100.0,
+            self.steam,
+            self.steam_fraction() * 100.0,
+            self.carbon_dioxide,
+            self.carbon_dioxide_fraction() * 100.0,
+            self.air_pressure(0.0),
+            self.temperature,
+        )
     }
 }
 
@@ -210,12 +703,25 @@ impl Default for AirData {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AirLeveler<COORD> {
     pub x: COORD,
     pub y: COORD,
     pub nitrogen: f32,
     pub oxygen: f32,
     pub fumes: f32,
+    /// The gases that are leveled. The levels of the other gases are ignored.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub gases: GasMask,
+    /// The amount of gas the leveler can still inject, like an oxygen tank.
+    /// None means the supply is unlimited.
+    pub reservoir: Option<f32>,
+    /// The fraction of the difference to the target levels that is closed per second.
+    /// None means the air is leveled instantly.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rate: Option<f32>,
+    /// A disabled leveler doesn't do anything
+    pub enabled: bool,
 }
 
 impl AirLeveler<isize> {
@@ -226,15 +732,60 @@ impl AirLeveler<isize> {
             nitrogen: self.nitrogen,
             oxygen: self.oxygen,
             fumes: self.fumes,
+            gases: self.gases,
+            reservoir: self.reservoir,
+            rate: self.rate,
+            enabled: self.enabled,
         }
     }
 }
 
+/// The gases an [AirLeveler] levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GasMask {
+    pub nitrogen: bool,
+    pub oxygen: bool,
+    pub fumes: bool,
+}
+
+impl GasMask {
+    /// Level all gases
+    pub const ALL: Self = Self {
+        nitrogen: true,
+        oxygen: true,
+        fumes: true,
+    };
+    /// Only level the oxygen, like an oxygen generator
+    pub const OXYGEN: Self = Self {
+        nitrogen: false,
+        oxygen: true,
+        fumes: false,
+    };
+    /// Only level the fumes, like a fume scrubber
+    pub const FUMES: Self = Self {
+        nitrogen: false,
+        oxygen: false,
+        fumes: true,
+    };
+}
+
+impl Default for GasMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OxygenUser<COORD> {
     pub x: COORD,
     pub y: COORD,
+    /// The oxygen that is turned into carbon dioxide per second, like a breathing character.
+    /// When negative, carbon dioxide is turned back into oxygen, like a plant or a scrubber.
     pub change_per_sec: f32,
+    /// A disabled user doesn't do anything
+    pub enabled: bool,
 }
 
 impl OxygenUser<isize> {
@@ -243,17 +794,55 @@ impl OxygenUser<isize> {
             x: base_x.wrapping_add_signed(self.x),
             y: base_y.wrapping_add_signed(self.y),
             change_per_sec: self.change_per_sec,
+            enabled: self.enabled,
         }
     }
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AirPusher<COORD> {
     pub x: COORD,
     pub y: COORD,
-    pub direction: Facing,
-    /// Fraction of the air in the pusher location that is push into the given direction per second
+    pub direction: Direction8,
+    /// Fraction of the air in the pusher location that is push into the given direction per second.
+    ///
+    /// A negative amount pulls air the other way, from the tile in the given direction into the pusher location.
+    /// The fraction is then of the air in the tile that is pulled from.
     pub amount: f32,
+    /// A disabled pusher doesn't do anything
+    pub enabled: bool,
+}
+
+impl AirPusher<usize> {
+    /// Turn a pulling pusher into the pusher that pushes the same air the same way.
+    ///
+    /// Returns None if the tile that would be pulled from is outside of the map.
+    fn as_pushing(self, width: usize, height: usize) -> Option<Self> {
+        if self.amount >= 0.0 {
+            return Some(self);
+        }
+
+        let (x, y) = self
+            .direction
+            .move_coords_in_direction(self.x, self.y, width, height)?;
+
+        Some(AirPusher {
+            x,
+            y,
+            direction: self.direction.rotate(Facing::South),
+            amount: -self.amount,
+            enabled: self.enabled,
+        })
+    }
+
+    /// Sort key that orders pushers with the same direction from upstream to downstream
+    fn duct_order(&self) -> (u8, isize) {
+        let (offset_x, offset_y) = self.direction.offset();
+        let position_along_direction = offset_x * self.x as isize + offset_y * self.y as isize;
+
+        (self.direction as u8, position_along_direction)
+    }
 }
 
 impl AirPusher<isize> {
@@ -266,8 +855,526 @@ impl AirPusher<isize> {
         AirPusher {
             x: base_x.wrapping_add_signed(self.x),
             y: base_y.wrapping_add_signed(self.y),
-            direction: base_direction.rotate(self.direction),
+            direction: self.direction.rotate(base_direction),
             amount: self.amount,
+            enabled: self.enabled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{objects::environment_object::EnvironmentObject, tiles::TileType};
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn air_pusher_duct() {
+        let mut map = Map::new_default(5, 1);
+        map.tiles[(0, 0)].tile_type.get_air_mut().unwrap().fumes = 0.1;
+
+        // Push the objects in reverse so the object order doesn't line up with the duct order
+        for x in (0..3).rev() {
+            map.objects_mut()
+                .push_object::<EnvironmentObject>(AirPusher {
+                    x,
+                    y: 0,
+                    direction: Direction8::East,
+                    amount: 1.0,
+                    enabled: true,
+                });
+        }
+
+        map.perform_simulation_tick(0.5);
+
+        let fumes = |x: usize| map.tiles[(x, 0)].tile_type.get_air().unwrap().fumes;
+
+        assert!(fumes(1) > 0.0);
+        assert!(fumes(2) > 0.0);
+        assert!(fumes(3) > 0.0);
+        // Diffusion alone can't reach this far in a single tick
+        assert_eq!(fumes(4), 0.0);
+    }
+
+    #[test]
+    fn diagonal_air_pusher() {
+        let fumes_after_push = |amount: f32| {
+            let mut map = Map::new_default(3, 3);
+            map.tiles[(1, 1)].tile_type.get_air_mut().unwrap().fumes = 0.1;
+            map.objects_mut()
+                .push_object::<EnvironmentObject>(AirPusher {
+                    x: 1,
+                    y: 1,
+                    direction: Direction8::NorthEast,
+                    amount,
+                    enabled: true,
+                });
+
+            map.perform_simulation_tick(0.1);
+            let fumes = |x: usize, y: usize| map.tiles[(x, y)].tile_type.get_air().unwrap().fumes;
+            (fumes(2, 0), fumes(0, 2))
+        };
+
+        let (still_target, still_opposite) = fumes_after_push(0.0);
+        let (pushed_target, pushed_opposite) = fumes_after_push(1.0);
+
+        assert!(pushed_target > still_target);
+        assert!(pushed_opposite <= still_opposite);
+    }
+
+    #[test]
+    fn debug_air_diff_high_pressure() {
+        let mut map = Map::new_default(3, 3);
+        *map.tiles[(1, 1)].tile_type.get_air_mut().unwrap() = AirData {
+            nitrogen: 1.58,
+            oxygen: 0.42,
+            fumes: 0.1,
+            steam: 0.0,
+            carbon_dioxide: 0.0,
+            temperature: Tile::DEFAULT_TEMPERATURE,
+        };
+
+        let air_diff = map.debug_air_diff(0.1);
+
+        assert!(air_diff[(1, 1)].nitrogen < 0.0);
+        assert!(air_diff[(1, 1)].oxygen < 0.0);
+        assert!(air_diff[(1, 1)].fumes < 0.0);
+
+        for (x, y) in map.all_tile_coords().filter(|coords| *coords != (1, 1)) {
+            assert!(air_diff[(x, y)].nitrogen > 0.0);
+            assert!(air_diff[(x, y)].oxygen > 0.0);
+            assert!(air_diff[(x, y)].fumes > 0.0);
+        }
+    }
+
+    #[test]
+    fn diffusion_through_flooded_tile() {
+        let fumes_diffused = |liquid_level: f32| {
+            let mut map = Map::new_default(2, 1);
+            *map.tiles[(0, 0)].tile_type.get_air_mut().unwrap() = AirData {
+                nitrogen: 0.79,
+                oxygen: 0.11,
+                fumes: 0.1,
+                steam: 0.0,
+                carbon_dioxide: 0.0,
+                temperature: Tile::DEFAULT_TEMPERATURE,
+            };
+            map.tiles[(1, 0)].tile_type = TileType::Ground {
+                air: Default::default(),
+                liquids: LiquidData::Water {
+                    level: liquid_level,
+                },
+            };
+
+            map.debug_air_diff(0.1)[(1, 0)].fumes
+        };
+
+        let open = fumes_diffused(0.0);
+        let half_flooded = fumes_diffused(LiquidData::MAX_LEVEL / 2.0);
+        let nearly_submerged = fumes_diffused(LiquidData::MAX_LEVEL * 0.9);
+
+        assert!(open > 0.0);
+        assert!(half_flooded < open);
+        assert!(nearly_submerged < half_flooded);
+        assert!(nearly_submerged > 0.0);
+    }
+
+    #[test]
+    fn pulling_air_pusher() {
+        let air_after_push = |amount: f32| {
+            let mut map = Map::new_default(3, 1);
+            map.objects_mut()
+                .push_object::<EnvironmentObject>(AirPusher {
+                    x: 1,
+                    y: 0,
+                    direction: Direction8::East,
+                    amount,
+                    enabled: true,
+                });
+
+            map.perform_simulation_tick(0.1);
+
+            let total_air = |x: usize| {
+                let air = map.tiles[(x, 0)].tile_type.get_air().unwrap();
+                air.nitrogen + air.oxygen + air.fumes
+            };
+            (total_air(1), total_air(2))
+        };
+
+        let (still_pusher, still_target) = air_after_push(0.0);
+        let (pushing_pusher, pushing_target) = air_after_push(1.0);
+        let (pulling_pusher, pulling_target) = air_after_push(-1.0);
+
+        assert!(pushing_pusher < still_pusher);
+        assert!(pushing_target > still_target);
+        assert!(pulling_pusher > still_pusher);
+        assert!(pulling_target < still_target);
+        assert_relative_eq!(pushing_target - still_target, still_target - pulling_target);
+
+        // Pulling from outside of the map doesn't do anything
+        let mut map = Map::new_default(1, 1);
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(AirPusher {
+                x: 0,
+                y: 0,
+                direction: Direction8::East,
+                amount: -1.0,
+                enabled: true,
+            });
+        map.perform_simulation_tick(0.1);
+        assert_eq!(
+            *map.tiles[(0, 0)].tile_type.get_air().unwrap(),
+            AirData::default()
+        );
+    }
+
+    #[test]
+    fn air_data_formatting() {
+        let air = AirData {
+            nitrogen: 1.5,
+            oxygen: 0.4,
+            fumes: 0.1,
+            steam: 0.0,
+            carbon_dioxide: 0.0,
+            temperature: Tile::DEFAULT_TEMPERATURE,
+        };
+
+        assert_eq!(
+            air.to_string(),
+            "N2: 1.500 (75.0%), O2: 0.400 (20.0%), fumes: 0.100 (5.0%), steam: 0.000 (0.0%), CO2: 0.000 (0.0%), pressure: 2.000, temperature: 20.0°C"
+        );
+
+        let debug = format!("{air:?}");
+        assert!(debug.contains("oxygen_fraction: 0.2"));
+        assert!(debug.contains("air_pressure: 2.0"));
+    }
+
+    #[test]
+    fn equalize_air() {
+        let mut map = Map::new_default(4, 1);
+        *map.tiles[(0, 0)].tile_type.get_air_mut().unwrap() = AirData {
+            nitrogen: 1.58,
+            oxygen: 0.42,
+            fumes: 0.5,
+            steam: 0.0,
+            carbon_dioxide: 0.0,
+            temperature: Tile::DEFAULT_TEMPERATURE,
+        };
+        map.tiles[(3, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 1.0 },
+        };
+
+        let pressure = |map: &Map, x: usize| {
+            let (air, liquids) = map.tiles[(x, 0)].tile_type.get_ground().unwrap();
+            air.air_pressure(liquids.get_level::<AnyLiquid>())
+        };
+        let total_air = |map: &Map| {
+            [0, 3]
+                .into_iter()
+                .map(|x| {
+                    let air = map.tiles[(x, 0)].tile_type.get_air().unwrap();
+                    air.nitrogen + air.oxygen + air.fumes
+                })
+                .sum::<f32>()
+        };
+
+        let start_total_air = total_air(&map);
+        let mut last_difference = (pressure(&map, 0) - pressure(&map, 3)).abs();
+
+        for _ in 0..100 {
+            map.equalize_air((0, 0), (3, 0), 1.0, 0.1);
+
+            let difference = (pressure(&map, 0) - pressure(&map, 3)).abs();
+            assert!(difference <= last_difference);
+            last_difference = difference;
+        }
+
+        assert_relative_eq!(pressure(&map, 0), pressure(&map, 3), epsilon = 0.0001);
+        assert_relative_eq!(total_air(&map), start_total_air, epsilon = 0.0001);
+        // The tiles in between aren't touched
+        assert_eq!(
+            *map.tiles[(1, 0)].tile_type.get_air().unwrap(),
+            AirData::default()
+        );
+    }
+
+    #[test]
+    fn air_leveler_reservoir() {
+        let mut map = Map::new_default(1, 1);
+        let leveler = map
+            .objects_mut()
+            .push_object::<EnvironmentObject>(AirLeveler {
+                x: 0,
+                y: 0,
+                nitrogen: 0.79,
+                oxygen: 0.21,
+                fumes: 0.0,
+                gases: GasMask::ALL,
+                reservoir: Some(0.15),
+                rate: None,
+                enabled: true,
+            });
+        let reservoir = |map: &Map| match &*map.objects().get_object(leveler).unwrap() {
+            EnvironmentObject::AirLeveler(al) => al.reservoir.unwrap(),
+            _ => unreachable!(),
+        };
+
+        // Someone breathes away some oxygen, the tank refills it
+        map.tiles[(0, 0)].tile_type.get_air_mut().unwrap().oxygen = 0.11;
+        map.perform_simulation_tick(0.1);
+        assert_relative_eq!(map.tiles[(0, 0)].tile_type.get_air().unwrap().oxygen, 0.21);
+        assert_relative_eq!(reservoir(&map), 0.05, epsilon = 0.0001);
+
+        // Only half of what's needed is left
+        map.tiles[(0, 0)].tile_type.get_air_mut().unwrap().oxygen = 0.11;
+        map.perform_simulation_tick(0.1);
+        assert_relative_eq!(
+            map.tiles[(0, 0)].tile_type.get_air().unwrap().oxygen,
+            0.16,
+            epsilon = 0.0001
+        );
+        assert_eq!(reservoir(&map), 0.0);
+
+        // The tank is empty, so it doesn't inject anything anymore
+        map.tiles[(0, 0)].tile_type.get_air_mut().unwrap().oxygen = 0.11;
+        map.perform_simulation_tick(0.1);
+        assert_relative_eq!(map.tiles[(0, 0)].tile_type.get_air().unwrap().oxygen, 0.11);
+    }
+
+    #[test]
+    fn air_leveler_gas_mask() {
+        let mut map = Map::new_default(1, 1);
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(AirLeveler {
+                x: 0,
+                y: 0,
+                nitrogen: 0.0,
+                oxygen: 0.5,
+                fumes: 0.0,
+                gases: GasMask::OXYGEN,
+                reservoir: None,
+                rate: None,
+                enabled: true,
+            });
+
+        // Only the oxygen is leveled, the nitrogen and fumes are left alone
+        map.tiles[(0, 0)].tile_type.get_air_mut().unwrap().fumes = 0.1;
+        map.perform_simulation_tick(0.1);
+        let air = *map.tiles[(0, 0)].tile_type.get_air().unwrap();
+        assert_eq!(air.oxygen, 0.5);
+        assert_eq!(air.nitrogen, AirData::default().nitrogen);
+        assert_eq!(air.fumes, 0.1);
+    }
+
+    #[test]
+    fn disabled_air_leveler() {
+        let mut map = Map::new_default(1, 1);
+        let leveler = map
+            .objects_mut()
+            .push_object::<EnvironmentObject>(AirLeveler {
+                x: 0,
+                y: 0,
+                nitrogen: 0.5,
+                oxygen: 0.5,
+                fumes: 0.5,
+                gases: GasMask::ALL,
+                reservoir: None,
+                rate: None,
+                enabled: true,
+            });
+
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.tiles[(0, 0)].tile_type.get_air().unwrap().fumes, 0.5);
+
+        map.objects()
+            .get_object_mut(leveler)
+            .unwrap()
+            .set_enabled(false);
+        map.tiles[(0, 0)].tile_type = Default::default();
+
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.tiles[(0, 0)].tile_type.get_air().unwrap().fumes, 0.0);
+        assert_eq!(
+            map.objects()
+                .get_objects::<EnvironmentObject>()
+                .map(|object| object.id())
+                .collect::<Vec<_>>(),
+            vec![leveler]
+        );
+        assert!(!map.objects().get_object(leveler).unwrap().is_enabled());
+    }
+
+    #[test]
+    fn breathing_and_plants() {
+        let mut map = Map::new_default(1, 1);
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(OxygenUser {
+                x: 0,
+                y: 0,
+                change_per_sec: 0.001,
+                enabled: true,
+            });
+
+        // Breathing makes carbon dioxide, not fumes
+        map.step_n(1.0, 10);
+        let air = *map.tiles[(0, 0)].tile_type.get_air().unwrap();
+        assert_relative_eq!(air.carbon_dioxide, 0.01, epsilon = 1e-5);
+        assert_relative_eq!(air.oxygen, 0.20, epsilon = 1e-5);
+        assert_eq!(air.fumes, 0.0);
+
+        // A plant turns it back into oxygen faster than it's breathed out
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(OxygenUser {
+                x: 0,
+                y: 0,
+                change_per_sec: -0.002,
+                enabled: true,
+            });
+        map.step_n(1.0, 20);
+        let air = *map.tiles[(0, 0)].tile_type.get_air().unwrap();
+        assert!(air.carbon_dioxide < 0.002);
+        assert_relative_eq!(air.oxygen + air.carbon_dioxide, 0.21, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn sealed_tile_excluded_from_air() {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(1, 0)].sealed = true;
+        *map.tiles[(1, 0)].tile_type.get_air_mut().unwrap() = AirData {
+            nitrogen: 2.0,
+            oxygen: 1.0,
+            fumes: 0.5,
+            steam: 0.0,
+            carbon_dioxide: 0.0,
+            temperature: Tile::DEFAULT_TEMPERATURE,
+        };
+
+        for _ in 0..10 {
+            map.perform_simulation_tick(0.5);
         }
+
+        let sealed_air = map.tiles[(1, 0)].tile_type.get_air().unwrap();
+        assert_eq!(sealed_air.nitrogen, 2.0);
+        assert_eq!(sealed_air.oxygen, 1.0);
+        assert_eq!(sealed_air.fumes, 0.5);
+
+        for x in [0, 2] {
+            let air = map.tiles[(x, 0)].tile_type.get_air().unwrap();
+            assert_eq!(air.nitrogen, AirData::new_default().nitrogen);
+            assert_eq!(air.fumes, 0.0);
+        }
+    }
+
+    #[test]
+    fn water_evaporates_when_hot_or_in_low_pressure() {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 1.0 },
+        };
+        map.tiles[(2, 0)].tile_type = TileType::Ground {
+            air: AirData {
+                nitrogen: 0.0,
+                oxygen: 0.0,
+                fumes: 0.0,
+                steam: 0.0,
+                carbon_dioxide: 0.0,
+                temperature: Tile::DEFAULT_TEMPERATURE,
+            },
+            liquids: LiquidData::Water { level: 1.0 },
+        };
+
+        // At room temperature and pressure, water stays water
+        assert_eq!(map.debug_air_diff(0.1)[(0, 0)].evaporated, 0.0);
+        // In a vacuum it boils
+        assert!(map.debug_air_diff(0.1)[(2, 0)].evaporated > 0.0);
+
+        map.tiles[(0, 0)].temperature = WATER_BOILING_TEMPERATURE;
+        let air_diff = map.debug_air_diff(0.1);
+        assert!(air_diff[(0, 0)].evaporated > 0.0);
+
+        // The water that's gone is now steam
+        let total_water = |map: &Map| {
+            map.all_tile_coords()
+                .filter_map(|(x, y)| map.tiles[(x, y)].tile_type.get_ground())
+                .map(|(air, liquids)| {
+                    liquids.get_level::<Water>() * STEAM_PER_WATER_LEVEL + air.steam
+                })
+                .sum::<f32>()
+        };
+        let water_before = total_water(&map);
+
+        map.apply_air_diff(&air_diff, 0.1);
+        let no_liquid_diff = Grid::new(3, 1, crate::liquids::LiquidDiff::default());
+        map.apply_liquid_diff(&no_liquid_diff, &no_liquid_diff, &air_diff, 1.0);
+
+        assert!(map.tiles[(0, 0)].tile_type.get_air().unwrap().steam > 0.0);
+        assert!(
+            map.tiles[(0, 0)]
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<Water>()
+                < 1.0
+        );
+        assert_relative_eq!(total_water(&map), water_before, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn steam_condenses_on_cold_tiles_and_walls() {
+        let mut map = Map::new_default(4, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Wall;
+        let air = AirData {
+            steam: 0.02,
+            ..AirData::new_default()
+        };
+
+        // Just under what the air can hold at room temperature, but the wall is colder
+        assert_eq!(
+            map.calculate_evaporation(3, 0, &air, &LiquidData::None, 1.0),
+            0.0
+        );
+        assert!(map.calculate_evaporation(1, 0, &air, &LiquidData::None, 1.0) < 0.0);
+
+        map.tiles[(3, 0)].temperature = 0.0;
+        assert!(map.calculate_evaporation(3, 0, &air, &LiquidData::None, 1.0) < 0.0);
+
+        // A whole tick puts the condensed steam into the tile as water
+        *map.tiles[(3, 0)].tile_type.get_air_mut().unwrap() = air;
+        map.perform_simulation_tick(0.1);
+        assert!(
+            map.tiles[(3, 0)]
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<Water>()
+                > 0.0
+        );
+    }
+
+    #[test]
+    fn hot_air_spreads_out() {
+        let mut map = Map::new_default(5, 1);
+        map.tiles[(2, 0)].temperature = 300.0;
+        let total_air = |map: &Map| {
+            map.all_tile_coords()
+                .map(|(x, y)| map.tiles[(x, y)].tile_type.get_air().unwrap().total())
+                .sum::<f32>()
+        };
+        let initial_air = total_air(&map);
+
+        for _ in 0..50 {
+            map.perform_simulation_tick(0.1);
+        }
+
+        let air = |x: usize| *map.tiles[(x, 0)].tile_type.get_air().unwrap();
+        // The air takes on the heat of the tile, expands and pushes some of itself out
+        assert!(air(2).temperature > 100.0);
+        assert!(air(2).total() < air(0).total());
+        // The air that's pushed out brings its heat with it
+        assert!(air(1).temperature > air(0).temperature);
+        assert!(air(0).temperature > Tile::DEFAULT_TEMPERATURE);
+        assert_relative_eq!(total_air(&map), initial_air, epsilon = 1e-4);
     }
 }
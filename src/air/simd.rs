@@ -0,0 +1,328 @@
+use wide::{f32x8, CmpGe, CmpGt, CmpLt};
+
+use super::{
+    air_fraction, AirDiff, PHASE_CHANGE_RATE, STEAM_PER_WATER_LEVEL, STEAM_PRESSURE_CURVE,
+    WALL_CONDENSATION_COOLING, ZERO_CELSIUS_IN_KELVIN,
+};
+use crate::{
+    grid::Grid,
+    heat::WATER_BOILING_TEMPERATURE,
+    liquids::{AnyLiquid, Water},
+    Map,
+};
+
+const LANES: usize = 8;
+
+/// The offsets to the neighbours in the column major tile arrays, as multiples of the column stride and rows
+const NEIGHBOUR_OFFSETS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+impl Map {
+    /// Does the same as the scalar air diff, but one neighbour direction at a time for [LANES] tiles at once.
+    ///
+    /// The air is first copied into flat column major arrays with a border of inactive tiles around the map,
+    /// so the neighbours of every tile can be loaded without bounds checks.
+    pub(super) fn calculate_air_diff_simd(
+        &self,
+        delta_time: f32,
+        air_diff_result: &mut Grid<AirDiff>,
+    ) {
+        let (width, height) = (self.width(), self.height());
+        let stride = height + 2;
+        // Extra room at the end so the last chunk can always be loaded in full
+        let len = (width + 2) * stride + LANES;
+        let index = |x: usize, y: usize| (x + 1) * stride + y + 1;
+
+        // Nitrogen, oxygen, fumes, steam and carbon dioxide
+        const GASES: usize = 5;
+        let mut gases = [(); GASES].map(|_| vec![0.0; len]);
+        let mut fractions = [(); GASES].map(|_| vec![0.0; len]);
+        let mut pressures = vec![0.0; len];
+        let mut diffusion_areas = vec![0.0; len];
+        let mut temperatures = vec![0.0; len];
+        let mut air_temperatures = vec![0.0; len];
+        let mut water_levels = vec![0.0; len];
+        // 1.0 for the tiles that take part in the air simulation this tick, 0.0 for the rest
+        let mut active = vec![0.0; len];
+        // 1.0 for walls, 0.0 for the rest
+        let mut walls = vec![0.0; len];
+
+        for (x, y) in self.all_tile_coords() {
+            let tile = &self.tiles[(x, y)];
+            if tile.tile_type.is_wall() {
+                walls[index(x, y)] = 1.0;
+            }
+
+            if self.settled[(x, y)] {
+                continue;
+            }
+
+            let Some((air, liquids)) = tile.get_simulated_air() else {
+                continue;
+            };
+
+            let i = index(x, y);
+            let liquid_level = liquids.get_level::<AnyLiquid>();
+
+            for (gas, (amount, fraction)) in [
+                (air.nitrogen, air.nitrogen_fraction()),
+                (air.oxygen, air.oxygen_fraction()),
+                (air.fumes, air.fumes_fraction()),
+                (air.steam, air.steam_fraction()),
+                (air.carbon_dioxide, air.carbon_dioxide_fraction()),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                gases[gas][i] = amount;
+                fractions[gas][i] = fraction;
+            }
+
+            pressures[i] = air.air_pressure(liquid_level);
+            diffusion_areas[i] = air_fraction(liquid_level);
+            temperatures[i] = tile.temperature;
+            air_temperatures[i] = air.temperature;
+            water_levels[i] = liquids.get_level::<Water>();
+            active[i] = 1.0;
+        }
+
+        let diffusion_rate =
+            f32x8::splat(self.simulation_params.air_diffusion_spread_rate * delta_time);
+        let pressure_rate = f32x8::splat(self.simulation_params.air_pressure_spread_rate);
+        let delta_time_lanes = f32x8::splat(delta_time);
+        let eighth = f32x8::splat(1.0 / 8.0);
+
+        let neighbour_offsets =
+            NEIGHBOUR_OFFSETS.map(|(offset_x, offset_y)| offset_x * stride as isize + offset_y);
+        // The chunks also cover the border tiles, but those are inactive so nothing is traded with them
+        let chunks = (index(0, 0)..=index(width - 1, height - 1)).step_by(LANES);
+
+        let mut diffs = [(); GASES].map(|_| vec![0.0; len]);
+        let mut heat_diffs = vec![0.0; len];
+
+        for offset in neighbour_offsets {
+            for i in chunks.clone() {
+                let n = i.wrapping_add_signed(offset);
+
+                let both_active = load(&active, i) * load(&active, n);
+                // Air can only diffuse through the part of the tiles that isn't flooded
+                let diffusion_area =
+                    load(&diffusion_areas, i).min(load(&diffusion_areas, n)) * both_active;
+
+                // Air moves due to the total pressure difference, not the difference between each element separately.
+                // When the neighbour has the higher pressure, the difference is 0 and so is the flow.
+                let air_pressure = load(&pressures, i);
+                let neighbour_air_pressure = load(&pressures, n);
+                let pressure_delta = (air_pressure - neighbour_air_pressure).max(f32x8::ZERO);
+                let applied_pressure_delta = ((pressure_delta * pressure_rate).sqrt()
+                    * delta_time_lanes)
+                    .min(air_pressure * eighth)
+                    * both_active;
+
+                let mut total_traded = f32x8::ZERO;
+                for gas in 0..GASES {
+                    let fraction = load(&fractions[gas], i);
+
+                    // We trade air equally. We give some, we take some
+                    let traded = (fraction * neighbour_air_pressure)
+                        .max(-load(&gases[gas], n))
+                        .min(load(&gases[gas], i) * eighth)
+                        * diffusion_rate
+                        * diffusion_area;
+                    total_traded += traded;
+                    let moved = traded + applied_pressure_delta * fraction;
+
+                    let diff = &mut diffs[gas];
+                    let neighbour_diff = load(diff, n) + moved;
+                    store(diff, n, neighbour_diff);
+                    let tile_diff = load(diff, i) - moved;
+                    store(diff, i, tile_diff);
+                }
+
+                // The air that is given away has our temperature, the air that is taken that of the neighbour.
+                // The fractions add up to 1, so all of the pressure flow is ours.
+                let air_temperature = load(&air_temperatures, i);
+                let heat = (total_traded.max(f32x8::ZERO) + applied_pressure_delta)
+                    * air_temperature
+                    + total_traded.min(f32x8::ZERO) * load(&air_temperatures, n);
+                let neighbour_heat_diff = load(&heat_diffs, n) + heat;
+                store(&mut heat_diffs, n, neighbour_heat_diff);
+                let tile_heat_diff = load(&heat_diffs, i) - heat;
+                store(&mut heat_diffs, i, tile_heat_diff);
+            }
+        }
+
+        // Same as the scalar evaporation, but with both outcomes calculated and then the right one picked
+        let phase_change_rate = f32x8::splat((PHASE_CHANGE_RATE * delta_time).min(1.0));
+        let mut evaporations = vec![0.0; len];
+
+        for i in chunks {
+            let next_to_wall =
+                neighbour_offsets
+                    .iter()
+                    .fold(f32x8::ZERO, |next_to_wall, offset| {
+                        next_to_wall.max(load(&walls, i.wrapping_add_signed(*offset)))
+                    });
+
+            let temperature = load(&temperatures, i);
+            let water_level = load(&water_levels, i);
+            let air_fraction = load(&diffusion_areas, i);
+            let steam = load(&gases[3], i);
+            let steam_pressure = steam / air_fraction.max(f32x8::splat(0.001));
+
+            let saturation_pressure = saturation_steam_pressure(temperature);
+            let boiling = temperature.cmp_ge(f32x8::splat(WATER_BOILING_TEMPERATURE))
+                | saturation_pressure.cmp_ge(load(&pressures, i));
+            let evaporating = water_level.cmp_gt(f32x8::ZERO)
+                & boiling
+                & steam_pressure.cmp_lt(saturation_pressure);
+            let evaporated =
+                ((saturation_pressure - steam_pressure) * air_fraction * phase_change_rate)
+                    .min(water_level * f32x8::splat(STEAM_PER_WATER_LEVEL));
+
+            let condensation_pressure = saturation_steam_pressure(
+                temperature - next_to_wall * f32x8::splat(WALL_CONDENSATION_COOLING),
+            );
+            let condensed =
+                ((steam_pressure - condensation_pressure) * air_fraction * phase_change_rate)
+                    .min(steam)
+                    .max(f32x8::ZERO);
+
+            let active = load(&active, i);
+            store(
+                &mut evaporations,
+                i,
+                evaporating.blend(evaporated, -condensed) * active,
+            );
+            // Steam comes off the water at the temperature of the tile and condenses out of the air at its temperature
+            let evaporation_heat = evaporating.blend(
+                evaporated * temperature,
+                -condensed * load(&air_temperatures, i),
+            );
+            let heat_diff = load(&heat_diffs, i) + evaporation_heat * active;
+            store(&mut heat_diffs, i, heat_diff);
+        }
+
+        air_diff_result.fill(AirDiff::default());
+
+        for (x, y) in self.all_tile_coords() {
+            let i = index(x, y);
+            if active[i] == 0.0 {
+                continue;
+            }
+
+            let [nitrogen, oxygen, fumes, steam, carbon_dioxide] =
+                diffs.each_ref().map(|diff| diff[i]);
+            let evaporated = evaporations[i];
+
+            air_diff_result[(x, y)] = AirDiff {
+                nitrogen,
+                oxygen,
+                fumes,
+                steam: steam + evaporated,
+                carbon_dioxide,
+                heat: heat_diffs[i],
+                evaporated,
+            };
+        }
+    }
+}
+
+#[inline(always)]
+fn load(values: &[f32], index: usize) -> f32x8 {
+    let lanes: [f32; LANES] = values[index..index + LANES].try_into().unwrap();
+    f32x8::from(lanes)
+}
+
+#[inline(always)]
+fn store(values: &mut [f32], index: usize, lanes: f32x8) {
+    values[index..index + LANES].copy_from_slice(&lanes.to_array());
+}
+
+/// The lanes version of [super::saturation_steam_pressure]
+#[inline(always)]
+fn saturation_steam_pressure(temperature: f32x8) -> f32x8 {
+    let kelvin = (temperature + f32x8::splat(ZERO_CELSIUS_IN_KELVIN)).max(f32x8::ONE);
+    let boiling_kelvin = WATER_BOILING_TEMPERATURE + ZERO_CELSIUS_IN_KELVIN;
+    (f32x8::splat(STEAM_PRESSURE_CURVE)
+        * (f32x8::splat(1.0 / boiling_kelvin) - f32x8::ONE / kelvin))
+        .exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        air::AirData,
+        liquids::LiquidData,
+        tiles::{Tile, TileType},
+        SimulationBackend, SimulationParams,
+    };
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn simd_matches_scalar() {
+        // A height that isn't a multiple of the lanes, so chunks cross over into the next column
+        let mut map = Map::new_default(7, 11);
+        map.set_simulation_params(SimulationParams::arcade());
+
+        for (x, y) in map.all_tile_coords() {
+            let seed = (x * 11 + y) as f32;
+            map.tiles[(x, y)].tile_type = TileType::Ground {
+                air: AirData {
+                    nitrogen: 0.5 + (seed * 0.37).sin().abs(),
+                    oxygen: 0.2 * (seed * 0.11).cos().abs(),
+                    fumes: if x == 3 { 0.4 } else { 0.0 },
+                    steam: if y == 2 { 0.1 } else { 0.0 },
+                    carbon_dioxide: if x == 5 { 0.05 } else { 0.0 },
+                    temperature: 20.0 + (seed * 0.23).sin() * 80.0,
+                },
+                liquids: LiquidData::Water {
+                    level: (seed * 0.07).sin().max(0.0) * LiquidData::MAX_LEVEL,
+                },
+            };
+        }
+        map.tiles[(2, 5)].tile_type = TileType::Wall;
+        map.tiles[(6, 0)].tile_type = TileType::Ground {
+            air: AirData {
+                nitrogen: 0.0,
+                oxygen: 0.0,
+                fumes: 0.0,
+                steam: 0.0,
+                carbon_dioxide: 0.0,
+                temperature: Tile::DEFAULT_TEMPERATURE,
+            },
+            liquids: Default::default(),
+        };
+        map.tiles[(4, 8)].sealed = true;
+        map.tiles[(0, 10)].temperature = 150.0;
+        map.settled[(5, 3)] = true;
+
+        map.set_simulation_backend(SimulationBackend::Scalar);
+        let scalar = map.debug_air_diff(0.05);
+        map.set_simulation_backend(SimulationBackend::Simd);
+        let simd = map.debug_air_diff(0.05);
+
+        for (x, y) in map.all_tile_coords() {
+            let (scalar, simd) = (scalar[(x, y)], simd[(x, y)]);
+            assert_relative_eq!(scalar.nitrogen, simd.nitrogen, epsilon = 1e-6);
+            assert_relative_eq!(scalar.oxygen, simd.oxygen, epsilon = 1e-6);
+            assert_relative_eq!(scalar.fumes, simd.fumes, epsilon = 1e-6);
+            assert_relative_eq!(scalar.steam, simd.steam, epsilon = 1e-6);
+            assert_relative_eq!(scalar.carbon_dioxide, simd.carbon_dioxide, epsilon = 1e-6);
+            assert_relative_eq!(scalar.evaporated, simd.evaporated, epsilon = 1e-6);
+            assert_relative_eq!(scalar.heat, simd.heat, epsilon = 1e-4);
+        }
+        assert!(scalar[(3, 4)].fumes.abs() > 0.0);
+        assert!(scalar[(3, 4)].heat.abs() > 0.0);
+        assert!(scalar[(0, 10)].evaporated > 0.0);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    liquids::{Lava, LiquidData, Water},
+    tiles::{Tile, TileType},
+    Map,
+};
+use std::fmt::Display;
+
+const WALL_GLYPH: char = '#';
+const GROUND_GLYPH: char = '.';
+const WATER_GLYPH: char = '~';
+const LAVA_GLYPH: char = '!';
+
+/// The liquid level that is used for liquid tiles loaded from ascii
+const ASCII_LIQUID_LEVEL: f32 = 1.0;
+
+impl Map {
+    /// Render the map layout as ascii.
+    ///
+    /// Every row is a y coordinate and every column an x coordinate.
+    /// - `#`: Wall
+    /// - `.`: Ground
+    /// - `~`: Water
+    /// - `!`: Lava
+    pub fn to_ascii(&self) -> String {
+        let mut output = String::with_capacity((self.width() + 1) * self.height());
+
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let glyph = match self.tiles[(x, y)].tile_type.get_liquids() {
+                    None => WALL_GLYPH,
+                    Some(liquids) if liquids.get_level_optional::<Lava>().is_some() => LAVA_GLYPH,
+                    Some(liquids) if liquids.get_level_optional::<Water>().is_some() => WATER_GLYPH,
+                    Some(_) => GROUND_GLYPH,
+                };
+
+                output.push(glyph);
+            }
+
+            output.push('\n');
+        }
+
+        output
+    }
+
+    /// Create a map from the same ascii layout [Self::to_ascii] produces.
+    ///
+    /// The amount of rows is the height of the map and the amount of columns is the width.
+    /// All rows must have the same amount of columns.
+    pub fn from_ascii(s: &str) -> Result<Self, AsciiMapError> {
+        let rows = s.lines().collect::<Vec<_>>();
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        if width == 0 {
+            return Err(AsciiMapError::Empty);
+        }
+
+        let mut map = Self::new_default(width, rows.len());
+
+        for (y, row) in rows.into_iter().enumerate() {
+            let columns = row.chars().count();
+            if columns != width {
+                return Err(AsciiMapError::WrongColumnCount {
+                    row: y,
+                    expected: width,
+                    found: columns,
+                });
+            }
+
+            for (x, glyph) in row.chars().enumerate() {
+                let tile_type = match glyph {
+                    WALL_GLYPH => TileType::Wall,
+                    GROUND_GLYPH => TileType::new_default(),
+                    WATER_GLYPH => TileType::Ground {
+                        air: Default::default(),
+                        liquids: LiquidData::Water {
+                            level: ASCII_LIQUID_LEVEL,
+                        },
+                    },
+                    LAVA_GLYPH => TileType::Ground {
+                        air: Default::default(),
+                        liquids: LiquidData::Lava {
+                            level: ASCII_LIQUID_LEVEL,
+                        },
+                    },
+                    glyph => return Err(AsciiMapError::UnknownGlyph { x, y, glyph }),
+                };
+
+                map.tiles[(x, y)] = Tile {
+                    tile_type,
+                    ..Default::default()
+                };
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsciiMapError {
+    /// There are no rows, or the first row is empty
+    Empty,
+    /// The row doesn't have as many columns as the first row
+    WrongColumnCount {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownGlyph {
+        x: usize,
+        y: usize,
+        glyph: char,
+    },
+}
+
+impl Display for AsciiMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsciiMapError::Empty => write!(f, "The layout has no tiles"),
+            AsciiMapError::WrongColumnCount {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Expected {expected} columns in row {row}, but found {found}"
+            ),
+            AsciiMapError::UnknownGlyph { x, y, glyph } => {
+                write!(f, "Unknown glyph '{glyph}' at {x},{y}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsciiMapError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = "\
+#####
+#..~#
+#.!~#
+#####
+";
+
+    #[test]
+    fn ascii_round_trip() {
+        let map = Map::from_ascii(LAYOUT).unwrap();
+
+        assert_eq!((map.width(), map.height()), (5, 4));
+        assert!(map.tiles[(0, 0)].tile_type.is_wall());
+        assert!(map.tiles[(1, 1)].tile_type.get_liquids().is_some());
+        assert_eq!(
+            map.tiles[(3, 1)]
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<Water>(),
+            ASCII_LIQUID_LEVEL
+        );
+        assert_eq!(
+            map.tiles[(2, 2)]
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<Lava>(),
+            ASCII_LIQUID_LEVEL
+        );
+
+        assert_eq!(map.to_ascii(), LAYOUT);
+        assert_eq!(Map::from_ascii(&map.to_ascii()).unwrap().to_ascii(), LAYOUT);
+    }
+
+    #[test]
+    fn ascii_wrong_dimensions() {
+        assert_eq!(Map::from_ascii("").unwrap_err(), AsciiMapError::Empty);
+        assert_eq!(Map::from_ascii("\n#").unwrap_err(), AsciiMapError::Empty);
+        assert_eq!(
+            Map::from_ascii("#####\n#..#\n").unwrap_err(),
+            AsciiMapError::WrongColumnCount {
+                row: 1,
+                expected: 5,
+                found: 4
+            }
+        );
+        assert_eq!(
+            Map::from_ascii("#?").unwrap_err(),
+            AsciiMapError::UnknownGlyph {
+                x: 1,
+                y: 0,
+                glyph: '?'
+            }
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    objects::{
+        building::{
+            working_fraction, Building, BuildingType, PlacementError, WorkSpot, WorkSpotOccupation,
+        },
+        characters::Character,
+        ObjectId,
+    },
+    Map,
+};
+use glam::{vec2, Vec2};
+use std::fmt::Display;
+
+/// Where the builders stand relative to the location of the building, on the tile itself
+const CONSTRUCTION_WORKSPOTS: [Vec2; 2] = [vec2(0.2, 0.5), vec2(0.8, 0.5)];
+
+impl Map {
+    /// Start building the building.
+    ///
+    /// This places it as a [BuildingType::UnderConstruction] with workspots on its location,
+    /// which characters with the [WorkGoal::Construct](crate::objects::characters::WorkGoal::Construct) goal come to man.
+    /// Until the `required_work` is done, the building only takes up its footprint.
+    /// Then it becomes the given building and its builders go look for something else to do.
+    ///
+    /// The building must be placeable, see [Map::can_place_building].
+    /// The required work is in seconds of work with all workspots manned and can't be negative.
+    pub fn construct(
+        &mut self,
+        building: Building,
+        required_work: f32,
+    ) -> Result<ObjectId<Building>, ConstructionError> {
+        if !required_work.is_finite() || required_work < 0.0 {
+            return Err(ConstructionError::InvalidRequiredWork);
+        }
+        self.can_place_building(&building)?;
+
+        Ok(self.objects.get_mut().unwrap().push_object(Building {
+            location: building.location,
+            facing: building.facing,
+            building_type: BuildingType::UnderConstruction {
+                building_type: Box::new(building.building_type),
+                workspots: CONSTRUCTION_WORKSPOTS.map(|location| WorkSpot {
+                    location,
+                    occupation: WorkSpotOccupation::Open,
+                }),
+                progress: 0.0,
+                required_work,
+            },
+        }))
+    }
+
+    /// Progress the buildings under construction and finish the ones that got all their work
+    pub(crate) fn apply_construction(&mut self, delta_time: f32) {
+        let objects = self.objects.get_mut().unwrap();
+        let mut finished_buildings = Vec::new();
+
+        for mut building in objects.get_objects_mut::<Building>() {
+            let BuildingType::UnderConstruction {
+                workspots,
+                progress,
+                required_work,
+                ..
+            } = &mut building.building_type
+            else {
+                continue;
+            };
+
+            *progress += working_fraction(workspots) * delta_time;
+            if *progress >= *required_work {
+                finished_buildings.push(building.id());
+            }
+        }
+
+        // The finished building starts with its own workspots, none of which are taken yet
+        for building_id in finished_buildings.iter() {
+            objects.replace_object(*building_id, |building| match building.building_type {
+                BuildingType::UnderConstruction { building_type, .. } => Building {
+                    building_type: *building_type,
+                    ..building
+                },
+                _ => building,
+            });
+        }
+
+        for building_id in finished_buildings {
+            for mut character in objects.get_objects_mut::<Character>() {
+                character.finish_construction(building_id);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstructionError {
+    /// The building can't be placed where it's going to be built
+    Placement(PlacementError),
+    /// The required work is negative or not a finite number
+    InvalidRequiredWork,
+}
+
+impl From<PlacementError> for ConstructionError {
+    fn from(error: PlacementError) -> Self {
+        Self::Placement(error)
+    }
+}
+
+impl Display for ConstructionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstructionError::Placement(error) => write!(f, "{error}"),
+            ConstructionError::InvalidRequiredWork => {
+                write!(f, "The required work must be a finite number of 0 or more")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConstructionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::{
+            characters::{CharacterEvent, WorkGoal},
+            ObjectProperties,
+        },
+        Facing,
+    };
+    use glam::uvec2;
+
+    #[test]
+    fn builders_finish_construction() {
+        let mut map = Map::new_default(6, 3);
+        let building = map
+            .construct(
+                Building {
+                    location: uvec2(4, 1),
+                    facing: Facing::North,
+                    building_type: BuildingType::PoweredVentilator { power: 1.0 },
+                },
+                0.5,
+            )
+            .unwrap();
+        let air_pushers = |map: &Map| {
+            map.objects()
+                .get_object(building)
+                .unwrap()
+                .air_pushers()
+                .len()
+        };
+
+        // The site takes up the tile, but doesn't have the air pusher of the ventilator yet
+        assert_eq!(
+            map.construct(
+                Building {
+                    location: uvec2(4, 1),
+                    facing: Facing::North,
+                    building_type: BuildingType::Stockpile { capacity: 1 },
+                },
+                1.0,
+            ),
+            Err(ConstructionError::Placement(PlacementError::Overlapping {
+                x: 4,
+                y: 1,
+                building
+            }))
+        );
+        assert_eq!(air_pushers(&map), 0);
+        map.step_n(0.1, 10);
+        assert_eq!(air_pushers(&map), 0);
+
+        let builders = [0.5, 1.5].map(|y| {
+            map.objects_mut().push_object::<Character>(Character::new(
+                vec2(0.5, y),
+                1.0,
+                vec![WorkGoal::Construct],
+            ))
+        });
+        for _ in 0..100 {
+            map.perform_frame_tick(0.1);
+            map.perform_simulation_tick(0.1);
+            if air_pushers(&map) > 0 {
+                break;
+            }
+        }
+
+        let objects = map.objects();
+        assert!(matches!(
+            objects.get_object(building).unwrap().building_type,
+            BuildingType::PoweredVentilator { .. }
+        ));
+        for builder in builders {
+            assert_eq!(
+                objects.get_object(builder).unwrap().recent_events().last(),
+                Some(&CharacterEvent::ConstructionFinished { building })
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_required_work() {
+        let mut map = Map::new_default(3, 3);
+        for required_work in [-1.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(
+                map.construct(
+                    Building {
+                        location: uvec2(1, 1),
+                        facing: Facing::North,
+                        building_type: BuildingType::Stockpile { capacity: 1 },
+                    },
+                    required_work,
+                ),
+                Err(ConstructionError::InvalidRequiredWork)
+            );
+        }
+        assert_eq!(map.objects().get_objects::<Building>().count(), 0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Images of the layers of a map, to see what the simulation is doing while debugging.
+//!
+//! Only available with the `debug_render` feature.
+//! The colors come from a [colorgrad::Gradient] and the animations are written with the [gif] crate.
+
+use crate::{Map, MapLayer};
+use colorgrad::Gradient;
+use std::io::Write;
+
+/// The color of tiles that don't have the layer
+const NO_VALUE_COLOR: [u8; 3] = [128, 128, 128];
+/// The color of values below the minimum
+const BELOW_MIN_COLOR: [u8; 3] = [0, 0, 0];
+/// The color of values above the maximum
+const ABOVE_MAX_COLOR: [u8; 3] = [255, 255, 255];
+
+/// Render the layer of the map to RGB pixels, one pixel per tile.
+///
+/// The pixels are row-major with 3 bytes each: the color of tile `(x, y)` starts at `(y * width + x) * 3`.
+/// Values from `min_value` to `max_value` get the color of the gradient.
+/// Values below are black, values above are white and tiles that don't have the layer are grey.
+pub fn render_layer_to_rgb(
+    map: &Map,
+    layer: MapLayer,
+    gradient: &Gradient,
+    min_value: f32,
+    max_value: f32,
+) -> Vec<u8> {
+    let mut values = vec![f32::NAN; map.width() * map.height()];
+    map.write_layer(layer, &mut values, map.width());
+
+    values
+        .into_iter()
+        .flat_map(|value| {
+            if value.is_nan() {
+                NO_VALUE_COLOR
+            } else if value < min_value {
+                BELOW_MIN_COLOR
+            } else if value > max_value {
+                ABOVE_MAX_COLOR
+            } else {
+                let fraction = (value - min_value) / (max_value - min_value);
+                let [r, g, b, _] = gradient.at(fraction as f64).to_rgba8();
+                [r, g, b]
+            }
+        })
+        .collect()
+}
+
+/// Records a layer of a map as an endlessly repeating GIF, one frame at a time
+pub struct MapGifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    layer: MapLayer,
+    gradient: Gradient,
+    min_value: f32,
+    max_value: f32,
+    width: usize,
+    height: usize,
+}
+
+impl<W: Write> MapGifRecorder<W> {
+    /// Start a GIF of the layer of maps with the given size.
+    /// The colors are picked like in [render_layer_to_rgb].
+    pub fn new(
+        writer: W,
+        width: usize,
+        height: usize,
+        layer: MapLayer,
+        gradient: Gradient,
+        min_value: f32,
+        max_value: f32,
+    ) -> Result<Self, gif::EncodingError> {
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        Ok(Self {
+            encoder,
+            layer,
+            gradient,
+            min_value,
+            max_value,
+            width,
+            height,
+        })
+    }
+
+    /// Add the layer of the map as it is now as the next frame
+    ///
+    /// # Panics
+    ///
+    /// When the map doesn't have the size the recorder was made for.
+    pub fn record_frame(&mut self, map: &Map) -> Result<(), gif::EncodingError> {
+        assert_eq!(
+            (map.width(), map.height()),
+            (self.width, self.height),
+            "The map must have the size of the recording"
+        );
+
+        let pixels = render_layer_to_rgb(
+            map,
+            self.layer,
+            &self.gradient,
+            self.min_value,
+            self.max_value,
+        );
+        self.encoder.write_frame(&gif::Frame::from_rgb(
+            self.width as u16,
+            self.height as u16,
+            &pixels,
+        ))
+    }
+
+    /// Finish the GIF and get the writer back
+    pub fn into_inner(self) -> std::io::Result<W> {
+        self.encoder.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{liquids::LiquidData, tiles::TileType};
+
+    #[test]
+    fn render_layer_colors() {
+        let mut map = Map::new_default(4, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Wall;
+        for (x, level) in [(2, 0.5), (3, 2.0)] {
+            *map.tiles[(x, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water { level };
+        }
+
+        let pixels = render_layer_to_rgb(&map, MapLayer::Water, &colorgrad::viridis(), 0.5, 1.0);
+
+        assert_eq!(pixels.len(), 4 * 3);
+        assert_eq!(pixels[0..3], NO_VALUE_COLOR);
+        assert_eq!(pixels[3..6], BELOW_MIN_COLOR);
+        assert_eq!(pixels[6..9], colorgrad::viridis().at(0.0).to_rgba8()[0..3]);
+        assert_eq!(pixels[9..12], ABOVE_MAX_COLOR);
+    }
+
+    #[test]
+    fn record_gif() {
+        let map = Map::new_default(3, 2);
+        let mut recorder = MapGifRecorder::new(
+            Vec::new(),
+            3,
+            2,
+            MapLayer::Oxygen,
+            colorgrad::viridis(),
+            0.0,
+            1.0,
+        )
+        .unwrap();
+        recorder.record_frame(&map).unwrap();
+        recorder.record_frame(&map).unwrap();
+
+        let gif = recorder.into_inner().unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+    }
+}
@@ -0,0 +1,287 @@
+use crate::{
+    grid::Grid,
+    objects::{
+        characters::{Character, CharacterEvent},
+        ObjectId, ObjectKind,
+    },
+    tiles::Tile,
+    Map, TileCoordIter,
+};
+
+const DEFAULT_TILE_THRESHOLD: f32 = 0.01;
+
+/// Something that changed on the map that a listener may want to react to
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapEvent {
+    /// The tile changed more than the tile event threshold since the last event for it
+    TileChanged { x: usize, y: usize },
+    /// An object was added to the map
+    ObjectAdded {
+        object: ObjectId<()>,
+        kind: ObjectKind,
+    },
+    /// An object was removed from the map
+    ObjectRemoved {
+        object: ObjectId<()>,
+        kind: ObjectKind,
+    },
+    /// A character decided something or something happened to it,
+    /// like claiming a workspot or switching to another task
+    Character {
+        character: ObjectId<Character>,
+        event: CharacterEvent,
+    },
+}
+
+/// Gets called with the events of the map it's subscribed to.
+///
+/// This is implemented for closures, so `Box::new(|event: &MapEvent| ...)` can be subscribed directly.
+pub trait MapEventListener: Send + Sync {
+    fn on_event(&mut self, event: &MapEvent);
+}
+
+impl<F: FnMut(&MapEvent) + Send + Sync> MapEventListener for F {
+    fn on_event(&mut self, event: &MapEvent) {
+        self(event)
+    }
+}
+
+/// Identifies a subscription so it can be removed again with [Map::unsubscribe]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u32);
+
+pub(crate) struct EventListeners {
+    listeners: Vec<(SubscriptionId, Box<dyn MapEventListener>)>,
+    next_subscription_id: u32,
+    tile_threshold: f32,
+    /// The tiles as they were when their last event was sent. Only kept while there are listeners.
+    tile_baseline: Option<Grid<Tile>>,
+}
+
+impl EventListeners {
+    pub(crate) const fn new() -> Self {
+        Self {
+            listeners: Vec::new(),
+            next_subscription_id: 0,
+            tile_threshold: DEFAULT_TILE_THRESHOLD,
+            tile_baseline: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for EventListeners {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventListeners")
+            .field("listeners", &self.listeners.len())
+            .field("tile_threshold", &self.tile_threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Map {
+    /// Send all events that happened since the last time to the listeners.
+    ///
+    /// This is done at the end of every simulation and frame tick,
+    /// so it only needs to be called to get the events of changes made outside of the ticks.
+    pub fn publish_events(&mut self) {
+        let objects = self.objects.get_mut().unwrap();
+        let mut events = objects.take_events();
+
+        for mut character in objects.get_objects_mut::<Character>() {
+            let character_id = character.id();
+            events.extend(
+                character
+                    .take_unpublished_events()
+                    .into_iter()
+                    .map(|event| MapEvent::Character {
+                        character: character_id,
+                        event,
+                    }),
+            );
+        }
+
+        let threshold = self.event_listeners.tile_threshold;
+        if let Some(baseline) = &mut self.event_listeners.tile_baseline {
+            for (x, y) in TileCoordIter::new(self.tiles.width(), self.tiles.height()) {
+                let old = &baseline[(x, y)];
+                let new = &self.tiles[(x, y)];
+
+                let changed = old.max_difference(new) > threshold
+                    || (old.temperature - new.temperature).abs() > threshold
+                    || old.sealed != new.sealed;
+
+                if changed {
+                    baseline[(x, y)] = *new;
+                    events.push(MapEvent::TileChanged { x, y });
+                }
+            }
+        }
+
+        for (_, listener) in self.event_listeners.listeners.iter_mut() {
+            for event in events.iter() {
+                listener.on_event(event);
+            }
+        }
+    }
+
+    /// Get the events of the map as they happen.
+    ///
+    /// Only events that happen after subscribing are sent to the listener.
+    pub fn subscribe(&mut self, listener: Box<dyn MapEventListener>) -> SubscriptionId {
+        // Anything that happened before shouldn't reach the new listener
+        self.publish_events();
+
+        if self.event_listeners.tile_baseline.is_none() {
+            self.event_listeners.tile_baseline = Some(self.tiles.clone());
+        }
+
+        let id = SubscriptionId(self.event_listeners.next_subscription_id);
+        self.event_listeners.next_subscription_id += 1;
+        self.event_listeners.listeners.push((id, listener));
+
+        id
+    }
+
+    /// Remove a listener. Returns the listener, or None if it was already removed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> Option<Box<dyn MapEventListener>> {
+        let index = self
+            .event_listeners
+            .listeners
+            .iter()
+            .position(|(listener_id, _)| *listener_id == id)?;
+        let (_, listener) = self.event_listeners.listeners.remove(index);
+
+        if self.event_listeners.listeners.is_empty() {
+            self.event_listeners.tile_baseline = None;
+        }
+
+        Some(listener)
+    }
+
+    /// Set how much a tile needs to change before a [MapEvent::TileChanged] is sent for it.
+    ///
+    /// The change is measured as the biggest change in any of the air components, liquid levels,
+    /// the ground level or the temperature since the last event of the tile.
+    pub fn set_tile_event_threshold(&mut self, threshold: f32) {
+        self.event_listeners.tile_threshold = threshold;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::{
+            building::Building,
+            characters::{tests::ventilator, WorkGoal},
+        },
+        Facing,
+    };
+    use glam::{uvec2, vec2};
+    use std::sync::{Arc, Mutex};
+
+    fn recorder(map: &mut Map) -> (SubscriptionId, Arc<Mutex<Vec<MapEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let listener_events = events.clone();
+        let id = map.subscribe(Box::new(move |event: &MapEvent| {
+            listener_events.lock().unwrap().push(event.clone())
+        }));
+        (id, events)
+    }
+
+    #[test]
+    fn map_events() {
+        let mut map = Map::new_default(10, 3);
+        map.objects_mut()
+            .push_object::<Character>(Character::new(vec2(8.5, 0.5), 1.0, vec![]));
+
+        let (id, events) = recorder(&mut map);
+
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(3, 1), Facing::East));
+        map.tile_mut(5, 0).temperature = 80.0;
+
+        // Nothing is sent until the end of a tick
+        assert!(events.lock().unwrap().is_empty());
+        map.perform_simulation_tick(0.05);
+
+        let received = std::mem::take(&mut *events.lock().unwrap());
+        assert!(received.iter().any(|event| matches!(
+            event,
+            MapEvent::Character {
+                character: c,
+                event: CharacterEvent::ClaimedWorkspot { building: b, .. },
+            } if *c == character && *b == building
+        )));
+        assert_eq!(
+            received[..2],
+            [
+                MapEvent::ObjectAdded {
+                    object: character.cast(),
+                    kind: ObjectKind::Character,
+                },
+                MapEvent::ObjectAdded {
+                    object: building.cast(),
+                    kind: ObjectKind::HandCrankedVentilator,
+                },
+            ]
+        );
+        assert!(received.contains(&MapEvent::TileChanged { x: 5, y: 0 }));
+        // The character that existed before subscribing doesn't show up
+        assert_eq!(
+            received
+                .iter()
+                .filter(|event| matches!(event, MapEvent::ObjectAdded { .. }))
+                .count(),
+            2
+        );
+
+        // Removing the building releases the workspot of the character
+        map.objects_mut().remove_object(building);
+        map.publish_events();
+        let received = std::mem::take(&mut *events.lock().unwrap());
+        assert_eq!(
+            received,
+            [
+                MapEvent::ObjectRemoved {
+                    object: building.cast(),
+                    kind: ObjectKind::HandCrankedVentilator,
+                },
+                MapEvent::Character {
+                    character,
+                    event: CharacterEvent::BuildingRemoved { building },
+                },
+            ]
+        );
+
+        assert!(map.unsubscribe(id).is_some());
+        assert!(map.unsubscribe(id).is_none());
+        map.objects_mut().remove_object(character);
+        map.perform_simulation_tick(0.05);
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tile_event_threshold() {
+        let mut map = Map::new_default(2, 1);
+        map.set_tile_event_threshold(1.0);
+        let (_, events) = recorder(&mut map);
+
+        // Small changes add up until they go over the threshold
+        for _ in 0..3 {
+            map.tiles[(0, 0)].temperature += 0.4;
+            map.publish_events();
+        }
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [MapEvent::TileChanged { x: 0, y: 0 }]
+        );
+    }
+}
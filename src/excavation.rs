@@ -0,0 +1,285 @@
+use crate::{
+    air::AirData,
+    objects::{
+        building::{working_fraction, Building, BuildingType, WorkSpot, WorkSpotOccupation},
+        ObjectId,
+    },
+    tiles::TileType,
+    Facing, Map,
+};
+use glam::{uvec2, vec2, Vec2};
+use std::fmt::Display;
+
+/// The fumes per second a new mining job releases when all its workspots are manned
+pub const DEFAULT_MINING_FUMES: f32 = 0.01;
+/// Where the miners stand relative to the wall when the job faces North, on the open tile next to it
+const MINING_WORKSPOTS: [Vec2; 2] = [vec2(0.25, -0.3), vec2(0.75, -0.3)];
+
+impl Map {
+    /// Start digging out the wall at the given tile.
+    ///
+    /// This places a [BuildingType::MiningJob] with its workspots on an open neighbouring tile,
+    /// which characters with the [WorkGoal::Mine](crate::objects::characters::WorkGoal::Mine) goal come to man.
+    /// The job makes `rate` progress per second when all of its workspots are manned and
+    /// turns the wall into ground once the progress reaches 1. The job is removed then.
+    /// The rate must be positive, or the wall would never be dug out.
+    ///
+    /// While being worked, the job releases [DEFAULT_MINING_FUMES] into the open neighbouring tiles.
+    /// Change the `fumes_per_sec` of the job to change that, for example to 0 for rock that doesn't give off fumes.
+    pub fn excavate(
+        &mut self,
+        x: usize,
+        y: usize,
+        rate: f32,
+    ) -> Result<ObjectId<Building>, ExcavationError> {
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(ExcavationError::InvalidRate);
+        }
+        if x >= self.width() || y >= self.height() {
+            return Err(ExcavationError::OutOfBounds { x, y });
+        }
+        if !self.tiles[(x, y)].tile_type.is_wall() {
+            return Err(ExcavationError::NotAWall { x, y });
+        }
+
+        let objects = self.objects.get_mut().unwrap();
+        let already_excavating = objects.get_objects::<Building>().any(|building| {
+            matches!(building.building_type, BuildingType::MiningJob { .. })
+                && building.location == uvec2(x as u32, y as u32)
+        });
+        if already_excavating {
+            return Err(ExcavationError::AlreadyExcavating { x, y });
+        }
+
+        // The miners stand on the first open tile next to the wall
+        let facing = Facing::ALL
+            .into_iter()
+            .find(|facing| {
+                facing
+                    .move_coords_in_direction(x, y, self.tiles.width(), self.tiles.height())
+                    .is_some_and(|neighbour| !self.tiles[neighbour].tile_type.is_wall())
+            })
+            .ok_or(ExcavationError::NoAccess { x, y })?;
+
+        Ok(objects.push_object(Building {
+            location: uvec2(x as u32, y as u32),
+            facing,
+            building_type: BuildingType::MiningJob {
+                workspots: MINING_WORKSPOTS.map(|location| WorkSpot {
+                    location,
+                    occupation: WorkSpotOccupation::Open,
+                }),
+                progress: 0.0,
+                rate,
+                fumes_per_sec: DEFAULT_MINING_FUMES,
+            },
+        }))
+    }
+
+    /// Progress the mining jobs and turn the walls of the finished ones into ground
+    pub(crate) fn apply_excavation(&mut self, delta_time: f32) {
+        let objects = self.objects.get_mut().unwrap();
+        let mut finished_jobs = Vec::new();
+
+        for mut building in objects.get_objects_mut::<Building>() {
+            let (x, y) = (building.location.x as usize, building.location.y as usize);
+            let building_id = building.id();
+            let BuildingType::MiningJob {
+                workspots,
+                progress,
+                rate,
+                fumes_per_sec,
+            } = &mut building.building_type
+            else {
+                continue;
+            };
+
+            let work_time = working_fraction(workspots) * delta_time;
+            if work_time == 0.0 {
+                continue;
+            }
+
+            *progress += *rate * work_time;
+
+            let open_tiles = Facing::side_neighbours(x, y, self.tiles.width(), self.tiles.height())
+                .filter(|neighbour| self.tiles[*neighbour].tile_type.get_air().is_some())
+                .collect::<Vec<_>>();
+            for open_tile in open_tiles.iter() {
+                let air = self.tiles[*open_tile].tile_type.get_air_mut().unwrap();
+                air.fumes += *fumes_per_sec * work_time / open_tiles.len() as f32;
+                self.render_dirty[*open_tile] = true;
+            }
+
+            if *progress >= 1.0 {
+                finished_jobs.push((building_id, (x, y)));
+            }
+        }
+
+        for (building_id, (x, y)) in finished_jobs {
+            // Removing the job makes the miners stop
+            self.objects.get_mut().unwrap().remove_object(building_id);
+
+            let air = self.opened_up_air(x, y);
+            self.tiles[(x, y)].tile_type = TileType::Ground {
+                air,
+                liquids: Default::default(),
+            };
+            self.render_dirty[(x, y)] = true;
+        }
+    }
+
+    /// The air a wall that's being opened up starts with: the average of the air around it,
+    /// so it doesn't cause a rush of air
+    pub(crate) fn opened_up_air(&self, x: usize, y: usize) -> AirData {
+        let neighbour_airs = Facing::side_neighbours(x, y, self.width(), self.height())
+            .filter_map(|neighbour| self.tiles[neighbour].tile_type.get_air())
+            .collect::<Vec<_>>();
+        if neighbour_airs.is_empty() {
+            return AirData::new_default();
+        }
+
+        let count = neighbour_airs.len() as f32;
+        AirData {
+            nitrogen: neighbour_airs.iter().map(|air| air.nitrogen).sum::<f32>() / count,
+            oxygen: neighbour_airs.iter().map(|air| air.oxygen).sum::<f32>() / count,
+            fumes: neighbour_airs.iter().map(|air| air.fumes).sum::<f32>() / count,
+            steam: neighbour_airs.iter().map(|air| air.steam).sum::<f32>() / count,
+            carbon_dioxide: neighbour_airs
+                .iter()
+                .map(|air| air.carbon_dioxide)
+                .sum::<f32>()
+                / count,
+            temperature: neighbour_airs
+                .iter()
+                .map(|air| air.temperature)
+                .sum::<f32>()
+                / count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExcavationError {
+    /// The tile is outside of the map
+    OutOfBounds {
+        x: usize,
+        y: usize,
+    },
+    NotAWall {
+        x: usize,
+        y: usize,
+    },
+    /// None of the tiles next to the wall is open, so there's nowhere for the miners to stand
+    NoAccess {
+        x: usize,
+        y: usize,
+    },
+    AlreadyExcavating {
+        x: usize,
+        y: usize,
+    },
+    /// The rate is 0 or less or not a finite number
+    InvalidRate,
+}
+
+impl Display for ExcavationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExcavationError::OutOfBounds { x, y } => {
+                write!(f, "The tile at {x},{y} is outside of the map")
+            }
+            ExcavationError::NotAWall { x, y } => write!(f, "The tile at {x},{y} is not a wall"),
+            ExcavationError::NoAccess { x, y } => {
+                write!(f, "The wall at {x},{y} has no open tile next to it")
+            }
+            ExcavationError::AlreadyExcavating { x, y } => {
+                write!(f, "The wall at {x},{y} is already being excavated")
+            }
+            ExcavationError::InvalidRate => {
+                write!(f, "The excavation rate must be a finite number above 0")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExcavationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::characters::{Character, WorkGoal};
+
+    #[test]
+    fn miners_dig_out_wall() {
+        let mut map = Map::new_default(6, 3);
+        for y in 0..3 {
+            map.tiles[(4, y)].tile_type = TileType::Wall;
+            map.tiles[(5, y)].tile_type = TileType::Wall;
+        }
+
+        let job = map.excavate(4, 1, 0.5).unwrap();
+        assert_eq!(map.objects().get_object(job).unwrap().facing, Facing::West);
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::Mine],
+        ));
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            vec![WorkGoal::Mine],
+        ));
+
+        let mut ticks = 0;
+        while map.tiles[(4, 1)].tile_type.is_wall() {
+            map.step_n(0.1, 1);
+            ticks += 1;
+            assert!(ticks < 200, "the wall was never dug out");
+        }
+
+        // It takes at least 2 seconds of work with both miners
+        assert!(ticks >= 20);
+        assert!(map.objects().get_object(job).is_none());
+        assert!(map.tiles[(4, 1)].tile_type.get_air().unwrap().fumes > 0.0);
+        assert!(map.tiles[(3, 1)].tile_type.get_air().unwrap().fumes > 0.0);
+        assert!(map.tiles[(4, 0)].tile_type.is_wall());
+    }
+
+    #[test]
+    fn excavation_errors() {
+        let mut map = Map::new_default(3, 3);
+        for (x, y) in map.all_tile_coords() {
+            map.tiles[(x, y)].tile_type = TileType::Wall;
+        }
+
+        assert_eq!(
+            map.excavate(1, 1, 1.0),
+            Err(ExcavationError::NoAccess { x: 1, y: 1 })
+        );
+
+        map.tiles[(1, 2)].tile_type = TileType::new_default();
+        assert_eq!(
+            map.excavate(1, 2, 1.0),
+            Err(ExcavationError::NotAWall { x: 1, y: 2 })
+        );
+        assert!(map.excavate(1, 1, 1.0).is_ok());
+        assert_eq!(
+            map.excavate(1, 1, 1.0),
+            Err(ExcavationError::AlreadyExcavating { x: 1, y: 1 })
+        );
+        assert_eq!(
+            map.excavate(3, 1, 1.0),
+            Err(ExcavationError::OutOfBounds { x: 3, y: 1 })
+        );
+    }
+
+    #[test]
+    fn invalid_rate() {
+        let mut map = Map::new_default(3, 3);
+        map.tiles[(1, 1)].tile_type = TileType::Wall;
+        for rate in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(map.excavate(1, 1, rate), Err(ExcavationError::InvalidRate));
+        }
+        assert_eq!(map.objects().get_objects::<Building>().count(), 0);
+    }
+}
@@ -0,0 +1,198 @@
+//! Explosions, for mining charges and combat.
+//!
+//! An explosion reaches `power` tiles far. Its strength drops from 1 at the center to 0 at that distance.
+//! The blast pushes a burst of gas into the air, which the air simulation spreads out again over the next ticks.
+//! Where the blast is at least half strength it breaks walls open and destroys buildings.
+//! Characters lose health the closer they are to the center.
+
+use crate::{
+    objects::{building::Building, characters::Character, ObjectId},
+    tiles::TileType,
+    Map,
+};
+use glam::{vec2, Vec2};
+
+/// The gas the blast pushes into a tile at full strength, per unit of power
+pub const BLAST_GAS_PER_POWER: f32 = 1.0;
+/// The fractions of nitrogen, oxygen and fumes in the gas of a blast
+const BLAST_GAS_MIX: [f32; 3] = [0.5, 0.2, 0.3];
+/// The health a character at the center of an explosion loses per unit of power
+pub const BLAST_DAMAGE_PER_POWER: f32 = 0.25;
+/// The strength of the blast from which walls break and buildings are destroyed
+pub const BLAST_DESTRUCTION_STRENGTH: f32 = 0.5;
+
+/// What an explosion did, see [Map::detonate]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Explosion {
+    /// The walls that were broken open into ground
+    pub broken_walls: Vec<(usize, usize)>,
+    /// The buildings that were destroyed and removed from the map
+    pub destroyed_buildings: Vec<ObjectId<Building>>,
+    /// The characters that were hurt with the health they lost.
+    /// Characters without health left die in the next frame tick.
+    pub hurt_characters: Vec<(ObjectId<Character>, f32)>,
+}
+
+impl Map {
+    /// Let an explosion go off at the center of the tile, see the [module docs](crate::explosions).
+    ///
+    /// A power of 0 or less doesn't do anything.
+    ///
+    /// # Panics
+    ///
+    /// When the tile is outside of the map
+    pub fn detonate(&mut self, x: usize, y: usize, power: f32) -> Explosion {
+        assert!(
+            x < self.width() && y < self.height(),
+            "The explosion at ({x}, {y}) is outside of the map"
+        );
+
+        let mut explosion = Explosion::default();
+        if power.is_nan() || power <= 0.0 {
+            return explosion;
+        }
+
+        let center = vec2(x as f32 + 0.5, y as f32 + 0.5);
+        let strength = |position: Vec2| (1.0 - position.distance(center) / power).max(0.0);
+
+        let reach = power.ceil() as usize;
+        let (width, height) = (self.width(), self.height());
+        let blast_tiles = (y.saturating_sub(reach)..(y + reach + 1).min(height))
+            .flat_map(|y| {
+                (x.saturating_sub(reach)..(x + reach + 1).min(width)).map(move |x| (x, y))
+            })
+            .filter_map(|(x, y)| {
+                let strength = strength(vec2(x as f32 + 0.5, y as f32 + 0.5));
+                (strength > 0.0).then_some((x, y, strength))
+            })
+            .collect::<Vec<_>>();
+
+        for &(x, y, strength) in blast_tiles.iter() {
+            if strength >= BLAST_DESTRUCTION_STRENGTH && self.tiles[(x, y)].tile_type.is_wall() {
+                let air = self.opened_up_air(x, y);
+                self.tiles[(x, y)].tile_type = TileType::Ground {
+                    air,
+                    liquids: Default::default(),
+                };
+                explosion.broken_walls.push((x, y));
+            }
+        }
+
+        for &(x, y, strength) in blast_tiles.iter() {
+            let Some(air) = self.tiles[(x, y)].tile_type.get_air_mut() else {
+                continue;
+            };
+
+            let gas = power * BLAST_GAS_PER_POWER * strength;
+            let [nitrogen, oxygen, fumes] = BLAST_GAS_MIX;
+            air.nitrogen += gas * nitrogen;
+            air.oxygen += gas * oxygen;
+            air.fumes += gas * fumes;
+            self.render_dirty[(x, y)] = true;
+        }
+
+        let objects = self.objects.get_mut().unwrap();
+
+        explosion.destroyed_buildings = objects
+            .get_objects::<Building>()
+            .filter(|building| {
+                strength(building.location.as_vec2() + 0.5) >= BLAST_DESTRUCTION_STRENGTH
+            })
+            .map(|building| building.id())
+            .collect();
+        for building in explosion.destroyed_buildings.iter() {
+            objects.remove_object(*building);
+        }
+
+        for mut character in objects.get_objects_mut::<Character>() {
+            let damage = (power * BLAST_DAMAGE_PER_POWER * strength(character.location))
+                .min(character.health);
+            if damage > 0.0 {
+                character.health -= damage;
+                explosion.hurt_characters.push((character.id(), damage));
+            }
+        }
+
+        explosion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        air::AirData,
+        objects::{
+            building::{temperature_control_workspots, BuildingType},
+            characters::FrameEvent,
+        },
+        Facing,
+    };
+    use glam::uvec2;
+
+    #[test]
+    fn explosion_breaks_walls_hurts_and_destroys() {
+        let mut map = Map::new_default(9, 1);
+        for x in [1, 7, 8] {
+            map.tiles[(x, 0)].tile_type = TileType::Wall;
+        }
+        let (near, far) = {
+            let objects = map.objects.get_mut().unwrap();
+            let near = objects.push_object(Character::new(vec2(3.5, 0.5), 1.0, Vec::new()));
+            let far = objects.push_object(Character::new(vec2(6.5, 0.5), 1.0, Vec::new()));
+            (near, far)
+        };
+        let building = map.objects.get_mut().unwrap().push_object(Building {
+            location: uvec2(2, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Heater {
+                target: 20.0,
+                power: 0.0,
+                workspots: temperature_control_workspots(),
+            },
+        });
+        let pressure = |map: &Map, x| {
+            map.tiles[(x, 0)]
+                .tile_type
+                .get_air()
+                .map(AirData::total)
+                .unwrap()
+        };
+
+        let explosion = map.detonate(3, 0, 4.0);
+
+        // The walls at 1 are in the inner half of the blast, the walls at 7 and 8 aren't
+        assert_eq!(explosion.broken_walls, vec![(1, 0)]);
+        assert!(map.tiles[(7, 0)].tile_type.is_wall());
+        assert_eq!(explosion.destroyed_buildings, vec![building]);
+        assert!(map.objects().get_object(building).is_none());
+        assert_eq!(explosion.hurt_characters, vec![(near, 1.0), (far, 0.25)]);
+
+        assert!(pressure(&map, 3) > pressure(&map, 5));
+        assert!(pressure(&map, 5) > pressure(&map, 0));
+        assert!(map.tiles[(3, 0)].tile_type.get_air().unwrap().fumes > 0.0);
+
+        // The pressure spike spreads out again
+        let before = pressure(&map, 3);
+        map.perform_simulation_tick(0.1);
+        assert!(pressure(&map, 3) < before);
+
+        let events = map.perform_frame_tick(0.1);
+        assert!(events.contains(&FrameEvent::Died {
+            character: near,
+            tile: uvec2(3, 0),
+        }));
+        assert!(map.objects().get_object(far).is_some());
+    }
+
+    #[test]
+    fn no_power_no_explosion() {
+        let mut map = Map::new_default(3, 3);
+        assert_eq!(map.detonate(1, 1, 0.0), Explosion::default());
+        assert_eq!(map.detonate(1, 1, f32::NAN), Explosion::default());
+        assert_eq!(
+            map.tiles[(1, 1)].tile_type.get_air(),
+            Some(&AirData::new_default())
+        );
+    }
+}
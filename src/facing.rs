@@ -1,4 +1,5 @@
 use glam::{vec2, Vec2};
+use std::{fmt::Display, str::FromStr};
 
 /// A cardinal direction something can be facing to.
 ///
@@ -16,23 +17,59 @@ pub enum Facing {
 }
 
 impl Facing {
-    pub(crate) fn move_coords_in_direction<const WIDTH: usize, const HEIGHT: usize>(
+    /// All facings, clockwise from North
+    pub const ALL: [Facing; 4] = [Facing::North, Facing::East, Facing::South, Facing::West];
+
+    /// The name of the facing. This is stable, so it can be used in save files.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Facing::North => "North",
+            Facing::East => "East",
+            Facing::South => "South",
+            Facing::West => "West",
+        }
+    }
+
+    pub(crate) fn move_coords_in_direction(
         &self,
         x: usize,
         y: usize,
+        width: usize,
+        height: usize,
     ) -> Option<(usize, usize)> {
         match self {
-            Facing::North => (y > 0).then_some((x, y - 1)),
-            Facing::East => (x < WIDTH - 1).then_some((x + 1, y)),
-            Facing::South => (y < HEIGHT - 1).then_some((x, y + 1)),
-            Facing::West => (x > 0).then_some((x - 1, y)),
+            Facing::North => (y > 0).then(|| (x, y - 1)),
+            Facing::East => (x < width - 1).then(|| (x + 1, y)),
+            Facing::South => (y < height - 1).then(|| (x, y + 1)),
+            Facing::West => (x > 0).then(|| (x - 1, y)),
+        }
+    }
+
+    /// The tiles directly next to the given tile, so without the diagonal ones
+    pub(crate) fn side_neighbours(
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> impl Iterator<Item = (usize, usize)> {
+        Self::ALL
+            .into_iter()
+            .filter_map(move |facing| facing.move_coords_in_direction(x, y, width, height))
+    }
+
+    /// The x and y offset of one step in the direction of the facing
+    pub(crate) fn offset(&self) -> (isize, isize) {
+        match self {
+            Facing::North => (0, -1),
+            Facing::East => (1, 0),
+            Facing::South => (0, 1),
+            Facing::West => (-1, 0),
         }
     }
 
     /// Rotates a facing. The default is North.
     ///
     /// So East rotate East = South.
-
     pub(crate) fn rotate(self, applied: Facing) -> Self {
         use num_enum::UnsafeFromPrimitive;
         let new_discriminant = (self as u8 + applied as u8) % 4;
@@ -41,7 +78,6 @@ impl Facing {
 
     /// Rotate the given coords according to the facing.
     /// They will be rotated relative to 0,0
-
     pub(crate) fn rotate_isize_coords(&self, x: isize, y: isize) -> (isize, isize) {
         match self {
             Facing::North => (x, y),
@@ -53,7 +89,6 @@ impl Facing {
 
     /// Rotate the given coords according to the facing.
     /// They will be rotated relative to 0.5,0.5 (which is the middle of tile 0,0)
-
     pub(crate) fn rotate_f32_coords(&self, mut coords: Vec2) -> Vec2 {
         coords -= vec2(0.5, 0.5);
 
@@ -68,6 +103,195 @@ impl Facing {
     }
 }
 
+impl Display for Facing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One of the eight directions to the tiles around a tile, with the same coord system as [Facing].
+///
+/// Things that act on a neighbouring tile, like [air pushers](crate::air::AirPusher), use this,
+/// so they can also act on the tiles diagonally next to them.
+#[derive(Debug, Clone, Copy, num_enum::UnsafeFromPrimitive, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Direction8 {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction8 {
+    /// All directions, clockwise from North
+    pub const ALL: [Direction8; 8] = [
+        Direction8::North,
+        Direction8::NorthEast,
+        Direction8::East,
+        Direction8::SouthEast,
+        Direction8::South,
+        Direction8::SouthWest,
+        Direction8::West,
+        Direction8::NorthWest,
+    ];
+
+    /// The name of the direction. This is stable, so it can be used in save files.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction8::North => "North",
+            Direction8::NorthEast => "NorthEast",
+            Direction8::East => "East",
+            Direction8::SouthEast => "SouthEast",
+            Direction8::South => "South",
+            Direction8::SouthWest => "SouthWest",
+            Direction8::West => "West",
+            Direction8::NorthWest => "NorthWest",
+        }
+    }
+
+    /// Returns true for NorthEast, SouthEast, SouthWest and NorthWest
+    pub fn is_diagonal(&self) -> bool {
+        *self as u8 % 2 == 1
+    }
+
+    /// The facing of the direction, or for a diagonal direction the facing it's 45 degrees clockwise of.
+    ///
+    /// So NorthEast gives North.
+    pub fn facing(&self) -> Facing {
+        use num_enum::UnsafeFromPrimitive;
+        unsafe { Facing::unchecked_transmute_from(*self as u8 / 2) }
+    }
+
+    pub(crate) fn move_coords_in_direction(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        let (offset_x, offset_y) = self.offset();
+        let x = x.checked_add_signed(offset_x).filter(|x| *x < width)?;
+        let y = y.checked_add_signed(offset_y).filter(|y| *y < height)?;
+        Some((x, y))
+    }
+
+    /// The x and y offset of one step in the direction
+    pub(crate) fn offset(&self) -> (isize, isize) {
+        let facing = self.facing();
+        let (x, y) = facing.offset();
+        if self.is_diagonal() {
+            // The diagonal is a step in the facing plus a step in the facing after it
+            let (next_x, next_y) = facing.rotate(Facing::East).offset();
+            (x + next_x, y + next_y)
+        } else {
+            (x, y)
+        }
+    }
+
+    /// Rotates a direction by a facing, like [Facing::rotate] does.
+    ///
+    /// So NorthEast rotate East = SouthEast.
+    pub(crate) fn rotate(self, applied: Facing) -> Self {
+        self.rotate_eighths(applied as u8 * 2)
+    }
+
+    /// Rotates a direction clockwise by the given amount of eighths of a full turn
+    pub(crate) fn rotate_eighths(self, eighths: u8) -> Self {
+        use num_enum::UnsafeFromPrimitive;
+        let new_discriminant = (self as u8 + eighths) % 8;
+        unsafe { Self::unchecked_transmute_from(new_discriminant) }
+    }
+}
+
+impl From<Facing> for Direction8 {
+    fn from(facing: Facing) -> Self {
+        Direction8::North.rotate(facing)
+    }
+}
+
+impl Display for Direction8 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Direction8 {
+    type Err = ParseFacingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Direction8::ALL
+            .into_iter()
+            .find(|direction| direction.as_str() == s)
+            .ok_or_else(|| ParseFacingError {
+                input: s.to_string(),
+            })
+    }
+}
+
+impl FromStr for Facing {
+    type Err = ParseFacingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "North" => Ok(Facing::North),
+            "East" => Ok(Facing::East),
+            "South" => Ok(Facing::South),
+            "West" => Ok(Facing::West),
+            _ => Err(ParseFacingError {
+                input: s.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Facing {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Facing {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Direction8 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Direction8 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A string that isn't the name of a [Facing] or [Direction8]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFacingError {
+    pub input: String,
+}
+
+impl Display for ParseFacingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown facing '{}'", self.input)
+    }
+}
+
+impl std::error::Error for ParseFacingError {}
+
 #[cfg(test)]
 mod tests {
     use approx::assert_relative_eq;
@@ -97,24 +321,100 @@ mod tests {
         assert_eq!(Facing::West.rotate(Facing::West), Facing::South);
     }
 
+    #[test]
+    fn facing_string_round_trip() {
+        for facing in [Facing::North, Facing::East, Facing::South, Facing::West] {
+            assert_eq!(facing.as_str().parse::<Facing>(), Ok(facing));
+            assert_eq!(facing.to_string(), facing.as_str());
+        }
+
+        assert_eq!(
+            "north".parse::<Facing>(),
+            Err(ParseFacingError {
+                input: "north".into()
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn facing_serde_round_trip() {
+        for facing in [Facing::North, Facing::East, Facing::South, Facing::West] {
+            let json = serde_json::to_string(&facing).unwrap();
+            assert_eq!(json, format!("\"{}\"", facing.as_str()));
+            assert_eq!(serde_json::from_str::<Facing>(&json).unwrap(), facing);
+        }
+
+        assert!(serde_json::from_str::<Facing>("\"Up\"").is_err());
+        assert!(serde_json::from_str::<Facing>("0").is_err());
+    }
+
     #[test]
     #[rustfmt::skip]
     fn facing_move_coords_in_direction() {
-        assert_eq!(Facing::North.move_coords_in_direction::<5, 10>(2, 0), None);
-        assert_eq!(Facing::North.move_coords_in_direction::<5, 10>(0, 1), Some((0, 0)));
-        assert_eq!(Facing::North.move_coords_in_direction::<5, 10>(4, 9), Some((4, 8)));
+        assert_eq!(Facing::North.move_coords_in_direction(2, 0, 5, 10), None);
+        assert_eq!(Facing::North.move_coords_in_direction(0, 1, 5, 10), Some((0, 0)));
+        assert_eq!(Facing::North.move_coords_in_direction(4, 9, 5, 10), Some((4, 8)));
+
+        assert_eq!(Facing::East.move_coords_in_direction(4, 2, 5, 10), None);
+        assert_eq!(Facing::East.move_coords_in_direction(3, 1, 5, 10), Some((4, 1)));
+        assert_eq!(Facing::East.move_coords_in_direction(0, 9, 5, 10), Some((1, 9)));
+
+        assert_eq!(Facing::South.move_coords_in_direction(4, 9, 5, 10), None);
+        assert_eq!(Facing::South.move_coords_in_direction(0, 8, 5, 10), Some((0, 9)));
+        assert_eq!(Facing::South.move_coords_in_direction(2, 0, 5, 10), Some((2, 1)));
+
+        assert_eq!(Facing::West.move_coords_in_direction(0, 6, 5, 10), None);
+        assert_eq!(Facing::West.move_coords_in_direction(1, 1, 5, 10), Some((0, 1)));
+        assert_eq!(Facing::West.move_coords_in_direction(4, 9, 5, 10), Some((3, 9)));
+    }
+
+    #[test]
+    fn direction8_rotation() {
+        assert_eq!(
+            Direction8::NorthEast.rotate(Facing::North),
+            Direction8::NorthEast
+        );
+        assert_eq!(
+            Direction8::NorthEast.rotate(Facing::East),
+            Direction8::SouthEast
+        );
+        assert_eq!(
+            Direction8::NorthWest.rotate(Facing::South),
+            Direction8::SouthEast
+        );
+        assert_eq!(Direction8::West.rotate(Facing::West), Direction8::South);
+        assert_eq!(Direction8::NorthWest.rotate_eighths(1), Direction8::North);
 
-        assert_eq!(Facing::East.move_coords_in_direction::<5, 10>(4, 2), None);
-        assert_eq!(Facing::East.move_coords_in_direction::<5, 10>(3, 1), Some((4, 1)));
-        assert_eq!(Facing::East.move_coords_in_direction::<5, 10>(0, 9), Some((1, 9)));
+        for facing in [Facing::North, Facing::East, Facing::South, Facing::West] {
+            let direction = Direction8::from(facing);
+            assert!(!direction.is_diagonal());
+            assert_eq!(direction.facing(), facing);
+            assert_eq!(direction.offset(), facing.offset());
+            assert_eq!(direction.as_str(), facing.as_str());
+        }
+        assert!(Direction8::SouthWest.is_diagonal());
+        assert_eq!(Direction8::SouthWest.facing(), Facing::South);
+    }
 
-        assert_eq!(Facing::South.move_coords_in_direction::<5, 10>(4, 9), None);
-        assert_eq!(Facing::South.move_coords_in_direction::<5, 10>(0, 8), Some((0, 9)));
-        assert_eq!(Facing::South.move_coords_in_direction::<5, 10>(2, 0), Some((2, 1)));
+    #[test]
+    #[rustfmt::skip]
+    fn direction8_move_coords_in_direction() {
+        assert_eq!(Direction8::NorthEast.move_coords_in_direction(2, 0, 5, 10), None);
+        assert_eq!(Direction8::NorthEast.move_coords_in_direction(4, 1, 5, 10), None);
+        assert_eq!(Direction8::NorthEast.move_coords_in_direction(3, 1, 5, 10), Some((4, 0)));
+        assert_eq!(Direction8::SouthWest.move_coords_in_direction(0, 5, 5, 10), None);
+        assert_eq!(Direction8::SouthWest.move_coords_in_direction(1, 8, 5, 10), Some((0, 9)));
+        assert_eq!(Direction8::South.move_coords_in_direction(4, 9, 5, 10), None);
+    }
 
-        assert_eq!(Facing::West.move_coords_in_direction::<5, 10>(0, 6), None);
-        assert_eq!(Facing::West.move_coords_in_direction::<5, 10>(1, 1), Some((0, 1)));
-        assert_eq!(Facing::West.move_coords_in_direction::<5, 10>(4, 9), Some((3, 9)));
+    #[test]
+    fn direction8_string_round_trip() {
+        for direction in Direction8::ALL {
+            assert_eq!(direction.as_str().parse::<Direction8>(), Ok(direction));
+            assert_eq!(direction.to_string(), direction.as_str());
+        }
+        assert!("northeast".parse::<Direction8>().is_err());
     }
 
     #[test]
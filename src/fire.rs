@@ -0,0 +1,188 @@
+//! Tiles that catch fire.
+//!
+//! A tile ignites when it's at least [IGNITION_TEMPERATURE] and its air has at least [MIN_FIRE_FUMES]
+//! and [MIN_FIRE_OXYGEN_FRACTION] oxygen. The fire grows to its full intensity, turns the oxygen of the air into fumes
+//! and heats up the tile. It jumps over to the neighbours whose air can burn and keeps burning until it runs out of oxygen
+//! or is put out by water.
+
+use crate::{
+    air::AirData,
+    grid::Grid,
+    liquids::{LiquidData, Water},
+    Map,
+};
+
+/// A tile catches fire at this temperature in degrees Celsius, when its air can burn
+pub const IGNITION_TEMPERATURE: f32 = 250.0;
+/// The air needs at least this much fumes to catch fire
+pub const MIN_FIRE_FUMES: f32 = 0.02;
+/// The air needs at least this oxygen fraction to catch fire and to keep burning
+pub const MIN_FIRE_OXYGEN_FRACTION: f32 = 0.12;
+/// A tile with more water than this can't burn and any fire on it is put out
+pub const MAX_FIRE_WATER_LEVEL: f32 = 0.01;
+/// The oxygen a fully burning tile turns into fumes per second
+pub const FIRE_OXYGEN_USE: f32 = 0.02;
+/// The degrees Celsius per second a fully burning tile heats up
+pub const FIRE_HEAT: f32 = 50.0;
+/// The intensity per second a fire grows with while it has what it needs
+const FIRE_GROWTH_RATE: f32 = 0.5;
+/// The intensity per second a fire loses without enough oxygen
+const FIRE_DECAY_RATE: f32 = 0.5;
+
+impl Map {
+    /// Calculate the fire diff of a tick into the given grid. Its old values are overwritten.
+    pub(crate) fn calculate_fire_diff(&self, delta_time: f32, fire_diff: &mut Grid<f32>) {
+        let spread_fraction = (self.simulation_params.fire_spread_rate * delta_time).min(1.0);
+
+        fire_diff.accumulate_into(!self.deterministic, |rect, fire_diff_result| {
+            for (x, y) in rect.coords() {
+                let tile = &self.tiles[(x, y)];
+                let Some((air, liquids)) = tile.tile_type.get_ground() else {
+                    continue;
+                };
+
+                if tile.fire <= 0.0 {
+                    if tile.temperature >= IGNITION_TEMPERATURE && can_burn(air, liquids) {
+                        fire_diff_result[(x, y)] += FIRE_GROWTH_RATE * delta_time;
+                    }
+                    continue;
+                }
+
+                if liquids.get_level::<Water>() > MAX_FIRE_WATER_LEVEL {
+                    fire_diff_result[(x, y)] -= tile.fire;
+                    continue;
+                }
+
+                if air.oxygen_fraction() < MIN_FIRE_OXYGEN_FRACTION {
+                    fire_diff_result[(x, y)] -= FIRE_DECAY_RATE * delta_time;
+                    continue;
+                }
+
+                fire_diff_result[(x, y)] += FIRE_GROWTH_RATE * delta_time;
+
+                // Closed doors keep the fire out
+                for (nx, ny, neighbour) in self.neighbour_tiles(x, y) {
+                    if neighbour.fire > 0.0 || neighbour.tile_type.blocks_flow() {
+                        continue;
+                    }
+
+                    if neighbour
+                        .tile_type
+                        .get_ground()
+                        .is_some_and(|(air, liquids)| can_burn(air, liquids))
+                    {
+                        fire_diff_result[(nx, ny)] += tile.fire * spread_fraction;
+                    }
+                }
+            }
+        })
+    }
+
+    pub(crate) fn apply_fire_diff(&mut self, fire_diff: &Grid<f32>, delta_time: f32) {
+        for (x, y) in self.all_tile_coords() {
+            let tile = &mut self.tiles[(x, y)];
+            let old_fire = tile.fire;
+
+            let Some(air) = tile.tile_type.get_air_mut() else {
+                // Whatever burned here has been walled in
+                tile.fire = 0.0;
+                continue;
+            };
+
+            tile.fire = (tile.fire + fire_diff[(x, y)]).clamp(0.0, 1.0);
+            if tile.fire > 0.0 {
+                let burned = (FIRE_OXYGEN_USE * tile.fire * delta_time).min(air.oxygen);
+                air.oxygen -= burned;
+                air.fumes += burned;
+                tile.temperature += FIRE_HEAT * tile.fire * delta_time;
+            }
+
+            if tile.fire > 0.0 || tile.fire != old_fire {
+                self.render_dirty[(x, y)] = true;
+            }
+        }
+    }
+}
+
+/// Whether air on top of the liquids is able to catch fire
+fn can_burn(air: &AirData, liquids: &LiquidData) -> bool {
+    air.fumes >= MIN_FIRE_FUMES
+        && air.oxygen_fraction() >= MIN_FIRE_OXYGEN_FRACTION
+        && liquids.get_level::<Water>() <= MAX_FIRE_WATER_LEVEL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiles::TileType;
+
+    #[test]
+    fn hot_fumes_ignite() {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(1, 0)].temperature = 300.0;
+        map.tiles[(1, 0)].tile_type.get_air_mut().unwrap().fumes = 0.05;
+        // Hot enough, but nothing to burn
+        map.tiles[(2, 0)].temperature = 300.0;
+
+        map.step_n(0.1, 5);
+
+        assert!(map.tiles[(1, 0)].fire > 0.0);
+        assert_eq!(map.tiles[(2, 0)].fire, 0.0);
+        let air = map.tiles[(1, 0)].tile_type.get_air().unwrap();
+        assert!(air.oxygen < 0.21);
+        assert!(air.fumes > 0.05);
+    }
+
+    #[test]
+    fn fire_spreads_to_burnable_air() {
+        let mut map = Map::new_default(4, 1);
+        for x in 0..4 {
+            map.tiles[(x, 0)].tile_type.get_air_mut().unwrap().fumes = 0.05;
+        }
+        map.tiles[(0, 0)].fire = 1.0;
+        map.tiles[(3, 0)].tile_type = TileType::Door {
+            open: false,
+            air: AirData {
+                fumes: 0.05,
+                ..AirData::new_default()
+            },
+            liquids: Default::default(),
+        };
+
+        map.step_n(0.1, 50);
+
+        assert!(map.tiles[(1, 0)].fire > 0.0);
+        assert!(map.tiles[(2, 0)].fire > 0.0);
+        assert_eq!(map.tiles[(3, 0)].fire, 0.0);
+    }
+
+    #[test]
+    fn water_puts_out_fire() {
+        let mut map = Map::new_default(2, 1);
+        map.tiles[(0, 0)].fire = 1.0;
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 0.5 },
+        };
+
+        map.step_n(0.1, 1);
+
+        assert_eq!(map.tiles[(0, 0)].fire, 0.0);
+    }
+
+    #[test]
+    fn fire_dies_without_oxygen() {
+        let mut map = Map::new_default(1, 1);
+        map.tiles[(0, 0)].fire = 1.0;
+
+        let mut ticks = 0;
+        while map.tiles[(0, 0)].fire > 0.0 {
+            map.step_n(0.1, 1);
+            ticks += 1;
+            assert!(ticks < 1000, "the fire kept burning");
+        }
+
+        let air = map.tiles[(0, 0)].tile_type.get_air().unwrap();
+        assert!(air.oxygen_fraction() < MIN_FIRE_OXYGEN_FRACTION);
+    }
+}
@@ -0,0 +1,201 @@
+//! Flow fields that lead every tile of the map to the closest of a set of goal tiles.
+//!
+//! Searching a path for every character is wasteful when a lot of them go to the same place.
+//! A flow field is searched once from the goals and then tells every tile which tile to walk to next,
+//! so any number of characters can follow it.
+
+use crate::{grid::Grid, Map};
+use glam::{uvec2, vec2, UVec2, Vec2};
+use ordered_float::OrderedFloat;
+use std::{cmp::Reverse, collections::BinaryHeap, f32::consts::SQRT_2};
+
+/// The path finding pays the penalty of a tile for every node it steps on, which is 8 per tile.
+/// Walking onto a tile of the flow field costs the same, so both prefer the same paths.
+const PENALTY_STEPS_PER_TILE: f32 = 8.0;
+
+/// The way from every tile to the closest goal tile, made with [Map::flow_field_to]
+#[derive(Debug, Clone)]
+pub struct FlowField {
+    /// The cost of walking to the closest goal. Infinite when no goal can be reached.
+    costs: Grid<f32>,
+    /// The tile to walk to next to get closer to a goal
+    next_tiles: Grid<Option<UVec2>>,
+    pub(crate) avoid_lava: bool,
+    pub(crate) avoid_drowning: bool,
+}
+
+impl FlowField {
+    /// The cost of walking from the tile to the closest goal, or None when no goal can be reached from it
+    pub fn cost(&self, tile: UVec2) -> Option<f32> {
+        self.costs
+            .get(tile.x as usize, tile.y as usize)
+            .copied()
+            .filter(|cost| cost.is_finite())
+    }
+
+    /// The tile to walk to next from the given tile.
+    /// None on the goal tiles and on the tiles that can't reach a goal.
+    pub fn next_tile(&self, tile: UVec2) -> Option<UVec2> {
+        self.next_tiles
+            .get(tile.x as usize, tile.y as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// The points to walk from the position to the centre of the closest goal tile,
+    /// or None when no goal can be reached from it.
+    ///
+    /// The points go via the centres of the tiles, so the straight lines between them never cut through a wall.
+    pub fn path_from(&self, from: Vec2) -> Option<Vec<Vec2>> {
+        if from.x < 0.0 || from.y < 0.0 {
+            return None;
+        }
+
+        let mut tile = from.as_uvec2();
+        self.cost(tile)?;
+
+        let mut points = vec![from];
+        // Characters can't walk a step of zero length
+        if from != tile_centre(tile) {
+            points.push(tile_centre(tile));
+        }
+        while let Some(next_tile) = self.next_tile(tile) {
+            points.push(tile_centre(next_tile));
+            tile = next_tile;
+        }
+
+        Some(points)
+    }
+
+    /// The amount of bytes the flow field takes up on the heap
+    pub fn memory_usage(&self) -> usize {
+        self.costs.memory_usage() + self.next_tiles.memory_usage()
+    }
+}
+
+fn tile_centre(tile: UVec2) -> Vec2 {
+    tile.as_vec2() + vec2(0.5, 0.5)
+}
+
+impl Map {
+    /// Create the flow field that leads every tile to the closest of the goal tiles.
+    ///
+    /// The walkable tiles and their costs are the same as for the path finding of the characters.
+    /// Goal tiles that can't be walked or are outside of the map are ignored.
+    pub fn flow_field_to(
+        &self,
+        goal_tiles: &[UVec2],
+        avoid_lava: bool,
+        avoid_drowning: bool,
+    ) -> FlowField {
+        let (width, height) = (self.width(), self.height());
+        let mut penalties = Grid::new(width, height, None);
+        for (x, y) in self.all_tile_coords() {
+            penalties[(x, y)] = self
+                .position_penalty(
+                    tile_centre(uvec2(x as u32, y as u32)),
+                    avoid_lava,
+                    avoid_drowning,
+                )
+                .map(|penalty| penalty.0);
+        }
+
+        let mut costs = Grid::new(width, height, f32::INFINITY);
+        let mut next_tiles = Grid::new(width, height, None);
+        // Ordered on the coordinates as well, so the same costs are always handled in the same order
+        let mut queue = BinaryHeap::new();
+
+        for goal in goal_tiles {
+            let (x, y) = (goal.x as usize, goal.y as usize);
+            if penalties.get(x, y).copied().flatten().is_none() || costs[(x, y)] == 0.0 {
+                continue;
+            }
+
+            costs[(x, y)] = 0.0;
+            queue.push(Reverse((OrderedFloat(0.0), x, y)));
+        }
+
+        while let Some(Reverse((OrderedFloat(cost), x, y))) = queue.pop() {
+            if cost > costs[(x, y)] {
+                // Was already reached in a cheaper way
+                continue;
+            }
+
+            // Only walkable tiles are queued
+            let penalty = penalties[(x, y)].unwrap() * PENALTY_STEPS_PER_TILE;
+
+            for (nx, ny) in self.neighbour_tile_coords(x, y) {
+                if penalties[(nx, ny)].is_none() {
+                    continue;
+                }
+
+                let distance = if nx != x && ny != y { SQRT_2 } else { 1.0 };
+                let neighbour_cost = cost + distance + penalty;
+                if neighbour_cost < costs[(nx, ny)] {
+                    costs[(nx, ny)] = neighbour_cost;
+                    next_tiles[(nx, ny)] = Some(uvec2(x as u32, y as u32));
+                    queue.push(Reverse((OrderedFloat(neighbour_cost), nx, ny)));
+                }
+            }
+        }
+
+        FlowField {
+            costs,
+            next_tiles,
+            avoid_lava,
+            avoid_drowning,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{liquids::LiquidData, tiles::TileType};
+
+    #[test]
+    fn leads_to_the_closest_goal() {
+        // ......
+        // .#####
+        // ......
+        let mut map = Map::new_default(6, 3);
+        for x in 1..6 {
+            map.tiles[(x, 1)].tile_type = TileType::Wall;
+        }
+
+        let field = map.flow_field_to(&[uvec2(5, 0), uvec2(5, 2), uvec2(3, 1)], true, true);
+
+        assert_eq!(field.cost(uvec2(5, 0)), Some(0.0));
+        // The wall can't be a goal
+        assert_eq!(field.cost(uvec2(3, 1)), None);
+        assert_eq!(field.next_tile(uvec2(3, 1)), None);
+        assert_eq!(field.next_tile(uvec2(4, 2)), Some(uvec2(5, 2)));
+        assert_eq!(field.next_tile(uvec2(1, 2)), Some(uvec2(2, 2)));
+        // Going around the wall to the top goal is just as far, so the left column goes to either
+        assert_eq!(field.next_tile(uvec2(0, 1)).map(|tile| tile.x), Some(1));
+
+        let path = field.path_from(vec2(4.2, 2.7)).unwrap();
+        assert_eq!(path, vec![vec2(4.2, 2.7), vec2(4.5, 2.5), vec2(5.5, 2.5)]);
+    }
+
+    #[test]
+    fn avoids_deep_water() {
+        let mut map = Map::new_default(3, 2);
+        for y in 0..2 {
+            *map.tiles[(1, y)].tile_type.get_liquids_mut().unwrap() =
+                LiquidData::Water { level: 3.0 };
+        }
+
+        let field = map.flow_field_to(&[uvec2(2, 0)], true, true);
+        assert_eq!(field.cost(uvec2(0, 0)), None);
+        assert!(field.path_from(vec2(0.5, 0.5)).is_none());
+
+        // Swimming is allowed, but costs a lot more than walking the same distance
+        let field = map.flow_field_to(&[uvec2(2, 0)], true, false);
+        assert!(field.cost(uvec2(0, 0)).unwrap() > 2.0);
+        assert_eq!(
+            field.path_from(vec2(0.5, 0.5)).unwrap().last(),
+            Some(&vec2(2.5, 0.5))
+        );
+    }
+}
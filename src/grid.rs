@@ -0,0 +1,445 @@
+use std::{
+    mem::size_of,
+    ops::{AddAssign, Index, IndexMut},
+};
+
+use rayon::prelude::*;
+
+use crate::{tiles::TileRect, TileCoordIter};
+
+/// The width and height of the square chunks the values of a [Grid] are stored in.
+/// Maps that are smaller than this in a direction get chunks of the next power of two of the map size.
+pub const CHUNK_SIZE: usize = 32;
+
+/// A value for every tile of a map, indexed with `(x, y)`.
+///
+/// The values are stored on the heap in chunks of [CHUNK_SIZE] by [CHUNK_SIZE] tiles,
+/// so tiles that are close together are also close together in memory and the chunks can be worked on in parallel.
+/// The chunks at the right and bottom edge can stick out of the map. The values out there can't be reached.
+#[derive(Clone, PartialEq)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    // The chunk sizes are powers of two, so the indexing only needs shifts and masks
+    chunk_width_shift: u32,
+    chunk_height_shift: u32,
+    chunks_y: usize,
+    values: Box<[T]>,
+}
+
+impl<T> Grid<T> {
+    /// Create a grid of the given size with the same value for every tile
+    pub fn new(width: usize, height: usize, value: T) -> Self
+    where
+        T: Clone,
+    {
+        let chunk_width = width.next_power_of_two().min(CHUNK_SIZE);
+        let chunk_height = height.next_power_of_two().min(CHUNK_SIZE);
+        let chunks_x = width.div_ceil(chunk_width);
+        let chunks_y = height.div_ceil(chunk_height);
+
+        Self {
+            width,
+            height,
+            chunk_width_shift: chunk_width.trailing_zeros(),
+            chunk_height_shift: chunk_height.trailing_zeros(),
+            chunks_y,
+            values: vec![value; chunks_x * chunks_y * chunk_width * chunk_height]
+                .into_boxed_slice(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline(always)]
+    fn chunk_width(&self) -> usize {
+        1 << self.chunk_width_shift
+    }
+
+    #[inline(always)]
+    fn chunk_height(&self) -> usize {
+        1 << self.chunk_height_shift
+    }
+
+    #[inline(always)]
+    fn chunk_len(&self) -> usize {
+        1 << (self.chunk_width_shift + self.chunk_height_shift)
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.width.div_ceil(self.chunk_width()) * self.chunks_y
+    }
+
+    #[inline(always)]
+    fn value_index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "({x}, {y}) is outside of the {}x{} grid",
+            self.width,
+            self.height
+        );
+
+        let chunk = (x >> self.chunk_width_shift) * self.chunks_y + (y >> self.chunk_height_shift);
+        (chunk << (self.chunk_width_shift + self.chunk_height_shift))
+            + ((x & (self.chunk_width() - 1)) << self.chunk_height_shift)
+            + (y & (self.chunk_height() - 1))
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        (x < self.width && y < self.height).then(|| &self[(x, y)])
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        (x < self.width && y < self.height).then(|| &mut self[(x, y)])
+    }
+
+    /// Set every tile to the given value
+    pub fn fill(&mut self, value: T)
+    where
+        T: Clone,
+    {
+        self.values.fill(value);
+    }
+
+    /// Iterate over the coords and values of all tiles, in the same order as [crate::Map::all_tile_coords]
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &T)> {
+        TileCoordIter::new(self.width, self.height).map(|(x, y)| (x, y, &self[(x, y)]))
+    }
+
+    /// The tiles of every chunk. The rects of the chunks at the edges are cut off at the edge of the map.
+    pub fn chunk_rects(&self) -> impl Iterator<Item = TileRect> {
+        let (width, height, chunks_y) = (self.width, self.height, self.chunks_y);
+        let (chunk_width, chunk_height) = (self.chunk_width(), self.chunk_height());
+
+        (0..width.div_ceil(chunk_width)).flat_map(move |chunk_x| {
+            (0..chunks_y).map(move |chunk_y| {
+                let (x, y) = (chunk_x * chunk_width, chunk_y * chunk_height);
+                TileRect::new(
+                    x,
+                    y,
+                    chunk_width.min(width - x),
+                    chunk_height.min(height - y),
+                )
+            })
+        })
+    }
+
+    /// Work on all chunks in parallel. The closure gets the rect of the chunk and can index the tiles in it.
+    pub fn par_for_each_chunk_mut(&mut self, f: impl Fn(TileRect, &mut GridChunkMut<'_, T>) + Sync)
+    where
+        T: Send,
+    {
+        let rects = self.chunk_rects().collect::<Vec<_>>();
+        let (chunk_len, chunk_height) = (self.chunk_len(), self.chunk_height());
+
+        self.values
+            .par_chunks_mut(chunk_len)
+            .zip(rects)
+            .for_each(|(values, rect)| {
+                f(
+                    rect,
+                    &mut GridChunkMut {
+                        rect,
+                        chunk_height,
+                        values,
+                    },
+                )
+            });
+    }
+
+    /// The amount of bytes the values take up on the heap
+    pub fn memory_usage(&self) -> usize {
+        self.values.len() * size_of::<T>()
+    }
+}
+
+impl<T: Default + Clone + AddAssign + Send> Grid<T> {
+    /// Calculate the values of the grid one chunk at a time, with all chunks in parallel if `parallel` is true.
+    /// The old values are reset to the default first, so a grid can be reused for the next calculation.
+    ///
+    /// Every chunk gets an [Accumulator] that covers the chunk and the tiles right around it,
+    /// so the calculation of a tile can also add to its neighbours, even when they're in another chunk.
+    /// What the chunks add to the tiles around them is summed up into the resulting grid afterwards,
+    /// always in the same order, so the result is the same with or without the parallelism.
+    pub(crate) fn accumulate_into(
+        &mut self,
+        parallel: bool,
+        calculate: impl Fn(TileRect, &mut Accumulator<T>) + Sync,
+    ) {
+        let rects = self.chunk_rects().collect::<Vec<_>>();
+        let (chunk_len, chunk_height) = (self.chunk_len(), self.chunk_height());
+        let accumulate = |(values, rect)| {
+            let mut accumulator = Accumulator::new(GridChunkMut {
+                rect,
+                chunk_height,
+                values,
+            });
+            calculate(rect, &mut accumulator);
+            (rect, accumulator.border)
+        };
+
+        self.fill(T::default());
+
+        // Small maps have only one chunk, which isn't worth sending to the thread pool
+        let borders = if !parallel || self.chunk_count() == 1 {
+            self.values
+                .chunks_mut(chunk_len)
+                .zip(rects)
+                .map(accumulate)
+                .collect::<Vec<_>>()
+        } else {
+            self.values
+                .par_chunks_mut(chunk_len)
+                .zip(rects)
+                .map(accumulate)
+                .collect::<Vec<_>>()
+        };
+
+        for (rect, border) in borders {
+            for ((x, y), value) in Accumulator::<T>::border_coords(rect).zip(border) {
+                if x < self.width && y < self.height {
+                    self[(x, y)] += value;
+                }
+            }
+        }
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    #[inline(always)]
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        &self.values[self.value_index(x, y)]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    #[inline(always)]
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Self::Output {
+        let index = self.value_index(x, y);
+        &mut self.values[index]
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Grid<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Show it like the nested arrays it replaces, one column at a time
+        f.debug_list()
+            .entries(
+                (0..self.width)
+                    .map(|x| (0..self.height).map(|y| &self[(x, y)]).collect::<Vec<_>>()),
+            )
+            .finish()
+    }
+}
+
+/// The tiles of one chunk of a [Grid], indexed with the map coords of the tiles
+pub struct GridChunkMut<'a, T> {
+    rect: TileRect,
+    /// The height of the chunk in memory, which can be more than the height of the rect at the edge of the map
+    chunk_height: usize,
+    values: &'a mut [T],
+}
+
+impl<'a, T> GridChunkMut<'a, T> {
+    #[inline(always)]
+    fn value_index(&self, x: usize, y: usize) -> usize {
+        self.chunk_index(x, y)
+            .unwrap_or_else(|| panic!("({x}, {y}) is outside of the chunk"))
+    }
+
+    /// The index of the tile in the values, or None if it's not in the chunk
+    #[inline(always)]
+    fn chunk_index(&self, x: usize, y: usize) -> Option<usize> {
+        let (local_x, local_y) = (x.wrapping_sub(self.rect.x), y.wrapping_sub(self.rect.y));
+        if local_x < self.rect.width && local_y < self.rect.height {
+            Some(local_x * self.chunk_height + local_y)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T> Index<(usize, usize)> for GridChunkMut<'a, T> {
+    type Output = T;
+
+    #[inline(always)]
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        &self.values[self.value_index(x, y)]
+    }
+}
+
+impl<'a, T> IndexMut<(usize, usize)> for GridChunkMut<'a, T> {
+    #[inline(always)]
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Self::Output {
+        let index = self.value_index(x, y);
+        &mut self.values[index]
+    }
+}
+
+/// Collects the values a chunk adds to its own tiles and the tiles right around it, see [Grid::accumulate_into].
+///
+/// Indexed with map coords.
+pub(crate) struct Accumulator<'a, T> {
+    chunk: GridChunkMut<'a, T>,
+    /// The values for the tiles right around the chunk.
+    /// First the columns left and right of the chunk including the corners, then the rows above and below it.
+    border: Vec<T>,
+}
+
+impl<'a, T: Default + Clone> Accumulator<'a, T> {
+    fn new(chunk: GridChunkMut<'a, T>) -> Self {
+        let TileRect { width, height, .. } = chunk.rect;
+
+        Self {
+            chunk,
+            border: vec![T::default(); 2 * (height + 2) + 2 * width],
+        }
+    }
+
+    /// The coords of the tiles right around the chunk, in the order of the border values.
+    /// Tiles that stick out of the map at the low sides have wrapped around coords.
+    fn border_coords(rect: TileRect) -> impl Iterator<Item = (usize, usize)> {
+        let TileRect {
+            x,
+            y,
+            width,
+            height,
+        } = rect;
+        let (left, top) = (x.wrapping_sub(1), y.wrapping_sub(1));
+        let column =
+            move |x: usize| (0..height + 2).map(move |offset| (x, top.wrapping_add(offset)));
+        let row = move |y: usize| (x..x + width).map(move |x| (x, y));
+
+        column(left)
+            .chain(column(x + width))
+            .chain(row(top))
+            .chain(row(y + height))
+    }
+
+    #[inline(always)]
+    fn border_index(&self, x: usize, y: usize) -> usize {
+        let TileRect {
+            x: rect_x,
+            y: rect_y,
+            width,
+            height,
+        } = self.chunk.rect;
+        // Shifted by one, so the tiles left of and above the chunk are at 0
+        let (shifted_x, shifted_y) = (
+            x.wrapping_add(1).wrapping_sub(rect_x),
+            y.wrapping_add(1).wrapping_sub(rect_y),
+        );
+        assert!(
+            shifted_x <= width + 1 && shifted_y <= height + 1,
+            "({x}, {y}) isn't next to the chunk"
+        );
+
+        if shifted_x == 0 {
+            shifted_y
+        } else if shifted_x == width + 1 {
+            height + 2 + shifted_y
+        } else if shifted_y == 0 {
+            2 * (height + 2) + shifted_x - 1
+        } else {
+            2 * (height + 2) + width + shifted_x - 1
+        }
+    }
+}
+
+impl<'a, T: Default + Clone> Index<(usize, usize)> for Accumulator<'a, T> {
+    type Output = T;
+
+    #[inline(always)]
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        match self.chunk.chunk_index(x, y) {
+            Some(index) => &self.chunk.values[index],
+            None => &self.border[self.border_index(x, y)],
+        }
+    }
+}
+
+impl<'a, T: Default + Clone> IndexMut<(usize, usize)> for Accumulator<'a, T> {
+    #[inline(always)]
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Self::Output {
+        match self.chunk.chunk_index(x, y) {
+            Some(index) => &mut self.chunk.values[index],
+            None => {
+                let index = self.border_index(x, y);
+                &mut self.border[index]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunked_indexing() {
+        let mut grid = Grid::new(70, 40, 0);
+        for (x, y) in TileCoordIter::new(70, 40) {
+            grid[(x, y)] = x * 1000 + y;
+        }
+
+        assert!(grid.iter().all(|(x, y, value)| *value == x * 1000 + y));
+        assert_eq!(grid.get(70, 0), None);
+        assert_eq!(
+            grid.chunk_rects().collect::<Vec<_>>(),
+            [
+                TileRect::new(0, 0, 32, 32),
+                TileRect::new(0, 32, 32, 8),
+                TileRect::new(32, 0, 32, 32),
+                TileRect::new(32, 32, 32, 8),
+                TileRect::new(64, 0, 6, 32),
+                TileRect::new(64, 32, 6, 8),
+            ]
+        );
+
+        // A map smaller than a chunk is a single chunk of its own size
+        assert_eq!(
+            Grid::new(20, 10, 0).chunk_rects().collect::<Vec<_>>(),
+            [TileRect::new(0, 0, 20, 10)]
+        );
+
+        grid.par_for_each_chunk_mut(|rect, chunk| {
+            for (x, y) in rect.coords() {
+                chunk[(x, y)] += 1;
+            }
+        });
+        assert_eq!(grid[(69, 39)], 69_040);
+    }
+
+    #[test]
+    fn accumulate_over_chunk_borders() {
+        // Start with junk to see that it's cleared
+        let mut grid = Grid::new(40, 40, 5);
+
+        // Every tile gives one to each of its neighbours, like the air and liquid calculations do
+        grid.accumulate_into(true, |rect, result| {
+            for (x, y) in rect.coords() {
+                for (nx, ny) in [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ] {
+                    if nx < 40 && ny < 40 {
+                        result[(nx, ny)] += 1;
+                        result[(x, y)] -= 1;
+                    }
+                }
+            }
+        });
+
+        assert!(grid.iter().all(|(_, _, value)| *value == 0));
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{
+    grid::Grid,
+    liquids::{Lava, LiquidData, Water},
+    Map,
+};
+
+/// The temperature lava heats its tile towards in degrees Celsius
+pub const LAVA_TEMPERATURE: f32 = 1200.0;
+/// The fraction of the difference to [LAVA_TEMPERATURE] a full tile of lava closes per second
+pub const LAVA_HEATING_RATE: f32 = 0.5;
+/// Water doesn't get hotter than this. Any extra heat goes into boiling it.
+pub const WATER_BOILING_TEMPERATURE: f32 = 100.0;
+/// How much more heat a full tile of water takes to warm up compared to a dry tile
+pub const WATER_HEAT_CAPACITY: f32 = 10.0;
+
+/// The most of a temperature difference that may be exchanged with a single neighbour per tick.
+/// A tile has up to 8 neighbours, so any more and the tiles could overshoot each other.
+const MAX_SPREAD_FRACTION: f32 = 0.1;
+
+impl Map {
+    /// Calculate the heat diff of a tick into the given grid. Its old values are overwritten.
+    pub(crate) fn calculate_heat_diff(&self, delta_time: f32, heat_diff: &mut Grid<f32>) {
+        let spread_fraction =
+            (self.simulation_params.heat_spread_rate * delta_time).min(MAX_SPREAD_FRACTION);
+
+        heat_diff.accumulate_into(!self.deterministic, |rect, heat_diff_result| {
+            for (x, y) in rect.coords() {
+                let tile = &self.tiles[(x, y)];
+                let Some(liquids) = tile.tile_type.get_liquids() else {
+                    continue;
+                };
+
+                let tile_heat_capacity = heat_capacity(liquids);
+
+                // Every pair of neighbours is visited twice, so only look at the neighbours we give heat to
+                for (nx, ny, neighbour) in self.neighbour_tiles(x, y) {
+                    let Some(neighbour_liquids) = neighbour.tile_type.get_liquids() else {
+                        continue;
+                    };
+
+                    if neighbour.temperature >= tile.temperature {
+                        continue;
+                    }
+
+                    // The heat that flows over is limited by the tile that changes temperature the most
+                    let neighbour_heat_capacity = heat_capacity(neighbour_liquids);
+                    let heat = (tile.temperature - neighbour.temperature) * spread_fraction
+                        / (1.0 / tile_heat_capacity + 1.0 / neighbour_heat_capacity);
+
+                    heat_diff_result[(x, y)] -= heat / tile_heat_capacity;
+                    heat_diff_result[(nx, ny)] += heat / neighbour_heat_capacity;
+                }
+
+                // Lava keeps its tile hot, which then radiates out to the neighbours
+                let lava_fraction = liquids.get_level::<Lava>() / LiquidData::MAX_LEVEL;
+                if lava_fraction > 0.0 {
+                    heat_diff_result[(x, y)] += (LAVA_TEMPERATURE - tile.temperature)
+                        * (LAVA_HEATING_RATE * lava_fraction * delta_time).min(1.0);
+                }
+            }
+        })
+    }
+
+    pub(crate) fn apply_heat_diff(&mut self, heat_diff: &Grid<f32>, delta_time: f32) {
+        for (x, y) in self.all_tile_coords() {
+            let tile = &mut self.tiles[(x, y)];
+            let Some(liquids) = tile.tile_type.get_liquids() else {
+                continue;
+            };
+
+            let old_temperature = tile.temperature;
+            tile.temperature += heat_diff[(x, y)];
+
+            if liquids.get_level::<Water>() > 0.0 {
+                tile.temperature = tile.temperature.min(WATER_BOILING_TEMPERATURE);
+            }
+
+            if tile.temperature != old_temperature {
+                self.render_dirty[(x, y)] = true;
+            }
+        }
+
+        for map_object in self.objects.read().unwrap().get_all_objects() {
+            for heat_source in map_object
+                .heat_sources()
+                .into_iter()
+                .filter(|heat_source| heat_source.enabled)
+            {
+                let tile = &mut self.tiles[(heat_source.x, heat_source.y)];
+                if tile.tile_type.is_wall() || tile.temperature >= heat_source.temperature {
+                    continue;
+                }
+
+                tile.temperature = (tile.temperature + heat_source.change_per_sec * delta_time)
+                    .min(heat_source.temperature);
+
+                self.render_dirty[(heat_source.x, heat_source.y)] = true;
+            }
+
+            for heat_sink in map_object
+                .heat_sinks()
+                .into_iter()
+                .filter(|heat_sink| heat_sink.enabled)
+            {
+                let tile = &mut self.tiles[(heat_sink.x, heat_sink.y)];
+                if tile.tile_type.is_wall() || tile.temperature <= heat_sink.temperature {
+                    continue;
+                }
+
+                tile.temperature = (tile.temperature - heat_sink.change_per_sec * delta_time)
+                    .max(heat_sink.temperature);
+
+                self.render_dirty[(heat_sink.x, heat_sink.y)] = true;
+            }
+        }
+    }
+}
+
+/// How much heat it takes to warm the tile up one degree, relative to a dry tile
+fn heat_capacity(liquids: &LiquidData) -> f32 {
+    1.0 + WATER_HEAT_CAPACITY * liquids.get_level::<Water>() / LiquidData::MAX_LEVEL
+}
+
+/// Heats up its tile until the tile reaches the temperature of the source
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeatSource<COORD> {
+    pub x: COORD,
+    pub y: COORD,
+    /// The temperature in degrees Celsius the tile is heated up to
+    pub temperature: f32,
+    /// How many degrees per second the tile is heated
+    pub change_per_sec: f32,
+    /// A disabled source doesn't do anything
+    pub enabled: bool,
+}
+
+impl HeatSource<isize> {
+    pub(crate) fn to_absolute(self, base_x: usize, base_y: usize) -> HeatSource<usize> {
+        HeatSource {
+            x: base_x.wrapping_add_signed(self.x),
+            y: base_y.wrapping_add_signed(self.y),
+            temperature: self.temperature,
+            change_per_sec: self.change_per_sec,
+            enabled: self.enabled,
+        }
+    }
+}
+
+/// Cools down its tile until the tile reaches the temperature of the sink
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeatSink<COORD> {
+    pub x: COORD,
+    pub y: COORD,
+    /// The temperature in degrees Celsius the tile is cooled down to
+    pub temperature: f32,
+    /// How many degrees per second the tile is cooled
+    pub change_per_sec: f32,
+    /// A disabled sink doesn't do anything
+    pub enabled: bool,
+}
+
+impl HeatSink<isize> {
+    pub(crate) fn to_absolute(self, base_x: usize, base_y: usize) -> HeatSink<usize> {
+        HeatSink {
+            x: base_x.wrapping_add_signed(self.x),
+            y: base_y.wrapping_add_signed(self.y),
+            temperature: self.temperature,
+            change_per_sec: self.change_per_sec,
+            enabled: self.enabled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::environment_object::EnvironmentObject,
+        tiles::{Tile, TileType},
+        SimulationParams,
+    };
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn heat_spreads_without_loss() {
+        let mut map = Map::new_default(5, 1);
+        map.set_simulation_params(SimulationParams::fast_settle());
+        map.tiles[(0, 0)].temperature = 120.0;
+
+        for _ in 0..1000 {
+            map.perform_simulation_tick(1.0);
+        }
+
+        for x in 0..5 {
+            assert_relative_eq!(map.tiles[(x, 0)].temperature, 40.0, epsilon = 0.01);
+        }
+    }
+
+    fn tick_heat(map: &mut Map) {
+        // Only look at the heat, so the liquids don't flow
+        let mut heat_diff = Grid::new(map.width(), map.height(), 0.0);
+        map.calculate_heat_diff(1.0, &mut heat_diff);
+        map.apply_heat_diff(&heat_diff, 1.0);
+    }
+
+    #[test]
+    fn lava_heats() {
+        let mut map = Map::new_default(2, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Lava {
+                level: LiquidData::MAX_LEVEL,
+            },
+        };
+
+        tick_heat(&mut map);
+        assert!(map.tiles[(0, 0)].temperature > Tile::DEFAULT_TEMPERATURE);
+
+        for _ in 0..100 {
+            tick_heat(&mut map);
+        }
+        assert!(map.tiles[(0, 0)].temperature > 1100.0);
+        assert!(map.tiles[(1, 0)].temperature > 500.0);
+    }
+
+    #[test]
+    fn water_absorbs_heat() {
+        let mut map = Map::new_default(2, 1);
+        map.tiles[(1, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water {
+                level: LiquidData::MAX_LEVEL,
+            },
+        };
+        map.set_simulation_params(SimulationParams::fast_settle());
+        map.tiles[(0, 0)].temperature = 2000.0;
+
+        // The water takes in all the heat, but warms up a lot less than the dry tile cools down
+        let mut heat_diff = Grid::new(2, 1, 0.0);
+        map.calculate_heat_diff(1.0, &mut heat_diff);
+        assert!(heat_diff[(1, 0)] > 0.0);
+        assert_relative_eq!(
+            heat_diff[(1, 0)] * (1.0 + WATER_HEAT_CAPACITY),
+            -heat_diff[(0, 0)],
+            epsilon = 0.001
+        );
+
+        for _ in 0..100 {
+            tick_heat(&mut map);
+        }
+        assert_eq!(map.tiles[(1, 0)].temperature, WATER_BOILING_TEMPERATURE);
+    }
+
+    #[test]
+    fn heat_sources_and_sinks() {
+        let mut map = Map::new_default(2, 1);
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(HeatSource {
+                x: 0,
+                y: 0,
+                temperature: 30.0,
+                change_per_sec: 4.0,
+                enabled: true,
+            });
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(HeatSink {
+                x: 1,
+                y: 0,
+                temperature: 15.0,
+                change_per_sec: 4.0,
+                enabled: true,
+            });
+
+        let no_heat_diff = Grid::new(2, 1, 0.0);
+        map.apply_heat_diff(&no_heat_diff, 1.0);
+        assert_eq!(map.tiles[(0, 0)].temperature, 24.0);
+        assert_eq!(map.tiles[(1, 0)].temperature, 16.0);
+
+        map.apply_heat_diff(&no_heat_diff, 1.0);
+        map.apply_heat_diff(&no_heat_diff, 1.0);
+        assert_eq!(map.tiles[(0, 0)].temperature, 30.0);
+        assert_eq!(map.tiles[(1, 0)].temperature, 15.0);
+    }
+}
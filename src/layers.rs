@@ -0,0 +1,202 @@
+use crate::{
+    grid::Grid,
+    liquids::{AnyLiquid, Lava, Water},
+    tiles::Tile,
+    Map,
+};
+
+/// A value of every tile that can be sampled with [Map::sample_layer], for example to draw it as a heat map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MapLayer {
+    /// The pressure of the air, including the weight of the liquids on the tile
+    AirPressure,
+    /// The fraction of the air that is oxygen
+    Oxygen,
+    /// The fraction of the air that is fumes
+    Fumes,
+    /// The fraction of the air that is carbon dioxide
+    CarbonDioxide,
+    /// The water level
+    Water,
+    /// The lava level
+    Lava,
+    /// The height of the top of the ground or liquid on the tile
+    SurfaceLevel,
+    /// The height of the ground itself
+    GroundLevel,
+    /// The temperature of the tile in degrees Celsius
+    Temperature,
+    /// How fiercely the tile is burning
+    Fire,
+    /// The height of the terrain to draw: the ground level, plus the [Tile::TUNNEL_HEIGHT] for walls
+    TerrainHeight,
+    /// The height of the top of the water on the tile. Tiles without water don't have it.
+    WaterSurface,
+    /// 1 for walls and 0 for all other tiles
+    Wall,
+}
+
+impl MapLayer {
+    /// The value of the layer on the tile, or `NaN` when the tile doesn't have the layer
+    fn value(self, tile: &Tile) -> f32 {
+        let value = match self {
+            MapLayer::AirPressure => tile
+                .tile_type
+                .get_ground()
+                .map(|(air, liquids)| air.air_pressure(liquids.get_level::<AnyLiquid>())),
+            MapLayer::Oxygen => tile.tile_type.get_air().map(|air| air.oxygen_fraction()),
+            MapLayer::Fumes => tile.tile_type.get_air().map(|air| air.fumes_fraction()),
+            MapLayer::CarbonDioxide => tile
+                .tile_type
+                .get_air()
+                .map(|air| air.carbon_dioxide_fraction()),
+            MapLayer::Water => tile
+                .tile_type
+                .get_liquids()
+                .map(|liquids| liquids.get_level::<Water>()),
+            MapLayer::Lava => tile
+                .tile_type
+                .get_liquids()
+                .map(|liquids| liquids.get_level::<Lava>()),
+            MapLayer::SurfaceLevel => Some(tile.surface_level()),
+            MapLayer::GroundLevel => Some(tile.ground_level),
+            MapLayer::Temperature => tile.temperature(),
+            MapLayer::Fire => tile.tile_type.get_ground().map(|_| tile.fire),
+            MapLayer::TerrainHeight => Some(if tile.tile_type.is_wall() {
+                tile.ground_level + Tile::TUNNEL_HEIGHT
+            } else {
+                tile.ground_level
+            }),
+            MapLayer::WaterSurface => tile
+                .tile_type
+                .get_liquids()
+                .map(|liquids| liquids.get_level::<Water>())
+                .filter(|level| *level > 0.0)
+                .map(|_| tile.surface_level()),
+            MapLayer::Wall => Some(if tile.tile_type.is_wall() { 1.0 } else { 0.0 }),
+        };
+
+        value.unwrap_or(f32::NAN)
+    }
+}
+
+impl Map {
+    /// Sample the value of the layer on every tile.
+    ///
+    /// Tiles that don't have the layer, like walls that have no air, are `NaN`.
+    pub fn sample_layer(&self, layer: MapLayer) -> Grid<f32> {
+        let mut result = Grid::new(self.width(), self.height(), f32::NAN);
+        for (x, y) in self.all_tile_coords() {
+            result[(x, y)] = layer.value(&self.tiles[(x, y)]);
+        }
+
+        result
+    }
+
+    /// Write the value of the layer on every tile into the buffer, for example to upload it to a texture.
+    ///
+    /// The buffer is row-major: the value of tile `(x, y)` goes to `data[y * stride + x]`.
+    /// The values between the end of a row and the start of the next aren't touched.
+    /// Tiles that don't have the layer are `NaN`, like with [Self::sample_layer].
+    ///
+    /// # Panics
+    ///
+    /// When the stride is less than the width of the map or the buffer is too small for all rows.
+    pub fn write_layer(&self, layer: MapLayer, data: &mut [f32], stride: usize) {
+        self.write_layer_tiles(layer, data, stride, self.all_tile_coords());
+    }
+
+    /// Like [Self::write_layer], but only for the given tiles.
+    ///
+    /// Together with [Self::take_render_dirty] this keeps a buffer up to date without writing all tiles every frame.
+    ///
+    /// # Panics
+    ///
+    /// When the stride is less than the width of the map, the buffer is too small for all rows
+    /// or a tile is outside of the map.
+    pub fn write_layer_tiles(
+        &self,
+        layer: MapLayer,
+        data: &mut [f32],
+        stride: usize,
+        tiles: impl IntoIterator<Item = (usize, usize)>,
+    ) {
+        let (width, height) = (self.width(), self.height());
+        assert!(
+            stride >= width,
+            "The stride ({stride}) is less than the width of the map ({width})"
+        );
+        let needed = stride * (height - 1) + width;
+        assert!(
+            data.len() >= needed,
+            "The buffer has {} values, but the map needs {needed}",
+            data.len()
+        );
+
+        for (x, y) in tiles {
+            assert!(
+                x < width && y < height,
+                "Tile ({x}, {y}) is outside of the map"
+            );
+            data[y * stride + x] = layer.value(&self.tiles[(x, y)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{liquids::LiquidData, tiles::TileType};
+
+    #[test]
+    fn walls_have_no_air() {
+        let mut map = Map::new_default(2, 1);
+        map.tiles[(1, 0)].tile_type = TileType::Wall;
+        *map.tiles[(0, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water { level: 0.5 };
+
+        let water = map.sample_layer(MapLayer::Water);
+        assert_eq!(water[(0, 0)], 0.5);
+        assert!(water[(1, 0)].is_nan());
+
+        let oxygen = map.sample_layer(MapLayer::Oxygen);
+        assert!((oxygen[(0, 0)] - 0.21).abs() < 0.01);
+        assert!(oxygen[(1, 0)].is_nan());
+
+        let ground_level = map.sample_layer(MapLayer::GroundLevel);
+        assert_eq!(ground_level[(1, 0)], map.tiles[(1, 0)].ground_level);
+
+        let water_surface = map.sample_layer(MapLayer::WaterSurface);
+        assert_eq!(water_surface[(0, 0)], 0.5);
+        assert!(water_surface[(1, 0)].is_nan());
+    }
+
+    #[test]
+    fn write_layer_with_stride() {
+        let mut map = Map::new_default(2, 2);
+        map.tiles[(1, 0)].tile_type = TileType::Wall;
+
+        // One extra value of padding after every row
+        let mut data = [-1.0; 5];
+        map.write_layer(MapLayer::Wall, &mut data, 3);
+        assert_eq!(data, [0.0, 1.0, -1.0, 0.0, 0.0]);
+
+        map.write_layer(MapLayer::TerrainHeight, &mut data, 3);
+        assert_eq!(data, [0.0, Tile::TUNNEL_HEIGHT, -1.0, 0.0, 0.0]);
+
+        // Only the given tiles are written
+        map.tiles[(0, 1)].tile_type = TileType::Wall;
+        map.tiles[(1, 1)].tile_type = TileType::Wall;
+        map.write_layer_tiles(MapLayer::TerrainHeight, &mut data, 3, [(0, 1)]);
+        assert_eq!(
+            data,
+            [0.0, Tile::TUNNEL_HEIGHT, -1.0, Tile::TUNNEL_HEIGHT, 0.0]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn write_layer_checks_the_buffer_size() {
+        let map = Map::new_default(2, 2);
+        map.write_layer(MapLayer::Wall, &mut [0.0; 4], 3);
+    }
+}
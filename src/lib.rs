@@ -1,34 +1,210 @@
-use air::AirDiff;
-use liquids::{Lava, Water};
-use objects::Objects;
+use air::{AirData, AirDiff};
+use events::EventListeners;
+use glam::{vec3, Vec2, Vec3};
+use grid::Grid;
+use liquids::{Lava, Liquid, LiquidBodies, LiquidData, LiquidDiff, LiquidEvent, LiquidKind, Water};
+use networks::TileNetworks;
+use objects::{
+    characters::{FrameEvent, HazardParams},
+    Objects,
+};
+use path_cache::PathCache;
+use rollback::Rollback;
+use rooms::Rooms;
 use std::{
     mem::size_of,
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, Instant},
 };
-use tiles::Tile;
+use tiles::{SurfaceKind, Tile, TileRect};
+use user_layers::UserLayers;
 
 pub mod air;
+pub mod ascii;
+pub mod construction;
+#[cfg(any(test, feature = "debug_render"))]
+pub mod debug_render;
+pub mod events;
+pub mod excavation;
+pub mod explosions;
 mod facing;
+pub mod fire;
+mod flow_field;
+pub mod grid;
+pub mod heat;
+mod layers;
 pub mod liquids;
+mod networks;
 pub mod objects;
+mod path_cache;
+pub mod pipes;
+pub mod power;
+pub mod rollback;
+pub mod rooms;
+pub mod runner;
+mod simulation_params;
+pub mod snapshot;
 pub mod tiles;
+mod user_layers;
 
-pub use facing::Facing;
+pub use facing::{Direction8, Facing, ParseFacingError};
+pub use flow_field::FlowField;
+pub use layers::MapLayer;
+pub use path_cache::PathCacheStats;
+pub use simulation_params::{LiquidSolver, SimulationBackend, SimulationParams};
 
 #[derive(Debug)]
-pub struct Map<const WIDTH: usize, const HEIGHT: usize> {
-    pub tiles: [[Tile; HEIGHT]; WIDTH],
+pub struct Map {
+    pub tiles: Grid<Tile>,
     objects: RwLock<Objects>,
     current_time: f64,
+    liquid_events: Vec<LiquidEvent>,
+    /// The speed of the liquid of every tile in the last tick, see [Self::liquid_flow]
+    liquid_flow: Grid<Vec2>,
+    /// Tiles that changed since the last time [Self::take_render_dirty] was called
+    render_dirty: Grid<bool>,
+    /// Tiles that, together with their neighbours, haven't changed more than the settled epsilon.
+    /// The air and liquid calculations skip these.
+    settled: Grid<bool>,
+    /// The tiles as they were when they last changed more than the settled epsilon.
+    /// None when all tiles need to be looked at again.
+    settled_baseline: Option<Grid<Tile>>,
+    profiling: bool,
+    /// Run the calculations of a tick without splitting them over threads
+    deterministic: bool,
+    simulation_params: SimulationParams,
+    simulation_backend: SimulationBackend,
+    hazard_params: HazardParams,
+    event_listeners: EventListeners,
+    /// Kept between ticks, so they don't need to be allocated every tick. None until the first tick.
+    tick_buffers: Option<TickBuffers>,
+    /// The paths the characters searched. Behind a mutex, because the AI of the characters runs on multiple threads.
+    path_cache: Mutex<PathCache>,
+    /// The room of every tile, see [rooms]
+    rooms: Rooms,
+    /// The recorded ticks. None when rollback isn't enabled.
+    rollback: Option<Rollback>,
+    /// The per-tile data of the game, see [user_layers]
+    user_layers: UserLayers,
+}
+
+/// The grids the calculations of a tick write into while the tiles are read,
+/// before they are applied to the tiles
+struct TickBuffers {
+    air_diff: Grid<AirDiff>,
+    water_diff: Grid<LiquidDiff>,
+    lava_diff: Grid<LiquidDiff>,
+    heat_diff: Grid<f32>,
+    fire_diff: Grid<f32>,
+    /// Scratch space of the water calculation
+    water_levels: Grid<f32>,
+    /// Scratch space of the lava calculation
+    lava_levels: Grid<f32>,
+    water_bodies: LiquidBodies,
+    lava_bodies: LiquidBodies,
+    /// The tiles that changed more than the settled epsilon at the start of the tick
+    changed_tiles: Vec<(usize, usize)>,
+    pipe_networks: TileNetworks,
+    power_networks: TileNetworks,
+}
+
+// Only the state of the map is interesting, not what the last tick left behind
+impl std::fmt::Debug for TickBuffers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TickBuffers").finish_non_exhaustive()
+    }
 }
 
-#[traitify::traitify(MapObject, dyn = [WIDTH, HEIGHT])]
-impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
-    pub const fn new_default() -> Self {
+impl TickBuffers {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            air_diff: Grid::new(width, height, AirDiff::default()),
+            water_diff: Grid::new(width, height, LiquidDiff::default()),
+            lava_diff: Grid::new(width, height, LiquidDiff::default()),
+            heat_diff: Grid::new(width, height, 0.0),
+            fire_diff: Grid::new(width, height, 0.0),
+            water_levels: Grid::new(width, height, 0.0),
+            lava_levels: Grid::new(width, height, 0.0),
+            water_bodies: LiquidBodies::new(width, height),
+            lava_bodies: LiquidBodies::new(width, height),
+            changed_tiles: Vec::new(),
+            pipe_networks: TileNetworks::new(width, height),
+            power_networks: TileNetworks::new(width, height),
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.air_diff.memory_usage()
+            + self.water_diff.memory_usage()
+            + self.lava_diff.memory_usage()
+            + self.heat_diff.memory_usage()
+            + self.fire_diff.memory_usage()
+            + self.water_levels.memory_usage()
+            + self.lava_levels.memory_usage()
+            + self.water_bodies.memory_usage()
+            + self.lava_bodies.memory_usage()
+            + self.changed_tiles.capacity() * size_of::<(usize, usize)>()
+            + self.pipe_networks.memory_usage()
+            + self.power_networks.memory_usage()
+    }
+}
+
+/// The result of a simulation tick
+#[derive(Debug, Clone, Default)]
+pub struct TickResult {
+    /// The time spent in every phase of the tick. Only present when profiling is enabled.
+    pub profile: Option<TickProfile>,
+}
+
+/// The time spent in every phase of a simulation tick.
+///
+/// The calculations run in parallel, so their sum can be more than the total tick time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TickProfile {
+    pub air_calculation: Duration,
+    pub water_calculation: Duration,
+    pub lava_calculation: Duration,
+    pub heat_calculation: Duration,
+    pub fire_calculation: Duration,
+    pub ai_calculation: Duration,
+    pub air_apply: Duration,
+    pub liquid_apply: Duration,
+    pub heat_apply: Duration,
+    pub fire_apply: Duration,
+    pub ai_apply: Duration,
+}
+
+#[traitify::traitify(MapObject)]
+impl Map {
+    /// Create a map of the given size with default tiles.
+    ///
+    /// # Panics
+    ///
+    /// When the width or height is 0.
+    /// A lot of the code expects there to be at least one tile in both directions.
+    pub fn new_default(width: usize, height: usize) -> Self {
+        assert!(width > 0 && height > 0, "A map must be at least 1x1");
+
         Self {
-            tiles: [[Tile::new_default(); HEIGHT]; WIDTH],
+            tiles: Grid::new(width, height, Tile::new_default()),
             objects: RwLock::new(Objects::new()),
             current_time: 0.0,
+            liquid_events: Vec::new(),
+            liquid_flow: Grid::new(width, height, Vec2::ZERO),
+            render_dirty: Grid::new(width, height, false),
+            settled: Grid::new(width, height, false),
+            settled_baseline: None,
+            profiling: false,
+            deterministic: false,
+            simulation_params: SimulationParams::realistic(),
+            simulation_backend: SimulationBackend::auto(),
+            hazard_params: HazardParams::new_default(),
+            event_listeners: EventListeners::new(),
+            tick_buffers: None,
+            path_cache: Mutex::new(PathCache::new()),
+            rooms: Rooms::new(width, height),
+            rollback: None,
+            user_layers: UserLayers::new(),
         }
     }
 
@@ -41,49 +217,66 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
     }
 
     pub fn tile(&self, x: usize, y: usize) -> &Tile {
-        &self.tiles[x][y]
+        &self.tiles[(x, y)]
     }
 
     pub fn tile_mut(&mut self, x: usize, y: usize) -> &mut Tile {
-        &mut self.tiles[x][y]
+        self.render_dirty[(x, y)] = true;
+        &mut self.tiles[(x, y)]
+    }
+
+    /// Get the coords of all tiles that changed since the last call and clear them.
+    ///
+    /// This tracks the changes of the simulation and of [Self::tile_mut].
+    /// Direct changes to the [Self::tiles] aren't tracked.
+    pub fn take_render_dirty(&mut self) -> Vec<(usize, usize)> {
+        let dirty_tiles = self
+            .all_tile_coords()
+            .filter(|(x, y)| self.render_dirty[(*x, *y)])
+            .collect();
+
+        self.render_dirty.fill(false);
+
+        dirty_tiles
     }
 
     pub fn width(&self) -> usize {
-        WIDTH
+        self.tiles.width()
     }
 
     pub fn height(&self) -> usize {
-        HEIGHT
+        self.tiles.height()
+    }
+
+    /// Estimate of the amount of bytes used by the map, including the tiles and the objects
+    pub fn memory_usage(&self) -> usize {
+        // The objects struct itself is already counted as part of the map
+        size_of::<Self>() + self.objects().memory_usage() - size_of::<Objects>()
+            + self.tiles.memory_usage()
+            + self.liquid_flow.memory_usage()
+            + self.render_dirty.memory_usage()
+            + self.settled.memory_usage()
+            + self
+                .settled_baseline
+                .as_ref()
+                .map_or(0, |baseline| baseline.memory_usage())
+            + self
+                .tick_buffers
+                .as_ref()
+                .map_or(0, |buffers| buffers.memory_usage())
+            + self.path_cache.lock().unwrap().memory_usage()
+            + self.rooms.memory_usage()
+            + self.rollback.as_ref().map_or(0, Rollback::memory_usage)
+            + self.user_layers.memory_usage()
     }
 
     #[inline(always)]
     pub fn all_tile_coords(&self) -> TileCoordIter {
-        TileCoordIter::new(WIDTH, HEIGHT)
+        TileCoordIter::new(self.width(), self.height())
     }
 
-    fn neighbour_tile_coords(target_tile_x: usize, target_tile_y: usize) -> NeighbourCoordsIter {
-        let has_neg_x_neighbour = target_tile_x > 0;
-        let has_neg_y_neighbour = target_tile_y > 0;
-        let has_pos_x_neighbour = target_tile_x < WIDTH - 1;
-        let has_pos_y_neighbour = target_tile_y < HEIGHT - 1;
-
-        NeighbourCoordsIter {
-            coords: [
-                (has_neg_x_neighbour && has_neg_y_neighbour)
-                    .then_some((target_tile_x - 1, target_tile_y - 1)),
-                (has_neg_x_neighbour).then_some((target_tile_x - 1, target_tile_y)),
-                (has_neg_x_neighbour && has_pos_y_neighbour)
-                    .then_some((target_tile_x - 1, target_tile_y + 1)),
-                (has_neg_y_neighbour).then_some((target_tile_x, target_tile_y - 1)),
-                (has_pos_y_neighbour).then_some((target_tile_x, target_tile_y + 1)),
-                (has_pos_x_neighbour && has_neg_y_neighbour)
-                    .then_some((target_tile_x + 1, target_tile_y - 1)),
-                (has_pos_x_neighbour).then_some((target_tile_x + 1, target_tile_y)),
-                (has_pos_x_neighbour && has_pos_y_neighbour)
-                    .then_some((target_tile_x + 1, target_tile_y + 1)),
-            ],
-            index: 0,
-        }
+    fn neighbour_tile_coords(&self, x: usize, y: usize) -> NeighbourCoordsIter {
+        NeighbourCoordsIter::new(x, y, self.width(), self.height())
     }
 
     fn neighbour_tiles(
@@ -92,7 +285,7 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
         target_tile_y: usize,
     ) -> NeighbourTilesIter<'_, Self> {
         NeighbourTilesIter {
-            coords: Self::neighbour_tile_coords(target_tile_x, target_tile_y),
+            coords: self.neighbour_tile_coords(target_tile_x, target_tile_y),
             map: self,
         }
     }
@@ -103,59 +296,579 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
         target_tile_y: usize,
     ) -> NeighbourTilesIter<'_, dyn MapObject> {
         NeighbourTilesIter {
-            coords: Self::neighbour_tile_coords(target_tile_x, target_tile_y),
+            coords: self.neighbour_tile_coords(target_tile_x, target_tile_y),
             map: self,
         }
     }
 
-    pub fn perform_simulation_tick(&mut self, delta_time: f32) {
-        let mut air_diff = [[AirDiff::default(); HEIGHT]; WIDTH];
-        let mut water_diff = [[0.0; HEIGHT]; WIDTH];
-        let mut lava_diff = [[0.0; HEIGHT]; WIDTH];
+    pub fn simulation_params(&self) -> &SimulationParams {
+        &self.simulation_params
+    }
+
+    pub fn set_simulation_params(&mut self, simulation_params: SimulationParams) {
+        self.simulation_params = simulation_params;
+        // Tiles that were settled with the old params may not be with the new ones
+        self.settled_baseline = None;
+    }
+
+    pub fn simulation_backend(&self) -> SimulationBackend {
+        self.simulation_backend
+    }
+
+    /// Choose how the air calculations are run. This changes the speed, the outcome only differs by rounding.
+    pub fn set_simulation_backend(&mut self, simulation_backend: SimulationBackend) {
+        self.simulation_backend = simulation_backend;
+    }
+
+    pub fn hazard_params(&self) -> &HazardParams {
+        &self.hazard_params
+    }
+
+    pub fn set_hazard_params(&mut self, hazard_params: HazardParams) {
+        self.hazard_params = hazard_params;
+    }
+
+    /// Keep performing simulation ticks until the air and liquids don't change anymore.
+    ///
+    /// The map is settled when no air component or liquid level changed more than the `tolerance` over the last two ticks.
+    /// Two ticks are compared, because the spreading can keep flipping back and forth between two states
+    /// for every tick once the differences get small.
+    /// Returns the amount of ticks it took, or None if the map didn't settle within `max_ticks`.
+    pub fn settle(&mut self, delta_time: f32, tolerance: f32, max_ticks: usize) -> Option<usize> {
+        let mut previous_tiles = [self.tiles.clone(), self.tiles.clone()];
+
+        for tick in 1..=max_ticks {
+            self.perform_simulation_tick(delta_time);
+
+            let settled = tick >= 2
+                && self.all_tile_coords().all(|(x, y)| {
+                    previous_tiles[0][(x, y)].max_difference(&self.tiles[(x, y)]) <= tolerance
+                });
+
+            if settled {
+                return Some(tick);
+            }
+
+            previous_tiles.swap(0, 1);
+            previous_tiles[1].clone_from(&self.tiles);
+        }
+
+        None
+    }
+
+    /// Run a simulation tick followed by a frame tick, `ticks` times
+    pub fn step_n(&mut self, delta_time: f32, ticks: usize) {
+        for _ in 0..ticks {
+            self.perform_simulation_tick(delta_time);
+            self.perform_frame_tick(delta_time);
+        }
+    }
+
+    /// How often the paths of the characters could be taken from the cache instead of being searched
+    pub fn path_cache_stats(&self) -> PathCacheStats {
+        self.path_cache.lock().unwrap().stats()
+    }
+
+    /// Enable or disable the timing of the phases of a simulation tick.
+    /// When enabled, the timings are reported in the [TickResult].
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiling = enabled;
+    }
+
+    /// Calculate the chunks of the tiles and the AI of the characters one after the other
+    /// instead of spreading them over threads.
+    ///
+    /// The chunks and characters are always combined in the same order, so the outcome of a tick
+    /// doesn't depend on the threading either way. This mode rules the thread pool out completely,
+    /// for example for lockstep multiplayer where every peer must end up with bit-identical maps.
+    /// The peers still need to run the same build with the same [SimulationBackend],
+    /// because the backends round differently.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn perform_simulation_tick(&mut self, delta_time: f32) -> TickResult {
+        self.record_rollback_tick();
+
+        let mut buffers = self
+            .tick_buffers
+            .take()
+            .unwrap_or_else(|| TickBuffers::new(self.width(), self.height()));
+        let TickBuffers {
+            air_diff,
+            water_diff,
+            lava_diff,
+            heat_diff,
+            fire_diff,
+            water_levels,
+            lava_levels,
+            water_bodies,
+            lava_bodies,
+            changed_tiles,
+            pipe_networks,
+            power_networks,
+        } = &mut buffers;
         let mut ai_changes = Vec::new();
 
+        self.update_settled(changed_tiles);
+        self.update_rooms(changed_tiles.iter().copied());
+        self.update_power_grid(power_networks);
+        self.tick_objects(delta_time);
+
+        let profiling = self.profiling;
+        let mut profile = TickProfile::default();
+
         rayon::scope(|s| {
-            s.spawn(|_| air_diff = self.calculate_air_diff(delta_time));
-            s.spawn(|_| water_diff = self.calculate_liquid_diff::<Water>(delta_time));
-            s.spawn(|_| lava_diff = self.calculate_liquid_diff::<Lava>(delta_time));
-            s.spawn(|_| ai_changes = self.calculate_ai_changes());
+            s.spawn(|_| {
+                let start = profiling.then(Instant::now);
+                self.calculate_air_diff(delta_time, air_diff);
+                profile.air_calculation = start.map(|start| start.elapsed()).unwrap_or_default();
+            });
+            s.spawn(|_| {
+                let start = profiling.then(Instant::now);
+                self.calculate_liquid_diff::<Water>(
+                    delta_time,
+                    water_levels,
+                    water_bodies,
+                    water_diff,
+                );
+                profile.water_calculation = start.map(|start| start.elapsed()).unwrap_or_default();
+            });
+            s.spawn(|_| {
+                let start = profiling.then(Instant::now);
+                self.calculate_liquid_diff::<Lava>(delta_time, lava_levels, lava_bodies, lava_diff);
+                profile.lava_calculation = start.map(|start| start.elapsed()).unwrap_or_default();
+            });
+            s.spawn(|_| {
+                let start = profiling.then(Instant::now);
+                self.calculate_heat_diff(delta_time, heat_diff);
+                profile.heat_calculation = start.map(|start| start.elapsed()).unwrap_or_default();
+            });
+            s.spawn(|_| {
+                let start = profiling.then(Instant::now);
+                self.calculate_fire_diff(delta_time, fire_diff);
+                profile.fire_calculation = start.map(|start| start.elapsed()).unwrap_or_default();
+            });
+            s.spawn(|_| {
+                let start = profiling.then(Instant::now);
+                ai_changes = self.calculate_ai_changes();
+                profile.ai_calculation = start.map(|start| start.elapsed()).unwrap_or_default();
+            });
         });
 
         if !ai_changes.is_empty() {
             log::debug!("AI changes at {}: {:?}", self.current_time, ai_changes);
         }
 
+        let start = profiling.then(Instant::now);
         self.apply_air_diff(air_diff, delta_time);
-        self.apply_liquid_diff(water_diff, lava_diff);
+        profile.air_apply = start.map(|start| start.elapsed()).unwrap_or_default();
+
+        let start = profiling.then(Instant::now);
+        self.add_pump_flows(water_diff, lava_diff, air_diff, pipe_networks, delta_time);
+        self.apply_liquid_diff(water_diff, lava_diff, air_diff, delta_time);
+        profile.liquid_apply = start.map(|start| start.elapsed()).unwrap_or_default();
+
+        let start = profiling.then(Instant::now);
+        self.apply_heat_diff(heat_diff, delta_time);
+        profile.heat_apply = start.map(|start| start.elapsed()).unwrap_or_default();
+
+        let start = profiling.then(Instant::now);
+        self.apply_fire_diff(fire_diff, delta_time);
+        profile.fire_apply = start.map(|start| start.elapsed()).unwrap_or_default();
+
+        let start = profiling.then(Instant::now);
         self.apply_ai_changes(ai_changes.into_iter());
+        profile.ai_apply = start.map(|start| start.elapsed()).unwrap_or_default();
+
+        self.apply_excavation(delta_time);
+        self.apply_construction(delta_time);
+
+        self.tick_buffers = Some(buffers);
 
         self.current_time += delta_time as f64;
+
+        self.publish_events();
+
+        TickResult {
+            profile: profiling.then_some(profile),
+        }
+    }
+
+    /// Moves the characters along and returns what happened to them
+    pub fn perform_frame_tick(&mut self, delta_time: f32) -> Vec<FrameEvent> {
+        let frame_events = self.perform_ai_tick(delta_time);
+        self.publish_events();
+        frame_events
+    }
+
+    /// Write the height of the terrain of every tile into the buffer, see [MapLayer::TerrainHeight].
+    ///
+    /// The buffer is row-major with the given stride, see [Self::write_layer] for the layout and when it panics.
+    pub fn set_terrain_height_map(&self, data: &mut [f32], stride: usize) {
+        self.write_layer(MapLayer::TerrainHeight, data, stride);
+    }
+}
+
+impl Map {
+    /// Find the tiles the air and liquid calculations can skip.
+    ///
+    /// Every tile is compared to how it was the last time it changed more than the settled epsilon,
+    /// so it doesn't matter if the change came from the simulation, an object or a direct edit of the tiles.
+    /// Small changes add up, so a tile that slowly drifts still gets looked at again.
+    ///
+    /// The tiles that changed are put in `changed_tiles`. The first time, that's all tiles.
+    fn update_settled(&mut self, changed_tiles: &mut Vec<(usize, usize)>) {
+        let epsilon = self.simulation_params.settled_epsilon;
+        changed_tiles.clear();
+
+        let Some(baseline) = &mut self.settled_baseline else {
+            self.settled.fill(false);
+            self.settled_baseline = Some(self.tiles.clone());
+            changed_tiles.extend(TileCoordIter::new(self.width(), self.height()));
+            return;
+        };
+
+        for (x, y) in TileCoordIter::new(self.tiles.width(), self.tiles.height()) {
+            let old = &baseline[(x, y)];
+            let new = &self.tiles[(x, y)];
+
+            if old.max_difference(new) > epsilon || old.sealed != new.sealed {
+                changed_tiles.push((x, y));
+                baseline[(x, y)] = *new;
+            }
+        }
+
+        // A tile is settled when neither it nor any of its neighbours changed
+        self.settled.fill(true);
+        for (x, y) in changed_tiles.iter().copied() {
+            self.settled[(x, y)] = false;
+            for neighbour in self.neighbour_tile_coords(x, y) {
+                self.settled[neighbour] = false;
+            }
+        }
+    }
+
+    /// Iterate over the tiles in the given rect. The parts of the rect that are out of bounds are skipped.
+    pub fn tiles_in_rect(&self, rect: TileRect) -> impl Iterator<Item = (usize, usize, &Tile)> {
+        let (width, height) = (self.width(), self.height());
+        let xs = rect.x.min(width)..(rect.x + rect.width).min(width);
+        let ys = rect.y.min(height)..(rect.y + rect.height).min(height);
+
+        xs.flat_map(move |x| ys.clone().map(move |y| (x, y, &self.tiles[(x, y)])))
+    }
+
+    /// The total level of the given liquid of all tiles in the rect
+    pub fn liquid_volume<L: Liquid>(&self, rect: TileRect) -> f32 {
+        self.tiles_in_rect(rect)
+            .filter_map(|(_, _, tile)| tile.tile_type.get_liquids())
+            .map(|liquids| liquids.get_level::<L>())
+            .sum()
+    }
+
+    /// The total air of all tiles in the rect.
+    ///
+    /// The temperature is the average of the tiles, weighted by the amount of air in them.
+    pub fn air_mass(&self, rect: TileRect) -> AirData {
+        self.tiles_in_rect(rect)
+            .filter_map(|(_, _, tile)| tile.tile_type.get_air())
+            .fold(
+                AirData {
+                    nitrogen: 0.0,
+                    oxygen: 0.0,
+                    fumes: 0.0,
+                    steam: 0.0,
+                    carbon_dioxide: 0.0,
+                    temperature: Tile::DEFAULT_TEMPERATURE,
+                },
+                |mut total, air| {
+                    total.mix_in(air.total(), air.total() * air.temperature);
+                    AirData {
+                        nitrogen: total.nitrogen + air.nitrogen,
+                        oxygen: total.oxygen + air.oxygen,
+                        fumes: total.fumes + air.fumes,
+                        steam: total.steam + air.steam,
+                        carbon_dioxide: total.carbon_dioxide + air.carbon_dioxide,
+                        temperature: total.temperature,
+                    }
+                },
+            )
+    }
+
+    /// Average a value over the neighbours of the given tile.
+    ///
+    /// Neighbours for which the extractor returns None are skipped.
+    /// If no neighbours are left, None is returned.
+    pub fn neighbour_average(
+        &self,
+        x: usize,
+        y: usize,
+        extract: impl Fn(&Tile) -> Option<f32>,
+    ) -> Option<f32> {
+        let (sum, count) = self
+            .neighbour_tiles(x, y)
+            .filter_map(|(_, _, tile)| extract(tile))
+            .fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+
+        (count > 0).then(|| sum / count as f32)
+    }
+
+    /// Iterate over the neighbours of the given tile that match the predicate
+    pub fn neighbours_matching<'a>(
+        &'a self,
+        x: usize,
+        y: usize,
+        predicate: impl Fn(&Tile) -> bool + 'a,
+    ) -> impl Iterator<Item = (usize, usize, &'a Tile)> + 'a {
+        self.neighbour_tiles(x, y)
+            .filter(move |(_, _, tile)| predicate(tile))
+    }
+
+    /// Returns true if any of the neighbours of the given tile match the predicate
+    pub fn has_neighbour_matching(
+        &self,
+        x: usize,
+        y: usize,
+        predicate: impl Fn(&Tile) -> bool,
+    ) -> bool {
+        self.neighbour_tiles(x, y)
+            .any(|(_, _, tile)| predicate(tile))
+    }
+
+    /// Iterate over all tiles that aren't walls, together with their air and liquids
+    pub fn ground_tiles(&self) -> impl Iterator<Item = (usize, usize, &AirData, &LiquidData)> {
+        self.all_tile_coords().filter_map(|(x, y)| {
+            self.tiles[(x, y)]
+                .tile_type
+                .get_ground()
+                .map(|(air, liquids)| (x, y, air, liquids))
+        })
+    }
+
+    /// Cast a ray and find the first surface it hits.
+    ///
+    /// The ray is in x, y, up space. The surface is a wall, the ground or the top of a liquid.
+    /// Returns None if the ray leaves the map before hitting anything.
+    pub fn raycast_to_surface(&self, from: Vec3, direction: Vec3) -> Option<(Vec3, SurfaceKind)> {
+        let direction = direction.try_normalize()?;
+        if from.x < 0.0 || from.y < 0.0 {
+            return None;
+        }
+
+        // Walk over the tiles the ray passes through, one tile border at a time
+        let (mut x, mut y) = (from.x as usize, from.y as usize);
+        let axis_setup = |position: f32, direction: f32| {
+            if direction > 0.0 {
+                (
+                    (position.floor() + 1.0 - position) / direction,
+                    1.0 / direction,
+                )
+            } else if direction < 0.0 {
+                ((position.floor() - position) / direction, -1.0 / direction)
+            } else {
+                (f32::INFINITY, f32::INFINITY)
+            }
+        };
+        let (mut next_border_x, border_distance_x) = axis_setup(from.x, direction.x);
+        let (mut next_border_y, border_distance_y) = axis_setup(from.y, direction.y);
+        let mut entry = 0.0;
+
+        while x < self.width() && y < self.height() {
+            let exit = next_border_x.min(next_border_y);
+            let tile = &self.tiles[(x, y)];
+            let height_at = |distance: f32| from.z + direction.z * distance;
+
+            if tile.tile_type.is_wall() {
+                return Some((from + direction * entry, SurfaceKind::Wall));
+            }
+
+            let surface_kind = match tile.tile_type.get_liquids().and_then(|l| l.kind()) {
+                Some(LiquidKind::Water) => SurfaceKind::Water,
+                Some(LiquidKind::Lava) => SurfaceKind::Lava,
+                None => SurfaceKind::Ground,
+            };
+
+            if height_at(entry) <= tile.surface_level() {
+                // We came in from the side below the surface
+                let kind = if height_at(entry) <= tile.liquid_floor_level() {
+                    SurfaceKind::Ground
+                } else {
+                    surface_kind
+                };
+                return Some((from + direction * entry, kind));
+            }
+
+            if direction.z < 0.0 {
+                let surface_distance = (tile.surface_level() - from.z) / direction.z;
+                if surface_distance <= exit {
+                    return Some((from + direction * surface_distance, surface_kind));
+                }
+            }
+
+            if exit.is_infinite() {
+                return None;
+            }
+
+            if next_border_x < next_border_y {
+                x = x.checked_add_signed(direction.x.signum() as isize)?;
+                next_border_x += border_distance_x;
+            } else {
+                y = y.checked_add_signed(direction.y.signum() as isize)?;
+                next_border_y += border_distance_y;
+            }
+            entry = exit;
+        }
+
+        None
     }
 
-    pub fn perform_frame_tick(&mut self, delta_time: f32) {
-        self.perform_ai_tick(delta_time);
+    /// Step the map like [Self::step_n] until the predicate holds.
+    ///
+    /// The predicate is checked before every step.
+    /// Returns false if it still didn't hold after `max_ticks` steps.
+    pub fn step_until(
+        &mut self,
+        delta_time: f32,
+        max_ticks: usize,
+        predicate: impl Fn(&Self) -> bool,
+    ) -> bool {
+        for _ in 0..max_ticks {
+            if predicate(self) {
+                return true;
+            }
+
+            self.step_n(delta_time, 1);
+        }
+
+        predicate(self)
     }
 
-    // Data must be a two dimensional array that fits an f32 for each tile
-    pub fn set_terrain_height_map(&self, data: &mut [u8]) {
-        assert_eq!(data.len(), WIDTH * HEIGHT * size_of::<f32>());
+    /// Collect the temperature of every tile. Walls don't have a temperature.
+    pub fn collect_temperature_map(&self) -> Grid<Option<f32>> {
+        let mut result = Grid::new(self.width(), self.height(), None);
+
+        for (x, y) in self.all_tile_coords() {
+            result[(x, y)] = self.tiles[(x, y)].temperature();
+        }
+
+        result
+    }
 
-        let data: &mut [[f32; HEIGHT]; WIDTH] = unsafe { &mut *(data.as_mut_ptr() as *mut _) };
+    /// Calculate the normal of the surface (ground or liquid) of every tile.
+    ///
+    /// The normals are in x, y, up space. The edges of the map use one-sided differences.
+    pub fn collect_surface_normal_map(&self) -> Grid<Vec3> {
+        let mut result = Grid::new(self.width(), self.height(), Vec3::Z);
 
         for (x, y) in self.all_tile_coords() {
-            data[x][y] = self.tiles[x][y].ground_level
-                + self.tiles[x][y]
-                    .tile_type
-                    .is_wall()
-                    .then_some(Tile::TUNNEL_HEIGHT)
-                    .unwrap_or_default();
+            // Central differences where possible, one-sided at the edges
+            let (low_x, high_x) = (x.saturating_sub(1), (x + 1).min(self.width() - 1));
+            let (low_y, high_y) = (y.saturating_sub(1), (y + 1).min(self.height() - 1));
+
+            let slope_x = if low_x == high_x {
+                0.0
+            } else {
+                (self.tiles[(high_x, y)].surface_level() - self.tiles[(low_x, y)].surface_level())
+                    / (high_x - low_x) as f32
+            };
+            let slope_y = if low_y == high_y {
+                0.0
+            } else {
+                (self.tiles[(x, high_y)].surface_level() - self.tiles[(x, low_y)].surface_level())
+                    / (high_y - low_y) as f32
+            };
+
+            result[(x, y)] = vec3(-slope_x, -slope_y, 1.0).normalize();
+        }
+
+        result
+    }
+}
+
+/// Only the simulation state is stored.
+/// The liquid events, the profiling setting and what still needs to be rendered are not part of it.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Map {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        // The tiles go in one column at a time, independent of how they're chunked in memory
+        struct Tiles<'a>(&'a Grid<Tile>);
+        struct Column<'a>(&'a Grid<Tile>, usize);
+
+        impl<'a> serde::Serialize for Tiles<'a> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq((0..self.0.width()).map(|x| Column(self.0, x)))
+            }
+        }
+
+        impl<'a> serde::Serialize for Column<'a> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq((0..self.0.height()).map(|y| &self.0[(self.1, y)]))
+            }
         }
+
+        let mut state = serializer.serialize_struct("Map", 7)?;
+        state.serialize_field("width", &self.width())?;
+        state.serialize_field("height", &self.height())?;
+        state.serialize_field("tiles", &Tiles(&self.tiles))?;
+        state.serialize_field("objects", &*self.objects())?;
+        state.serialize_field("current_time", &self.current_time)?;
+        state.serialize_field("simulation_params", &self.simulation_params)?;
+        state.serialize_field("hazard_params", &self.hazard_params)?;
+        state.end()
     }
 }
 
-impl<const WIDTH: usize, const HEIGHT: usize> Default for Map<WIDTH, HEIGHT> {
-    fn default() -> Self {
-        Self::new_default()
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Map {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "Map")]
+        struct MapData {
+            width: usize,
+            height: usize,
+            tiles: Vec<Vec<Tile>>,
+            objects: Objects,
+            current_time: f64,
+            simulation_params: SimulationParams,
+            hazard_params: HazardParams,
+        }
+
+        let data = MapData::deserialize(deserializer)?;
+
+        let (width, height) = (data.width, data.height);
+        if width == 0 || height == 0 {
+            return Err(D::Error::custom(format!(
+                "a map must be at least 1x1, but found a {width}x{height} map"
+            )));
+        }
+        if data.tiles.len() != width || data.tiles.iter().any(|column| column.len() != height) {
+            return Err(D::Error::custom(format!(
+                "the tiles don't match the {width}x{height} map size"
+            )));
+        }
+
+        let mut map = Self::new_default(width, height);
+        for (x, column) in data.tiles.into_iter().enumerate() {
+            for (y, tile) in column.into_iter().enumerate() {
+                map.tiles[(x, y)] = tile;
+            }
+        }
+        map.objects = RwLock::new(data.objects);
+        map.current_time = data.current_time;
+        map.simulation_params = data.simulation_params;
+        map.hazard_params = data.hazard_params;
+        // Nothing has been rendered of the loaded map yet
+        map.render_dirty.fill(true);
+
+        Ok(map)
     }
 }
 
@@ -202,6 +915,39 @@ pub struct NeighbourCoordsIter {
     index: usize,
 }
 
+impl NeighbourCoordsIter {
+    /// The coords of the tiles around the target tile that are within the given map size
+    pub(crate) fn new(
+        target_tile_x: usize,
+        target_tile_y: usize,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        let has_neg_x_neighbour = target_tile_x > 0;
+        let has_neg_y_neighbour = target_tile_y > 0;
+        let has_pos_x_neighbour = target_tile_x < width - 1;
+        let has_pos_y_neighbour = target_tile_y < height - 1;
+
+        Self {
+            coords: [
+                (has_neg_x_neighbour && has_neg_y_neighbour)
+                    .then(|| (target_tile_x - 1, target_tile_y - 1)),
+                (has_neg_x_neighbour).then(|| (target_tile_x - 1, target_tile_y)),
+                (has_neg_x_neighbour && has_pos_y_neighbour)
+                    .then(|| (target_tile_x - 1, target_tile_y + 1)),
+                (has_neg_y_neighbour).then(|| (target_tile_x, target_tile_y - 1)),
+                (has_pos_y_neighbour).then(|| (target_tile_x, target_tile_y + 1)),
+                (has_pos_x_neighbour && has_neg_y_neighbour)
+                    .then(|| (target_tile_x + 1, target_tile_y - 1)),
+                (has_pos_x_neighbour).then(|| (target_tile_x + 1, target_tile_y)),
+                (has_pos_x_neighbour && has_pos_y_neighbour)
+                    .then(|| (target_tile_x + 1, target_tile_y + 1)),
+            ],
+            index: 0,
+        }
+    }
+}
+
 impl Iterator for NeighbourCoordsIter {
     type Item = (usize, usize);
 
@@ -239,17 +985,23 @@ impl<'m, M: MapObject + ?Sized> Iterator for NeighbourTilesIter<'m, M> {
 mod tests {
     use super::*;
     use crate::{
-        air::{AirLeveler, AirPusher, OxygenUser},
-        liquids::{AnyLiquid, Lava, Liquid, LiquidData, LiquidLeveler, Water},
+        air::{AirLeveler, AirPusher, GasMask, OxygenUser},
+        debug_render::MapGifRecorder,
+        liquids::{AnyLiquid, Lava, LiquidData, LiquidLeveler, Water},
         objects::{
-            building::{Building, BuildingType, WorkSpot, WorkSpotOccupation},
-            characters::{Character, WorkGoal},
+            building::Building,
+            characters::{tests::ventilator, Character, WorkGoal},
             environment_object::EnvironmentObject,
         },
         tiles::TileType,
     };
-    use glam::{uvec2, vec2};
-    use std::{fs::File, path::PathBuf};
+    use approx::assert_relative_eq;
+    use glam::{uvec2, vec2, vec3};
+    use std::{
+        fs::File,
+        hash::{DefaultHasher, Hash, Hasher},
+        path::PathBuf,
+    };
     use test_log::test;
 
     #[test]
@@ -258,105 +1010,534 @@ mod tests {
         assert_eq!(iter, &[(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
     }
 
-    impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
-        fn collect_air_pressure_map(&self) -> [[f32; HEIGHT]; WIDTH] {
-            let mut result = [[0.0; HEIGHT]; WIDTH];
+    #[test]
+    fn surface_normals() {
+        let mut map = Map::new_default(4, 3);
 
-            for (x, y) in self.all_tile_coords() {
-                result[x][y] = self.tiles[x][y]
-                    .tile_type
-                    .get_ground()
-                    .map(|(air, liquids)| air.air_pressure(liquids.get_level::<AnyLiquid>()))
-                    .unwrap_or(f32::NAN);
-            }
+        for (_, _, normal) in map.collect_surface_normal_map().iter() {
+            assert_relative_eq!(normal.x, 0.0);
+            assert_relative_eq!(normal.y, 0.0);
+            assert_relative_eq!(normal.z, 1.0);
+        }
 
-            result
+        // Slope up towards the east
+        for (x, y) in map.all_tile_coords() {
+            map.tiles[(x, y)].ground_level = x as f32;
         }
 
-        fn collect_oxygen_map(&self) -> [[f32; HEIGHT]; WIDTH] {
-            let mut result = [[0.0; HEIGHT]; WIDTH];
+        for (_, _, normal) in map.collect_surface_normal_map().iter() {
+            assert_relative_eq!(normal.x, -std::f32::consts::FRAC_1_SQRT_2);
+            assert_relative_eq!(normal.y, 0.0);
+            assert_relative_eq!(normal.z, std::f32::consts::FRAC_1_SQRT_2);
+        }
+    }
 
-            for (x, y) in self.all_tile_coords() {
-                result[x][y] = self.tiles[x][y]
-                    .tile_type
-                    .get_air()
-                    .map(|air| air.oxygen_fraction())
-                    .unwrap_or(f32::NAN);
-            }
+    #[test]
+    fn low_wall_surface() {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(1, 0)].tile_type = TileType::LowWall {
+            height: 1.0,
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 0.5 },
+        };
+
+        // The water rests on top of the wall
+        assert_relative_eq!(map.tiles[(1, 0)].surface_level(), 1.5);
+        assert_relative_eq!(map.liquid_render_info(1, 0).unwrap().surface_height, 1.5);
+
+        let normals = map.collect_surface_normal_map();
+        assert!(normals[(0, 0)].x < 0.0);
+        assert!(normals[(2, 0)].x > 0.0);
+
+        let (point, kind) = map
+            .raycast_to_surface(vec3(1.5, 0.5, 5.0), vec3(0.0, 0.0, -1.0))
+            .unwrap();
+        assert_eq!(kind, SurfaceKind::Water);
+        assert_relative_eq!(point.z, 1.5);
+
+        // Hitting the side of the wall below the water is hitting the ground
+        let (point, kind) = map
+            .raycast_to_surface(vec3(0.5, 0.5, 0.5), vec3(1.0, 0.0, 0.0))
+            .unwrap();
+        assert_eq!(kind, SurfaceKind::Ground);
+        assert_relative_eq!(point.x, 1.0);
+    }
 
-            result
+    #[test]
+    fn region_totals() {
+        let mut map = Map::new_default(4, 4);
+
+        for (x, y) in map.all_tile_coords() {
+            map.tiles[(x, y)].tile_type = TileType::Ground {
+                air: AirData {
+                    nitrogen: 1.0,
+                    oxygen: 0.5,
+                    fumes: x as f32,
+                    steam: 0.0,
+                    carbon_dioxide: 0.0,
+                    temperature: Tile::DEFAULT_TEMPERATURE,
+                },
+                liquids: LiquidData::Water {
+                    level: (x + y) as f32,
+                },
+            };
         }
+        map.tiles[(1, 1)].tile_type = TileType::Wall;
+
+        let rect = TileRect::new(1, 1, 2, 2);
+        let expected_water = map
+            .all_tile_coords()
+            .filter(|(x, y)| rect.contains(*x, *y))
+            .filter_map(|(x, y)| map.tiles[(x, y)].tile_type.get_liquids())
+            .map(|liquids| liquids.get_level::<Water>())
+            .sum::<f32>();
+
+        assert_eq!(map.liquid_volume::<Water>(rect), expected_water);
+        assert_eq!(map.liquid_volume::<Water>(rect), 3.0 + 3.0 + 4.0);
+        assert_eq!(map.liquid_volume::<Lava>(rect), 0.0);
+
+        let air = map.air_mass(rect);
+        assert_eq!(air.nitrogen, 3.0);
+        assert_eq!(air.oxygen, 1.5);
+        assert_eq!(air.fumes, 1.0 + 2.0 + 2.0);
+
+        // Out of bounds parts are skipped
+        assert_eq!(map.liquid_volume::<Water>(TileRect::new(3, 3, 10, 10)), 6.0);
+    }
 
-        fn collect_fumes_map(&self) -> [[f32; HEIGHT]; WIDTH] {
-            let mut result = [[0.0; HEIGHT]; WIDTH];
+    #[test]
+    fn neighbour_average() {
+        let mut map = Map::new_default(3, 3);
 
-            for (x, y) in self.all_tile_coords() {
-                result[x][y] = self.tiles[x][y]
-                    .tile_type
-                    .get_air()
-                    .map(|air| air.fumes_fraction())
-                    .unwrap_or(f32::NAN);
-            }
+        for (x, y) in map.all_tile_coords() {
+            map.tiles[(x, y)].ground_level = (x * 3 + y) as f32;
+        }
+
+        // The corner only has three neighbours: (0, 1), (1, 0) and (1, 1)
+        assert_eq!(
+            map.neighbour_average(0, 0, |tile| Some(tile.ground_level)),
+            Some((1.0 + 3.0 + 4.0) / 3.0)
+        );
+
+        map.tiles[(1, 1)].tile_type = TileType::Wall;
+        assert_eq!(
+            map.neighbour_average(0, 0, |tile| tile
+                .tile_type
+                .get_ground()
+                .map(|_| tile.ground_level)),
+            Some((1.0 + 3.0) / 2.0)
+        );
+        assert_eq!(map.neighbour_average(0, 0, |_| None), None);
+    }
 
-            result
+    #[test]
+    fn neighbours_matching() {
+        let mut map = Map::new_default(5, 3);
+        map.tiles[(0, 1)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Lava { level: 0.5 },
+        };
+        let is_lava = |tile: &Tile| {
+            tile.tile_type
+                .get_liquids()
+                .and_then(|liquids| liquids.get_level_optional::<Lava>())
+                .is_some()
+        };
+
+        assert!(map.has_neighbour_matching(1, 1, is_lava));
+        assert!(!map.has_neighbour_matching(4, 1, is_lava));
+        // A tile isn't its own neighbour
+        assert!(!map.has_neighbour_matching(0, 1, is_lava));
+
+        let lava_neighbours = map
+            .neighbours_matching(1, 2, is_lava)
+            .map(|(x, y, _)| (x, y))
+            .collect::<Vec<_>>();
+        assert_eq!(lava_neighbours, vec![(0, 1)]);
+    }
+
+    #[test]
+    fn settled_tiles() {
+        let mut map = Map::new_default(20, 20);
+
+        // A map in equilibrium settles everywhere
+        map.perform_simulation_tick(0.1);
+        map.perform_simulation_tick(0.1);
+        assert!(map.all_tile_coords().all(|(x, y)| map.settled[(x, y)]));
+
+        // Direct changes are picked up too
+        map.tiles[(0, 0)].tile_type.get_air_mut().unwrap().fumes = 0.5;
+        map.perform_simulation_tick(0.1);
+        assert!(!map.settled[(0, 0)]);
+        assert!(!map.settled[(1, 1)]);
+        assert!(map.settled[(10, 10)]);
+        assert_eq!(
+            map.tiles[(10, 10)].max_difference(&Tile::new_default()),
+            0.0
+        );
+    }
+
+    #[test]
+    fn settled_tiles_dont_change_the_result() {
+        let mut skipping = Map::new_default(20, 20);
+        skipping.set_simulation_params(SimulationParams::arcade());
+        // A chamber in the corner where everything happens
+        for i in 0..6 {
+            skipping.tiles[(5, i)].tile_type = TileType::Wall;
+            skipping.tiles[(i, 5)].tile_type = TileType::Wall;
+        }
+        skipping.tiles[(1, 1)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 2.0 },
+        };
+        skipping.tiles[(3, 3)]
+            .tile_type
+            .get_air_mut()
+            .unwrap()
+            .oxygen += 0.5;
+
+        let mut not_skipping = Map::new_default(20, 20);
+        not_skipping.tiles = skipping.tiles.clone();
+        not_skipping.set_simulation_params(SimulationParams {
+            // Everything is always further than this from its baseline
+            settled_epsilon: -1.0,
+            ..SimulationParams::arcade()
+        });
+
+        for _ in 0..100 {
+            skipping.perform_simulation_tick(0.05);
+            not_skipping.perform_simulation_tick(0.05);
         }
 
-        fn collect_liquids_map<L: Liquid>(&self) -> [[f32; HEIGHT]; WIDTH] {
-            let mut result = [[0.0; HEIGHT]; WIDTH];
+        // Only the chamber and the tiles around it are still simulated
+        assert_eq!(
+            skipping
+                .all_tile_coords()
+                .filter(|(x, y)| !skipping.settled[(*x, *y)])
+                .count(),
+            6 * 6
+        );
+        assert!(not_skipping
+            .all_tile_coords()
+            .all(|(x, y)| !not_skipping.settled[(x, y)]));
+
+        for (x, y) in skipping.all_tile_coords() {
+            assert_eq!(
+                skipping.tiles[(x, y)].max_difference(&not_skipping.tiles[(x, y)]),
+                0.0
+            );
+        }
+    }
 
-            for (x, y) in self.all_tile_coords() {
-                result[x][y] = self.tiles[x][y]
-                    .tile_type
-                    .get_liquids()
-                    .map(|liquids| liquids.get_level::<L>())
-                    .unwrap_or(f32::NAN);
+    #[test]
+    fn deterministic_ticks() {
+        // Big enough for multiple chunks, so the sums over the chunk borders are part of the outcome
+        let build_map = |deterministic| {
+            let mut map = Map::new_default(40, 40);
+            map.set_simulation_params(SimulationParams::arcade());
+            map.set_deterministic(deterministic);
+
+            for y in 10..40 {
+                map.tiles[(20, y)].tile_type = TileType::Wall;
+            }
+            map.tiles[(30, 31)].tile_type = TileType::Ground {
+                air: Default::default(),
+                liquids: LiquidData::Water { level: 2.0 },
+            };
+            map.tiles[(31, 33)].temperature = 150.0;
+            map.tiles[(8, 35)].tile_type.get_air_mut().unwrap().oxygen += 0.5;
+
+            map.objects_mut()
+                .push_object::<Building>(ventilator(uvec2(12, 30), Facing::East));
+            for i in 0..3 {
+                map.objects_mut().push_object::<Character>(Character::new(
+                    vec2(5.5 + i as f32, 31.5),
+                    1.0,
+                    vec![WorkGoal::WorkAtVentilation],
+                ));
             }
 
-            result
+            map
+        };
+
+        // The debug output has every float in full, so any difference ends up in the hash
+        let state_hash = |map: &Map| {
+            let mut hasher = DefaultHasher::new();
+            for (x, y) in map.all_tile_coords() {
+                format!("{:?}", map.tiles[(x, y)]).hash(&mut hasher);
+            }
+            for building in map.objects().get_objects::<Building>() {
+                format!("{:?}", *building).hash(&mut hasher);
+            }
+            for character in map.objects().get_objects::<Character>() {
+                format!("{:?}", *character).hash(&mut hasher);
+            }
+            hasher.finish()
+        };
+
+        let mut maps = [build_map(true), build_map(true), build_map(false)];
+        let initial_hash = state_hash(&maps[0]);
+        for map in maps.iter_mut() {
+            map.step_n(0.05, 100);
+        }
+        let hashes = maps.each_ref().map(state_hash);
+
+        assert_ne!(hashes[0], initial_hash);
+        assert_eq!(hashes[0], hashes[1]);
+        // Spreading the work over threads doesn't change the outcome either
+        assert_eq!(hashes[0], hashes[2]);
+
+        // Nor does what the previous tick left in the reused buffers
+        let mut fresh_buffers_map = build_map(true);
+        for _ in 0..100 {
+            fresh_buffers_map.tick_buffers = None;
+            fresh_buffers_map.step_n(0.05, 1);
         }
+        assert_eq!(state_hash(&fresh_buffers_map), hashes[0]);
+    }
+
+    #[test]
+    fn render_dirty() {
+        let mut map = Map::new_default(5, 1);
 
-        fn collect_surface_level_map(&self) -> [[f32; HEIGHT]; WIDTH] {
-            let mut result = [[0.0; HEIGHT]; WIDTH];
+        // A map in equilibrium doesn't change
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.take_render_dirty(), vec![]);
 
-            for (x, y) in self.all_tile_coords() {
-                result[x][y] = self.tiles[x][y]
+        map.tiles[(0, 0)].tile_type.get_air_mut().unwrap().fumes = 0.5;
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.take_render_dirty(), vec![(0, 0), (1, 0)]);
+        assert_eq!(map.take_render_dirty(), vec![]);
+
+        map.tile_mut(3, 0).ground_level = 1.0;
+        assert_eq!(map.take_render_dirty(), vec![(3, 0)]);
+    }
+
+    #[test]
+    fn runtime_size() {
+        // Sizes that are only known at runtime, that don't fill the chunks and that are bigger than one chunk
+        for (width, height) in [(32, 16), (37, 1), (70, 33)] {
+            let mut map: Box<dyn MapObject> = Box::new(Map::new_default(width, height));
+            assert_eq!((map.width(), map.height()), (width, height));
+            assert_eq!(map.all_tile_coords().count(), width * height);
+
+            // The simulation works through the trait
+            map.tile_mut(0, 0).tile_type.get_air_mut().unwrap().fumes = 0.5;
+            map.tile_mut(width - 1, height - 1)
+                .tile_type
+                .get_air_mut()
+                .unwrap()
+                .fumes = 0.5;
+            map.take_render_dirty();
+            map.perform_simulation_tick(0.1);
+            assert!(map.tile(1, 0).tile_type.get_air().unwrap().fumes > 0.0);
+            assert!(
+                map.tile(width - 2, height - 1)
                     .tile_type
-                    .get_liquids()
-                    .map(|liquids| self.tiles[x][y].ground_level + liquids.get_level::<AnyLiquid>())
-                    .unwrap_or(self.tiles[x][y].ground_level);
-            }
+                    .get_air()
+                    .unwrap()
+                    .fumes
+                    > 0.0
+            );
+            assert!(map.take_render_dirty().contains(&(1, 0)));
+        }
+    }
 
-            result
+    #[test]
+    fn degenerate_maps() {
+        fn simulate(map: &mut Map) {
+            for _ in 0..100 {
+                map.perform_simulation_tick(0.1);
+                map.perform_frame_tick(0.1);
+            }
         }
 
-        fn collect_ground_level_map(&self) -> [[f32; HEIGHT]; WIDTH] {
-            let mut result = [[0.0; HEIGHT]; WIDTH];
+        let mut map = Map::new_default(1, 1);
+        assert_eq!(map.all_tile_coords().collect::<Vec<_>>(), vec![(0, 0)]);
+        assert_eq!(map.neighbour_tiles(0, 0).count(), 0);
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        simulate(&mut map);
+
+        let mut map = Map::new_default(1, 10);
+        assert_eq!(map.all_tile_coords().count(), 10);
+        assert_eq!(map.neighbour_tiles(0, 5).count(), 2);
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 1.0 },
+        };
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 9.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        map.objects_mut()
+            .push_object::<Building>(ventilator(uvec2(0, 0), Facing::North));
+        simulate(&mut map);
+
+        let mut map = Map::new_default(10, 1);
+        simulate(&mut map);
+    }
 
-            for (x, y) in self.all_tile_coords() {
-                result[x][y] = self.tiles[x][y].ground_level;
-            }
+    #[test]
+    fn tick_profile() {
+        let mut map = Map::new_default(10, 10);
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+
+        assert!(map.perform_simulation_tick(0.1).profile.is_none());
+
+        map.set_profiling(true);
+        let profile = map.perform_simulation_tick(0.1).profile.unwrap();
+
+        let phases = [
+            profile.air_calculation,
+            profile.water_calculation,
+            profile.lava_calculation,
+            profile.ai_calculation,
+            profile.air_apply,
+            profile.liquid_apply,
+            profile.ai_apply,
+        ];
+        // Single phases can be too fast for a coarse clock, but together they must have taken some time
+        assert!(phases.iter().sum::<Duration>() > Duration::ZERO);
+
+        map.set_profiling(false);
+        assert!(map.perform_simulation_tick(0.1).profile.is_none());
+    }
+
+    #[test]
+    fn closed_doors_block_air_and_liquids() {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: AirData {
+                nitrogen: 1.58,
+                oxygen: 0.42,
+                fumes: 0.5,
+                steam: 0.0,
+                carbon_dioxide: 0.0,
+                temperature: Tile::DEFAULT_TEMPERATURE,
+            },
+            liquids: LiquidData::Water { level: 2.0 },
+        };
+        map.tiles[(1, 0)].tile_type = TileType::Door {
+            open: false,
+            air: Default::default(),
+            liquids: Default::default(),
+        };
+
+        for _ in 0..20 {
+            map.perform_simulation_tick(0.1);
+        }
 
-            result
+        let ground = |map: &Map, x: usize| {
+            let (air, liquids) = map.tiles[(x, 0)].tile_type.get_ground().unwrap();
+            (*air, *liquids)
+        };
+        for x in [1, 2] {
+            let (air, liquids) = ground(&map, x);
+            assert_eq!(air, AirData::new_default());
+            assert_eq!(liquids.get_level::<AnyLiquid>(), 0.0);
+        }
+
+        // Opening the door lets everything through, even though the tiles had settled
+        if let TileType::Door { open, .. } = &mut map.tiles[(1, 0)].tile_type {
+            *open = true;
+        }
+        for _ in 0..20 {
+            map.perform_simulation_tick(0.1);
         }
+
+        let (air, liquids) = ground(&map, 2);
+        assert!(air.fumes > 0.0);
+        assert!(liquids.get_level::<Water>() > 0.0);
+    }
+
+    #[test]
+    fn ground_tiles() {
+        let mut map = Map::new_default(4, 3);
+        map.tiles[(0, 0)].tile_type = TileType::Wall;
+        map.tiles[(3, 2)].tile_type = TileType::Wall;
+        map.tiles[(1, 1)].tile_type = TileType::LowWall {
+            height: 1.0,
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 0.5 },
+        };
+
+        assert_eq!(map.ground_tiles().count(), 4 * 3 - 2);
+        assert!(map
+            .ground_tiles()
+            .all(|(x, y, _, _)| !map.tiles[(x, y)].tile_type.is_wall()));
+        assert_eq!(
+            map.ground_tiles()
+                .find(|(x, y, _, _)| (*x, *y) == (1, 1))
+                .map(|(_, _, _, liquids)| liquids.get_level::<Water>()),
+            Some(0.5)
+        );
+    }
+
+    #[test]
+    fn raycast_to_surface() {
+        let mut map = Map::new_default(5, 1);
+        map.tiles[(2, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 1.0 },
+        };
+        map.tiles[(4, 0)].tile_type = TileType::Wall;
+
+        let (point, kind) = map
+            .raycast_to_surface(vec3(2.5, 0.5, 5.0), vec3(0.0, 0.0, -1.0))
+            .unwrap();
+        assert_eq!(kind, SurfaceKind::Water);
+        assert_relative_eq!(point.z, 1.0);
+        assert_relative_eq!(point.x, 2.5);
+
+        let (point, kind) = map
+            .raycast_to_surface(vec3(0.5, 0.5, 1.0), vec3(1.0, 0.0, -1.0))
+            .unwrap();
+        assert_eq!(kind, SurfaceKind::Ground);
+        assert_relative_eq!(point.x, 1.5);
+        assert_relative_eq!(point.z, 0.0);
+
+        // Flying over the water into the wall
+        let (point, kind) = map
+            .raycast_to_surface(vec3(0.5, 0.5, 1.5), vec3(1.0, 0.0, 0.0))
+            .unwrap();
+        assert_eq!(kind, SurfaceKind::Wall);
+        assert_relative_eq!(point.x, 4.0);
+
+        // Going up or out of the map doesn't hit anything
+        assert!(map
+            .raycast_to_surface(vec3(0.5, 0.5, 1.0), vec3(0.0, 0.0, 1.0))
+            .is_none());
+        assert!(map
+            .raycast_to_surface(vec3(0.5, 0.5, 1.0), vec3(-1.0, 0.0, 0.0))
+            .is_none());
     }
 
     #[test]
     fn neighbours() {
-        let neighbours = Map::<10, 10>::neighbour_tile_coords(0, 0).collect::<Vec<_>>();
+        let neighbours = NeighbourCoordsIter::new(0, 0, 10, 10).collect::<Vec<_>>();
 
         assert!(neighbours.contains(&(0, 1)));
         assert!(neighbours.contains(&(1, 1)));
         assert!(neighbours.contains(&(1, 0)));
         assert_eq!(neighbours.len(), 3);
 
-        let neighbours = Map::<10, 10>::neighbour_tile_coords(9, 9).collect::<Vec<_>>();
+        let neighbours = NeighbourCoordsIter::new(9, 9, 10, 10).collect::<Vec<_>>();
 
         assert!(neighbours.contains(&(8, 9)));
         assert!(neighbours.contains(&(8, 8)));
         assert!(neighbours.contains(&(9, 8)));
         assert_eq!(neighbours.len(), 3);
 
-        let neighbours = Map::<10, 10>::neighbour_tile_coords(5, 5).collect::<Vec<_>>();
+        let neighbours = NeighbourCoordsIter::new(5, 5, 10, 10).collect::<Vec<_>>();
 
         assert!(neighbours.contains(&(4, 4)));
         assert!(neighbours.contains(&(4, 5)));
@@ -368,83 +1549,49 @@ mod tests {
         assert!(neighbours.contains(&(6, 6)));
         assert_eq!(neighbours.len(), 8);
 
-        let neighbours = Map::<10, 1>::neighbour_tile_coords(1, 0).collect::<Vec<_>>();
+        let neighbours = NeighbourCoordsIter::new(1, 0, 10, 1).collect::<Vec<_>>();
 
         assert!(neighbours.contains(&(0, 0)));
         assert!(neighbours.contains(&(2, 0)));
         assert_eq!(neighbours.len(), 2);
     }
 
-    fn all_tile_coords_gif<const WIDTH: usize, const HEIGHT: usize>(
-    ) -> impl Iterator<Item = (usize, usize)> {
-        (0..HEIGHT)
-            .map(|y| (0..WIDTH).map(move |x| (x, y)))
-            .flatten()
-    }
-
-    struct GifSetup<const WIDTH: usize, const HEIGHT: usize> {
+    struct GifSetup {
         path: PathBuf,
         max_value: f32,
         min_value: f32,
         gradient: colorgrad::Gradient,
-        data_getter: fn(&Map<WIDTH, HEIGHT>) -> [[f32; HEIGHT]; WIDTH],
+        layer: MapLayer,
     }
 
-    fn create_map_gif<const WIDTH: usize, const HEIGHT: usize>(
-        map: &mut Map<WIDTH, HEIGHT>,
+    fn create_map_gif(
+        map: &mut Map,
         total_frames: usize,
         gif_frame_every_nth_frame: usize,
         frame_rate: f32,
         simulation_every_nth_frame: usize,
-        gif_setups: &[GifSetup<WIDTH, HEIGHT>],
+        gif_setups: Vec<GifSetup>,
     ) {
-        let mut encoders = gif_setups
-            .iter()
+        let mut recorders = gif_setups
+            .into_iter()
             .map(|setup| {
-                let image = File::create(&setup.path).unwrap();
-                let mut encoder =
-                    gif::Encoder::new(image, WIDTH as u16, HEIGHT as u16, &[]).unwrap();
-                encoder.set_repeat(gif::Repeat::Infinite).unwrap();
-                encoder
+                MapGifRecorder::new(
+                    File::create(&setup.path).unwrap(),
+                    map.width(),
+                    map.height(),
+                    setup.layer,
+                    setup.gradient,
+                    setup.min_value,
+                    setup.max_value,
+                )
+                .unwrap()
             })
             .collect::<Vec<_>>();
 
         for frame_index in 0..total_frames {
             if frame_index % gif_frame_every_nth_frame == 0 {
-                for (setup, encoder) in gif_setups.iter().zip(encoders.iter_mut()) {
-                    let data = (setup.data_getter)(&map);
-
-                    let mut pixels = vec![128; WIDTH * HEIGHT * 3];
-                    for (i, (x, y)) in all_tile_coords_gif::<WIDTH, HEIGHT>().enumerate() {
-                        if data[x][y].is_nan() {
-                            continue;
-                        }
-
-                        if data[x][y] < setup.min_value {
-                            pixels[i * 3 + 0] = 0;
-                            pixels[i * 3 + 1] = 0;
-                            pixels[i * 3 + 2] = 0;
-                        } else if data[x][y] > setup.max_value {
-                            pixels[i * 3 + 0] = 255;
-                            pixels[i * 3 + 1] = 255;
-                            pixels[i * 3 + 2] = 255;
-                        } else {
-                            let fraction = (data[x][y] - setup.min_value)
-                                / (setup.max_value - setup.min_value);
-                            let [r, g, b, _] = setup.gradient.at(fraction as f64).to_rgba8();
-
-                            pixels[i * 3 + 0] = r;
-                            pixels[i * 3 + 1] = g;
-                            pixels[i * 3 + 2] = b;
-                        }
-                    }
-                    encoder
-                        .write_frame(&gif::Frame::from_rgb(
-                            WIDTH as u16,
-                            HEIGHT as u16,
-                            &mut pixels,
-                        ))
-                        .unwrap();
+                for recorder in recorders.iter_mut() {
+                    recorder.record_frame(map).unwrap();
                 }
             }
 
@@ -458,197 +1605,192 @@ mod tests {
 
     #[test]
     fn simulate() {
-        std::thread::Builder::new()
-            .name("TestThread".into())
-            .stack_size(16 * 1024 * 1024)
-            .spawn(|| {
-                let mut map = Map::<20, 10>::new_default();
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(AirLeveler {
-                        x: 0,
-                        y: 9,
-                        nitrogen: 0.79 / 2.0,
-                        oxygen: 0.21 / 2.0,
-                        fumes: 0.0,
-                    });
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(AirLeveler {
-                        x: 9,
-                        y: 0,
-                        nitrogen: 0.79,
-                        oxygen: 0.21,
-                        fumes: 0.0,
-                    });
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(OxygenUser {
-                        x: 5,
-                        y: 5,
-                        change_per_sec: 0.0001,
-                    });
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(OxygenUser {
-                        x: 18,
-                        y: 2,
-                        change_per_sec: 0.0001,
-                    });
-
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(LiquidLeveler {
-                        x: 19,
-                        y: 0,
-                        target: LiquidData::Water { level: 1.0 },
-                    });
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(LiquidLeveler {
-                        x: 19,
-                        y: 9,
-                        target: LiquidData::Lava { level: 1.1 },
-                    });
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(AirPusher {
-                        x: 18,
-                        y: 4,
-                        direction: Facing::South,
-                        amount: 2.0,
-                    });
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(AirPusher {
-                        x: 16,
-                        y: 8,
-                        direction: Facing::West,
-                        amount: 2.0,
-                    });
-                map.objects_mut()
-                    .push_object::<EnvironmentObject>(AirPusher {
-                        x: 10,
-                        y: 8,
-                        direction: Facing::West,
-                        amount: 2.0,
-                    });
-                map.objects_mut().push_object::<Character>(Character::new(
-                    vec2(0.5, 0.5),
-                    1.0,
-                    vec![WorkGoal::WorkAtVentilation],
-                ));
-                map.objects_mut().push_object::<Building>(Building {
-                    location: uvec2(3, 4),
-                    facing: Facing::East,
-                    building_type: BuildingType::HandCrankedVentilator {
-                        workspots: [
-                            WorkSpot {
-                                location: vec2(0.2, 0.5),
-                                occupation: WorkSpotOccupation::Open,
-                            },
-                            WorkSpot {
-                                location: vec2(0.8, 0.5),
-                                occupation: WorkSpotOccupation::Open,
-                            },
-                        ],
-                    },
-                });
-
-                for (x, y) in map.all_tile_coords().filter(|(x, _)| *x >= 10) {
-                    map.tiles[x][y].ground_level = -1.1;
-                }
+        let mut map = Map::new_default(20, 10);
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(AirLeveler {
+                x: 0,
+                y: 9,
+                nitrogen: 0.79 / 2.0,
+                oxygen: 0.21 / 2.0,
+                fumes: 0.0,
+                gases: GasMask::ALL,
+                reservoir: None,
+                rate: None,
+                enabled: true,
+            });
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(AirLeveler {
+                x: 9,
+                y: 0,
+                nitrogen: 0.79,
+                oxygen: 0.21,
+                fumes: 0.0,
+                gases: GasMask::ALL,
+                reservoir: None,
+                rate: None,
+                enabled: true,
+            });
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(OxygenUser {
+                x: 5,
+                y: 5,
+                change_per_sec: 0.0001,
+                enabled: true,
+            });
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(OxygenUser {
+                x: 18,
+                y: 2,
+                change_per_sec: 0.0001,
+                enabled: true,
+            });
+
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(LiquidLeveler {
+                x: 19,
+                y: 0,
+                target: LiquidData::Water { level: 1.0 },
+                enabled: true,
+                solidify: false,
+            });
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(LiquidLeveler {
+                x: 19,
+                y: 9,
+                target: LiquidData::Lava { level: 1.1 },
+                enabled: true,
+                solidify: false,
+            });
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(AirPusher {
+                x: 18,
+                y: 4,
+                direction: Direction8::South,
+                amount: 2.0,
+                enabled: true,
+            });
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(AirPusher {
+                x: 16,
+                y: 8,
+                direction: Direction8::West,
+                amount: 2.0,
+                enabled: true,
+            });
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(AirPusher {
+                x: 10,
+                y: 8,
+                direction: Direction8::West,
+                amount: 2.0,
+                enabled: true,
+            });
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        map.objects_mut()
+            .push_object::<Building>(ventilator(uvec2(3, 4), Facing::East));
+
+        for (x, y) in map.all_tile_coords().filter(|(x, _)| *x >= 10) {
+            map.tiles[(x, y)].ground_level = -1.1;
+        }
 
-                for i in 1..8 {
-                    map.tiles[1][i] = Tile {
-                        tile_type: TileType::Wall,
-                        ..Default::default()
-                    };
-                }
-                for i in 1..8 {
-                    map.tiles[i][1] = Tile {
-                        tile_type: TileType::Wall,
-                        ..Default::default()
-                    };
-                }
-                for i in 5..8 {
-                    map.tiles[3][i] = Tile {
-                        tile_type: TileType::Wall,
-                        ..Default::default()
-                    };
-                }
-                for i in 3..8 {
-                    map.tiles[i][3] = Tile {
-                        tile_type: TileType::Wall,
-                        ..Default::default()
-                    };
-                }
-                for i in 3..7 {
-                    map.tiles[7][i] = Tile {
-                        tile_type: TileType::Wall,
-                        ..Default::default()
-                    };
-                }
-                for i in 3..6 {
-                    map.tiles[i][7] = Tile {
-                        tile_type: TileType::Wall,
-                        ..Default::default()
-                    };
-                }
+        for i in 1..8 {
+            map.tiles[(1, i)] = Tile {
+                tile_type: TileType::Wall,
+                ..Default::default()
+            };
+        }
+        for i in 1..8 {
+            map.tiles[(i, 1)] = Tile {
+                tile_type: TileType::Wall,
+                ..Default::default()
+            };
+        }
+        for i in 5..8 {
+            map.tiles[(3, i)] = Tile {
+                tile_type: TileType::Wall,
+                ..Default::default()
+            };
+        }
+        for i in 3..8 {
+            map.tiles[(i, 3)] = Tile {
+                tile_type: TileType::Wall,
+                ..Default::default()
+            };
+        }
+        for i in 3..7 {
+            map.tiles[(7, i)] = Tile {
+                tile_type: TileType::Wall,
+                ..Default::default()
+            };
+        }
+        for i in 3..6 {
+            map.tiles[(i, 7)] = Tile {
+                tile_type: TileType::Wall,
+                ..Default::default()
+            };
+        }
 
-                create_map_gif(
-                    &mut map,
-                    1000000,
-                    600,
-                    60.0,
-                    3,
-                    &[
-                        GifSetup {
-                            path: "target/total_air_pressure.gif".into(),
-                            max_value: 1.02,
-                            min_value: 0.00,
-                            gradient: colorgrad::viridis(),
-                            data_getter: |map| map.collect_air_pressure_map(),
-                        },
-                        GifSetup {
-                            path: "target/oxygen.gif".into(),
-                            max_value: 0.21,
-                            min_value: 0.10,
-                            gradient: colorgrad::viridis(),
-                            data_getter: |map| map.collect_oxygen_map(),
-                        },
-                        GifSetup {
-                            path: "target/fumes.gif".into(),
-                            max_value: 0.005,
-                            min_value: 0.00,
-                            gradient: colorgrad::viridis(),
-                            data_getter: |map| map.collect_fumes_map(),
-                        },
-                        GifSetup {
-                            path: "target/water.gif".into(),
-                            max_value: 3.00,
-                            min_value: 0.00,
-                            gradient: colorgrad::viridis(),
-                            data_getter: |map| map.collect_liquids_map::<Water>(),
-                        },
-                        GifSetup {
-                            path: "target/lava.gif".into(),
-                            max_value: 3.00,
-                            min_value: 0.00,
-                            gradient: colorgrad::viridis(),
-                            data_getter: |map| map.collect_liquids_map::<Lava>(),
-                        },
-                        GifSetup {
-                            path: "target/surface.gif".into(),
-                            max_value: 1.00,
-                            min_value: -1.1,
-                            gradient: colorgrad::viridis(),
-                            data_getter: |map| map.collect_surface_level_map(),
-                        },
-                        GifSetup {
-                            path: "target/ground_level.gif".into(),
-                            max_value: 1.00,
-                            min_value: -1.1,
-                            gradient: colorgrad::viridis(),
-                            data_getter: |map| map.collect_ground_level_map(),
-                        },
-                    ],
-                );
-            })
-            .unwrap()
-            .join()
-            .unwrap();
+        create_map_gif(
+            &mut map,
+            1000000,
+            600,
+            60.0,
+            3,
+            vec![
+                GifSetup {
+                    path: "target/total_air_pressure.gif".into(),
+                    max_value: 1.02,
+                    min_value: 0.00,
+                    gradient: colorgrad::viridis(),
+                    layer: MapLayer::AirPressure,
+                },
+                GifSetup {
+                    path: "target/oxygen.gif".into(),
+                    max_value: 0.21,
+                    min_value: 0.10,
+                    gradient: colorgrad::viridis(),
+                    layer: MapLayer::Oxygen,
+                },
+                GifSetup {
+                    path: "target/fumes.gif".into(),
+                    max_value: 0.005,
+                    min_value: 0.00,
+                    gradient: colorgrad::viridis(),
+                    layer: MapLayer::Fumes,
+                },
+                GifSetup {
+                    path: "target/water.gif".into(),
+                    max_value: 3.00,
+                    min_value: 0.00,
+                    gradient: colorgrad::viridis(),
+                    layer: MapLayer::Water,
+                },
+                GifSetup {
+                    path: "target/lava.gif".into(),
+                    max_value: 3.00,
+                    min_value: 0.00,
+                    gradient: colorgrad::viridis(),
+                    layer: MapLayer::Lava,
+                },
+                GifSetup {
+                    path: "target/surface.gif".into(),
+                    max_value: 1.00,
+                    min_value: -1.1,
+                    gradient: colorgrad::viridis(),
+                    layer: MapLayer::SurfaceLevel,
+                },
+                GifSetup {
+                    path: "target/ground_level.gif".into(),
+                    max_value: 1.00,
+                    min_value: -1.1,
+                    gradient: colorgrad::viridis(),
+                    layer: MapLayer::GroundLevel,
+                },
+            ],
+        );
     }
 }
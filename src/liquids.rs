@@ -1,81 +1,269 @@
-use crate::{tiles::Tile, Map};
+use crate::{
+    air::{AirDiff, STEAM_PER_WATER_LEVEL},
+    grid::Grid,
+    objects::environment_object::EnvironmentObject,
+    tiles::Tile,
+    LiquidSolver, Map, NeighbourCoordsIter, SimulationParams,
+};
+use glam::{vec2, Vec2};
+use ordered_float::OrderedFloat;
+use std::{cmp::Reverse, collections::BinaryHeap, ops::AddAssign};
 
-impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
+/// The degrees Celsius a tile heats up for every level of lava that solidifies against water
+pub const SOLIDIFICATION_HEAT_PER_LEVEL: f32 = 200.0;
+
+impl Map {
+    /// Calculate the liquid diff of a tick into the given grid. Its old values are overwritten.
+    ///
+    /// `levels` is scratch space for the levels of the liquid, so it doesn't need to be allocated every tick.
+    /// `bodies` are the bodies of the liquid the [LiquidSolver::Hydraulic] solver found in the last tick.
     pub(crate) fn calculate_liquid_diff<L: Liquid>(
         &self,
         delta_time: f32,
-    ) -> [[f32; HEIGHT]; WIDTH] {
-        let mut liquid_diff_result = [[0.0; HEIGHT]; WIDTH];
+        levels: &mut Grid<f32>,
+        bodies: &mut LiquidBodies,
+        liquid_diff: &mut Grid<LiquidDiff>,
+    ) {
+        levels.fill(0.0);
+        for (x, y, _, liquids) in self.ground_tiles() {
+            levels[(x, y)] = liquids.get_level::<L>();
+        }
 
-        for (x, y) in self.all_tile_coords() {
-            let Some(liquids) = self.tiles[x][y].tile_type.get_liquids() else {
-                continue;
-            };
-            let ground_level = self.tiles[x][y].ground_level;
-            let liquid_level = liquids.get_level::<L>();
-            let total_level = ground_level + liquid_level;
+        match self.simulation_params.liquid_solver {
+            // The levels are never touched and all flows are collected in the diff,
+            // so the chunks don't depend on each other
+            LiquidSolver::Jacobi => {
+                let levels = &*levels;
+                liquid_diff.accumulate_into(!self.deterministic, |rect, liquid_diff_result| {
+                    for (x, y) in rect.coords() {
+                        if !self.liquid_can_spread::<L>(x, y, levels) {
+                            continue;
+                        }
 
-            if liquid_level < L::MINIMAL_HEIGHT_TO_SPREAD {
-                continue;
+                        for (nx, ny, neighbour_floor_level) in self.liquid_spread_targets(x, y) {
+                            let Some((applied_height_delta, _)) = self.liquid_spread::<L>(
+                                (x, y),
+                                (nx, ny, neighbour_floor_level),
+                                levels,
+                                delta_time,
+                            ) else {
+                                continue;
+                            };
+
+                            liquid_diff_result[(nx, ny)].level += applied_height_delta;
+                            liquid_diff_result[(x, y)].level -= applied_height_delta;
+                            liquid_diff_result[(x, y)].outflow +=
+                                spread_direction((x, y), (nx, ny)) * applied_height_delta;
+                        }
+                    }
+                })
             }
+            // The levels are updated during the scan, so later tiles see the flows of earlier tiles
+            LiquidSolver::GaussSeidel => {
+                liquid_diff.fill(LiquidDiff::default());
+                for (x, y) in self.all_tile_coords() {
+                    if !self.liquid_can_spread::<L>(x, y, levels) {
+                        continue;
+                    }
 
-            let neighbour_liquids = self
-                // Get all neighbours
-                .neighbour_tiles(x, y)
-                // Get only the ones that are ground
-                .filter_map(|(x, y, tile)| {
-                    tile.tile_type
-                        .get_liquids()
-                        .map(|liquids| (x, y, tile.ground_level, liquids.get_level::<L>()))
-                });
+                    for (nx, ny, neighbour_floor_level) in self.liquid_spread_targets(x, y) {
+                        let Some((applied_height_delta, height_delta)) = self.liquid_spread::<L>(
+                            (x, y),
+                            (nx, ny, neighbour_floor_level),
+                            levels,
+                            delta_time,
+                        ) else {
+                            continue;
+                        };
 
-            for (nx, ny, neighbour_ground_level, neighbour_liquid_level) in neighbour_liquids {
-                let neighbour_total_level = neighbour_ground_level + neighbour_liquid_level;
-                if neighbour_total_level >= total_level
-                    || neighbour_liquid_level >= Tile::TUNNEL_HEIGHT
-                {
-                    continue;
+                        // The levels are up to date, so we can stop exactly where both levels are equal
+                        // instead of overshooting and sloshing back next tick
+                        let applied_height_delta = applied_height_delta
+                            .min(levels[(x, y)])
+                            .min(height_delta / 2.0);
+                        levels[(nx, ny)] += applied_height_delta;
+                        levels[(x, y)] -= applied_height_delta;
+                        liquid_diff[(x, y)].outflow +=
+                            spread_direction((x, y), (nx, ny)) * applied_height_delta;
+                    }
                 }
 
-                let height_delta = total_level - neighbour_total_level;
-                let applied_height_delta =
-                    ((height_delta * L::SPREAD_RATE).sqrt() * delta_time).min(liquid_level / 0.8);
+                // The tiles still have the original levels
+                for (x, y, _, liquids) in self.ground_tiles() {
+                    liquid_diff[(x, y)].level = levels[(x, y)] - liquids.get_level::<L>();
+                }
+            }
+            LiquidSolver::Hydraulic => {
+                liquid_diff.fill(LiquidDiff::default());
+                self.calculate_hydraulic_diff::<L>(delta_time, levels, bodies, liquid_diff);
+            }
+        }
+    }
 
-                liquid_diff_result[nx][ny] += applied_height_delta;
-                liquid_diff_result[x][y] -= applied_height_delta;
+    /// Move every connected body of liquid towards the surface level at which it would hold the same volume.
+    ///
+    /// A body is made of the tiles with enough liquid to spread that are connected through each other,
+    /// together with the tiles around them it could flow into. See [LiquidBodies] for how they're kept up to date.
+    fn calculate_hydraulic_diff<L: Liquid>(
+        &self,
+        delta_time: f32,
+        levels: &Grid<f32>,
+        bodies: &mut LiquidBodies,
+        liquid_diff: &mut Grid<LiquidDiff>,
+    ) {
+        let fraction = (L::spread_rate(&self.simulation_params).sqrt() * delta_time).min(1.0);
+
+        bodies.update::<L>(self, levels);
+
+        for body in bodies.bodies.iter() {
+            let volume = body.iter().map(|tile| levels[*tile]).sum::<f32>();
+            let surface_level = common_surface_level(
+                body.iter()
+                    .map(|tile| self.tiles[*tile].liquid_floor_level()),
+                volume,
+            );
+
+            for tile in body.iter().copied() {
+                let target_level = (surface_level - self.tiles[tile].liquid_floor_level())
+                    .clamp(0.0, LiquidData::MAX_LEVEL);
+                liquid_diff[tile].level += (target_level - levels[tile]) * fraction;
             }
         }
 
-        liquid_diff_result
+        // The liquid a tile loses flows out towards the neighbours that gain liquid.
+        // This is done after all levels are known, because the neighbours can be part of another body.
+        for tile in bodies.bodies.iter().flatten().copied() {
+            let lost_level = -liquid_diff[tile].level;
+            if lost_level <= 0.0 {
+                continue;
+            }
+
+            let direction = self
+                .liquid_spread_targets(tile.0, tile.1)
+                .map(|(nx, ny, _)| {
+                    spread_direction(tile, (nx, ny)) * liquid_diff[(nx, ny)].level.max(0.0)
+                })
+                .sum::<Vec2>();
+            liquid_diff[tile].outflow = direction.normalize_or_zero() * lost_level;
+        }
+    }
+
+    fn liquid_can_spread<L: Liquid>(&self, x: usize, y: usize, levels: &Grid<f32>) -> bool {
+        let tile_type = &self.tiles[(x, y)].tile_type;
+        !self.settled[(x, y)]
+            && levels[(x, y)] >= L::MINIMAL_HEIGHT_TO_SPREAD
+            && tile_type.get_ground().is_some()
+            && !tile_type.blocks_flow()
+    }
+
+    /// The neighbours the liquid of a tile can spread to, with their liquid floor level
+    fn liquid_spread_targets(
+        &self,
+        x: usize,
+        y: usize,
+    ) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+        self
+            // Get all neighbours
+            .neighbour_tiles(x, y)
+            // Get only the ones that are ground, not settled and not behind a closed door
+            .filter(|(x, y, tile)| !self.settled[(*x, *y)] && !tile.tile_type.blocks_flow())
+            .filter_map(|(x, y, tile)| {
+                tile.tile_type
+                    .get_liquids()
+                    .map(|_| (x, y, tile.liquid_floor_level()))
+            })
+    }
+
+    /// The amount of liquid that flows from the tile to a lower neighbour, together with the height difference
+    /// of their surfaces. None if the neighbour isn't lower or is already full.
+    fn liquid_spread<L: Liquid>(
+        &self,
+        (x, y): (usize, usize),
+        (nx, ny, neighbour_floor_level): (usize, usize, f32),
+        levels: &Grid<f32>,
+        delta_time: f32,
+    ) -> Option<(f32, f32)> {
+        let liquid_level = levels[(x, y)];
+        let total_level = self.tiles[(x, y)].liquid_floor_level() + liquid_level;
+        let neighbour_liquid_level = levels[(nx, ny)];
+        let neighbour_total_level = neighbour_floor_level + neighbour_liquid_level;
+        if neighbour_total_level >= total_level || neighbour_liquid_level >= LiquidData::MAX_LEVEL {
+            return None;
+        }
+
+        let height_delta = total_level - neighbour_total_level;
+        let applied_height_delta =
+            ((height_delta * L::spread_rate(&self.simulation_params)).sqrt() * delta_time)
+                .min(liquid_level / 0.8);
+
+        Some((applied_height_delta, height_delta))
     }
 
+    /// Apply the liquid diffs to the tiles and keep the [Self::liquid_flow] of the tick
     pub(crate) fn apply_liquid_diff(
         &mut self,
-        water_diff: [[f32; HEIGHT]; WIDTH],
-        lava_diff: [[f32; HEIGHT]; WIDTH],
+        water_diff: &Grid<LiquidDiff>,
+        lava_diff: &Grid<LiquidDiff>,
+        air_diff: &Grid<AirDiff>,
+        delta_time: f32,
     ) {
         for (x, y) in self.all_tile_coords() {
-            let Some(liquids) = self.tiles[x][y].tile_type.get_liquids_mut() else {
-                    continue;
-                };
+            let tile = &mut self.tiles[(x, y)];
+            let Some((air, liquids)) = tile.tile_type.get_ground_mut() else {
+                self.liquid_flow[(x, y)] = Vec2::ZERO;
+                continue;
+            };
 
-            let new_water_level = (liquids.get_level::<Water>() + water_diff[x][y]).max(0.0);
-            let new_lava_level = (liquids.get_level::<Lava>() + lava_diff[x][y]).max(0.0);
+            let old_liquids = *liquids;
+            let old_ground_level = tile.ground_level;
 
-            *liquids = if new_water_level == 0.0 && new_lava_level == 0.0 {
-                LiquidData::None
+            // The outflow is a level that moved one tile, so spread over the height of the liquid it's a speed
+            let level = old_liquids.get_level::<AnyLiquid>();
+            let outflow = water_diff[(x, y)].outflow + lava_diff[(x, y)].outflow;
+            self.liquid_flow[(x, y)] = if level > 0.0 && delta_time > 0.0 {
+                outflow / (level * delta_time)
             } else {
-                let difference = new_water_level - new_lava_level;
+                Vec2::ZERO
+            };
 
-                if new_water_level > 0.0 && new_lava_level > 0.0 {
-                    self.tiles[x][y].ground_level += difference.abs();
-                }
+            // The air diff already holds the steam of the water that evaporated or condensed
+            let evaporated_level = air_diff[(x, y)].evaporated / STEAM_PER_WATER_LEVEL;
+            let new_water_level = (liquids.get_level::<Water>() + water_diff[(x, y)].level
+                - evaporated_level)
+                .max(0.0);
+            let new_lava_level = (liquids.get_level::<Lava>() + lava_diff[(x, y)].level).max(0.0);
+
+            // Where water and lava meet, the lava cools down into stone that raises the ground
+            // and the same amount of water boils off as steam
+            let reacted_level = new_water_level.min(new_lava_level);
+            let new_water_level = new_water_level - reacted_level;
+            let new_lava_level = new_lava_level - reacted_level;
 
-                if difference >= 0.0 {
-                    LiquidData::Water { level: difference }
-                } else {
-                    LiquidData::Lava { level: -difference }
+            *liquids = if new_water_level > 0.0 {
+                LiquidData::Water {
+                    level: new_water_level,
                 }
+            } else if new_lava_level > 0.0 {
+                LiquidData::Lava {
+                    level: new_lava_level,
+                }
+            } else {
+                LiquidData::None
+            };
+
+            if let Some(event) = liquids.clamp_to_max_level(x, y) {
+                self.liquid_events.push(event);
+            }
+
+            if reacted_level > 0.0 {
+                air.steam += reacted_level * STEAM_PER_WATER_LEVEL;
+                tile.ground_level += reacted_level;
+                tile.temperature += reacted_level * SOLIDIFICATION_HEAT_PER_LEVEL;
+                self.liquid_events.push(LiquidEvent::Solidified { x, y });
+            }
+
+            if *liquids != old_liquids || tile.ground_level != old_ground_level {
+                self.render_dirty[(x, y)] = true;
             }
         }
 
@@ -85,17 +273,431 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
             .unwrap()
             .get_all_objects()
             .flat_map(|object| object.liquid_levelers())
+            .filter(|liquid_leveler| liquid_leveler.enabled)
         {
-            let Some(liquids) = self.tiles[liquid_leveler.x][liquid_leveler.y].tile_type.get_liquids_mut() else {
+            let tile = &mut self.tiles[(liquid_leveler.x, liquid_leveler.y)];
+            let Some(liquids) = tile.tile_type.get_liquids_mut() else {
                 continue;
             };
 
+            let old_liquids = *liquids;
+
+            if liquid_leveler.solidify && matches!(liquid_leveler.target, LiquidData::None) {
+                tile.ground_level += liquids.get_level::<Lava>();
+            }
+
             *liquids = liquid_leveler.target;
+
+            if let Some(event) = liquids.clamp_to_max_level(liquid_leveler.x, liquid_leveler.y) {
+                self.liquid_events.push(event);
+            }
+
+            if *liquids != old_liquids {
+                self.render_dirty[(liquid_leveler.x, liquid_leveler.y)] = true;
+            }
         }
     }
+
+    /// Move liquid between two tiles so their total levels get closer. The tiles don't need to be neighbours.
+    ///
+    /// `rate` is the fraction of the level difference that is equalized per second.
+    /// Liquid only flows into a tile that is empty or holds the same liquid, and never above [LiquidData::MAX_LEVEL].
+    /// No liquid is lost or created.
+    pub fn equalize_liquid<L: Liquid>(
+        &mut self,
+        a: (usize, usize),
+        b: (usize, usize),
+        rate: f32,
+        delta_time: f32,
+    ) {
+        let (Some(liquids_a), Some(liquids_b)) = (
+            self.tiles[(a.0, a.1)].tile_type.get_liquids(),
+            self.tiles[(b.0, b.1)].tile_type.get_liquids(),
+        ) else {
+            return;
+        };
+
+        let total_level_a =
+            self.tiles[(a.0, a.1)].liquid_floor_level() + liquids_a.get_level::<L>();
+        let total_level_b =
+            self.tiles[(b.0, b.1)].liquid_floor_level() + liquids_b.get_level::<L>();
+
+        let ((high, high_liquids), (low, low_liquids)) = if total_level_a >= total_level_b {
+            ((a, *liquids_a), (b, *liquids_b))
+        } else {
+            ((b, *liquids_b), (a, *liquids_a))
+        };
+
+        let (Some(kind), Some(high_level)) = (high_liquids.kind(), L::get_level(&high_liquids))
+        else {
+            return;
+        };
+        if low_liquids.kind().is_some_and(|low_kind| low_kind != kind) {
+            return;
+        }
+        let low_level = low_liquids.get_level::<AnyLiquid>();
+
+        let moved = ((total_level_a - total_level_b).abs() / 2.0
+            * (rate * delta_time).clamp(0.0, 1.0))
+        .min(high_level)
+        .min((LiquidData::MAX_LEVEL - low_level).max(0.0));
+
+        if moved <= 0.0 {
+            return;
+        }
+
+        *self.tiles[(high.0, high.1)]
+            .tile_type
+            .get_liquids_mut()
+            .unwrap() = LiquidData::new(kind, high_level - moved);
+        *self.tiles[(low.0, low.1)]
+            .tile_type
+            .get_liquids_mut()
+            .unwrap() = LiquidData::new(kind, low_level + moved);
+
+        self.render_dirty[(a.0, a.1)] = true;
+        self.render_dirty[(b.0, b.1)] = true;
+    }
+
+    /// Predict which tiles would be flooded when the given volume of water is added at the source tile.
+    ///
+    /// The water first runs downhill from the source until it reaches a pit.
+    /// From there it fills up the lowest reachable tile first, like a lake that rises until it runs out of water.
+    /// This only looks at the terrain, so the flow speed, existing liquids and the tile cap are ignored.
+    /// Closed doors hold the water back like walls.
+    /// The tiles are returned in the order they are flooded. A source tile that is a wall floods nothing.
+    pub fn predict_flood(&self, source: (usize, usize), volume: f32) -> Vec<(usize, usize)> {
+        if self.tiles[(source.0, source.1)].tile_type.blocks_flow() {
+            return Vec::new();
+        }
+
+        let mut source = source;
+        while let Some((x, y, lowest_neighbour)) = self
+            .neighbour_tiles(source.0, source.1)
+            .filter(|(_, _, tile)| !tile.tile_type.blocks_flow())
+            .min_by(|(_, _, a), (_, _, b)| {
+                a.liquid_floor_level().total_cmp(&b.liquid_floor_level())
+            })
+        {
+            if lowest_neighbour.liquid_floor_level()
+                >= self.tiles[(source.0, source.1)].liquid_floor_level()
+            {
+                break;
+            }
+            source = (x, y);
+        }
+
+        let mut visited = Grid::new(self.width(), self.height(), false);
+        let mut candidates = BinaryHeap::new();
+        let mut flooded = Vec::new();
+
+        let mut level = self.tiles[(source.0, source.1)].liquid_floor_level();
+        let mut remaining_volume = volume;
+
+        let mut flood = |(x, y): (usize, usize),
+                         flooded: &mut Vec<(usize, usize)>,
+                         candidates: &mut BinaryHeap<_>| {
+            visited[(x, y)] = true;
+            flooded.push((x, y));
+
+            for (nx, ny, neighbour) in self.neighbour_tiles(x, y) {
+                if !visited[(nx, ny)] && !neighbour.tile_type.blocks_flow() {
+                    visited[(nx, ny)] = true;
+                    candidates.push((
+                        Reverse(OrderedFloat(neighbour.liquid_floor_level())),
+                        nx,
+                        ny,
+                    ));
+                }
+            }
+        };
+
+        flood(source, &mut flooded, &mut candidates);
+
+        while let Some((Reverse(OrderedFloat(floor_level)), x, y)) = candidates.pop() {
+            let needed_volume = if floor_level > level {
+                // The whole lake has to rise before it reaches this tile
+                (floor_level - level) * flooded.len() as f32
+            } else {
+                // The tile is below the surface, so it takes in water until it's at the lake level
+                level - floor_level
+            };
+
+            if floor_level > level && needed_volume >= remaining_volume {
+                break;
+            }
+
+            level = level.max(floor_level);
+            flood((x, y), &mut flooded, &mut candidates);
+
+            if needed_volume >= remaining_volume {
+                break;
+            }
+            remaining_volume -= needed_volume;
+        }
+
+        flooded
+    }
+
+    /// Predict the liquid levels the map would settle at when the given leveler is placed.
+    ///
+    /// The simulation runs on a scratch copy of the tiles, so the map itself isn't changed.
+    /// Only the tiles and the given leveler are taken into account, the other objects of the map are ignored.
+    /// The spreading runs with [SimulationParams::fast_settle], so the result is an approximation.
+    pub fn preview_leveler_steady_state(&self, leveler: LiquidLeveler<usize>) -> Grid<f32> {
+        const DELTA_TIME: f32 = 0.1;
+        const TOLERANCE: f32 = 0.0001;
+        const MAX_TICKS: usize = 10_000;
+
+        let mut scratch = Self::new_default(self.width(), self.height());
+        scratch.tiles = self.tiles.clone();
+        scratch.set_simulation_params(SimulationParams::fast_settle());
+        scratch
+            .objects_mut()
+            .push_object::<EnvironmentObject>(leveler);
+
+        // If it doesn't settle in time, the state we've reached is still the best guess we have
+        scratch.settle(DELTA_TIME, TOLERANCE, MAX_TICKS);
+
+        let mut levels = Grid::new(self.width(), self.height(), 0.0);
+        for (x, y, _, liquids) in scratch.ground_tiles() {
+            levels[(x, y)] = liquids.get_level::<AnyLiquid>();
+        }
+        levels
+    }
+
+    /// Get the info a renderer needs to draw the liquid of a tile.
+    ///
+    /// Returns None if the tile has no liquid.
+    pub fn liquid_render_info(&self, x: usize, y: usize) -> Option<LiquidRender> {
+        let tile = &self.tiles[(x, y)];
+        let liquids = tile.tile_type.get_liquids()?;
+        let kind = liquids.kind()?;
+        let level = liquids.get_level::<AnyLiquid>();
+
+        Some(LiquidRender {
+            surface_height: tile.surface_level(),
+            fill_fraction: (level / LiquidData::MAX_LEVEL).clamp(0.0, 1.0),
+            kind,
+        })
+    }
+
+    /// The direction and speed the liquid of the tile flowed in during the last simulation tick, in tiles per second.
+    ///
+    /// Liquid flows from a tile to its lower neighbours, so this points downhill.
+    /// Zero for tiles without liquid and for tiles outside of the map.
+    pub fn liquid_flow(&self, x: usize, y: usize) -> Vec2 {
+        self.liquid_flow.get(x, y).copied().unwrap_or_default()
+    }
+
+    /// Drain the liquid events that happened since the last time they were drained
+    pub fn drain_liquid_events(&mut self) -> std::vec::Drain<'_, LiquidEvent> {
+        self.liquid_events.drain(..)
+    }
+}
+
+/// What the flows of a tick do to the liquid of a tile
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct LiquidDiff {
+    /// How much the level changes
+    pub level: f32,
+    /// The levels that flow out of the tile, each in the direction of the neighbour it flows to
+    pub outflow: Vec2,
+}
+
+impl AddAssign for LiquidDiff {
+    fn add_assign(&mut self, other: Self) {
+        self.level += other.level;
+        self.outflow += other.outflow;
+    }
+}
+
+/// The bodies of a liquid, kept between ticks so only the bodies around the tiles that changed are searched again.
+///
+/// Which bodies there are only depends on which tiles the liquid can spread from and to, see [Self::update].
+/// Every tile is part of one body at most. A tile the liquid can flow into from two bodies
+/// is part of the body of its first neighbour the liquid can spread from.
+pub(crate) struct LiquidBodies {
+    /// The [Self::CAN_SPREAD] and [Self::CAN_BE_SPREAD_INTO] flags of every tile in the last update
+    states: Grid<u8>,
+    /// The index of the body every tile is part of
+    body_of: Grid<Option<usize>>,
+    /// The sorted tiles of every body. The bodies that were removed are empty and reused.
+    bodies: Vec<Vec<(usize, usize)>>,
+    /// The indices of the removed bodies
+    free: Vec<usize>,
+    /// Scratch space for the tiles that have to be searched again
+    to_search: Vec<(usize, usize)>,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl LiquidBodies {
+    /// The liquid of the tile can spread to its neighbours
+    const CAN_SPREAD: u8 = 1;
+    /// Liquid of a neighbour can spread into the tile
+    const CAN_BE_SPREAD_INTO: u8 = 2;
+
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            states: Grid::new(width, height, 0),
+            body_of: Grid::new(width, height, None),
+            bodies: Vec::new(),
+            free: Vec::new(),
+            to_search: Vec::new(),
+        }
+    }
+
+    /// Bring the bodies up to date with the map.
+    ///
+    /// A body only changes when the flags of one of its tiles or their neighbours change.
+    /// Those bodies are removed and searched again, together with the tiles that just got their flags.
+    fn update<L: Liquid>(&mut self, map: &Map, levels: &Grid<f32>) {
+        let mut to_search = std::mem::take(&mut self.to_search);
+        to_search.clear();
+
+        for (x, y) in map.all_tile_coords() {
+            let tile_type = &map.tiles[(x, y)].tile_type;
+            let can_be_spread_into = !map.settled[(x, y)]
+                && !tile_type.blocks_flow()
+                && tile_type.get_liquids().is_some();
+            let state = if map.liquid_can_spread::<L>(x, y, levels) {
+                Self::CAN_SPREAD | Self::CAN_BE_SPREAD_INTO
+            } else if can_be_spread_into {
+                Self::CAN_BE_SPREAD_INTO
+            } else {
+                0
+            };
+            if self.states[(x, y)] == state {
+                continue;
+            }
+            self.states[(x, y)] = state;
+
+            to_search.push((x, y));
+            for (nx, ny) in std::iter::once((x, y)).chain(map.neighbour_tile_coords(x, y)) {
+                if let Some(body) = self.body_of[(nx, ny)] {
+                    self.remove_body(body, &mut to_search);
+                }
+            }
+        }
+
+        for (x, y) in to_search.iter().copied() {
+            if self.body_of[(x, y)].is_none() && self.states[(x, y)] & Self::CAN_SPREAD != 0 {
+                self.search_body(x, y);
+            }
+        }
+
+        self.to_search = to_search;
+    }
+
+    fn neighbour_tile_coords(&self, x: usize, y: usize) -> NeighbourCoordsIter {
+        NeighbourCoordsIter::new(x, y, self.states.width(), self.states.height())
+    }
+
+    fn remove_body(&mut self, body: usize, to_search: &mut Vec<(usize, usize)>) {
+        for tile in self.bodies[body].drain(..) {
+            self.body_of[tile] = None;
+            to_search.push(tile);
+        }
+        self.free.push(body);
+    }
+
+    /// Add the body the tile, which the liquid can spread from, is part of
+    fn search_body(&mut self, x: usize, y: usize) {
+        let body = self.free.pop().unwrap_or_else(|| {
+            self.bodies.push(Vec::new());
+            self.bodies.len() - 1
+        });
+        let mut tiles = std::mem::take(&mut self.bodies[body]);
+
+        // First all tiles the liquid can spread from that are connected through each other
+        self.body_of[(x, y)] = Some(body);
+        let mut to_visit = vec![(x, y)];
+        while let Some((x, y)) = to_visit.pop() {
+            tiles.push((x, y));
+            for (nx, ny) in self.neighbour_tile_coords(x, y) {
+                if self.states[(nx, ny)] & Self::CAN_SPREAD != 0 && self.body_of[(nx, ny)].is_none()
+                {
+                    self.body_of[(nx, ny)] = Some(body);
+                    to_visit.push((nx, ny));
+                }
+            }
+        }
+
+        // Then the tiles around them the liquid can only flow into.
+        // They belong to the body of their first neighbour the liquid can spread from,
+        // so it doesn't matter in which order the bodies are searched.
+        for index in 0..tiles.len() {
+            let (x, y) = tiles[index];
+            for (nx, ny) in self.neighbour_tile_coords(x, y) {
+                if self.states[(nx, ny)] != Self::CAN_BE_SPREAD_INTO
+                    || self.body_of[(nx, ny)].is_some()
+                {
+                    continue;
+                }
+
+                let owner = self
+                    .neighbour_tile_coords(nx, ny)
+                    .find(|neighbour| self.states[*neighbour] & Self::CAN_SPREAD != 0);
+                if owner.and_then(|owner| self.body_of[owner]) == Some(body) {
+                    self.body_of[(nx, ny)] = Some(body);
+                    tiles.push((nx, ny));
+                }
+            }
+        }
+
+        // The volume of a body is summed in this order, so it must not depend on where the search started
+        tiles.sort_unstable();
+        self.bodies[body] = tiles;
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.states.memory_usage()
+            + self.body_of.memory_usage()
+            + self
+                .bodies
+                .iter()
+                .map(|body| body.capacity() * std::mem::size_of::<(usize, usize)>())
+                .sum::<usize>()
+            + self.bodies.capacity() * std::mem::size_of::<Vec<(usize, usize)>>()
+            + self.free.capacity() * std::mem::size_of::<usize>()
+            + self.to_search.capacity() * std::mem::size_of::<(usize, usize)>()
+    }
+}
+
+/// The surface level at which tiles with the given liquid floor levels together hold the volume.
+///
+/// Every tile holds the liquid between its floor and the surface, up to [LiquidData::MAX_LEVEL].
+/// When the tiles can't hold the volume, the level at which all of them are full is returned.
+fn common_surface_level(floor_levels: impl Iterator<Item = f32>, volume: f32) -> f32 {
+    // A tile starts filling up at its floor and stops when it's full
+    let mut changes = floor_levels
+        .flat_map(|floor_level| [(floor_level, 1), (floor_level + LiquidData::MAX_LEVEL, -1)])
+        .collect::<Vec<(f32, i32)>>();
+    changes.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut surface_level = changes.first().map_or(0.0, |change| change.0);
+    let mut held = 0.0;
+    let mut filling = 0;
+    for (level, change) in changes {
+        let next_held = held + filling as f32 * (level - surface_level);
+        if filling > 0 && next_held >= volume {
+            return surface_level + (volume - held) / filling as f32;
+        }
+
+        held = next_held;
+        surface_level = level;
+        filling += change;
+    }
+
+    surface_level
+}
+
+/// The direction liquid spreads in from a tile to its neighbour
+fn spread_direction((x, y): (usize, usize), (nx, ny): (usize, usize)) -> Vec2 {
+    vec2(nx as f32 - x as f32, ny as f32 - y as f32).normalize()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LiquidData {
     None,
     Water { level: f32 },
@@ -103,15 +705,59 @@ pub enum LiquidData {
 }
 
 impl LiquidData {
+    /// The maximum level of liquid a tile can hold
+    pub const MAX_LEVEL: f32 = Tile::TUNNEL_HEIGHT;
+
     pub const fn new_default() -> Self {
         Self::None
     }
 
-    pub(crate) fn get_level<L: Liquid>(&self) -> f32 {
+    /// Create liquid data of the given kind. A level of 0 or lower is no liquid.
+    pub fn new(kind: LiquidKind, level: f32) -> Self {
+        if level <= 0.0 {
+            return LiquidData::None;
+        }
+
+        match kind {
+            LiquidKind::Water => LiquidData::Water { level },
+            LiquidKind::Lava => LiquidData::Lava { level },
+        }
+    }
+
+    /// The kind of liquid, if there is any
+    pub fn kind(&self) -> Option<LiquidKind> {
+        match self {
+            LiquidData::None => None,
+            LiquidData::Water { .. } => Some(LiquidKind::Water),
+            LiquidData::Lava { .. } => Some(LiquidKind::Lava),
+        }
+    }
+
+    /// Clamp the level to the [Self::MAX_LEVEL].
+    /// If the level had to be clamped, the tile overflowed and the corresponding event is returned.
+    fn clamp_to_max_level(&mut self, x: usize, y: usize) -> Option<LiquidEvent> {
+        let (LiquidData::Water { level } | LiquidData::Lava { level }) = self else {
+            return None;
+        };
+
+        if *level <= Self::MAX_LEVEL {
+            return None;
+        }
+
+        *level = Self::MAX_LEVEL;
+
+        Some(LiquidEvent::Overflow {
+            x,
+            y,
+            kind: self.kind()?,
+        })
+    }
+
+    pub fn get_level<L: Liquid>(&self) -> f32 {
         self.get_level_optional::<L>().unwrap_or_default()
     }
 
-    pub(crate) fn get_level_optional<L: Liquid>(&self) -> Option<f32> {
+    pub fn get_level_optional<L: Liquid>(&self) -> Option<f32> {
         L::get_level(self)
     }
 }
@@ -122,18 +768,53 @@ impl Default for LiquidData {
     }
 }
 
-pub(crate) trait Liquid {
-    const SPREAD_RATE: f32;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiquidKind {
+    Water,
+    Lava,
+}
+
+/// How the liquid of a tile should be drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LiquidRender {
+    /// The absolute height of the top of the liquid
+    pub surface_height: f32,
+    /// How much of the tile is filled, from 0 to 1
+    pub fill_fraction: f32,
+    pub kind: LiquidKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiquidEvent {
+    /// The tile received more liquid than it could hold and was capped at [LiquidData::MAX_LEVEL]
+    Overflow {
+        x: usize,
+        y: usize,
+        kind: LiquidKind,
+    },
+    /// Lava met water in the tile. The lava turned into stone, raising the ground,
+    /// and the water boiled off into the air as steam.
+    Solidified { x: usize, y: usize },
+}
+
+/// A kind of liquid that can be simulated
+pub trait Liquid {
     const MINIMAL_HEIGHT_TO_SPREAD: f32;
 
+    fn spread_rate(params: &SimulationParams) -> f32;
+
     fn get_level(data: &LiquidData) -> Option<f32>;
 }
 
-pub(crate) struct AnyLiquid;
+/// Any of the liquids
+pub struct AnyLiquid;
 impl Liquid for AnyLiquid {
-    const SPREAD_RATE: f32 = 0.0;
     const MINIMAL_HEIGHT_TO_SPREAD: f32 = 0.0;
 
+    fn spread_rate(_params: &SimulationParams) -> f32 {
+        0.0
+    }
+
     fn get_level(data: &LiquidData) -> Option<f32> {
         match data {
             LiquidData::None => None,
@@ -143,11 +824,14 @@ impl Liquid for AnyLiquid {
     }
 }
 
-pub(crate) struct Water;
+pub struct Water;
 impl Liquid for Water {
-    const SPREAD_RATE: f32 = 0.01;
     const MINIMAL_HEIGHT_TO_SPREAD: f32 = 0.01;
 
+    fn spread_rate(params: &SimulationParams) -> f32 {
+        params.water_spread_rate
+    }
+
     fn get_level(data: &LiquidData) -> Option<f32> {
         match data {
             LiquidData::Water { level } => Some(*level),
@@ -156,11 +840,14 @@ impl Liquid for Water {
     }
 }
 
-pub(crate) struct Lava;
+pub struct Lava;
 impl Liquid for Lava {
-    const SPREAD_RATE: f32 = 0.001;
     const MINIMAL_HEIGHT_TO_SPREAD: f32 = 0.1;
 
+    fn spread_rate(params: &SimulationParams) -> f32 {
+        params.lava_spread_rate
+    }
+
     fn get_level(data: &LiquidData) -> Option<f32> {
         match data {
             LiquidData::Lava { level } => Some(*level),
@@ -170,10 +857,16 @@ impl Liquid for Lava {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LiquidLeveler<COORD> {
     pub x: COORD,
     pub y: COORD,
     pub target: LiquidData,
+    /// A disabled leveler doesn't do anything
+    pub enabled: bool,
+    /// When the target is [LiquidData::None], any lava that is removed solidifies into rock
+    /// and raises the ground level instead of disappearing
+    pub solidify: bool,
 }
 
 impl LiquidLeveler<isize> {
@@ -182,6 +875,471 @@ impl LiquidLeveler<isize> {
             x: base_x.wrapping_add_signed(self.x),
             y: base_y.wrapping_add_signed(self.y),
             target: self.target,
+            enabled: self.enabled,
+            solidify: self.solidify,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiles::TileType;
+    use approx::assert_relative_eq;
+
+    fn low_wall_map(water_level: f32) -> Map {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: water_level },
+        };
+        map.tiles[(1, 0)].tile_type = TileType::LowWall {
+            height: 1.0,
+            air: Default::default(),
+            liquids: LiquidData::None,
+        };
+        map
+    }
+
+    #[test]
+    fn low_wall_contains_liquid_below_height() {
+        let mut map = low_wall_map(0.5);
+
+        for _ in 0..100 {
+            map.perform_simulation_tick(1.0);
+        }
+
+        let water_level = |x: usize| {
+            map.tiles[(x, 0)]
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<Water>()
+        };
+
+        assert_eq!(water_level(0), 0.5);
+        assert_eq!(water_level(1), 0.0);
+        assert_eq!(water_level(2), 0.0);
+    }
+
+    #[test]
+    fn low_wall_overflows_above_height() {
+        let mut map = low_wall_map(2.0);
+
+        for _ in 0..100 {
+            map.perform_simulation_tick(1.0);
+        }
+
+        let water_level = |x: usize| {
+            map.tiles[(x, 0)]
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<Water>()
+        };
+
+        assert!(water_level(0) < 2.0);
+        assert!(water_level(2) > 0.0);
+    }
+
+    #[test]
+    fn overflow_events() {
+        let mut map = Map::new_default(2, 1);
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(LiquidLeveler {
+                x: 0,
+                y: 0,
+                target: LiquidData::Water {
+                    level: LiquidData::MAX_LEVEL + 1.0,
+                },
+                enabled: true,
+                solidify: false,
+            });
+
+        for _ in 0..3 {
+            map.perform_simulation_tick(0.1);
+
+            assert_eq!(
+                map.drain_liquid_events().collect::<Vec<_>>(),
+                vec![LiquidEvent::Overflow {
+                    x: 0,
+                    y: 0,
+                    kind: LiquidKind::Water
+                }]
+            );
+            assert_eq!(
+                map.tiles[(0, 0)]
+                    .tile_type
+                    .get_liquids()
+                    .unwrap()
+                    .get_level::<Water>(),
+                LiquidData::MAX_LEVEL
+            );
+        }
+
+        assert_eq!(map.drain_liquid_events().count(), 0);
+    }
+
+    #[test]
+    fn water_and_lava_make_stone() {
+        let mut map = Map::new_default(1, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Lava { level: 1.0 },
+        };
+
+        map.apply_liquid_diff(
+            &Grid::new(
+                1,
+                1,
+                LiquidDiff {
+                    level: 0.4,
+                    outflow: Vec2::ZERO,
+                },
+            ),
+            &Grid::new(1, 1, LiquidDiff::default()),
+            &Grid::new(1, 1, AirDiff::default()),
+            1.0,
+        );
+
+        let tile = &map.tiles[(0, 0)];
+        assert_relative_eq!(
+            tile.tile_type.get_liquids().unwrap().get_level::<Lava>(),
+            0.6
+        );
+        assert_eq!(
+            tile.tile_type.get_liquids().unwrap().get_level::<Water>(),
+            0.0
+        );
+        assert_relative_eq!(tile.ground_level, 0.4);
+        assert_relative_eq!(
+            tile.tile_type.get_air().unwrap().steam,
+            0.4 * STEAM_PER_WATER_LEVEL
+        );
+        assert_relative_eq!(
+            tile.temperature,
+            Tile::DEFAULT_TEMPERATURE + 0.4 * SOLIDIFICATION_HEAT_PER_LEVEL
+        );
+        assert_eq!(
+            map.drain_liquid_events().collect::<Vec<_>>(),
+            vec![LiquidEvent::Solidified { x: 0, y: 0 }]
+        );
+
+        // Water that flows into lava
+        let mut map = Map::new_default(4, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Lava { level: 2.0 },
+        };
+        map.tiles[(3, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 2.0 },
+        };
+        map.set_simulation_params(SimulationParams::fast_settle());
+        map.step_n(0.1, 100);
+
+        assert!(map
+            .drain_liquid_events()
+            .any(|event| matches!(event, LiquidEvent::Solidified { .. })));
+        assert!((0..4).any(|x| map.tiles[(x, 0)].ground_level > 0.0));
+    }
+
+    #[test]
+    fn leveler_solidifies_lava() {
+        let lava_map = || {
+            let mut map = Map::new_default(1, 1);
+            map.tiles[(0, 0)].tile_type = TileType::Ground {
+                air: Default::default(),
+                liquids: LiquidData::Lava { level: 0.5 },
+            };
+            map
+        };
+
+        for solidify in [false, true] {
+            let mut map = lava_map();
+            map.objects_mut()
+                .push_object::<EnvironmentObject>(LiquidLeveler {
+                    x: 0,
+                    y: 0,
+                    target: LiquidData::None,
+                    enabled: true,
+                    solidify,
+                });
+
+            map.perform_simulation_tick(0.1);
+
+            assert!(matches!(
+                map.tiles[(0, 0)].tile_type.get_liquids(),
+                Some(LiquidData::None)
+            ));
+            assert_eq!(
+                map.tiles[(0, 0)].ground_level,
+                if solidify { 0.5 } else { 0.0 }
+            );
+        }
+    }
+
+    #[test]
+    fn predict_flood_fills_basin() {
+        // A bowl with a deep 3x3 center, a shallow ring around it and a high rim
+        let mut map = Map::new_default(7, 7);
+        for (x, y) in map.all_tile_coords() {
+            let distance_from_center = x.abs_diff(3).max(y.abs_diff(3));
+            map.tiles[(x, y)].ground_level = match distance_from_center {
+                0 | 1 => 0.0,
+                2 => 1.0,
+                _ => 5.0,
+            };
+        }
+
+        let basin_center = |map: &Map| {
+            map.all_tile_coords()
+                .filter(|(x, y)| x.abs_diff(3) <= 1 && y.abs_diff(3) <= 1)
+                .collect::<Vec<_>>()
+        };
+
+        let mut basin = map.predict_flood((3, 3), 4.5);
+        basin.sort();
+        assert_eq!(basin, basin_center(&map));
+
+        let mut basin = map.predict_flood((3, 3), 20.0);
+        basin.sort();
+        assert_eq!(
+            basin,
+            map.all_tile_coords()
+                .filter(|(x, y)| x.abs_diff(3) <= 2 && y.abs_diff(3) <= 2)
+                .collect::<Vec<_>>()
+        );
+
+        // Water added on the slope runs down into the same basin
+        let mut basin_from_slope = map.predict_flood((1, 3), 4.5);
+        basin_from_slope.sort();
+        assert_eq!(basin_from_slope, basin_center(&map));
+
+        map.tiles[(3, 3)].tile_type = TileType::Wall;
+        assert!(map.predict_flood((3, 3), 1.0).is_empty());
+    }
+
+    #[test]
+    fn preview_leveler_steady_state() {
+        // A flat basin surrounded by walls
+        let mut map = Map::new_default(7, 7);
+        for (x, y) in map.all_tile_coords() {
+            if x == 0 || y == 0 || x == 6 || y == 6 {
+                map.tiles[(x, y)].tile_type = TileType::Wall;
+            }
+        }
+
+        let levels = map.preview_leveler_steady_state(LiquidLeveler {
+            x: 3,
+            y: 3,
+            target: LiquidData::Water { level: 1.0 },
+            enabled: true,
+            solidify: false,
+        });
+
+        for (x, y) in map.all_tile_coords() {
+            if map.tiles[(x, y)].tile_type.is_wall() {
+                assert_eq!(levels[(x, y)], 0.0);
+            } else {
+                assert_relative_eq!(levels[(x, y)], 1.0, epsilon = 0.05);
+            }
+        }
+
+        // The map itself didn't change
+        assert!(map
+            .ground_tiles()
+            .all(|(_, _, _, liquids)| *liquids == LiquidData::None));
+        assert_eq!(map.objects().get_all_objects().count(), 0);
+    }
+
+    #[test]
+    fn liquid_render_info() {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(0, 0)].ground_level = 1.0;
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water {
+                level: LiquidData::MAX_LEVEL / 2.0,
+            },
+        };
+        map.tiles[(2, 0)].tile_type = TileType::Wall;
+
+        let render = map.liquid_render_info(0, 0).unwrap();
+        assert_eq!(render.kind, LiquidKind::Water);
+        assert_relative_eq!(render.fill_fraction, 0.5);
+        assert_relative_eq!(render.surface_height, 1.0 + LiquidData::MAX_LEVEL / 2.0);
+
+        assert_eq!(map.liquid_render_info(1, 0), None);
+        assert_eq!(map.liquid_render_info(2, 0), None);
+    }
+
+    #[test]
+    fn liquid_flows_downhill() {
+        let mut map = Map::new_default(3, 2);
+        for y in 0..2 {
+            *map.tiles[(0, y)].tile_type.get_liquids_mut().unwrap() =
+                LiquidData::Water { level: 1.0 };
+        }
+        map.tiles[(2, 1)].tile_type = TileType::Wall;
+        map.perform_simulation_tick(0.1);
+
+        // The water of the top tile also flows diagonally down to the bottom right
+        let flow = map.liquid_flow(0, 0);
+        assert!(flow.x > 0.0 && flow.y > 0.0);
+        let flow = map.liquid_flow(0, 1);
+        assert!(flow.x > 0.0 && flow.y < 0.0);
+        // There's no water to flow yet
+        assert_eq!(map.liquid_flow(2, 0), Vec2::ZERO);
+        assert_eq!(map.liquid_flow(2, 1), Vec2::ZERO);
+        assert_eq!(map.liquid_flow(3, 0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn equalize_liquid() {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(0, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water { level: 2.0 },
+        };
+        map.tiles[(2, 0)].ground_level = 0.5;
+
+        for _ in 0..100 {
+            map.equalize_liquid::<Water>((0, 0), (2, 0), 1.0, 0.1);
+        }
+
+        let level = |map: &Map, x: usize| {
+            map.tiles[(x, 0)]
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<Water>()
+        };
+        assert_relative_eq!(level(&map, 0), 1.25, epsilon = 0.0001);
+        assert_relative_eq!(level(&map, 2), 0.75, epsilon = 0.0001);
+        assert_eq!(level(&map, 1), 0.0);
+
+        // Water doesn't flow into lava
+        map.tiles[(2, 0)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Lava { level: 0.1 },
+        };
+        map.equalize_liquid::<Water>((0, 0), (2, 0), 1.0, 0.1);
+        assert_relative_eq!(level(&map, 0), 1.25, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn hydraulic_liquid_rises_through_u_bend() {
+        let water_after = |liquid_solver: LiquidSolver| {
+            // The middle tile is a deep bend. Full, its surface is still below the floor of the right tile.
+            let mut map = Map::new_default(3, 1);
+            map.set_simulation_params(SimulationParams {
+                liquid_solver,
+                ..SimulationParams::fast_settle()
+            });
+            map.tiles[(1, 0)].ground_level = -4.0;
+            for x in 0..2 {
+                *map.tiles[(x, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water {
+                    level: LiquidData::MAX_LEVEL,
+                };
+            }
+
+            // Only look at the liquids, so no water evaporates
+            let mut levels = Grid::new(3, 1, 0.0);
+            let mut bodies = LiquidBodies::new(3, 1);
+            let mut water_diff = Grid::new(3, 1, LiquidDiff::default());
+            let no_lava_diff = Grid::new(3, 1, LiquidDiff::default());
+            let no_air_diff = Grid::new(3, 1, AirDiff::default());
+            for _ in 0..500 {
+                map.calculate_liquid_diff::<Water>(0.1, &mut levels, &mut bodies, &mut water_diff);
+                map.apply_liquid_diff(&water_diff, &no_lava_diff, &no_air_diff, 0.1);
+            }
+
+            [0, 1, 2].map(|x| {
+                map.tiles[(x, 0)]
+                    .tile_type
+                    .get_liquids()
+                    .unwrap()
+                    .get_level::<Water>()
+            })
+        };
+
+        assert_eq!(water_after(LiquidSolver::Jacobi), [3.0, 3.0, 0.0]);
+
+        // Both ends of the bend end up at the same surface level
+        let [left, bend, right] = water_after(LiquidSolver::Hydraulic);
+        assert_relative_eq!(left, 1.5, epsilon = 0.001);
+        assert_relative_eq!(bend, LiquidData::MAX_LEVEL);
+        assert_relative_eq!(right, 1.5, epsilon = 0.001);
+        assert_relative_eq!(
+            left + bend + right,
+            2.0 * LiquidData::MAX_LEVEL,
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn hydraulic_bodies_share_no_tiles() {
+        // The pit between the two bodies can hold all the water of one of them, but not of both
+        let mut map = Map::new_default(3, 1);
+        map.set_simulation_params(SimulationParams {
+            liquid_solver: LiquidSolver::Hydraulic,
+            ..SimulationParams::fast_settle()
+        });
+        map.tiles[(1, 0)].ground_level = -LiquidData::MAX_LEVEL;
+        for x in [0, 2] {
+            *map.tiles[(x, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water {
+                level: LiquidData::MAX_LEVEL,
+            };
+        }
+
+        let mut water_diff = Grid::new(3, 1, LiquidDiff::default());
+        map.calculate_liquid_diff::<Water>(
+            10.0,
+            &mut Grid::new(3, 1, 0.0),
+            &mut LiquidBodies::new(3, 1),
+            &mut water_diff,
+        );
+        assert_relative_eq!(water_diff[(1, 0)].level, LiquidData::MAX_LEVEL);
+        assert_relative_eq!(water_diff[(0, 0)].level, -LiquidData::MAX_LEVEL);
+        assert_eq!(water_diff[(2, 0)].level, 0.0);
+    }
+
+    #[test]
+    fn hydraulic_bodies_are_kept_up_to_date() {
+        let mut map = Map::new_default(12, 8);
+        map.set_simulation_params(SimulationParams {
+            liquid_solver: LiquidSolver::Hydraulic,
+            ..SimulationParams::default()
+        });
+        for (x, y) in [(1, 1), (2, 1), (9, 6), (5, 3)] {
+            *map.tiles[(x, y)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water {
+                level: LiquidData::MAX_LEVEL,
+            };
+        }
+
+        let mut levels = Grid::new(12, 8, 0.0);
+        let mut bodies = LiquidBodies::new(12, 8);
+        let mut water_diff = Grid::new(12, 8, LiquidDiff::default());
+        let mut searched_water_diff = Grid::new(12, 8, LiquidDiff::default());
+        for tick in 0..200 {
+            if tick == 50 {
+                map.tiles[(5, 4)].tile_type = TileType::Wall;
+            }
+            map.perform_simulation_tick(0.1);
+
+            // The kept bodies give the same flows as bodies that are searched from scratch
+            map.calculate_liquid_diff::<Water>(0.1, &mut levels, &mut bodies, &mut water_diff);
+            map.calculate_liquid_diff::<Water>(
+                0.1,
+                &mut levels,
+                &mut LiquidBodies::new(12, 8),
+                &mut searched_water_diff,
+            );
+            assert!(
+                water_diff == searched_water_diff,
+                "Different flows in tick {tick}"
+            );
         }
     }
 }
@@ -0,0 +1,87 @@
+//! Groups of tiles that are directly next to each other, like the pipes and cables that form networks.
+
+use crate::{grid::Grid, Facing};
+
+/// The networks of a set of tiles, see [Self::search]
+pub(crate) struct TileNetworks {
+    /// The tiles that are part of a network
+    members: Grid<bool>,
+    /// The index of the network of every member tile
+    networks: Grid<Option<usize>>,
+    /// The member tiles of the last search, sorted
+    tiles: Vec<(usize, usize)>,
+}
+
+impl TileNetworks {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            members: Grid::new(width, height, false),
+            networks: Grid::new(width, height, None),
+            tiles: Vec::new(),
+        }
+    }
+
+    /// Split the given tiles into networks of tiles that are directly next to each other.
+    /// Tiles outside of the map are left out. The networks of an earlier search are forgotten.
+    ///
+    /// Returns the tiles of every network, sorted so the result doesn't depend on the order of the search.
+    /// The index of a network in the list is what [Self::network] returns for its tiles.
+    pub(crate) fn search(
+        &mut self,
+        tiles: impl IntoIterator<Item = (usize, usize)>,
+    ) -> Vec<Vec<(usize, usize)>> {
+        // Only the tiles of the last search have to be cleared
+        for (x, y) in self.tiles.drain(..) {
+            self.members[(x, y)] = false;
+            self.networks[(x, y)] = None;
+        }
+
+        let (width, height) = (self.members.width(), self.members.height());
+        for (x, y) in tiles {
+            if x < width && y < height {
+                self.members[(x, y)] = true;
+                self.tiles.push((x, y));
+            }
+        }
+        // Networks are numbered in the order of their first tile
+        self.tiles.sort_unstable();
+
+        let mut network_tiles = Vec::new();
+        for (x, y) in self.tiles.iter().copied() {
+            if self.networks[(x, y)].is_some() {
+                continue;
+            }
+
+            let network = network_tiles.len();
+            let mut tiles = Vec::new();
+            let mut to_visit = vec![(x, y)];
+            self.networks[(x, y)] = Some(network);
+            while let Some((x, y)) = to_visit.pop() {
+                tiles.push((x, y));
+
+                for (nx, ny) in Facing::side_neighbours(x, y, width, height) {
+                    if self.members[(nx, ny)] && self.networks[(nx, ny)].is_none() {
+                        self.networks[(nx, ny)] = Some(network);
+                        to_visit.push((nx, ny));
+                    }
+                }
+            }
+
+            tiles.sort_unstable();
+            network_tiles.push(tiles);
+        }
+
+        network_tiles
+    }
+
+    /// The index of the network the tile is part of, or None when it's not part of one or outside of the map
+    pub(crate) fn network(&self, x: usize, y: usize) -> Option<usize> {
+        self.networks.get(x, y).copied().flatten()
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.members.memory_usage()
+            + self.networks.memory_usage()
+            + self.tiles.capacity() * std::mem::size_of::<(usize, usize)>()
+    }
+}
@@ -1,13 +1,45 @@
-use glam::{UVec2, Vec2};
+use glam::{uvec2, vec2, UVec2, Vec2};
 
-use super::{characters::Character, ObjectId, ObjectProperties};
+use super::{
+    characters::{Character, WorkGoal},
+    item::{Item, ItemKind},
+    ObjectId, ObjectKind, ObjectProperties, Objects, TickContext,
+};
 use crate::{
-    air::{AirLeveler, AirPusher, OxygenUser},
-    liquids::LiquidLeveler,
-    Facing,
+    air::{AirLeveler, AirPusher, GasMask, OxygenUser},
+    heat::{HeatSink, HeatSource},
+    liquids::{AnyLiquid, LiquidLeveler},
+    tiles::TileType,
+    Direction8, Facing, Map,
 };
+use std::fmt::Display;
+
+/// The oxygen level an [BuildingType::OxygenGenerator] levels the air on its tile to
+const OXYGEN_GENERATOR_LEVEL: f32 = 0.4;
+/// The leveling rate of the life support buildings when all their workspots are manned
+const LIFE_SUPPORT_RATE: f32 = 2.0;
+/// Workspots are never further than this from the position of their building,
+/// so the distance to a building says how close its workspots can be
+pub(crate) const MAX_WORKSPOT_REACH: f32 = 2.0;
+/// Buildings can't be placed on tiles with more liquid than this
+pub const MAX_PLACEMENT_LIQUID_LEVEL: f32 = 0.5;
+/// The air a ventilator pushes when it's fully manned or powered
+const VENTILATOR_AIR_PUSH: f32 = 0.5;
+/// The power a [BuildingType::PoweredVentilator] needs to push all of its air
+pub const POWERED_VENTILATOR_DEMAND: f32 = 1.0;
+/// The seconds a [BuildingType::Generator] that burns fuel runs on one ore
+pub const GENERATOR_FUEL_PER_ORE: f32 = 60.0;
+/// The amount of ore a [BuildingType::Generator] that burns fuel can hold
+pub const GENERATOR_FUEL_SLOTS: usize = 3;
+/// The power a [BuildingType::Heater] or [BuildingType::Cooler] needs to run at full speed
+pub const TEMPERATURE_CONTROL_DEMAND: f32 = 1.0;
+/// The degrees per second a heater or cooler changes the temperature of its tile with when fully powered and manned
+const TEMPERATURE_CONTROL_RATE: f32 = 5.0;
+/// Where the workspots of a [BuildingType::Heater] or [BuildingType::Cooler] are, relative to its location
+pub const TEMPERATURE_CONTROL_WORKSPOTS: [Vec2; 2] = [vec2(0.2, 0.5), vec2(0.8, 0.5)];
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Building {
     pub location: UVec2,
     pub facing: Facing,
@@ -15,18 +47,32 @@ pub struct Building {
 }
 
 impl Building {
+    /// The tiles of the two doors if this is an airlock
+    pub fn airlock_doors(&self) -> Option<[UVec2; 2]> {
+        if !matches!(self.building_type, BuildingType::Airlock) {
+            return None;
+        }
+
+        let door = |(x, y): (isize, isize)| {
+            let (x, y) = self.facing.rotate_isize_coords(x, y);
+            Some(uvec2(
+                (self.location.x as usize).checked_add_signed(x)? as u32,
+                (self.location.y as usize).checked_add_signed(y)? as u32,
+            ))
+        };
+
+        Some([door((0, -1))?, door((0, 1))?])
+    }
+
     pub(crate) fn workspots(&self) -> Vec<WorkSpot> {
+        self.building_type.workspots(self.location, self.facing)
+    }
+
+    /// The tiles the building occupies that are on the map
+    pub(crate) fn footprint_tiles(&self) -> Vec<UVec2> {
         self.building_type
-            .relative_workspots()
-            .iter()
-            .cloned()
-            .map(|mut workspot| {
-                let absolute_location =
-                    self.facing.rotate_f32_coords(workspot.location) + self.location.as_vec2();
-                workspot.location = absolute_location;
-                workspot
-            })
-            .collect()
+            .effect_preview(self.location, self.facing)
+            .footprint
     }
 
     pub(crate) fn release_workspot(&mut self, index: usize) {
@@ -52,6 +98,19 @@ impl Building {
         Ok(())
     }
 
+    /// Open up the workspots the character claimed or is working at
+    pub(crate) fn release_workspots_of(&mut self, character: ObjectId<Character>) {
+        for workspot in self.building_type.relative_workspots_mut() {
+            if let WorkSpotOccupation::Claimed(claimer) | WorkSpotOccupation::Working(claimer) =
+                workspot.occupation
+            {
+                if claimer == character {
+                    workspot.occupation = WorkSpotOccupation::Open;
+                }
+            }
+        }
+    }
+
     pub(crate) fn start_work_at_workspot(
         &mut self,
         index: usize,
@@ -75,6 +134,39 @@ impl Building {
 }
 
 impl ObjectProperties for Building {
+    fn render_kind(&self) -> ObjectKind {
+        match self.building_type {
+            BuildingType::HandCrankedVentilator { .. } => ObjectKind::HandCrankedVentilator,
+            BuildingType::Airlock => ObjectKind::Airlock,
+            BuildingType::OxygenGenerator { .. } => ObjectKind::OxygenGenerator,
+            BuildingType::FumeScrubber { .. } => ObjectKind::FumeScrubber,
+            BuildingType::MiningJob { .. } => ObjectKind::MiningJob,
+            BuildingType::Pump { .. } => ObjectKind::Pump,
+            BuildingType::Generator { .. } => ObjectKind::Generator,
+            BuildingType::PoweredVentilator { .. } => ObjectKind::PoweredVentilator,
+            BuildingType::Stockpile { .. } => ObjectKind::Stockpile,
+            BuildingType::Heater { .. } => ObjectKind::Heater,
+            BuildingType::Cooler { .. } => ObjectKind::Cooler,
+            BuildingType::UnderConstruction { .. } => ObjectKind::ConstructionSite,
+        }
+    }
+
+    fn position(&self) -> Option<Vec2> {
+        // The middle of the tile the building is located at
+        Some(self.location.as_vec2() + vec2(0.5, 0.5))
+    }
+
+    fn is_on_tile(&self, x: usize, y: usize) -> bool {
+        self.building_type
+            .footprint()
+            .into_iter()
+            .any(|(offset_x, offset_y)| {
+                let (offset_x, offset_y) = self.facing.rotate_isize_coords(offset_x, offset_y);
+                (self.location.x as usize).checked_add_signed(offset_x) == Some(x)
+                    && (self.location.y as usize).checked_add_signed(offset_y) == Some(y)
+            })
+    }
+
     fn air_levelers(&self) -> Vec<AirLeveler<usize>> {
         self.building_type
             .air_levelers()
@@ -112,16 +204,359 @@ impl ObjectProperties for Building {
             })
             .collect()
     }
+
+    fn heat_sources(&self) -> Vec<HeatSource<usize>> {
+        self.building_type
+            .heat_sources()
+            .into_iter()
+            .map(|val| val.to_absolute(self.location.x as usize, self.location.y as usize))
+            .collect()
+    }
+
+    fn heat_sinks(&self) -> Vec<HeatSink<usize>> {
+        self.building_type
+            .heat_sinks()
+            .into_iter()
+            .map(|val| val.to_absolute(self.location.x as usize, self.location.y as usize))
+            .collect()
+    }
+
+    fn tick(&mut self, _ctx: &TickContext, delta_time: f32) {
+        self.building_type.burn_fuel(delta_time);
+    }
+
+    fn on_removed(&self, id: ObjectId<()>, objects: &mut Objects) {
+        // Characters must not keep working at or hauling to a building that doesn't exist anymore
+        let mut released_items = Vec::new();
+        let mut dropped_items = Vec::new();
+        for mut character in objects.get_objects_mut::<Character>() {
+            character.stop_working_at(id.cast());
+            match character.stop_hauling_to(id.cast()) {
+                Some(Ok(item)) => released_items.push(item),
+                Some(Err(item)) => dropped_items.push(item),
+                None => {}
+            }
+        }
+        for mut item in objects.get_objects_mut::<Item>() {
+            if released_items.contains(&item.id()) {
+                item.hauler = None;
+            }
+        }
+        for item in dropped_items {
+            objects.push_object::<Item>(item);
+        }
+    }
+}
+
+impl Map {
+    /// Check if the building can be placed on the map as it is now, for example to show a preview in a build mode.
+    ///
+    /// The footprint must be within the map, on ground that isn't flooded above [MAX_PLACEMENT_LIQUID_LEVEL]
+    /// and not overlap with any other building. The tiles at the ends of an airlock must be doors
+    /// and a mining job must be on a wall. The first problem that is found is returned.
+    pub fn can_place_building(&self, building: &Building) -> Result<(), PlacementError> {
+        let tiles = building
+            .building_type
+            .footprint()
+            .into_iter()
+            .map(|(offset_x, offset_y)| {
+                let (rotated_x, rotated_y) =
+                    building.facing.rotate_isize_coords(offset_x, offset_y);
+                let x = (building.location.x as usize)
+                    .checked_add_signed(rotated_x)
+                    .filter(|x| *x < self.width())?;
+                let y = (building.location.y as usize)
+                    .checked_add_signed(rotated_y)
+                    .filter(|y| *y < self.height())?;
+                Some(((offset_x, offset_y), (x, y)))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(PlacementError::OutOfBounds)?;
+
+        let objects = self.objects();
+        for (offset, (x, y)) in tiles {
+            let tile_type = &self.tiles[(x, y)].tile_type;
+            if !building.building_type.fits_on_tile(offset, tile_type) {
+                return Err(PlacementError::UnsuitableTile { x, y });
+            }
+
+            let liquid_level = tile_type
+                .get_liquids()
+                .map_or(0.0, |liquids| liquids.get_level::<AnyLiquid>());
+            if liquid_level > MAX_PLACEMENT_LIQUID_LEVEL {
+                return Err(PlacementError::Flooded { x, y });
+            }
+
+            if let Some(other) = objects
+                .get_objects::<Building>()
+                .find(|other| other.is_on_tile(x, y))
+            {
+                return Err(PlacementError::Overlapping {
+                    x,
+                    y,
+                    building: other.id(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Why a building can't be placed, see [Map::can_place_building]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacementError {
+    /// Part of the footprint is outside of the map
+    OutOfBounds,
+    /// The tile isn't ground, or isn't the kind of tile this part of the building needs
+    UnsuitableTile { x: usize, y: usize },
+    /// There's more liquid on the tile than [MAX_PLACEMENT_LIQUID_LEVEL]
+    Flooded { x: usize, y: usize },
+    /// Another building is already on the tile
+    Overlapping {
+        x: usize,
+        y: usize,
+        building: ObjectId<Building>,
+    },
 }
 
+impl Display for PlacementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlacementError::OutOfBounds => write!(f, "The building doesn't fit on the map"),
+            PlacementError::UnsuitableTile { x, y } => {
+                write!(f, "The building can't stand on the tile at {x},{y}")
+            }
+            PlacementError::Flooded { x, y } => write!(f, "The tile at {x},{y} is flooded"),
+            PlacementError::Overlapping { x, y, building } => {
+                write!(f, "The tile at {x},{y} is taken by building {building:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlacementError {}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BuildingType {
-    HandCrankedVentilator { workspots: [WorkSpot; 2] },
+    HandCrankedVentilator {
+        workspots: [WorkSpot; 2],
+    },
+    /// Two doors with a chamber in between, of which only one door can be open at a time.
+    ///
+    /// The building doesn't place the doors, they must be [TileType::Door](crate::tiles::TileType::Door)
+    /// tiles on both ends of the footprint.
+    Airlock,
+    /// Adds oxygen to the air of its tile while its workspots are manned
+    OxygenGenerator {
+        workspots: [WorkSpot; 2],
+    },
+    /// Removes the fumes from the air of its tile while its workspots are manned
+    FumeScrubber {
+        workspots: [WorkSpot; 2],
+    },
+    /// Digs out the wall at its location while its workspots are manned. Made by [Map::excavate](crate::Map::excavate).
+    ///
+    /// The workspots are on the open tile the building faces.
+    MiningJob {
+        workspots: [WorkSpot; 2],
+        /// How far the wall has been dug out, from 0 to 1
+        progress: f32,
+        /// The progress per second when all workspots are manned
+        rate: f32,
+        /// The fumes per second that are released into the open neighbouring tiles when all workspots are manned
+        fumes_per_sec: f32,
+    },
+    /// Pumps the liquid on its tile into the [Pipe](crate::pipes::Pipe) on the tile it faces
+    Pump {
+        /// The maximum liquid level per second that is pumped
+        rate: f32,
+        /// A disabled pump doesn't do anything
+        enabled: bool,
+    },
+    /// Makes power for the buildings on its [Cable](crate::power::Cable) network
+    Generator {
+        /// The power it makes
+        output: f32,
+        /// A disabled generator doesn't make any power
+        enabled: bool,
+        /// The seconds of running the ore the characters brought to it still lasts.
+        /// None for a generator that doesn't need any fuel.
+        ///
+        /// A generator that burns fuel only makes power while it has some left.
+        /// Its input slots take [ItemKind::Ore], see [GENERATOR_FUEL_SLOTS].
+        #[cfg_attr(feature = "serde", serde(default))]
+        fuel: Option<f32>,
+    },
+    /// Pushes air like the [BuildingType::HandCrankedVentilator], but runs on power instead of workers
+    PoweredVentilator {
+        /// The fraction of the power it needs that it got in the last simulation tick, from 0 to 1
+        power: f32,
+    },
+    /// The place the characters haul the [Item]s on the map to
+    Stockpile {
+        /// The amount of items that fit on the stockpile
+        capacity: usize,
+    },
+    /// Heats its tile up to the target temperature, as fast as its power and workers allow
+    Heater {
+        /// The temperature in degrees Celsius the tile is heated up to
+        target: f32,
+        /// The fraction of the power it needs that it got in the last simulation tick, from 0 to 1
+        power: f32,
+        /// The spots where characters tend it. See [TEMPERATURE_CONTROL_WORKSPOTS].
+        #[cfg_attr(feature = "serde", serde(default = "temperature_control_workspots"))]
+        workspots: [WorkSpot; 2],
+    },
+    /// Cools its tile down to the target temperature, as fast as its power and workers allow
+    Cooler {
+        /// The temperature in degrees Celsius the tile is cooled down to
+        target: f32,
+        /// The fraction of the power it needs that it got in the last simulation tick, from 0 to 1
+        power: f32,
+        /// The spots where characters tend it. See [TEMPERATURE_CONTROL_WORKSPOTS].
+        #[cfg_attr(feature = "serde", serde(default = "temperature_control_workspots"))]
+        workspots: [WorkSpot; 2],
+    },
+    /// A building that is still being built while its workspots are manned. Made by [Map::construct].
+    ///
+    /// It takes up the footprint of the building it becomes, but doesn't do anything else until it's finished.
+    UnderConstruction {
+        /// What the building becomes once it's finished
+        building_type: Box<BuildingType>,
+        workspots: [WorkSpot; 2],
+        /// The work done so far
+        progress: f32,
+        /// The work it takes to finish the building.
+        /// Every second all workspots are manned adds 1 work.
+        required_work: f32,
+    },
 }
 
 impl BuildingType {
+    /// Get the effects the building would have when placed at the given location and facing.
+    ///
+    /// This doesn't touch the map, so it can be used to show a preview before placing the building.
+    pub fn effect_preview(&self, location: UVec2, facing: Facing) -> BuildingPreview {
+        let offset_location = |(x, y): (isize, isize)| {
+            Some(uvec2(
+                (location.x as usize).checked_add_signed(x)? as u32,
+                (location.y as usize).checked_add_signed(y)? as u32,
+            ))
+        };
+
+        BuildingPreview {
+            footprint: self
+                .footprint()
+                .into_iter()
+                .filter_map(|(x, y)| offset_location(facing.rotate_isize_coords(x, y)))
+                .collect(),
+            air_pusher_targets: self
+                .air_pushers()
+                .into_iter()
+                .map(|air_pusher| {
+                    air_pusher.to_absolute(location.x as usize, location.y as usize, facing)
+                })
+                .filter_map(|air_pusher| {
+                    let (offset_x, offset_y) = air_pusher.direction.offset();
+                    Some(uvec2(
+                        air_pusher.x.checked_add_signed(offset_x)? as u32,
+                        air_pusher.y.checked_add_signed(offset_y)? as u32,
+                    ))
+                })
+                .collect(),
+            workspots: self
+                .workspots(location, facing)
+                .into_iter()
+                .map(|workspot| workspot.location)
+                .collect(),
+        }
+    }
+
+    /// The tiles the building occupies, relative to its location when facing North
+    pub(crate) fn footprint(&self) -> Vec<(isize, isize)> {
+        match self {
+            BuildingType::HandCrankedVentilator { .. }
+            | BuildingType::OxygenGenerator { .. }
+            | BuildingType::FumeScrubber { .. }
+            | BuildingType::MiningJob { .. }
+            | BuildingType::Pump { .. }
+            | BuildingType::Generator { .. }
+            | BuildingType::PoweredVentilator { .. }
+            | BuildingType::Stockpile { .. }
+            | BuildingType::Heater { .. }
+            | BuildingType::Cooler { .. } => vec![(0, 0)],
+            BuildingType::Airlock => vec![(0, -1), (0, 0), (0, 1)],
+            BuildingType::UnderConstruction { building_type, .. } => building_type.footprint(),
+        }
+    }
+
+    /// Whether the part of the building at the given offset of the [Self::footprint] can be on the tile
+    fn fits_on_tile(&self, offset: (isize, isize), tile_type: &TileType) -> bool {
+        let (_, offset_y) = offset;
+        match self {
+            BuildingType::Airlock if offset_y != 0 => matches!(tile_type, TileType::Door { .. }),
+            BuildingType::MiningJob { .. } => tile_type.is_wall(),
+            BuildingType::UnderConstruction { building_type, .. } => {
+                building_type.fits_on_tile(offset, tile_type)
+            }
+            _ => matches!(tile_type, TileType::Ground { .. }),
+        }
+    }
+
+    fn workspots(&self, location: UVec2, facing: Facing) -> Vec<WorkSpot> {
+        self.relative_workspots()
+            .iter()
+            .cloned()
+            .map(|mut workspot| {
+                let absolute_location =
+                    facing.rotate_f32_coords(workspot.location) + location.as_vec2();
+                workspot.location = absolute_location;
+                debug_assert!(
+                    absolute_location.distance(location.as_vec2() + vec2(0.5, 0.5))
+                        <= MAX_WORKSPOT_REACH
+                );
+                workspot
+            })
+            .collect()
+    }
+
     fn air_levelers(&self) -> Vec<AirLeveler<isize>> {
-        Vec::new()
+        match self {
+            BuildingType::OxygenGenerator { workspots } => vec![AirLeveler {
+                x: 0,
+                y: 0,
+                nitrogen: 0.0,
+                oxygen: OXYGEN_GENERATOR_LEVEL,
+                fumes: 0.0,
+                gases: GasMask::OXYGEN,
+                reservoir: None,
+                rate: Some(LIFE_SUPPORT_RATE * working_fraction(workspots).powf(2.0)),
+                enabled: true,
+            }],
+            BuildingType::FumeScrubber { workspots } => vec![AirLeveler {
+                x: 0,
+                y: 0,
+                nitrogen: 0.0,
+                oxygen: 0.0,
+                fumes: 0.0,
+                gases: GasMask::FUMES,
+                reservoir: None,
+                rate: Some(LIFE_SUPPORT_RATE * working_fraction(workspots).powf(2.0)),
+                enabled: true,
+            }],
+            BuildingType::HandCrankedVentilator { .. }
+            | BuildingType::Airlock
+            | BuildingType::MiningJob { .. }
+            | BuildingType::Pump { .. }
+            | BuildingType::Generator { .. }
+            | BuildingType::PoweredVentilator { .. }
+            | BuildingType::Stockpile { .. }
+            | BuildingType::Heater { .. }
+            | BuildingType::Cooler { .. }
+            | BuildingType::UnderConstruction { .. } => Vec::new(),
+        }
     }
 
     fn oxygen_users(&self) -> Vec<OxygenUser<isize>> {
@@ -137,42 +572,230 @@ impl BuildingType {
             BuildingType::HandCrankedVentilator { workspots } => vec![AirPusher {
                 x: 0,
                 y: 0,
-                direction: Facing::North,
-                amount: 0.5
-                    * (workspots
-                        .iter()
-                        .map(|ws| ws.occupation.is_working() as usize)
-                        .sum::<usize>() as f32
-                        / workspots.len() as f32)
-                        .powf(2.0),
+                direction: Direction8::North,
+                amount: VENTILATOR_AIR_PUSH * working_fraction(workspots).powf(2.0),
+                enabled: true,
             }],
+            BuildingType::PoweredVentilator { power } => vec![AirPusher {
+                x: 0,
+                y: 0,
+                direction: Direction8::North,
+                amount: VENTILATOR_AIR_PUSH * power,
+                enabled: true,
+            }],
+            BuildingType::Airlock
+            | BuildingType::OxygenGenerator { .. }
+            | BuildingType::FumeScrubber { .. }
+            | BuildingType::MiningJob { .. }
+            | BuildingType::Pump { .. }
+            | BuildingType::Generator { .. }
+            | BuildingType::Stockpile { .. }
+            | BuildingType::Heater { .. }
+            | BuildingType::Cooler { .. }
+            | BuildingType::UnderConstruction { .. } => Vec::new(),
         }
     }
 
-    pub(crate) fn is_ventilator(&self) -> bool {
-        matches!(self, Self::HandCrankedVentilator { .. })
+    fn heat_sources(&self) -> Vec<HeatSource<isize>> {
+        match self {
+            BuildingType::Heater {
+                target,
+                power,
+                workspots,
+            } => vec![HeatSource {
+                x: 0,
+                y: 0,
+                temperature: *target,
+                change_per_sec: TEMPERATURE_CONTROL_RATE * power * working_fraction(workspots),
+                enabled: true,
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    fn heat_sinks(&self) -> Vec<HeatSink<isize>> {
+        match self {
+            BuildingType::Cooler {
+                target,
+                power,
+                workspots,
+            } => vec![HeatSink {
+                x: 0,
+                y: 0,
+                temperature: *target,
+                change_per_sec: TEMPERATURE_CONTROL_RATE * power * working_fraction(workspots),
+                enabled: true,
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The work goal of the characters that man this building, if it can be manned
+    pub(crate) fn work_goal(&self) -> Option<WorkGoal> {
+        match self {
+            BuildingType::HandCrankedVentilator { .. } => Some(WorkGoal::WorkAtVentilation),
+            BuildingType::OxygenGenerator { .. }
+            | BuildingType::FumeScrubber { .. }
+            | BuildingType::Heater { .. }
+            | BuildingType::Cooler { .. } => Some(WorkGoal::WorkAtLifeSupport),
+            BuildingType::MiningJob { .. } => Some(WorkGoal::Mine),
+            BuildingType::UnderConstruction { .. } => Some(WorkGoal::Construct),
+            BuildingType::Airlock
+            | BuildingType::Pump { .. }
+            | BuildingType::Generator { .. }
+            | BuildingType::PoweredVentilator { .. }
+            | BuildingType::Stockpile { .. } => None,
+        }
+    }
+
+    /// The power the building makes for its cable network
+    pub fn power_supply(&self) -> f32 {
+        match self {
+            BuildingType::Generator {
+                output,
+                enabled: true,
+                fuel,
+            } if fuel.is_none_or(|fuel| fuel > 0.0) => *output,
+            _ => 0.0,
+        }
+    }
+
+    /// Burn the fuel of a running generator for the given amount of seconds
+    fn burn_fuel(&mut self, delta_time: f32) {
+        if let BuildingType::Generator {
+            enabled: true,
+            fuel: Some(fuel),
+            ..
+        } = self
+        {
+            *fuel = (*fuel - delta_time).max(0.0);
+        }
+    }
+
+    /// The amount of items of the kind that still fit in the input slots of the building
+    pub fn input_room(&self, kind: ItemKind) -> usize {
+        match (self, kind) {
+            (
+                BuildingType::Generator {
+                    fuel: Some(fuel), ..
+                },
+                ItemKind::Ore,
+            ) => {
+                GENERATOR_FUEL_SLOTS.saturating_sub((fuel / GENERATOR_FUEL_PER_ORE).ceil() as usize)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Put an item in the input slots of the building.
+    /// Returns false when the building doesn't take it, so the item should stay where it is.
+    pub(crate) fn put_input(&mut self, kind: ItemKind) -> bool {
+        if self.input_room(kind) == 0 {
+            return false;
+        }
+
+        match self {
+            BuildingType::Generator {
+                fuel: Some(fuel), ..
+            } => {
+                *fuel += GENERATOR_FUEL_PER_ORE;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The power the building needs from its cable network
+    pub fn power_demand(&self) -> f32 {
+        match self {
+            BuildingType::PoweredVentilator { .. } => POWERED_VENTILATOR_DEMAND,
+            BuildingType::Heater { .. } | BuildingType::Cooler { .. } => TEMPERATURE_CONTROL_DEMAND,
+            _ => 0.0,
+        }
+    }
+
+    /// Set the fraction of its power demand the building gets
+    pub(crate) fn set_power(&mut self, fraction: f32) {
+        if let BuildingType::PoweredVentilator { power }
+        | BuildingType::Heater { power, .. }
+        | BuildingType::Cooler { power, .. } = self
+        {
+            *power = fraction;
+        }
     }
 
     fn relative_workspots(&self) -> &[WorkSpot] {
         match self {
-            BuildingType::HandCrankedVentilator { workspots } => workspots,
+            BuildingType::HandCrankedVentilator { workspots }
+            | BuildingType::OxygenGenerator { workspots }
+            | BuildingType::FumeScrubber { workspots }
+            | BuildingType::MiningJob { workspots, .. }
+            | BuildingType::Heater { workspots, .. }
+            | BuildingType::Cooler { workspots, .. }
+            | BuildingType::UnderConstruction { workspots, .. } => workspots,
+            BuildingType::Airlock
+            | BuildingType::Pump { .. }
+            | BuildingType::Generator { .. }
+            | BuildingType::PoweredVentilator { .. }
+            | BuildingType::Stockpile { .. } => &[],
         }
     }
 
     fn relative_workspots_mut(&mut self) -> &mut [WorkSpot] {
         match self {
-            BuildingType::HandCrankedVentilator { workspots } => workspots,
+            BuildingType::HandCrankedVentilator { workspots }
+            | BuildingType::OxygenGenerator { workspots }
+            | BuildingType::FumeScrubber { workspots }
+            | BuildingType::MiningJob { workspots, .. }
+            | BuildingType::Heater { workspots, .. }
+            | BuildingType::Cooler { workspots, .. }
+            | BuildingType::UnderConstruction { workspots, .. } => workspots,
+            BuildingType::Airlock
+            | BuildingType::Pump { .. }
+            | BuildingType::Generator { .. }
+            | BuildingType::PoweredVentilator { .. }
+            | BuildingType::Stockpile { .. } => &mut [],
         }
     }
 }
 
+/// The open workspots of a new [BuildingType::Heater] or [BuildingType::Cooler]
+pub fn temperature_control_workspots() -> [WorkSpot; 2] {
+    TEMPERATURE_CONTROL_WORKSPOTS.map(|location| WorkSpot {
+        location,
+        occupation: WorkSpotOccupation::Open,
+    })
+}
+
+/// The fraction of the workspots that have a character working at them
+pub(crate) fn working_fraction(workspots: &[WorkSpot]) -> f32 {
+    workspots
+        .iter()
+        .filter(|workspot| workspot.occupation.is_working())
+        .count() as f32
+        / workspots.len() as f32
+}
+
+/// The effects a building would have on the map, in absolute tile coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildingPreview {
+    /// The tiles the building occupies
+    pub footprint: Vec<UVec2>,
+    /// The tiles the air pushers of the building push air into
+    pub air_pusher_targets: Vec<UVec2>,
+    /// The locations of the workspots
+    pub workspots: Vec<Vec2>,
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WorkSpot {
     pub location: Vec2,
     pub occupation: WorkSpotOccupation,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WorkSpotOccupation {
     /// No character is working this spot, nor is one coming to work it
     Open,
@@ -199,3 +822,138 @@ impl WorkSpotOccupation {
         matches!(self, Self::Working(..))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::characters::tests::ventilator;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn ventilator_effect_preview() {
+        let building_type = ventilator(uvec2(3, 3), Facing::East).building_type;
+
+        let preview = building_type.effect_preview(uvec2(3, 3), Facing::East);
+
+        assert_eq!(preview.footprint, vec![uvec2(3, 3)]);
+        assert_eq!(preview.air_pusher_targets, vec![uvec2(4, 3)]);
+        assert_eq!(preview.workspots.len(), 2);
+        assert_relative_eq!(preview.workspots[0].x, 3.5);
+        assert_relative_eq!(preview.workspots[0].y, 3.2);
+        assert_relative_eq!(preview.workspots[1].x, 3.5);
+        assert_relative_eq!(preview.workspots[1].y, 3.8);
+
+        // A pusher pointing off the map has no target
+        let preview = building_type.effect_preview(uvec2(3, 0), Facing::North);
+        assert!(preview.air_pusher_targets.is_empty());
+    }
+
+    #[test]
+    fn placement_validation() {
+        let mut map = Map::new_default(5, 5);
+        map.tiles[(4, 4)].tile_type = TileType::Wall;
+        *map.tiles[(0, 4)].tile_type.get_liquids_mut().unwrap() =
+            crate::liquids::LiquidData::Water { level: 1.0 };
+        let scrubber = |x, y| Building {
+            location: uvec2(x, y),
+            facing: Facing::North,
+            building_type: BuildingType::FumeScrubber {
+                workspots: [(); 2].map(|_| WorkSpot {
+                    location: vec2(0.5, 0.5),
+                    occupation: WorkSpotOccupation::Open,
+                }),
+            },
+        };
+
+        assert_eq!(map.can_place_building(&scrubber(2, 2)), Ok(()));
+        assert_eq!(
+            map.can_place_building(&scrubber(5, 2)),
+            Err(PlacementError::OutOfBounds)
+        );
+        assert_eq!(
+            map.can_place_building(&scrubber(4, 4)),
+            Err(PlacementError::UnsuitableTile { x: 4, y: 4 })
+        );
+        assert_eq!(
+            map.can_place_building(&scrubber(0, 4)),
+            Err(PlacementError::Flooded { x: 0, y: 4 })
+        );
+
+        // An airlock needs doors on both ends and can't stick out of the map
+        let airlock = |x, y, facing| Building {
+            location: uvec2(x, y),
+            facing,
+            building_type: BuildingType::Airlock,
+        };
+        assert_eq!(
+            map.can_place_building(&airlock(1, 2, Facing::North)),
+            Err(PlacementError::UnsuitableTile { x: 1, y: 1 })
+        );
+        assert_eq!(
+            map.can_place_building(&airlock(0, 2, Facing::East)),
+            Err(PlacementError::OutOfBounds)
+        );
+        for y in [1, 3] {
+            map.tiles[(1, y)].tile_type = TileType::Door {
+                open: false,
+                air: Default::default(),
+                liquids: Default::default(),
+            };
+        }
+        assert_eq!(
+            map.can_place_building(&airlock(1, 2, Facing::North)),
+            Ok(())
+        );
+
+        let placed = map
+            .objects_mut()
+            .push_object::<Building>(airlock(1, 2, Facing::North));
+        assert_eq!(
+            map.can_place_building(&scrubber(1, 2)),
+            Err(PlacementError::Overlapping {
+                x: 1,
+                y: 2,
+                building: placed
+            })
+        );
+    }
+
+    #[test]
+    fn life_support_scales_with_workers() {
+        let generator = |occupations: [WorkSpotOccupation; 2]| BuildingType::OxygenGenerator {
+            workspots: occupations.map(|occupation| WorkSpot {
+                location: vec2(0.5, 0.5),
+                occupation,
+            }),
+        };
+        let rate = |building_type: BuildingType| building_type.air_levelers()[0].rate.unwrap();
+
+        let worker = ObjectId::new(0);
+        assert_relative_eq!(
+            rate(generator([
+                WorkSpotOccupation::Open,
+                WorkSpotOccupation::Claimed(worker)
+            ])),
+            0.0
+        );
+        assert_relative_eq!(
+            rate(generator([
+                WorkSpotOccupation::Working(worker),
+                WorkSpotOccupation::Open
+            ])),
+            LIFE_SUPPORT_RATE * 0.25
+        );
+        assert_relative_eq!(
+            rate(generator([
+                WorkSpotOccupation::Working(worker),
+                WorkSpotOccupation::Working(ObjectId::new(1))
+            ])),
+            LIFE_SUPPORT_RATE
+        );
+
+        let leveler =
+            generator([WorkSpotOccupation::Open, WorkSpotOccupation::Open]).air_levelers()[0];
+        assert_eq!(leveler.oxygen, OXYGEN_GENERATOR_LEVEL);
+        assert_eq!(leveler.gases, GasMask::OXYGEN);
+    }
+}
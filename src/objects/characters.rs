@@ -1,24 +1,180 @@
-use glam::{vec2, Vec2};
+use glam::{vec2, UVec2, Vec2};
 use ordered_float::OrderedFloat;
+use rayon::prelude::*;
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::SQRT_2,
+    sync::OnceLock,
+};
 
-use super::{building::Building, ObjectId, ObjectProperties};
+use super::{
+    building::{Building, BuildingType},
+    inventory::{Inventory, OXYGEN_TANK_LOSS_FACTOR},
+    item::{Item, ItemKind},
+    LockedObject, ObjectId, ObjectKind, ObjectProperties, Objects,
+};
 use crate::{
     air::OxygenUser,
-    liquids::{AnyLiquid, Lava},
+    flow_field::FlowField,
+    grid::Grid,
+    liquids::{AnyLiquid, Lava, LiquidData, Water},
+    path_cache::PathKey,
+    snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter},
+    tiles::{Tile, TileRect, TileType},
     Map,
 };
 
-/// Walk speed in meters per second
-const CHARACTER_WALK_SPEED: f32 = 1.2;
+/// Time in seconds a character sticks with a new goal before it reconsiders it
+const GOAL_SWITCH_COOLDOWN: f32 = 1.0;
+/// Distance in meters a new workspot must be closer than the current one before a character switches to it
+const WORKSPOT_SWITCH_MARGIN: f32 = 1.0;
+/// Scale of the path penalty of walking through liquid
+const LIQUID_PENALTY_SCALE: f32 = 0.01;
+/// How quickly the path penalty grows with the depth of the liquid
+const LIQUID_PENALTY_STEEPNESS: f32 = 16.0;
+/// Walking through lava is this many times worse than walking through the same depth of water
+const LAVA_PENALTY_FACTOR: f32 = 100000.0;
+/// Path penalty of walking through a closed door, for the time it takes to open it
+const CLOSED_DOOR_PENALTY: f32 = 0.5;
+/// What characters consider dangerous
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct HazardParams {
+    /// Above this temperature in degrees Celsius a character gets burned
+    pub max_safe_temperature: f32,
+    /// Health lost per second for every degree above the [Self::max_safe_temperature]
+    pub burn_damage_per_degree: f32,
+    /// Health lost per second while standing in lava
+    pub lava_damage_per_sec: f32,
+    /// When true, characters won't path over tiles that are hotter than the [Self::max_safe_temperature]
+    pub avoid_unsafe_temperature: bool,
+    /// Characters run away when there's lava this many tiles away or closer.
+    /// At 0 only lava on the tile of the character itself is a reason to run.
+    pub lava_danger_distance: usize,
+    /// Characters run away when the oxygen fraction of the air they're in is below this
+    pub min_safe_oxygen_fraction: f32,
+    /// Above this liquid level a character can't breathe. Characters run away from it and won't path through it.
+    pub drown_liquid_level: f32,
+    /// Characters standing in water deeper than this are pushed along by its current, see [Map::liquid_flow]
+    pub deep_water_level: f32,
+    /// The fraction of the speed of the current that characters in deep water are pushed along with
+    pub current_push_fraction: f32,
+}
+
+impl HazardParams {
+    pub const fn new_default() -> Self {
+        Self {
+            max_safe_temperature: 60.0,
+            burn_damage_per_degree: 0.001,
+            lava_damage_per_sec: 0.5,
+            avoid_unsafe_temperature: true,
+            lava_danger_distance: 0,
+            min_safe_oxygen_fraction: 0.1,
+            drown_liquid_level: 2.0,
+            deep_water_level: 1.0,
+            current_push_fraction: 0.5,
+        }
+    }
+}
+
+impl Default for HazardParams {
+    fn default() -> Self {
+        Self::new_default()
+    }
+}
+
+/// The amount of recent events a character remembers
+const RECENT_EVENTS_CAPACITY: usize = 8;
+/// The health of a fully healthy character
+pub const MAX_HEALTH: f32 = 1.0;
+/// Health regained per second when the character is in safe conditions
+const HEALTH_REGEN_PER_SEC: f32 = 0.01;
+/// A character hungrier than this won't regenerate health
+const REGEN_MAX_HUNGER: f32 = 0.5;
+/// The oxygen fraction of the air must be at least this much for a character to breathe well and regenerate health
+const REGEN_MIN_OXYGEN_FRACTION: f32 = 0.18;
+
+/// Hunger gained per second. A character starves in an hour without food.
+const HUNGER_PER_SEC: f32 = 1.0 / 3600.0;
+/// Fatigue gained per second while walking
+const WALKING_FATIGUE_PER_SEC: f32 = 1.0 / 1800.0;
+/// Fatigue gained per second while working at a workspot
+const WORKING_FATIGUE_PER_SEC: f32 = 1.0 / 600.0;
+/// Fatigue lost per second while idling or resting
+const REST_RECOVERY_PER_SEC: f32 = 1.0 / 120.0;
+/// Oxygen saturation lost per second when the character can't breathe
+const OXYGEN_LOSS_PER_SEC: f32 = 1.0 / 30.0;
+/// Oxygen saturation regained per second when the character breathes well
+const OXYGEN_RECOVERY_PER_SEC: f32 = 1.0 / 5.0;
+
+/// A character hungrier than this stops what it's doing to prevent starvation
+const STARVING_HUNGER: f32 = 0.8;
+/// A character more tired than this goes to rest
+const EXHAUSTED_FATIGUE: f32 = 0.9;
+/// A resting character gets back to work once its fatigue is below this
+const RESTED_FATIGUE: f32 = 0.2;
+/// Below this oxygen saturation, a character goes looking for better air
+const LOW_OXYGEN_SATURATION: f32 = 0.5;
+
+/// Health lost per second when a character is fully starving
+const STARVATION_DAMAGE_PER_SEC: f32 = 0.005;
+/// Health lost per second when a character is fully exhausted
+const EXHAUSTION_DAMAGE_PER_SEC: f32 = 0.001;
+/// Health lost per second when a character has no oxygen left
+const SUFFOCATION_DAMAGE_PER_SEC: f32 = 0.05;
+
+/// The physical needs of a character
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Needs {
+    /// How hungry the character is. 0.0 is well fed and 1.0 is starving.
+    /// There's no food on the map, so this needs to be lowered by the game when the character eats.
+    pub hunger: f32,
+    /// How tired the character is. 0.0 is well rested and 1.0 is exhausted
+    pub fatigue: f32,
+    /// How much oxygen the character has in its blood. 1.0 is fully saturated and 0.0 is suffocating
+    pub oxygen_saturation: f32,
+}
+
+impl Needs {
+    pub const fn new_default() -> Self {
+        Self {
+            hunger: 0.0,
+            fatigue: 0.0,
+            oxygen_saturation: 1.0,
+        }
+    }
+}
+
+impl Default for Needs {
+    fn default() -> Self {
+        Self::new_default()
+    }
+}
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Character {
     pub location: Vec2,
     pub health: f32,
+    pub needs: Needs,
+    /// The group or squad the character is part of, if any
+    pub group: Option<u32>,
     pub(crate) work_goals_order: Vec<WorkGoal>,
     pub(crate) current_goal: CharacterGoal,
     pub(crate) current_task: CharacterTask,
     pub(crate) current_path: Option<Path>,
+    /// Time in seconds until the current goal may be reconsidered
+    pub(crate) goal_cooldown: f32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) inventory: Inventory,
+    /// The doors the character opened to walk through, which it closes again once it's through
+    opened_doors: Vec<UVec2>,
+    recent_events: VecDeque<CharacterEvent>,
+    /// The events that haven't been sent to the map event listeners yet
+    #[cfg_attr(feature = "serde", serde(skip))]
+    unpublished_events: Vec<CharacterEvent>,
 }
 
 impl Character {
@@ -26,39 +182,240 @@ impl Character {
         Self {
             location,
             health,
+            needs: Needs::new_default(),
+            group: None,
             work_goals_order,
             current_goal: CharacterGoal::Idle,
             current_task: CharacterTask::Idle,
             current_path: None,
+            goal_cooldown: 0.0,
+            inventory: Inventory::default(),
+            opened_doors: Vec::new(),
+            recent_events: VecDeque::with_capacity(RECENT_EVENTS_CAPACITY),
+            unpublished_events: Vec::new(),
+        }
+    }
+
+    /// The work the character wants to do, most important first
+    pub fn work_goals(&self) -> &[WorkGoal] {
+        &self.work_goals_order
+    }
+
+    /// Change the work the character wants to do, most important first.
+    ///
+    /// The character reconsiders what it's doing on the next AI tick.
+    pub fn set_work_goals(&mut self, work_goals_order: Vec<WorkGoal>) {
+        self.work_goals_order = work_goals_order;
+        self.goal_cooldown = 0.0;
+    }
+
+    /// The items the character carries with it, see [Objects::pick_up_item].
+    /// The item the character is hauling is in here too, from when it's picked up until it's delivered.
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    /// The last couple of decisions and things that happened to the character, oldest first
+    pub fn recent_events(&self) -> impl Iterator<Item = &CharacterEvent> {
+        self.recent_events.iter()
+    }
+
+    fn record_event(&mut self, event: CharacterEvent) {
+        if self.recent_events.len() == RECENT_EVENTS_CAPACITY {
+            self.recent_events.pop_front();
+        }
+        self.recent_events.push_back(event.clone());
+        self.unpublished_events.push(event);
+    }
+
+    pub(crate) fn take_unpublished_events(&mut self) -> Vec<CharacterEvent> {
+        std::mem::take(&mut self.unpublished_events)
+    }
+}
+
+impl Snapshot for Character {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.location);
+        writer.write(&self.health);
+        writer.write(&self.needs);
+        writer.write(&self.group);
+        writer.write(&self.work_goals_order);
+        writer.write(&self.current_goal);
+        writer.write(&self.current_task);
+        writer.write(&self.current_path);
+        writer.write(&self.goal_cooldown);
+        writer.write(&self.opened_doors);
+        writer.write(&self.recent_events);
+        writer.write(&self.inventory);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            location: reader.read()?,
+            health: reader.read()?,
+            needs: reader.read()?,
+            group: reader.read()?,
+            work_goals_order: reader.read()?,
+            current_goal: reader.read()?,
+            current_task: reader.read()?,
+            current_path: reader.read()?,
+            goal_cooldown: reader.read()?,
+            opened_doors: reader.read()?,
+            recent_events: reader.read()?,
+            inventory: reader.read()?,
+            // Events are for the listeners of the map that saved the snapshot, the loaded map starts without them
+            unpublished_events: Vec::new(),
+        })
+    }
+}
+
+impl Character {
+    /// Makes the character idle if it is working at or going to the given building
+    pub(crate) fn stop_working_at(&mut self, building_id: ObjectId<Building>) {
+        self.leave_building(
+            building_id,
+            CharacterEvent::BuildingRemoved {
+                building: building_id,
+            },
+        );
+    }
+
+    /// Makes the character idle if it is constructing the given building, which was just finished
+    pub(crate) fn finish_construction(&mut self, building_id: ObjectId<Building>) {
+        self.leave_building(
+            building_id,
+            CharacterEvent::ConstructionFinished {
+                building: building_id,
+            },
+        );
+    }
+
+    fn leave_building(&mut self, building_id: ObjectId<Building>, event: CharacterEvent) {
+        if matches!(self.current_task, CharacterTask::WorkAtSpot { building, .. } if building == building_id)
+        {
+            self.current_goal = CharacterGoal::Idle;
+            self.current_task = CharacterTask::Idle;
+            self.current_path = None;
+            self.record_event(event);
+        }
+    }
+
+    /// The building the character is hauling an item to, if any
+    pub(crate) fn hauling_to(&self) -> Option<ObjectId<Building>> {
+        match self.current_task {
+            CharacterTask::PickUp { destination, .. }
+            | CharacterTask::Deliver { destination, .. } => Some(destination),
+            _ => None,
+        }
+    }
+
+    /// Makes the character idle if it is going to pick up the given item
+    pub(crate) fn stop_hauling(&mut self, item_id: ObjectId<Item>) {
+        if matches!(self.current_task, CharacterTask::PickUp { item, .. } if item == item_id) {
+            self.current_goal = CharacterGoal::Idle;
+            self.current_task = CharacterTask::Idle;
+            self.current_path = None;
         }
     }
+
+    /// Makes the character idle if it is hauling an item to the given building.
+    /// Returns the item it was going to pick up, which it doesn't hold on to anymore,
+    /// or the item it was delivering, which it put down where it stands.
+    pub(crate) fn stop_hauling_to(
+        &mut self,
+        building_id: ObjectId<Building>,
+    ) -> Option<Result<ObjectId<Item>, Item>> {
+        let stopped = match self.current_task {
+            CharacterTask::PickUp { item, destination } if destination == building_id => Ok(item),
+            CharacterTask::Deliver { destination, .. } if destination == building_id => {
+                Err(self.take_delivered_item()?)
+            }
+            _ => return None,
+        };
+
+        self.current_goal = CharacterGoal::Idle;
+        self.current_task = CharacterTask::Idle;
+        self.current_path = None;
+        Some(stopped)
+    }
+
+    /// Take the item the character is delivering out of its inventory, to put it on the map where the character stands
+    fn take_delivered_item(&mut self) -> Option<Item> {
+        let CharacterTask::Deliver { kind, .. } = self.current_task else {
+            return None;
+        };
+        self.inventory
+            .remove(kind)
+            .then(|| Item::new(kind, self.location))
+    }
 }
 
 impl ObjectProperties for Character {
+    fn render_kind(&self) -> ObjectKind {
+        ObjectKind::Character
+    }
+
+    fn position(&self) -> Option<Vec2> {
+        Some(self.location)
+    }
+
     fn oxygen_users(&self) -> Vec<OxygenUser<usize>> {
         vec![OxygenUser {
             x: self.location.x.floor() as usize,
             y: self.location.y.floor() as usize,
             change_per_sec: 0.00001,
+            enabled: true,
         }]
     }
+
+    fn on_removed(&self, id: ObjectId<()>, objects: &mut Objects) {
+        // Nobody would ever come to free the workspots and items the character took
+        for mut building in objects.get_objects_mut::<Building>() {
+            building.release_workspots_of(id.cast());
+        }
+        for mut item in objects.get_objects_mut::<Item>() {
+            if item.hauler == Some(id.cast()) {
+                item.hauler = None;
+            }
+        }
+        for kind in self.inventory.all_items() {
+            objects.push_object::<Item>(Item::new(kind, self.location));
+        }
+    }
 }
 
-const SURVIVE_GOAL_ORDER: [SurviveGoal; 2] =
-    [SurviveGoal::RunFromDanger, SurviveGoal::PreventStarvation];
+const SURVIVE_GOAL_ORDER: [SurviveGoal; 4] = [
+    SurviveGoal::RunFromDanger,
+    SurviveGoal::FindAir,
+    SurviveGoal::PreventStarvation,
+    SurviveGoal::Rest,
+];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum SurviveGoal {
     RunFromDanger,
+    FindAir,
     PreventStarvation,
+    Rest,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WorkGoal {
     WorkAtVentilation,
+    /// Man the oxygen generators and fume scrubbers
+    WorkAtLifeSupport,
+    /// Dig out walls at the mining jobs
+    Mine,
+    /// Build the buildings that are under construction
+    Construct,
+    /// Bring the items on the map to the stockpiles and the input slots of the buildings
+    HaulItems,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum CharacterGoal {
     Survive(SurviveGoal),
     Work(WorkGoal),
@@ -66,7 +423,9 @@ pub(crate) enum CharacterGoal {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum CharacterTask {
+    /// Run to the target at the end of the current path and become idle there
     PanicRun {
         target: Vec2,
     },
@@ -74,9 +433,93 @@ pub(crate) enum CharacterTask {
         building: ObjectId<Building>,
         workspot_index: usize,
     },
+    /// Walk to the item at the end of the current path and pick it up
+    PickUp {
+        item: ObjectId<Item>,
+        destination: ObjectId<Building>,
+    },
+    /// Carry the item of the kind in the inventory to the building at the end of the current path
+    /// and put it down there
+    Deliver {
+        kind: ItemKind,
+        destination: ObjectId<Building>,
+    },
+    /// Walk to the end of the current path and stay there
+    MoveTo,
+    Rest,
     Idle,
 }
 
+/// Something a character decided or that happened to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CharacterEvent {
+    /// Claimed a workspot and is going there
+    ClaimedWorkspot {
+        building: ObjectId<Building>,
+        workspot_index: usize,
+    },
+    /// Stopped going to or working at a workspot
+    ReleasedWorkspot {
+        building: ObjectId<Building>,
+        workspot_index: usize,
+    },
+    /// Arrived at a workspot and started working there
+    StartedWorking {
+        building: ObjectId<Building>,
+        workspot_index: usize,
+    },
+    /// The path got blocked, so the character stopped what it was doing
+    PathBlocked,
+    /// The building the character was working at was removed
+    BuildingRemoved { building: ObjectId<Building> },
+    /// The building the character was constructing was finished
+    ConstructionFinished { building: ObjectId<Building> },
+}
+
+/// Something that happened to a character during a frame tick
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameEvent {
+    /// The character reached the end of its path
+    Arrived { character: ObjectId<Character> },
+    /// The character arrived at a workspot and started working there
+    StartedWork {
+        character: ObjectId<Character>,
+        building: ObjectId<Building>,
+        workspot_index: usize,
+    },
+    /// The character arrived at a workspot, but couldn't work there
+    FailedWork {
+        character: ObjectId<Character>,
+        building: ObjectId<Building>,
+        workspot_index: usize,
+    },
+    /// The character picked up the item it's hauling. The item left the map and is in its inventory now.
+    PickedUp {
+        character: ObjectId<Character>,
+        item: ObjectId<Item>,
+    },
+    /// The character took an item of the kind out of its inventory
+    /// and put it on the stockpile or in the input slots of the building.
+    /// An item put on a stockpile is a new item on the map.
+    Delivered {
+        character: ObjectId<Character>,
+        kind: ItemKind,
+        destination: ObjectId<Building>,
+    },
+    /// The character arrived at the item, but couldn't take it to where it was going
+    FailedHaul {
+        character: ObjectId<Character>,
+        item: ObjectId<Item>,
+    },
+    /// The character ran out of health and was removed from the map
+    Died {
+        character: ObjectId<Character>,
+        /// The tile the character died on
+        tile: UVec2,
+    },
+}
+
 #[derive(Debug)]
 pub(crate) struct AiChange {
     character_id: ObjectId<Character>,
@@ -85,97 +528,280 @@ pub(crate) struct AiChange {
     new_path: Option<Path>,
 }
 
-impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
-    const LIQUID_DROWN_HEIGHT: f32 = 2.0;
+/// The flow fields to the places that all characters look for, shared by all of them during one AI calculation.
+/// They're only made once the first character needs them.
+#[derive(Default)]
+struct SharedFlowFields {
+    safe_spots: OnceLock<FlowField>,
+    breathable_spots: OnceLock<FlowField>,
+    /// The open workspots of the work goals that have them, see [Self::open_workspots]
+    open_workspots: [OnceLock<OpenWorkspots>; 4],
+}
+
+impl SharedFlowFields {
+    /// None for the goals that aren't done at workspots
+    fn open_workspots(&self, work_goal: WorkGoal) -> Option<&OnceLock<OpenWorkspots>> {
+        let index = match work_goal {
+            WorkGoal::WorkAtVentilation => 0,
+            WorkGoal::WorkAtLifeSupport => 1,
+            WorkGoal::Mine => 2,
+            WorkGoal::Construct => 3,
+            WorkGoal::HaulItems => return None,
+        };
+        Some(&self.open_workspots[index])
+    }
+}
+
+/// The open workspots of the buildings of a work goal, with the flow field that leads to the closest of them
+struct OpenWorkspots {
+    flow_field: FlowField,
+    /// The building, workspot index and location of the workspots on every tile
+    workspots: HashMap<UVec2, Vec<(ObjectId<Building>, usize, Vec2)>>,
+}
 
+impl Map {
     pub(crate) fn calculate_ai_changes(&self) -> Vec<AiChange> {
-        let mut ai_changes = Vec::new();
+        self.calculate_ai_changes_with(!self.deterministic)
+    }
+
+    /// Calculate the AI changes of all characters, optionally spread over multiple threads.
+    ///
+    /// The changes are always in the order of the character ids,
+    /// so the result is the same regardless of the threading.
+    fn calculate_ai_changes_with(&self, parallel: bool) -> Vec<AiChange> {
+        self.refresh_path_cache();
+        let flow_fields = SharedFlowFields::default();
+        let objects = self.objects();
+
+        let mut ai_changes: Vec<AiChange> = if parallel {
+            let characters = objects.get_objects::<Character>().collect::<Vec<_>>();
+            characters
+                .par_iter()
+                .filter_map(|character| self.calculate_character_ai_change(character, &flow_fields))
+                .collect()
+        } else {
+            objects
+                .get_objects::<Character>()
+                .filter_map(|character| {
+                    self.calculate_character_ai_change(&character, &flow_fields)
+                })
+                .collect()
+        };
+
+        ai_changes.sort_by_key(|ai_change| ai_change.character_id);
+
+        ai_changes
+    }
+
+    fn calculate_character_ai_change(
+        &self,
+        character: &LockedObject<'_, Character>,
+        flow_fields: &SharedFlowFields,
+    ) -> Option<AiChange> {
+        'survive_loop: for possible_survive_goal in SURVIVE_GOAL_ORDER.iter() {
+            let is_current_goal =
+                character.current_goal == CharacterGoal::Survive(*possible_survive_goal);
+            let needs = &character.needs;
+
+            match possible_survive_goal {
+                SurviveGoal::RunFromDanger => {
+                    let target_is_safe = matches!(
+                        character.current_task,
+                        CharacterTask::PanicRun { target } if !self.is_in_danger(target)
+                    );
+                    if is_current_goal && target_is_safe {
+                        // We're already running to safety. The danger may have spread to where we
+                        // wanted to go though, in which case we look for another spot.
+                        return None;
+                    }
+
+                    if !self.is_in_danger(character.location) {
+                        continue 'survive_loop;
+                    }
+
+                    let Some(path) = self.find_safe_spot(character.location, flow_fields) else {
+                        // There's nowhere safe to run to, so we might as well keep doing what we're doing
+                        continue 'survive_loop;
+                    };
+
+                    return Some(AiChange {
+                        character_id: character.id(),
+                        new_goal: CharacterGoal::Survive(SurviveGoal::RunFromDanger),
+                        new_task: CharacterTask::PanicRun {
+                            target: *path.points.last().unwrap(),
+                        },
+                        new_path: Some(path),
+                    });
+                }
+                SurviveGoal::FindAir => {
+                    // Once we're looking for air, we keep breathing until we're fully saturated again
+                    let needs_air = if is_current_goal {
+                        needs.oxygen_saturation < 1.0
+                    } else {
+                        needs.oxygen_saturation < LOW_OXYGEN_SATURATION
+                    };
+                    if !needs_air {
+                        continue 'survive_loop;
+                    }
+
+                    if is_current_goal
+                        && (character.current_path.is_some()
+                            || self.is_breathable(character.location))
+                    {
+                        // Still on the way, or already breathing
+                        return None;
+                    }
+
+                    let Some(path) = self.find_breathable_spot(character.location, flow_fields)
+                    else {
+                        // There's no air to go to, so we might as well keep doing what we're doing
+                        continue 'survive_loop;
+                    };
 
-        'character_loop: for character in self.objects().get_objects::<Character>() {
-            'survive_loop: for possible_survive_goal in SURVIVE_GOAL_ORDER.iter() {
-                if character.current_goal == CharacterGoal::Survive(*possible_survive_goal) {
-                    // We already work on a goal of this importance
-                    continue 'character_loop;
+                    return Some(AiChange {
+                        character_id: character.id(),
+                        new_goal: CharacterGoal::Survive(SurviveGoal::FindAir),
+                        new_task: CharacterTask::MoveTo,
+                        new_path: Some(path),
+                    });
                 }
+                SurviveGoal::PreventStarvation => {
+                    if needs.hunger < STARVING_HUNGER {
+                        continue 'survive_loop;
+                    }
+                    if is_current_goal {
+                        return None;
+                    }
 
-                match possible_survive_goal {
-                    SurviveGoal::RunFromDanger => {
-                        let danger_detected = false;
-                        if !danger_detected {
-                            continue 'survive_loop;
-                        }
-                        todo!()
+                    // There's no food to go to, so the best we can do is stop working
+                    return Some(AiChange {
+                        character_id: character.id(),
+                        new_goal: CharacterGoal::Survive(SurviveGoal::PreventStarvation),
+                        new_task: CharacterTask::Idle,
+                        new_path: None,
+                    });
+                }
+                SurviveGoal::Rest => {
+                    let needs_rest = if is_current_goal {
+                        needs.fatigue > RESTED_FATIGUE
+                    } else {
+                        needs.fatigue >= EXHAUSTED_FATIGUE
+                    };
+                    if !needs_rest {
+                        continue 'survive_loop;
                     }
-                    SurviveGoal::PreventStarvation => {
-                        let is_starving = false;
-                        if !is_starving {
-                            continue 'survive_loop;
-                        }
-                        todo!()
+                    if is_current_goal {
+                        return None;
                     }
+
+                    return Some(AiChange {
+                        character_id: character.id(),
+                        new_goal: CharacterGoal::Survive(SurviveGoal::Rest),
+                        new_task: CharacterTask::Rest,
+                        new_path: None,
+                    });
                 }
             }
+        }
+
+        for possible_work_goal in character.work_goals_order.iter() {
+            let is_current_goal =
+                character.current_goal == CharacterGoal::Work(*possible_work_goal);
+
+            if is_current_goal
+                && (character.goal_cooldown > 0.0 || character.current_path.is_none())
+            {
+                // We already work on a goal of this importance and have either just committed to it
+                // or already arrived, so we stick with it
+                return None;
+            }
 
-            for possible_work_goal in character.work_goals_order.iter() {
-                if character.current_goal == CharacterGoal::Work(*possible_work_goal) {
-                    // We already work on a goal of this importance
-                    continue 'character_loop;
-                }
+            match possible_work_goal {
+                WorkGoal::WorkAtVentilation
+                | WorkGoal::WorkAtLifeSupport
+                | WorkGoal::Mine
+                | WorkGoal::Construct => {
+                    let closest_workspot = self.find_open_workspot(
+                        *possible_work_goal,
+                        character.location,
+                        flow_fields,
+                    );
 
-                match possible_work_goal {
-                    WorkGoal::WorkAtVentilation => {
-                        let closest_workspot = self
-                            .objects()
-                            // Get all buildings
-                            .get_objects::<Building>()
-                            // Only keep the ventilators
-                            .filter(|building| building.building_type.is_ventilator())
-                            // Get the open workspots of the ventilator and its index and the building id
-                            .flat_map(|building| {
-                                building
-                                    .workspots()
-                                    .into_iter()
-                                    .enumerate()
-                                    .filter(|(_, workspot)| workspot.occupation.is_open())
-                                    .map(move |(workspot_index, workspot)| {
-                                        (workspot_index, workspot, building.id())
-                                    })
-                            })
-                            // Calculate the path to the workspot and only keep the workspots that have a valid path
-                            .filter_map(|workspot| {
-                                self.find_path(character.location, workspot.1.location, true, true)
-                                    .map(|path| (workspot.0, workspot.2, path))
-                            })
-                            // Take the workspot with the shortest path
-                            .min_by_key(|(_, _, path)| OrderedFloat(path.total_length()));
-
-                        if let Some((closest_workspot_index, building_id, path)) = closest_workspot
-                        {
-                            ai_changes.push(AiChange {
-                                character_id: character.id(),
-                                new_goal: CharacterGoal::Work(WorkGoal::WorkAtVentilation),
-                                new_task: CharacterTask::WorkAtSpot {
-                                    building: building_id,
-                                    workspot_index: closest_workspot_index,
-                                },
-                                new_path: Some(path),
-                            })
+                    if let Some((closest_workspot_index, building_id, path)) = closest_workspot {
+                        if is_current_goal {
+                            let current_path_length = character
+                                .current_path
+                                .as_ref()
+                                .map(|path| path.total_length())
+                                .unwrap_or(f32::INFINITY);
+
+                            if path.total_length() + WORKSPOT_SWITCH_MARGIN >= current_path_length {
+                                // Not enough of an improvement to switch workspots
+                                return None;
+                            }
                         }
+
+                        return Some(AiChange {
+                            character_id: character.id(),
+                            new_goal: CharacterGoal::Work(*possible_work_goal),
+                            new_task: CharacterTask::WorkAtSpot {
+                                building: building_id,
+                                workspot_index: closest_workspot_index,
+                            },
+                            new_path: Some(path),
+                        });
+                    } else if is_current_goal {
+                        // There's no other option, so we keep working on the current one
+                        return None;
+                    }
+                }
+                WorkGoal::HaulItems => {
+                    if is_current_goal {
+                        // An item is brought all the way before another one is picked
+                        return None;
+                    }
+                    if character.inventory.is_full() {
+                        // There's no room to pick up an item
+                        continue;
+                    }
+
+                    let objects = self.objects();
+                    let haul = self.find_haul(&objects, character.location);
+                    drop(objects);
+
+                    if let Some((item, destination, path)) = haul {
+                        return Some(AiChange {
+                            character_id: character.id(),
+                            new_goal: CharacterGoal::Work(WorkGoal::HaulItems),
+                            new_task: CharacterTask::PickUp { item, destination },
+                            new_path: Some(path),
+                        });
                     }
                 }
             }
         }
 
-        ai_changes
+        if matches!(character.current_goal, CharacterGoal::Survive(_)) {
+            // The need that made us stop is taken care of and there's nothing else to do
+            return Some(AiChange {
+                character_id: character.id(),
+                new_goal: CharacterGoal::Idle,
+                new_task: CharacterTask::Idle,
+                new_path: None,
+            });
+        }
+
+        None
     }
 
     pub(crate) fn apply_ai_changes(&mut self, ai_changes: impl Iterator<Item = AiChange>) {
         let objects = self.objects();
+        // The items that characters were delivering when they switched to something else
+        let mut dropped_items = Vec::new();
 
         for ai_change in ai_changes {
             // We need to make some changes to the environment like workspot claims
             match &ai_change.new_task {
-                CharacterTask::PanicRun { .. } => todo!(),
                 CharacterTask::WorkAtSpot {
                     building,
                     workspot_index,
@@ -193,7 +819,32 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
                         continue;
                     }
                 }
-                CharacterTask::Idle => todo!(),
+                CharacterTask::PickUp { item, destination } => {
+                    let Some(kind) = objects.get_object(*item).map(|item| item.kind) else {
+                        log::warn!("Could not get item {:?}", item);
+                        continue;
+                    };
+                    if objects.item_room(*destination, kind) == 0 {
+                        // Another character is already bringing the last item that fits
+                        continue;
+                    }
+
+                    let Some(mut target_item) = objects.get_object_mut(*item) else {
+                        log::warn!("Could not get item {:?}", item);
+                        continue;
+                    };
+
+                    if target_item.hauler.is_some() {
+                        // Another character has just taken this
+                        continue;
+                    }
+                    target_item.hauler = Some(ai_change.character_id);
+                }
+                CharacterTask::Deliver { .. }
+                | CharacterTask::PanicRun { .. }
+                | CharacterTask::MoveTo
+                | CharacterTask::Rest
+                | CharacterTask::Idle => {}
             }
 
             let Some(mut character) = objects.get_object_mut(ai_change.character_id) else {
@@ -204,7 +855,6 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
             // We need to book off anything the character will stop doing like old workspots
 
             match character.current_task.clone() {
-                CharacterTask::PanicRun { .. } => todo!(),
                 CharacterTask::WorkAtSpot {
                     building,
                     workspot_index,
@@ -214,22 +864,155 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
                     } else {
                         log::warn!("Could not get building {:?}", building);
                     }
+
+                    character.record_event(CharacterEvent::ReleasedWorkspot {
+                        building,
+                        workspot_index,
+                    });
+                }
+                CharacterTask::PickUp { item, .. } => release_item(&objects, item),
+                CharacterTask::Deliver { .. } => {
+                    dropped_items.extend(character.take_delivered_item())
                 }
-                CharacterTask::Idle => {}
+                CharacterTask::PanicRun { .. }
+                | CharacterTask::MoveTo
+                | CharacterTask::Rest
+                | CharacterTask::Idle => {}
+            }
+
+            if let CharacterTask::WorkAtSpot {
+                building,
+                workspot_index,
+            } = ai_change.new_task
+            {
+                character.record_event(CharacterEvent::ClaimedWorkspot {
+                    building,
+                    workspot_index,
+                });
             }
 
             character.current_goal = ai_change.new_goal;
             character.current_task = ai_change.new_task;
             character.current_path = ai_change.new_path;
+            character.goal_cooldown = GOAL_SWITCH_COOLDOWN;
+        }
+        drop(objects);
+
+        let objects = self.objects.get_mut().unwrap();
+        for item in dropped_items {
+            objects.push_object::<Item>(item);
         }
     }
 
-    pub(crate) fn perform_ai_tick(&mut self, delta_time: f32) {
+    pub(crate) fn perform_ai_tick(&mut self, delta_time: f32) -> Vec<FrameEvent> {
         let objects = self.objects.read().unwrap();
+        let mut events = Vec::new();
+        let mut dead_characters = Vec::new();
+        // The items that were picked up into an inventory
+        let mut picked_up_items = Vec::new();
+        // The items that characters put on the map
+        let mut dropped_items = Vec::new();
+
+        let airlocks = objects
+            .get_objects::<Building>()
+            .filter_map(|building| building.airlock_doors())
+            .collect::<Vec<_>>();
+
+        'characters: for mut character in objects.get_objects_mut::<Character>() {
+            character.goal_cooldown = (character.goal_cooldown - delta_time).max(0.0);
+
+            let is_working = matches!(character.current_task, CharacterTask::WorkAtSpot { .. })
+                && character.current_path.is_none();
+            let is_walking = character.current_path.is_some();
+            let is_resting = matches!(
+                character.current_task,
+                CharacterTask::Rest | CharacterTask::Idle
+            );
+            let is_breathing = self.is_breathable(character.location);
+            let has_oxygen_tank = character.inventory.equipped() == Some(ItemKind::OxygenTank);
+            update_needs(
+                &mut character.needs,
+                delta_time,
+                is_working,
+                is_walking,
+                is_resting,
+                is_breathing,
+                has_oxygen_tank,
+            );
+
+            let damage = (self.burn_damage(character.location)
+                + self.lava_damage(character.location)
+                + need_damage(&character.needs))
+                * delta_time;
+            if damage > 0.0 {
+                character.health = (character.health - damage).max(0.0);
+            } else if character.health > 0.0
+                && character.needs.hunger <= REGEN_MAX_HUNGER
+                && self.is_safe_to_regenerate(character.location)
+            {
+                character.health =
+                    (character.health + HEALTH_REGEN_PER_SEC * delta_time).min(MAX_HEALTH);
+            }
+
+            if character.health <= 0.0 {
+                // Nobody would close the doors the character is walking through anymore
+                for door in character.opened_doors.drain(..) {
+                    close_door(&mut self.tiles, &mut self.render_dirty, door);
+                }
+                events.push(FrameEvent::Died {
+                    character: character.id(),
+                    tile: character.location.as_uvec2(),
+                });
+                dead_characters.push(character.id());
+                continue;
+            }
+
+            if let Some(path) = &character.current_path {
+                if self.is_path_blocked(path) {
+                    // Something changed on our path, so we stop what we were doing
+                    log::debug!(
+                        "Path of character {:?} got blocked, replanning",
+                        character.id()
+                    );
+                    dropped_items.extend(abandon_path(&objects, &mut character));
+                    continue;
+                }
+            }
+
+            if let Some(pushed) = self.current_push(character.location, delta_time) {
+                // Walls stop the character, like they do when walking
+                let location = self
+                    .walk_obstruction(character.location, pushed)
+                    .unwrap_or(pushed);
+                character.location = location;
+                if let Some(path) = &mut character.current_path {
+                    path.points[0] = location;
+                }
+            }
+
+            let next_tile = character
+                .current_path
+                .as_ref()
+                .and_then(|path| path.points.get(1))
+                .map(|point| point.as_uvec2());
+            let door_opening = match next_tile {
+                Some(next_tile) => open_door(
+                    &mut self.tiles,
+                    &mut self.render_dirty,
+                    &airlocks,
+                    next_tile,
+                ),
+                None => DoorOpening::NoDoor,
+            };
+            if let (DoorOpening::Opened, Some(door)) = (door_opening, next_tile) {
+                character.opened_doors.push(door);
+            }
 
-        for mut character in objects.get_objects_mut::<Character>() {
-            let arrived_at_destination = if let Some(mut path) = character.current_path.take() {
-                let mut distance_to_go = CHARACTER_WALK_SPEED * delta_time;
+            let arrived_at_destination = if door_opening == DoorOpening::HeldShut {
+                // The airlock first has to close its other door, so we wait
+                false
+            } else if let Some(mut path) = character.current_path.take() {
+                let mut distance_to_go = self.simulation_params.character_walk_speed * delta_time;
 
                 while distance_to_go.min(path.total_length()) > f32::EPSILON {
                     let walk_vector = path.points[1] - path.points[0];
@@ -237,7 +1020,18 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
                     let walk_direction = walk_vector / walk_distance;
 
                     let distance_walked = walk_distance.min(distance_to_go);
-                    character.location += walk_direction * distance_walked;
+                    let new_location = character.location + walk_direction * distance_walked;
+                    if let Some(stop) = self.walk_obstruction(character.location, new_location) {
+                        // The path only checks its points, a wall can still be in between them
+                        log::debug!(
+                            "Character {:?} walked into a wall, replanning",
+                            character.id()
+                        );
+                        character.location = stop;
+                        dropped_items.extend(abandon_path(&objects, &mut character));
+                        continue 'characters;
+                    }
+                    character.location = new_location;
                     path.points[0] = character.location;
 
                     distance_to_go -= distance_walked;
@@ -258,9 +1052,39 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
                 false
             };
 
+            if !character.opened_doors.is_empty() {
+                let current_tile = character.location.as_uvec2();
+                let next_tile = character
+                    .current_path
+                    .as_ref()
+                    .and_then(|path| path.points.get(1))
+                    .map(|point| point.as_uvec2());
+
+                // Close the doors we're through behind us
+                let (passed_doors, doors_in_use) = character
+                    .opened_doors
+                    .iter()
+                    .copied()
+                    .partition::<Vec<_>, _>(|door| {
+                        *door != current_tile && Some(*door) != next_tile
+                    });
+                for door in passed_doors {
+                    close_door(&mut self.tiles, &mut self.render_dirty, door);
+                }
+                character.opened_doors = doors_in_use;
+            }
+
             if arrived_at_destination {
+                events.push(FrameEvent::Arrived {
+                    character: character.id(),
+                });
+
                 match character.current_task {
-                    CharacterTask::PanicRun { .. } => todo!(),
+                    // We got away, so we can think about what to do next
+                    CharacterTask::PanicRun { .. } => {
+                        character.current_goal = CharacterGoal::Idle;
+                        character.current_task = CharacterTask::Idle;
+                    }
                     CharacterTask::WorkAtSpot {
                         building,
                         workspot_index,
@@ -269,6 +1093,11 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
                             character.current_goal = CharacterGoal::Idle;
                             character.current_task = CharacterTask::Idle;
                             log::warn!("Could not get building {building:?} to work at workspot {workspot_index:?}");
+                            events.push(FrameEvent::FailedWork {
+                                character: character.id(),
+                                building,
+                                workspot_index,
+                            });
                             continue;
                         };
 
@@ -279,12 +1108,102 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
                             character.current_goal = CharacterGoal::Idle;
                             character.current_task = CharacterTask::Idle;
                             log::warn!("Could not work at the designated spot at building {building:?} workspot {workspot_index:?}");
+                            events.push(FrameEvent::FailedWork {
+                                character: character.id(),
+                                building,
+                                workspot_index,
+                            });
+                        } else {
+                            character.record_event(CharacterEvent::StartedWorking {
+                                building,
+                                workspot_index,
+                            });
+                            events.push(FrameEvent::StartedWork {
+                                character: character.id(),
+                                building,
+                                workspot_index,
+                            });
                         }
                     }
-                    CharacterTask::Idle => todo!(),
+                    CharacterTask::PickUp { item, destination } => {
+                        let kind = objects
+                            .get_object(item)
+                            .filter(|item| item.hauler == Some(character.id()))
+                            .map(|item| item.kind);
+                        let path = objects
+                            .get_object(destination)
+                            .and_then(|destination| destination.position())
+                            .and_then(|to| {
+                                self.find_cached_path(character.location, to, true, true)
+                            });
+                        let picked_up = path.is_some()
+                            && kind.is_some_and(|kind| character.inventory.add(kind));
+
+                        match (path, kind) {
+                            (Some(path), Some(kind)) if picked_up => {
+                                character.current_task =
+                                    CharacterTask::Deliver { kind, destination };
+                                character.current_path = Some(path);
+                                picked_up_items.push(item);
+                                events.push(FrameEvent::PickedUp {
+                                    character: character.id(),
+                                    item,
+                                });
+                            }
+                            _ => {
+                                release_item(&objects, item);
+                                character.current_goal = CharacterGoal::Idle;
+                                character.current_task = CharacterTask::Idle;
+                                log::warn!(
+                                    "Could not take item {item:?} to building {destination:?}"
+                                );
+                                events.push(FrameEvent::FailedHaul {
+                                    character: character.id(),
+                                    item,
+                                });
+                            }
+                        }
+                    }
+                    CharacterTask::Deliver { kind, destination } => {
+                        // What doesn't go into an input slot, like everything brought to a stockpile, is put down
+                        let put_in = character.inventory.count(kind) > 0
+                            && objects
+                                .get_object_mut(destination)
+                                .is_some_and(|mut building| building.building_type.put_input(kind));
+                        if put_in {
+                            character.inventory.remove(kind);
+                        } else {
+                            dropped_items.extend(character.take_delivered_item());
+                        }
+
+                        character.current_goal = CharacterGoal::Idle;
+                        character.current_task = CharacterTask::Idle;
+                        events.push(FrameEvent::Delivered {
+                            character: character.id(),
+                            kind,
+                            destination,
+                        });
+                    }
+                    // We're there, so we just stay
+                    CharacterTask::MoveTo | CharacterTask::Rest | CharacterTask::Idle => {}
                 }
             }
         }
+        drop(objects);
+
+        // Removing a character also releases its workspots and stops its oxygen use
+        let objects = self.objects.get_mut().unwrap();
+        for character in dead_characters {
+            objects.remove_object(character);
+        }
+        for item in picked_up_items {
+            objects.remove_object(item);
+        }
+        for item in dropped_items {
+            objects.push_object::<Item>(item);
+        }
+
+        events
     }
 
     fn find_path(
@@ -295,13 +1214,20 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
         avoid_drowning: bool,
     ) -> Option<Path> {
         const NODES_PER_METER: u32 = 8;
+        const HEURISTIC_TIE_BREAKER: f32 = 1.001;
 
         // First make sure the from and to vectors are valid open positions
         self.position_penalty(from, avoid_lava, avoid_drowning)?;
         self.position_penalty(to, avoid_lava, avoid_drowning)?;
 
-        let node_snapped_from = (from * NODES_PER_METER as f32).round() / NODES_PER_METER as f32
-            + vec2(1.0 / NODES_PER_METER as f32, 1.0 / NODES_PER_METER as f32) / 2.0;
+        // The nodes are in the middle of a grid of cells. Snapping to the cell the position is in
+        // keeps the node on the same tile as the position.
+        let snap_to_node = |pos: Vec2| {
+            (pos * NODES_PER_METER as f32).floor() / NODES_PER_METER as f32
+                + vec2(1.0 / NODES_PER_METER as f32, 1.0 / NODES_PER_METER as f32) / 2.0
+        };
+        let node_snapped_from = snap_to_node(from);
+        let node_snapped_to = snap_to_node(to);
 
         let (path, _) = pathfinding::directed::astar::astar(
             &(
@@ -333,13 +1259,17 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
                 })
             },
             |pos| {
-                let pos = vec2(pos.0 .0, pos.1 .0);
-                pos.distance_squared(to).into()
-            },
-            |pos| {
-                let pos = vec2(pos.0 .0, pos.1 .0);
-                pos.distance_squared(to) <= 1.0 / (NODES_PER_METER as f32).powi(2)
+                // The estimate must never be more than the real cost, or the path can take detours.
+                // Every step goes straight or diagonally between the nodes and costs at least its length,
+                // so the octile distance is the cheapest a path can be.
+                // On open ground a lot of paths are equally short. Making the estimate a tiny bit bigger
+                // prefers the nodes closest to the target, so we don't explore all of those paths.
+                let delta = (node_snapped_to - vec2(pos.0 .0, pos.1 .0)).abs();
+                ((delta.max_element() + (SQRT_2 - 1.0) * delta.min_element())
+                    * HEURISTIC_TIE_BREAKER)
+                    .into()
             },
+            |pos| vec2(pos.0 .0, pos.1 .0) == node_snapped_to,
         )?;
 
         let mut points: Vec<_> = path.into_iter().map(|(x, y)| vec2(x.0, y.0)).collect();
@@ -347,49 +1277,1982 @@ impl<const WIDTH: usize, const HEIGHT: usize> Map<WIDTH, HEIGHT> {
         points[0] = from;
         points[num_points - 1] = to;
 
-        Some(Path { points })
+        Some(Path {
+            points,
+            avoid_lava,
+            avoid_drowning,
+        })
     }
 
-    /// - None if the position cannot be walked at all
-    /// - Some with number if walkable. Lower numbers are preferential.
-    fn position_penalty(
+    /// Like [Self::find_path], but searched between the centres of the tiles of the positions and kept in the
+    /// path cache, see [crate::path_cache]. The path still starts and ends at the exact positions.
+    pub(crate) fn find_cached_path(
         &self,
-        pos: Vec2,
+        from: Vec2,
+        to: Vec2,
         avoid_lava: bool,
         avoid_drowning: bool,
-    ) -> Option<OrderedFloat<f32>> {
-        let tile_coord = pos.as_uvec2();
-        let tile = &self.tiles[tile_coord.x as usize][tile_coord.y as usize];
+    ) -> Option<Path> {
+        // This also makes sure the positions are on the map
+        self.position_penalty(from, avoid_lava, avoid_drowning)?;
+        self.position_penalty(to, avoid_lava, avoid_drowning)?;
 
-        // Tile must have a ground, may have a little bit of water and optionally a bit of lava (so we can pathfind to escape it)
-        let liquids = tile.tile_type.get_liquids()?;
+        let key = PathKey::new(from, to, avoid_lava, avoid_drowning);
+        let cached_points = self.path_cache.lock().unwrap().get(&key);
+        let points = match cached_points {
+            Some(points) => points?,
+            None => {
+                let tile_centre = |tile: UVec2| tile.as_vec2() + vec2(0.5, 0.5);
+                let points = self
+                    .find_path(
+                        tile_centre(key.from),
+                        tile_centre(key.to),
+                        avoid_lava,
+                        avoid_drowning,
+                    )
+                    .map(|path| path.points);
+                self.path_cache.lock().unwrap().insert(key, points.clone());
+                points?
+            }
+        };
 
-        let liquid_level = liquids.get_level::<AnyLiquid>();
-        let will_drown = liquid_level > Self::LIQUID_DROWN_HEIGHT;
-        let is_lava = liquids.get_level::<Lava>() > 0.001;
+        // A straight line between two points on the same tile can always be walked. So the path can go straight
+        // from the position to the last point on its tile and from the first point on the target tile to the target,
+        // instead of walking to the tile centres first.
+        let first = points
+            .iter()
+            .rposition(|point| point.as_uvec2() == key.from)
+            .unwrap_or(0);
+        let last = points[first..]
+            .iter()
+            .position(|point| point.as_uvec2() == key.to)
+            .map_or(points.len() - 1, |index| first + index);
 
-        if will_drown && avoid_drowning || is_lava && avoid_lava {
-            return None;
-        }
+        let mut path_points = Vec::with_capacity(last - first + 3);
+        path_points.push(from);
+        path_points.extend_from_slice(&points[first..=last]);
+        path_points.push(to);
 
-        Some(
-            (liquid_level
-                * if is_lava { 100000.0 } else { 1.0 }
-                * if will_drown { 100000.0 } else { 1.0 })
-            .into(),
-        )
+        Some(Path {
+            points: path_points,
+            avoid_lava,
+            avoid_drowning,
+        })
     }
-}
 
-#[derive(Debug)]
-pub(crate) struct Path {
-    points: Vec<Vec2>,
-}
+    /// Find a path to any walkable tile next to the target tile.
+    ///
+    /// The target tile itself may be impassable (like a wall that has to be dug or worked on).
+    /// The path ends in the middle of the neighbour tile that gives the shortest path.
+    pub fn find_path_adjacent(
+        &self,
+        from: Vec2,
+        target_tile: UVec2,
+        avoid_lava: bool,
+        avoid_drowning: bool,
+    ) -> Option<Path> {
+        self.neighbour_tiles(target_tile.x as usize, target_tile.y as usize)
+            .map(|(x, y, _)| vec2(x as f32, y as f32) + vec2(0.5, 0.5))
+            .filter(|to| {
+                self.position_penalty(*to, avoid_lava, avoid_drowning)
+                    .is_some()
+            })
+            .filter_map(|to| self.find_path(from, to, avoid_lava, avoid_drowning))
+            .min_by(|a, b| a.total_length().total_cmp(&b.total_length()))
+    }
 
-impl Path {
-    pub(crate) fn total_length(&self) -> f32 {
-        self.points
-            .windows(2)
+    /// The health a character at the given position loses per second due to the heat
+    fn burn_damage(&self, pos: Vec2) -> f32 {
+        let tile_coord = pos.as_uvec2();
+        let Some(temperature) =
+            self.tiles[(tile_coord.x as usize, tile_coord.y as usize)].temperature()
+        else {
+            return 0.0;
+        };
+
+        (temperature - self.hazard_params.max_safe_temperature).max(0.0)
+            * self.hazard_params.burn_damage_per_degree
+    }
+
+    /// The health a character at the given position loses per second due to standing in lava
+    fn lava_damage(&self, pos: Vec2) -> f32 {
+        let tile_coord = pos.as_uvec2();
+        let in_lava = self.tiles[(tile_coord.x as usize, tile_coord.y as usize)]
+            .tile_type
+            .get_liquids()
+            .is_some_and(|liquids| liquids.get_level::<Lava>() > 0.001);
+
+        if in_lava {
+            self.hazard_params.lava_damage_per_sec
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns true if a character at the given position is breathing well and isn't in any danger
+    fn is_safe_to_regenerate(&self, pos: Vec2) -> bool {
+        let tile_coord = pos.as_uvec2();
+        let Some(liquids) = self.tiles[(tile_coord.x as usize, tile_coord.y as usize)]
+            .tile_type
+            .get_liquids()
+        else {
+            return false;
+        };
+
+        liquids.get_level::<Lava>() <= 0.001 && self.is_breathable(pos)
+    }
+
+    /// Returns true if a character at the given position isn't under liquid and has enough oxygen in the air
+    fn is_breathable(&self, pos: Vec2) -> bool {
+        let tile_coord = pos.as_uvec2();
+        let Some((air, liquids)) = self.tiles[(tile_coord.x as usize, tile_coord.y as usize)]
+            .tile_type
+            .get_ground()
+        else {
+            return false;
+        };
+
+        liquids.get_level::<AnyLiquid>() <= self.hazard_params.drown_liquid_level
+            && air.oxygen_fraction() >= REGEN_MIN_OXYGEN_FRACTION
+    }
+
+    /// Find the closest open workspot of the buildings of the work goal and the path to it.
+    /// All characters with the goal follow the same flow field to the tile of the workspot.
+    ///
+    /// Of the workspots on that tile, the one closest to where the path comes onto the tile is taken.
+    /// On equal distances, the lowest building id and workspot index wins.
+    fn find_open_workspot(
+        &self,
+        work_goal: WorkGoal,
+        from: Vec2,
+        flow_fields: &SharedFlowFields,
+    ) -> Option<(usize, ObjectId<Building>, Path)> {
+        let open_workspots = flow_fields.open_workspots(work_goal)?.get_or_init(|| {
+            let mut workspots = HashMap::<_, Vec<_>>::new();
+            for building in self.objects().get_objects::<Building>() {
+                if building.building_type.work_goal() != Some(work_goal) {
+                    continue;
+                }
+
+                for (index, workspot) in building.workspots().into_iter().enumerate() {
+                    if workspot.occupation.is_open() && workspot.location.min_element() >= 0.0 {
+                        workspots
+                            .entry(workspot.location.as_uvec2())
+                            .or_default()
+                            .push((building.id(), index, workspot.location));
+                    }
+                }
+            }
+
+            let goal_tiles = workspots.keys().copied().collect::<Vec<_>>();
+            OpenWorkspots {
+                flow_field: self.flow_field_to(&goal_tiles, true, true),
+                workspots,
+            }
+        });
+
+        let mut points = open_workspots.flow_field.path_from(from)?;
+        let goal_tile = points.last()?.as_uvec2();
+        if from.as_uvec2() == goal_tile {
+            // Already on the tile, so go straight to the workspot
+            points.truncate(1);
+        }
+        let approach = if points.len() > 1 {
+            points[points.len() - 2]
+        } else {
+            from
+        };
+
+        let (building, workspot_index, location) = open_workspots
+            .workspots
+            .get(&goal_tile)?
+            .iter()
+            .copied()
+            .min_by_key(|(building, workspot_index, location)| {
+                (
+                    OrderedFloat(location.distance(approach)),
+                    *building,
+                    *workspot_index,
+                )
+            })?;
+        // Characters can't walk a step of zero length
+        if points.last() != Some(&location) {
+            points.push(location);
+        }
+
+        Some((
+            workspot_index,
+            building,
+            Path {
+                points,
+                avoid_lava: true,
+                avoid_drowning: true,
+            },
+        ))
+    }
+
+    /// Find the path to the closest place with breathable air the character can walk to
+    fn find_breathable_spot(&self, from: Vec2, flow_fields: &SharedFlowFields) -> Option<Path> {
+        let flow_field = flow_fields.breathable_spots.get_or_init(|| {
+            let targets = self
+                .all_tile_coords()
+                .map(|(x, y)| UVec2::new(x as u32, y as u32))
+                .filter(|target| self.is_breathable(target.as_vec2() + vec2(0.5, 0.5)))
+                .collect::<Vec<_>>();
+            self.flow_field_to(&targets, true, true)
+        });
+
+        Self::follow_flow_field(flow_field, from)
+    }
+
+    /// Where the current of the water carries a character at the given position to in the given time,
+    /// or None when the water isn't deep enough or doesn't flow
+    fn current_push(&self, pos: Vec2, delta_time: f32) -> Option<Vec2> {
+        let tile_coord = pos.as_uvec2();
+        let (x, y) = (tile_coord.x as usize, tile_coord.y as usize);
+        let liquids = self.tiles.get(x, y)?.tile_type.get_liquids()?;
+        if liquids.get_level::<Water>() <= self.hazard_params.deep_water_level {
+            return None;
+        }
+
+        let push = self.liquid_flow(x, y) * self.hazard_params.current_push_fraction * delta_time;
+        (push != Vec2::ZERO).then_some(pos + push)
+    }
+
+    /// Returns true if a character at the given position is near lava, is drowning or has hardly any oxygen
+    fn is_in_danger(&self, pos: Vec2) -> bool {
+        let tile_coord = pos.as_uvec2();
+        let (x, y) = (tile_coord.x as usize, tile_coord.y as usize);
+        let Some((air, liquids)) = self.tiles[(x, y)].tile_type.get_ground() else {
+            return false;
+        };
+
+        let distance = self.hazard_params.lava_danger_distance;
+        let (min_x, min_y) = (x.saturating_sub(distance), y.saturating_sub(distance));
+        let lava_nearby = self
+            .tiles_in_rect(TileRect::new(
+                min_x,
+                min_y,
+                x + distance + 1 - min_x,
+                y + distance + 1 - min_y,
+            ))
+            .filter_map(|(_, _, tile)| tile.tile_type.get_liquids())
+            .any(|liquids| liquids.get_level::<Lava>() > 0.001);
+
+        lava_nearby
+            || liquids.get_level::<AnyLiquid>() > self.hazard_params.drown_liquid_level
+            || air.oxygen_fraction() < self.hazard_params.min_safe_oxygen_fraction
+    }
+
+    /// Find the path to the closest place that isn't dangerous the character can get to.
+    /// Places where the character can breathe are preferred, so it doesn't flee into air that's too thin.
+    ///
+    /// The path may go through lava and deep liquid, because that may be the only way out.
+    fn find_safe_spot(&self, from: Vec2, flow_fields: &SharedFlowFields) -> Option<Path> {
+        let flow_field = flow_fields.safe_spots.get_or_init(|| {
+            let safe_spots = self
+                .all_tile_coords()
+                .map(|(x, y)| UVec2::new(x as u32, y as u32))
+                .filter(|target| {
+                    let centre = target.as_vec2() + vec2(0.5, 0.5);
+                    self.position_penalty(centre, true, true).is_some()
+                        && !self.is_in_danger(centre)
+                })
+                .collect::<Vec<_>>();
+            let breathable_spots = safe_spots
+                .iter()
+                .copied()
+                .filter(|target| self.is_breathable(target.as_vec2() + vec2(0.5, 0.5)))
+                .collect::<Vec<_>>();
+
+            if breathable_spots.is_empty() {
+                self.flow_field_to(&safe_spots, false, false)
+            } else {
+                self.flow_field_to(&breathable_spots, false, false)
+            }
+        });
+
+        Self::follow_flow_field(flow_field, from)
+    }
+
+    fn follow_flow_field(flow_field: &FlowField, from: Vec2) -> Option<Path> {
+        Some(Path {
+            points: flow_field.path_from(from)?,
+            avoid_lava: flow_field.avoid_lava,
+            avoid_drowning: flow_field.avoid_drowning,
+        })
+    }
+
+    /// Find the closest item that has somewhere to go and the building that can take it that is closest to it.
+    /// Items that are already on a stockpile are only taken to the input slots of other buildings.
+    /// The path leads to the item.
+    fn find_haul(
+        &self,
+        objects: &Objects,
+        from: Vec2,
+    ) -> Option<(ObjectId<Item>, ObjectId<Building>, Path)> {
+        // The buildings that have room, with the kinds of items they take
+        let destinations = objects
+            .get_objects::<Building>()
+            .filter_map(|building| {
+                let is_stockpile = matches!(building.building_type, BuildingType::Stockpile { .. });
+                let kinds = ItemKind::ALL
+                    .into_iter()
+                    // Counting the incoming items is slow, so first skip the buildings without any room
+                    .filter(|kind| is_stockpile || building.building_type.input_room(*kind) > 0)
+                    .filter(|kind| objects.item_room(building.id(), *kind) > 0)
+                    .collect::<Vec<_>>();
+                if kinds.is_empty() {
+                    return None;
+                }
+
+                Some((building.id(), building.position()?, is_stockpile, kinds))
+            })
+            .collect::<Vec<_>>();
+        if destinations.is_empty() {
+            return None;
+        }
+
+        let takes =
+            |(_, _, is_stockpile, kinds): &(ObjectId<Building>, Vec2, bool, Vec<ItemKind>),
+             kind: ItemKind,
+             is_stored: bool| {
+                kinds.contains(&kind) && !(is_stored && *is_stockpile)
+            };
+
+        let mut closest_item: Option<(ObjectId<Item>, ItemKind, bool, Path)> = None;
+        for (min_distance, items) in objects.objects_by_distance::<Item>(from) {
+            // A path is never shorter than the straight line to the item
+            if closest_item
+                .as_ref()
+                .is_some_and(|(_, _, _, path)| path.total_length() < min_distance)
+            {
+                break;
+            }
+
+            for item in items.iter().filter(|item| item.hauler.is_none()) {
+                let is_stored = objects.is_stored(item);
+                if !destinations
+                    .iter()
+                    .any(|destination| takes(destination, item.kind, is_stored))
+                {
+                    continue;
+                }
+
+                let Some(path) = self.find_cached_path(from, item.location, true, true) else {
+                    continue;
+                };
+
+                // On equal lengths, the lowest item id wins
+                let is_closer =
+                    closest_item
+                        .as_ref()
+                        .is_none_or(|(closest, _, _, closest_path)| {
+                            (OrderedFloat(path.total_length()), item.id())
+                                < (OrderedFloat(closest_path.total_length()), *closest)
+                        });
+                if is_closer {
+                    closest_item = Some((item.id(), item.kind, is_stored, path));
+                }
+            }
+        }
+
+        let (item, kind, is_stored, path) = closest_item?;
+        let item_location = *path.points.last().unwrap();
+        let (destination, _) = destinations
+            .iter()
+            .filter(|destination| takes(destination, kind, is_stored))
+            .filter_map(|(destination, location, _, _)| {
+                let path = self.find_cached_path(item_location, *location, true, true)?;
+                Some((*destination, OrderedFloat(path.total_length())))
+            })
+            .min_by_key(|(destination, length)| (*length, *destination))?;
+
+        Some((item, destination, path))
+    }
+
+    /// Returns true if any of the points we still have to walk to can't be walked anymore
+    fn is_path_blocked(&self, path: &Path) -> bool {
+        path.points.iter().skip(1).any(|point| {
+            self.position_penalty(*point, path.avoid_lava, path.avoid_drowning)
+                .is_none()
+        })
+    }
+
+    /// Where a character walking in a straight line gets stopped by a tile it can't stand on,
+    /// or None when nothing is in the way.
+    ///
+    /// Every tile the line passes through is checked, except for the one it starts in.
+    /// Passing exactly through the corner of two tiles only checks the tile diagonally across,
+    /// just like the path finding lets characters squeeze between two walls that touch at a corner.
+    fn walk_obstruction(&self, from: Vec2, to: Vec2) -> Option<Vec2> {
+        // How close to a tile border the character stops
+        const MARGIN: f32 = 0.001;
+        // Border crossings closer together than this are taken as passing through a corner
+        const CORNER_EPSILON: f32 = 0.0001;
+
+        let delta = to - from;
+        let length = delta.length();
+        if length <= f32::EPSILON {
+            return None;
+        }
+
+        let mut tile = from.floor();
+        let end_tile = to.floor();
+        let step = vec2(delta.x.signum(), delta.y.signum());
+        // How far along the line (0 at from, 1 at to) one tile is in every direction
+        let t_delta = vec2(1.0 / delta.x.abs(), 1.0 / delta.y.abs());
+        // How far along the line the next tile border is in every direction
+        let border_t = |position: f32, tile: f32, delta: f32| {
+            if delta > 0.0 {
+                (tile + 1.0 - position) / delta
+            } else if delta < 0.0 {
+                (position - tile) / -delta
+            } else {
+                f32::INFINITY
+            }
+        };
+        let mut t_max = vec2(
+            border_t(from.x, tile.x, delta.x),
+            border_t(from.y, tile.y, delta.y),
+        );
+
+        while tile != end_tile {
+            let t = t_max.min_element();
+            if t > 1.0 {
+                break;
+            }
+
+            if (t_max.x - t_max.y).abs() < CORNER_EPSILON {
+                tile += step;
+                t_max += t_delta;
+            } else if t_max.x < t_max.y {
+                tile.x += step.x;
+                t_max.x += t_delta.x;
+            } else {
+                tile.y += step.y;
+                t_max.y += t_delta.y;
+            }
+
+            let walkable = tile.x >= 0.0
+                && tile.y >= 0.0
+                && self
+                    .tiles
+                    .get(tile.x as usize, tile.y as usize)
+                    .is_some_and(|tile| tile.tile_type.get_liquids().is_some());
+            if !walkable {
+                return Some(from + delta * (t - MARGIN / length).max(0.0));
+            }
+        }
+
+        None
+    }
+
+    /// - None if the position cannot be walked at all
+    /// - Some with number if walkable. Lower numbers are preferential.
+    pub(crate) fn position_penalty(
+        &self,
+        pos: Vec2,
+        avoid_lava: bool,
+        avoid_drowning: bool,
+    ) -> Option<OrderedFloat<f32>> {
+        // Positions outside of the map can't be walked
+        if pos.x < 0.0 || pos.y < 0.0 {
+            return None;
+        }
+        let tile_coord = pos.as_uvec2();
+        let tile = self
+            .tiles
+            .get(tile_coord.x as usize, tile_coord.y as usize)?;
+
+        // Tile must have a ground, may have a little bit of water and optionally a bit of lava (so we can pathfind to escape it)
+        let liquids = tile.tile_type.get_liquids()?;
+
+        let liquid_level = liquids.get_level::<AnyLiquid>();
+        let will_drown = liquid_level > self.hazard_params.drown_liquid_level;
+        let is_lava = liquids.get_level::<Lava>() > 0.001;
+
+        if will_drown && avoid_drowning || is_lava && avoid_lava {
+            return None;
+        }
+
+        let door_penalty = if tile.tile_type.is_closed_door() {
+            CLOSED_DOOR_PENALTY
+        } else {
+            0.0
+        };
+
+        if self.hazard_params.avoid_unsafe_temperature
+            && tile
+                .temperature()
+                .is_some_and(|temperature| temperature > self.hazard_params.max_safe_temperature)
+        {
+            return None;
+        }
+
+        // Grows slowly for shallow liquid and ever steeper when getting close to drowning depth
+        let depth = (liquid_level / LiquidData::MAX_LEVEL).clamp(0.0, 1.0);
+        let liquid_penalty =
+            ((depth * LIQUID_PENALTY_STEEPNESS).exp() - 1.0) * LIQUID_PENALTY_SCALE;
+
+        Some(
+            (liquid_penalty * if is_lava { LAVA_PENALTY_FACTOR } else { 1.0 } + door_penalty)
+                .into(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DoorOpening {
+    /// There's no closed door in the way
+    NoDoor,
+    /// A closed door was in the way and is open now
+    Opened,
+    /// A closed door is in the way, but its airlock has the other door open
+    HeldShut,
+}
+
+fn open_door(
+    tiles: &mut Grid<Tile>,
+    render_dirty: &mut Grid<bool>,
+    airlocks: &[[UVec2; 2]],
+    door: UVec2,
+) -> DoorOpening {
+    let (x, y) = (door.x as usize, door.y as usize);
+    if !tiles[(x, y)].tile_type.is_closed_door() {
+        return DoorOpening::NoDoor;
+    }
+
+    let other_door_open = airlocks
+        .iter()
+        .filter(|doors| doors.contains(&door))
+        .flatten()
+        .filter(|other_door| **other_door != door)
+        .any(|other_door| {
+            matches!(
+                tiles[(other_door.x as usize, other_door.y as usize)].tile_type,
+                TileType::Door { open: true, .. }
+            )
+        });
+    if other_door_open {
+        return DoorOpening::HeldShut;
+    }
+
+    if let TileType::Door { open, .. } = &mut tiles[(x, y)].tile_type {
+        *open = true;
+    }
+    render_dirty[(x, y)] = true;
+
+    DoorOpening::Opened
+}
+
+fn close_door(tiles: &mut Grid<Tile>, render_dirty: &mut Grid<bool>, door: UVec2) {
+    let (x, y) = (door.x as usize, door.y as usize);
+    if let TileType::Door { open, .. } = &mut tiles[(x, y)].tile_type {
+        *open = false;
+        render_dirty[(x, y)] = true;
+    }
+}
+
+fn update_needs(
+    needs: &mut Needs,
+    delta_time: f32,
+    is_working: bool,
+    is_walking: bool,
+    is_resting: bool,
+    is_breathing: bool,
+    has_oxygen_tank: bool,
+) {
+    needs.hunger = (needs.hunger + HUNGER_PER_SEC * delta_time).min(1.0);
+
+    let fatigue_change = if is_working {
+        WORKING_FATIGUE_PER_SEC
+    } else if is_walking {
+        WALKING_FATIGUE_PER_SEC
+    } else if is_resting {
+        -REST_RECOVERY_PER_SEC
+    } else {
+        0.0
+    };
+    needs.fatigue = (needs.fatigue + fatigue_change * delta_time).clamp(0.0, 1.0);
+
+    let oxygen_change = if is_breathing {
+        OXYGEN_RECOVERY_PER_SEC
+    } else if has_oxygen_tank {
+        -OXYGEN_LOSS_PER_SEC * OXYGEN_TANK_LOSS_FACTOR
+    } else {
+        -OXYGEN_LOSS_PER_SEC
+    };
+    needs.oxygen_saturation =
+        (needs.oxygen_saturation + oxygen_change * delta_time).clamp(0.0, 1.0);
+}
+
+/// The health a character loses per second due to its needs not being met
+fn need_damage(needs: &Needs) -> f32 {
+    let mut damage = 0.0;
+
+    if needs.hunger >= 1.0 {
+        damage += STARVATION_DAMAGE_PER_SEC;
+    }
+    if needs.fatigue >= 1.0 {
+        damage += EXHAUSTION_DAMAGE_PER_SEC;
+    }
+    if needs.oxygen_saturation <= 0.0 {
+        damage += SUFFOCATION_DAMAGE_PER_SEC;
+    }
+
+    damage
+}
+
+/// Stop following the current path and whatever the character was going to do at the end of it.
+/// The next AI calculation will pick a new goal with a fresh path.
+/// Returns the item the character was delivering, which it put down where it stands
+fn abandon_path(objects: &Objects, character: &mut Character) -> Option<Item> {
+    let dropped_item = character.take_delivered_item();
+    match character.current_task {
+        CharacterTask::WorkAtSpot {
+            building,
+            workspot_index,
+        } => {
+            if let Some(mut target_building) = objects.get_object_mut(building) {
+                target_building.release_workspot(workspot_index);
+            }
+        }
+        CharacterTask::PickUp { item, .. } => release_item(objects, item),
+        CharacterTask::Deliver { .. }
+        | CharacterTask::PanicRun { .. }
+        | CharacterTask::MoveTo
+        | CharacterTask::Rest
+        | CharacterTask::Idle => {}
+    }
+
+    character.current_goal = CharacterGoal::Idle;
+    character.current_task = CharacterTask::Idle;
+    character.current_path = None;
+    character.record_event(CharacterEvent::PathBlocked);
+    dropped_item
+}
+
+/// Let go of the item, so it stays where it is and any character can haul it
+fn release_item(objects: &Objects, item: ObjectId<Item>) {
+    if let Some(mut item) = objects.get_object_mut(item) {
+        item.hauler = None;
+    }
+}
+
+/// The points a character walks along in straight lines
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Path {
+    points: Vec<Vec2>,
+    avoid_lava: bool,
+    avoid_drowning: bool,
+}
+
+impl Snapshot for Path {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.points);
+        writer.write(&self.avoid_lava);
+        writer.write(&self.avoid_drowning);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            points: reader.read()?,
+            avoid_lava: reader.read()?,
+            avoid_drowning: reader.read()?,
+        })
+    }
+}
+
+impl Path {
+    pub fn points(&self) -> &[Vec2] {
+        &self.points
+    }
+
+    /// The distance walked along all points
+    pub fn total_length(&self) -> f32 {
+        self.points
+            .windows(2)
             .fold(0.0, |len, points| len + points[0].distance(points[1]))
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        air::{AirData, AirLeveler, GasMask},
+        liquids::LiquidData,
+        objects::{
+            building::{BuildingType, WorkSpot, WorkSpotOccupation, GENERATOR_FUEL_PER_ORE},
+            environment_object::EnvironmentObject,
+        },
+        tiles::TileType,
+        Facing, SimulationParams,
+    };
+    use glam::uvec2;
+
+    /// Two open workspots on the tile of the building, left and right of its centre
+    pub(crate) fn open_workspots() -> [WorkSpot; 2] {
+        [
+            WorkSpot {
+                location: vec2(0.2, 0.5),
+                occupation: WorkSpotOccupation::Open,
+            },
+            WorkSpot {
+                location: vec2(0.8, 0.5),
+                occupation: WorkSpotOccupation::Open,
+            },
+        ]
+    }
+
+    pub(crate) fn ventilator(location: UVec2, facing: Facing) -> Building {
+        Building {
+            location,
+            facing,
+            building_type: BuildingType::HandCrankedVentilator {
+                workspots: open_workspots(),
+            },
+        }
+    }
+
+    #[test]
+    fn replan_blocked_path() {
+        let mut map = Map::new_default(10, 3);
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(8, 1), Facing::East));
+
+        map.perform_simulation_tick(0.05);
+        assert!(map
+            .objects()
+            .get_object(character)
+            .unwrap()
+            .current_path
+            .is_some());
+
+        // Flood the straight route with a thin layer of lava that won't spread
+        map.tiles[(4, 1)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Lava { level: 0.05 },
+        };
+
+        for frame in 0..1200 {
+            if frame % 3 == 0 {
+                map.perform_simulation_tick(0.05);
+            }
+            map.perform_frame_tick(1.0 / 60.0);
+
+            let location = map.objects().get_object(character).unwrap().location;
+            assert_ne!(location.floor().as_uvec2(), uvec2(4, 1));
+        }
+
+        assert!(map
+            .objects()
+            .get_object(building)
+            .unwrap()
+            .workspots()
+            .iter()
+            .any(|workspot| workspot.occupation.is_working()));
+    }
+
+    #[test]
+    fn workspot_stickiness() {
+        let mut map = Map::new_default(10, 3);
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        // Facing east, both workspots are equally far away from the character
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(6, 1), Facing::East));
+
+        let current_workspot =
+            |map: &Map| match map.objects().get_object(character).unwrap().current_task {
+                CharacterTask::WorkAtSpot { workspot_index, .. } => Some(workspot_index),
+                _ => None,
+            };
+
+        map.perform_simulation_tick(0.05);
+        let committed_workspot = current_workspot(&map).unwrap();
+
+        for frame in 0..600 {
+            if frame % 3 == 0 {
+                map.perform_simulation_tick(0.05);
+            }
+            map.perform_frame_tick(1.0 / 60.0);
+
+            assert_eq!(current_workspot(&map), Some(committed_workspot));
+        }
+
+        assert!(
+            map.objects().get_object(building).unwrap().workspots()[committed_workspot]
+                .occupation
+                .is_working()
+        );
+    }
+
+    #[test]
+    fn characters_go_to_the_closest_workspot_by_walking() {
+        // The ventilator at the wall is closer as the crow flies, but the way around the wall is longer
+        let mut map = Map::new_default(10, 3);
+        for y in 0..2 {
+            map.tiles[(3, y)].tile_type = TileType::Wall;
+        }
+        let [behind_wall, open] = [uvec2(4, 0), uvec2(0, 0)].map(|location| {
+            map.objects_mut()
+                .push_object::<Building>(ventilator(location, Facing::North))
+        });
+        let [left, right] = [vec2(2.5, 0.5), vec2(6.5, 0.5)].map(|location| {
+            map.objects_mut().push_object::<Character>(Character::new(
+                location,
+                1.0,
+                vec![WorkGoal::WorkAtVentilation],
+            ))
+        });
+
+        map.perform_simulation_tick(0.05);
+
+        let objects = map.objects();
+        let working_at = |character: ObjectId<Character>| match objects
+            .get_object(character)
+            .unwrap()
+            .current_task
+        {
+            CharacterTask::WorkAtSpot { building, .. } => Some(building),
+            _ => None,
+        };
+        assert_eq!(working_at(left), Some(open));
+        assert_eq!(working_at(right), Some(behind_wall));
+    }
+
+    #[test]
+    fn building_removal_idles_workers() {
+        let mut map = Map::new_default(10, 3);
+        let worker = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let walker = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(2, 1), Facing::East));
+
+        // Let the first character start working
+        for frame in 0..120 {
+            if frame % 3 == 0 {
+                map.perform_simulation_tick(0.05);
+            }
+            map.perform_frame_tick(1.0 / 60.0);
+        }
+        assert!(map
+            .objects()
+            .get_object(building)
+            .unwrap()
+            .workspots()
+            .iter()
+            .any(|workspot| matches!(workspot.occupation, WorkSpotOccupation::Working(id) if id == worker)));
+
+        map.objects_mut().remove_object(building);
+
+        for character in [worker, walker] {
+            let objects = map.objects();
+            let character = objects.get_object(character).unwrap();
+            assert_eq!(character.current_goal, CharacterGoal::Idle);
+            assert!(matches!(character.current_task, CharacterTask::Idle));
+            assert!(character.current_path.is_none());
+        }
+    }
+
+    #[test]
+    fn character_removal_releases_workspots() {
+        let mut map = Map::new_default(10, 3);
+        let worker = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let walker = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(2, 1), Facing::East));
+
+        // One character works and the other has claimed the other workspot
+        for frame in 0..120 {
+            if frame % 3 == 0 {
+                map.perform_simulation_tick(0.05);
+            }
+            map.perform_frame_tick(1.0 / 60.0);
+        }
+        let occupations = |map: &Map| {
+            map.objects()
+                .get_object(building)
+                .unwrap()
+                .workspots()
+                .into_iter()
+                .map(|workspot| workspot.occupation)
+                .collect::<Vec<_>>()
+        };
+        assert!(occupations(&map)
+            .iter()
+            .all(|occupation| !occupation.is_open()));
+
+        map.objects_mut().remove_object(worker);
+        map.objects_mut().remove_object(walker);
+
+        assert!(occupations(&map)
+            .iter()
+            .all(|occupation| occupation.is_open()));
+    }
+
+    #[test]
+    fn characters_haul_items_to_stockpiles() {
+        let mut map = Map::new_default(8, 1);
+        let mut objects = map.objects_mut();
+        let stockpile = objects.push_object::<Building>(Building {
+            location: uvec2(7, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Stockpile { capacity: 1 },
+        });
+        let far = objects.push_object::<Item>(Item::new(ItemKind::Food, vec2(4.5, 0.5)));
+        let near = objects.push_object::<Item>(Item::new(ItemKind::Ore, vec2(2.5, 0.5)));
+        let hauler = objects.push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            vec![WorkGoal::HaulItems],
+        ));
+        drop(objects);
+
+        map.perform_simulation_tick(0.1);
+        assert_eq!(
+            map.objects().get_object(near).unwrap().hauler(),
+            Some(hauler)
+        );
+
+        let mut events = Vec::new();
+        for _ in 0..100 {
+            map.perform_simulation_tick(0.1);
+            events.extend(map.perform_frame_tick(0.1));
+        }
+        assert!(events.contains(&FrameEvent::PickedUp {
+            character: hauler,
+            item: near
+        }));
+        assert!(events.contains(&FrameEvent::Delivered {
+            character: hauler,
+            kind: ItemKind::Ore,
+            destination: stockpile
+        }));
+
+        // The stockpile is full, so the other item is left where it is
+        let stored = {
+            let objects = map.objects();
+            // The picked up item left the map, a new one was put on the stockpile
+            assert!(objects.get_object(near).is_none());
+            let stored_item = objects
+                .nearest_item(vec2(7.5, 0.5), ItemKind::Ore, |_| true)
+                .unwrap();
+            assert_eq!(stored_item.location.as_uvec2(), uvec2(7, 0));
+            assert_eq!(stored_item.hauler(), None);
+            assert!(objects.is_stored(&stored_item));
+            let far_item = objects.get_object(far).unwrap();
+            assert_eq!(far_item.location, vec2(4.5, 0.5));
+            assert_eq!(far_item.hauler(), None);
+            assert_eq!(objects.item_room(stockpile, ItemKind::Food), 0);
+            assert!(objects.get_object(hauler).unwrap().inventory().is_empty());
+            stored_item.id()
+        };
+
+        // A character carrying an item drops it when its stockpile is removed
+        map.objects_mut().remove_object(stored);
+        map.objects_mut().remove_object(stockpile);
+        let second_stockpile = map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(0, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Stockpile { capacity: 1 },
+        });
+        let mut picked_up = false;
+        for _ in 0..100 {
+            map.perform_simulation_tick(0.1);
+            picked_up |= map.perform_frame_tick(0.1).contains(&FrameEvent::PickedUp {
+                character: hauler,
+                item: far,
+            });
+            if picked_up {
+                break;
+            }
+        }
+        assert!(picked_up);
+        assert!(map.objects().get_object(far).is_none());
+        assert_eq!(
+            map.objects()
+                .get_object(hauler)
+                .unwrap()
+                .inventory()
+                .count(ItemKind::Food),
+            1
+        );
+        map.perform_frame_tick(0.5);
+        map.objects_mut().remove_object(second_stockpile);
+
+        let objects = map.objects();
+        let hauler = objects.get_object(hauler).unwrap();
+        assert!(hauler.inventory().is_empty());
+        let dropped_item = objects
+            .nearest_item(hauler.location, ItemKind::Food, |_| true)
+            .unwrap();
+        assert_eq!(dropped_item.hauler(), None);
+        assert_eq!(dropped_item.location, hauler.location);
+        assert!(dropped_item.location.x < 4.5);
+    }
+
+    #[test]
+    fn characters_feed_generators() {
+        let mut map = Map::new_default(6, 1);
+        let mut objects = map.objects_mut();
+        objects.push_object::<Building>(Building {
+            location: uvec2(0, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Stockpile { capacity: 4 },
+        });
+        let generator = objects.push_object::<Building>(Building {
+            location: uvec2(5, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Generator {
+                output: 1.0,
+                enabled: false,
+                fuel: Some(0.0),
+            },
+        });
+        // The stockpile can't use it, but the generator can
+        let ore = objects.push_object::<Item>(Item::new(ItemKind::Ore, vec2(0.5, 0.5)));
+        let food = objects.push_object::<Item>(Item::new(ItemKind::Food, vec2(0.7, 0.5)));
+        let hauler = objects.push_object::<Character>(Character::new(
+            vec2(2.5, 0.5),
+            1.0,
+            vec![WorkGoal::HaulItems],
+        ));
+        drop(objects);
+
+        let mut events = Vec::new();
+        for _ in 0..100 {
+            map.perform_simulation_tick(0.1);
+            events.extend(map.perform_frame_tick(0.1));
+        }
+        assert!(events.contains(&FrameEvent::Delivered {
+            character: hauler,
+            kind: ItemKind::Ore,
+            destination: generator
+        }));
+
+        let objects = map.objects();
+        // The ore was burned, the food stayed on the stockpile
+        assert!(objects.get_object(ore).is_none());
+        assert_eq!(objects.get_object(food).unwrap().location, vec2(0.7, 0.5));
+        assert!(objects.get_object(hauler).unwrap().inventory().is_empty());
+        assert!(matches!(
+            objects.get_object(generator).unwrap().building_type,
+            BuildingType::Generator {
+                fuel: Some(fuel),
+                ..
+            } if fuel == GENERATOR_FUEL_PER_ORE
+        ));
+    }
+
+    #[test]
+    fn liquid_penalty_is_smooth() {
+        let mut map = Map::new_default(1, 1);
+        let mut penalty = |level: f32| {
+            map.tiles[(0, 0)].tile_type = TileType::Ground {
+                air: Default::default(),
+                liquids: LiquidData::Water { level },
+            };
+            map.position_penalty(vec2(0.5, 0.5), false, false)
+                .unwrap()
+                .0
+        };
+
+        assert_eq!(penalty(0.0), 0.0);
+        assert!(penalty(0.5) < penalty(1.0));
+
+        let mut last_penalty = penalty(0.1);
+        for level in (2..=30).map(|level| level as f32 / 10.0) {
+            let new_penalty = penalty(level);
+            // Always a bit worse, but never a cliff
+            assert!(new_penalty > last_penalty);
+            assert!(new_penalty < last_penalty * 3.0);
+            last_penalty = new_penalty;
+        }
+    }
+
+    #[test]
+    fn set_work_goals() {
+        let mut map = Map::new_default(10, 3);
+        let character =
+            map.objects_mut()
+                .push_object::<Character>(Character::new(vec2(0.5, 1.5), 1.0, vec![]));
+        map.objects_mut()
+            .push_object::<Building>(ventilator(uvec2(3, 1), Facing::East));
+
+        // Without any goals, the character doesn't do anything
+        map.perform_simulation_tick(0.05);
+        assert_eq!(
+            map.objects().get_object(character).unwrap().current_goal,
+            CharacterGoal::Idle
+        );
+
+        map.objects()
+            .get_object_mut(character)
+            .unwrap()
+            .set_work_goals(vec![WorkGoal::WorkAtVentilation]);
+        assert_eq!(
+            map.objects().get_object(character).unwrap().work_goals(),
+            &[WorkGoal::WorkAtVentilation]
+        );
+
+        map.perform_simulation_tick(0.05);
+        assert_eq!(
+            map.objects().get_object(character).unwrap().current_goal,
+            CharacterGoal::Work(WorkGoal::WorkAtVentilation)
+        );
+    }
+
+    #[test]
+    fn step_until_working() {
+        let mut map = Map::new_default(10, 3);
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(3, 1), Facing::East));
+
+        let is_working = |map: &Map| {
+            map.objects()
+                .get_object(building)
+                .unwrap()
+                .workspots()
+                .iter()
+                .any(|workspot| workspot.occupation.is_working())
+        };
+
+        assert!(!map.step_until(1.0 / 60.0, 10, is_working));
+        assert!(map.step_until(1.0 / 60.0, 600, is_working));
+    }
+
+    #[test]
+    fn recent_events() {
+        let mut map = Map::new_default(10, 3);
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(3, 1), Facing::East));
+
+        map.perform_simulation_tick(0.05);
+
+        let workspot_index = match map.objects().get_object(character).unwrap().current_task {
+            CharacterTask::WorkAtSpot { workspot_index, .. } => workspot_index,
+            _ => panic!("The character should be going to work"),
+        };
+        assert_eq!(
+            map.objects()
+                .get_object(character)
+                .unwrap()
+                .recent_events()
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![CharacterEvent::ClaimedWorkspot {
+                building,
+                workspot_index
+            }]
+        );
+
+        for _ in 0..600 {
+            map.perform_frame_tick(1.0 / 60.0);
+        }
+        assert_eq!(
+            map.objects()
+                .get_object(character)
+                .unwrap()
+                .recent_events()
+                .last(),
+            Some(&CharacterEvent::StartedWorking {
+                building,
+                workspot_index
+            })
+        );
+
+        // Only the most recent events are remembered
+        let mut character = Character::new(vec2(0.5, 0.5), 1.0, vec![]);
+        for _ in 0..RECENT_EVENTS_CAPACITY * 2 {
+            character.record_event(CharacterEvent::PathBlocked);
+        }
+        assert_eq!(character.recent_events().count(), RECENT_EVENTS_CAPACITY);
+    }
+
+    #[test]
+    fn frame_events() {
+        let mut map = Map::new_default(10, 3);
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(3, 1), Facing::East));
+
+        map.perform_simulation_tick(0.05);
+
+        let mut events = Vec::new();
+        for _ in 0..600 {
+            events.extend(map.perform_frame_tick(1.0 / 60.0));
+        }
+
+        let CharacterTask::WorkAtSpot { workspot_index, .. } =
+            map.objects().get_object(character).unwrap().current_task
+        else {
+            panic!("The character should be working");
+        };
+        assert_eq!(
+            events,
+            vec![
+                FrameEvent::Arrived { character },
+                FrameEvent::StartedWork {
+                    character,
+                    building,
+                    workspot_index
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn burn_damage() {
+        let mut map = Map::new_default(3, 1);
+        map.tiles[(0, 0)].temperature = 200.0;
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            0.5,
+            Vec::new(),
+        ));
+
+        assert_eq!(map.collect_temperature_map()[(0, 0)], Some(200.0));
+
+        for _ in 0..10 {
+            map.perform_ai_tick(0.1);
+        }
+        assert!(map.objects().get_object(character).unwrap().health < 0.5);
+
+        // The hot tile is avoided when configured
+        assert!(map
+            .find_path(vec2(1.5, 0.5), vec2(0.5, 0.5), false, false)
+            .is_none());
+        map.set_hazard_params(HazardParams {
+            avoid_unsafe_temperature: false,
+            ..Default::default()
+        });
+        assert!(map
+            .find_path(vec2(1.5, 0.5), vec2(0.5, 0.5), false, false)
+            .is_some());
+    }
+
+    #[test]
+    fn characters_die_in_lava() {
+        let mut map = Map::new_default(3, 1);
+        *map.tiles[(0, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Lava { level: 0.1 };
+        let unlucky = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            0.2,
+            Vec::new(),
+        ));
+        let bystander = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(2.5, 0.5),
+            0.2,
+            Vec::new(),
+        ));
+
+        let mut events = Vec::new();
+        for _ in 0..10 {
+            events.extend(map.perform_ai_tick(0.1));
+        }
+
+        assert_eq!(
+            events,
+            vec![FrameEvent::Died {
+                character: unlucky,
+                tile: uvec2(0, 0),
+            }]
+        );
+        assert!(map.objects().get_object(unlucky).is_none());
+        assert!(map.objects().get_object(bystander).is_some());
+    }
+
+    #[test]
+    fn walking_stops_at_walls() {
+        let mut map = Map::new_default(5, 2);
+        map.tiles[(2, 0)].tile_type = TileType::Wall;
+
+        // A path with no points on the wall, so only the movement itself can notice it
+        let mut character = Character::new(vec2(0.5, 0.5), 1.0, Vec::new());
+        character.current_path = Some(Path {
+            points: vec![vec2(0.5, 0.5), vec2(4.5, 0.5)],
+            avoid_lava: false,
+            avoid_drowning: false,
+        });
+        let character = map.objects_mut().push_object::<Character>(character);
+
+        for _ in 0..30 {
+            map.perform_ai_tick(0.1);
+        }
+
+        let objects = map.objects();
+        let character = objects.get_object(character).unwrap();
+        assert!(character.location.x < 2.0 && character.location.x > 1.9);
+        assert!(character.current_path.is_none());
+        assert_eq!(
+            character.recent_events().last(),
+            Some(&CharacterEvent::PathBlocked)
+        );
+    }
+
+    #[test]
+    fn idle_characters_stay_where_they_arrive() {
+        let mut map = Map::new_default(3, 1);
+
+        let mut character = Character::new(vec2(0.5, 0.5), 1.0, Vec::new());
+        character.current_path = Some(Path {
+            points: vec![vec2(0.5, 0.5), vec2(2.5, 0.5)],
+            avoid_lava: false,
+            avoid_drowning: false,
+        });
+        let character = map.objects_mut().push_object::<Character>(character);
+
+        for _ in 0..30 {
+            map.perform_ai_tick(0.1);
+        }
+
+        let objects = map.objects();
+        let character = objects.get_object(character).unwrap();
+        assert_eq!(character.location, vec2(2.5, 0.5));
+        assert!(matches!(character.current_task, CharacterTask::Idle));
+        assert!(character.current_path.is_none());
+    }
+
+    #[test]
+    fn walk_speed_is_configurable() {
+        let walked_distance = |character_walk_speed| {
+            let mut map = Map::new_default(5, 1);
+            map.set_simulation_params(SimulationParams {
+                character_walk_speed,
+                ..Default::default()
+            });
+
+            let mut character = Character::new(vec2(0.5, 0.5), 1.0, Vec::new());
+            character.current_path = Some(Path {
+                points: vec![vec2(0.5, 0.5), vec2(4.5, 0.5)],
+                avoid_lava: false,
+                avoid_drowning: false,
+            });
+            let character = map.objects_mut().push_object::<Character>(character);
+
+            for _ in 0..5 {
+                map.perform_ai_tick(0.1);
+            }
+
+            let location = map.objects().get_object(character).unwrap().location;
+            location.x - 0.5
+        };
+
+        assert!((walked_distance(1.0) - 0.5).abs() < 0.01);
+        assert!((walked_distance(2.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn walk_obstruction() {
+        let mut map = Map::new_default(3, 3);
+        map.tiles[(1, 0)].tile_type = TileType::Wall;
+        map.tiles[(0, 1)].tile_type = TileType::Wall;
+
+        // Squeezing diagonally between two walls that touch at a corner is fine, like the path finding allows
+        assert_eq!(map.walk_obstruction(vec2(0.5, 0.5), vec2(1.5, 1.5)), None);
+        // But cutting past the corner of a wall isn't
+        let stop = map
+            .walk_obstruction(vec2(0.5, 0.5), vec2(1.5, 1.2))
+            .unwrap();
+        assert!(stop.x < 1.0 && stop.x > 0.99);
+        // Walking out of a wall is fine, so a character that got walled in can get out
+        assert_eq!(map.walk_obstruction(vec2(1.5, 0.5), vec2(1.5, 2.5)), None);
+        // Walking off the map isn't
+        assert!(map
+            .walk_obstruction(vec2(2.5, 2.5), vec2(3.5, 2.5))
+            .is_some());
+    }
+
+    #[test]
+    fn parallel_ai_matches_serial() {
+        let map = Map::new_default(20, 20);
+
+        for i in 0..3 {
+            map.objects_mut()
+                .push_object::<Building>(ventilator(uvec2(5 + i * 5, 10), Facing::North));
+        }
+        for i in 0..30 {
+            map.objects_mut().push_object::<Character>(Character::new(
+                vec2((i % 20) as f32 + 0.5, (i / 20) as f32 * 15.0 + 0.5),
+                1.0,
+                vec![WorkGoal::WorkAtVentilation],
+            ));
+        }
+
+        let serial = map.calculate_ai_changes_with(false);
+        let parallel = map.calculate_ai_changes_with(true);
+
+        assert_eq!(serial.len(), 30);
+        assert_eq!(format!("{serial:?}"), format!("{parallel:?}"));
+    }
+
+    #[test]
+    fn health_regeneration() {
+        let mut map = Map::new_default(3, 3);
+        let wounded = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(1.5, 1.5),
+            0.8,
+            Vec::new(),
+        ));
+        let mut starving_character = Character::new(vec2(1.5, 1.5), 0.8, Vec::new());
+        starving_character.needs.hunger = 1.0;
+        let starving = map
+            .objects_mut()
+            .push_object::<Character>(starving_character);
+
+        for _ in 0..100 {
+            map.perform_ai_tick(0.1);
+        }
+
+        let health =
+            |map: &Map, id: ObjectId<Character>| map.objects().get_object(id).unwrap().health;
+        assert!(health(&map, wounded) > 0.8);
+        assert!(health(&map, wounded) <= MAX_HEALTH);
+        assert!(health(&map, starving) < 0.8);
+
+        for _ in 0..10000 {
+            map.perform_ai_tick(0.1);
+        }
+        assert_eq!(health(&map, wounded), MAX_HEALTH);
+    }
+
+    #[test]
+    fn suffocating_character_finds_air() {
+        let mut map = Map::new_default(6, 3);
+        // Keep the left part of the map too low on oxygen to breathe, but not so low it's a reason to run
+        for x in 0..2 {
+            for y in 0..3 {
+                map.objects_mut()
+                    .push_object::<EnvironmentObject>(AirLeveler {
+                        x,
+                        y,
+                        nitrogen: 0.85,
+                        oxygen: 0.15,
+                        fumes: 0.0,
+                        gases: GasMask::ALL,
+                        reservoir: None,
+                        rate: None,
+                        enabled: true,
+                    });
+            }
+        }
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            Vec::new(),
+        ));
+        let get = |map: &Map| {
+            let objects = map.objects();
+            let character = objects.get_object(character).unwrap();
+            (character.needs, character.current_goal, character.location)
+        };
+
+        map.step_n(0.1, 100);
+        let (needs, goal, _) = get(&map);
+        assert!(needs.oxygen_saturation < 1.0);
+        assert_eq!(goal, CharacterGoal::Idle);
+
+        map.step_n(0.1, 60);
+        let (_, goal, _) = get(&map);
+        assert_eq!(goal, CharacterGoal::Survive(SurviveGoal::FindAir));
+
+        // Once it can breathe again and has caught its breath, it stops worrying about it
+        map.step_n(0.1, 100);
+        let (needs, goal, location) = get(&map);
+        assert!(location.x >= 2.0);
+        assert_eq!(needs.oxygen_saturation, 1.0);
+        assert_eq!(goal, CharacterGoal::Idle);
+    }
+
+    #[test]
+    fn oxygen_tank_slows_suffocation() {
+        let mut without_tank = Needs::new_default();
+        let mut with_tank = Needs::new_default();
+        update_needs(&mut without_tank, 1.0, false, false, false, false, false);
+        update_needs(&mut with_tank, 1.0, false, false, false, false, true);
+
+        approx::assert_relative_eq!(
+            1.0 - with_tank.oxygen_saturation,
+            (1.0 - without_tank.oxygen_saturation) * OXYGEN_TANK_LOSS_FACTOR
+        );
+    }
+
+    #[test]
+    fn characters_run_to_breathable_air() {
+        // No oxygen at all on the left, air that's safe but too thin to breathe in the middle
+        let mut map = Map::new_default(8, 1);
+        for x in 0..4 {
+            let oxygen = if x == 0 { 0.0 } else { 0.14 };
+            map.objects_mut()
+                .push_object::<EnvironmentObject>(AirLeveler {
+                    x,
+                    y: 0,
+                    nitrogen: 1.0 - oxygen,
+                    oxygen,
+                    fumes: 0.0,
+                    gases: GasMask::ALL,
+                    reservoir: None,
+                    rate: None,
+                    enabled: true,
+                });
+        }
+        map.step_n(0.1, 10);
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            Vec::new(),
+        ));
+
+        map.perform_simulation_tick(0.1);
+        let objects = map.objects();
+        let character = objects.get_object(character).unwrap();
+        assert_eq!(
+            character.current_goal,
+            CharacterGoal::Survive(SurviveGoal::RunFromDanger)
+        );
+        assert!(matches!(
+            character.current_task,
+            CharacterTask::PanicRun { target } if target.x >= 4.0
+        ));
+    }
+
+    #[test]
+    fn currents_push_characters() {
+        let pushed_location = |hazard_params| {
+            let mut map = Map::new_default(8, 1);
+            map.set_hazard_params(hazard_params);
+            for x in 0..3 {
+                *map.tiles[(x, 0)].tile_type.get_liquids_mut().unwrap() =
+                    LiquidData::Water { level: 1.5 };
+            }
+            let character = map.objects_mut().push_object::<Character>(Character::new(
+                vec2(2.5, 0.5),
+                1.0,
+                Vec::new(),
+            ));
+
+            map.perform_simulation_tick(0.1);
+            map.perform_frame_tick(0.1);
+            let location = map.objects().get_object(character).unwrap().location;
+            location
+        };
+
+        assert!(pushed_location(HazardParams::default()).x > 2.5);
+        // Water that isn't deep doesn't push
+        let location = pushed_location(HazardParams {
+            deep_water_level: 1.5,
+            ..Default::default()
+        });
+        assert_eq!(location, vec2(2.5, 0.5));
+    }
+
+    #[test]
+    fn characters_run_from_danger() {
+        let mut map = Map::new_default(8, 3);
+        // A thin layer of lava that won't spread
+        map.tiles[(1, 1)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Lava { level: 0.05 },
+        };
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(1.5, 1.5),
+            1.0,
+            Vec::new(),
+        ));
+        let get = |map: &Map| {
+            let objects = map.objects();
+            let character = objects.get_object(character).unwrap();
+            (
+                character.current_goal,
+                character.current_task.clone(),
+                character.location,
+            )
+        };
+
+        map.perform_simulation_tick(0.05);
+        let (goal, task, _) = get(&map);
+        assert_eq!(goal, CharacterGoal::Survive(SurviveGoal::RunFromDanger));
+        assert!(matches!(task, CharacterTask::PanicRun { .. }));
+
+        // Once it got away, it calms down again
+        map.step_n(0.1, 30);
+        let (goal, task, location) = get(&map);
+        assert_ne!(location.floor().as_uvec2(), uvec2(1, 1));
+        assert_eq!(goal, CharacterGoal::Idle);
+        assert!(matches!(task, CharacterTask::Idle));
+
+        // Lava further away is only a danger when configured
+        assert!(!map.is_in_danger(vec2(2.5, 1.5)));
+        map.set_hazard_params(HazardParams {
+            lava_danger_distance: 1,
+            ..Default::default()
+        });
+        assert!(map.is_in_danger(vec2(2.5, 1.5)));
+
+        // Deep water and air without oxygen are dangerous too
+        map.tiles[(5, 1)].tile_type = TileType::Ground {
+            air: Default::default(),
+            liquids: LiquidData::Water {
+                level: LiquidData::MAX_LEVEL,
+            },
+        };
+        map.tiles[(6, 1)].tile_type = TileType::Ground {
+            air: AirData {
+                nitrogen: 1.0,
+                oxygen: 0.0,
+                fumes: 0.0,
+                steam: 0.0,
+                carbon_dioxide: 0.0,
+                temperature: Tile::DEFAULT_TEMPERATURE,
+            },
+            liquids: Default::default(),
+        };
+        assert!(map.is_in_danger(vec2(5.5, 1.5)));
+        assert!(map.is_in_danger(vec2(6.5, 1.5)));
+        assert!(!map.is_in_danger(vec2(7.5, 1.5)));
+    }
+
+    #[test]
+    fn characters_man_life_support() {
+        let mut map = Map::new_default(10, 3);
+        for (x, y) in map.all_tile_coords() {
+            if let Some(air) = map.tiles[(x, y)].tile_type.get_air_mut() {
+                air.fumes = 0.2;
+            }
+        }
+        let total_fumes = |map: &Map| {
+            map.all_tile_coords()
+                .filter_map(|(x, y)| map.tiles[(x, y)].tile_type.get_air())
+                .map(|air| air.fumes)
+                .sum::<f32>()
+        };
+        let initial_fumes = total_fumes(&map);
+
+        let scrubber = map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(5, 1),
+            facing: Facing::East,
+            building_type: BuildingType::FumeScrubber {
+                workspots: open_workspots(),
+            },
+        });
+        // Only interested in the life support
+        let ventilation_worker = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 0.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let life_support_worker = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtLifeSupport],
+        ));
+
+        // Nobody works at the scrubber yet, so it doesn't do anything
+        map.perform_simulation_tick(0.05);
+        assert!(total_fumes(&map) >= initial_fumes);
+
+        map.step_n(0.1, 100);
+
+        let objects = map.objects();
+        assert_eq!(
+            objects.get_object(ventilation_worker).unwrap().current_goal,
+            CharacterGoal::Idle
+        );
+        assert_eq!(
+            objects
+                .get_object(life_support_worker)
+                .unwrap()
+                .current_goal,
+            CharacterGoal::Work(WorkGoal::WorkAtLifeSupport)
+        );
+        assert!(objects
+            .get_object(scrubber)
+            .unwrap()
+            .workspots()
+            .iter()
+            .any(|workspot| matches!(workspot.occupation, WorkSpotOccupation::Working(id) if id == life_support_worker)));
+        drop(objects);
+
+        // The scrubber cleans its own tile the fastest, while the rest of the fumes diffuse towards it
+        assert!(map.tiles[(5, 1)].tile_type.get_air().unwrap().fumes < 0.05);
+        assert!(total_fumes(&map) < initial_fumes);
+    }
+
+    #[test]
+    fn tired_and_hungry_characters_stop_working() {
+        let mut map = Map::new_default(10, 3);
+        map.objects_mut()
+            .push_object::<Building>(ventilator(uvec2(5, 1), Facing::East));
+        let mut tired_character =
+            Character::new(vec2(0.5, 1.5), 1.0, vec![WorkGoal::WorkAtVentilation]);
+        tired_character.needs.fatigue = 0.95;
+        let tired = map.objects_mut().push_object::<Character>(tired_character);
+        let mut hungry_character =
+            Character::new(vec2(0.5, 0.5), 1.0, vec![WorkGoal::WorkAtVentilation]);
+        hungry_character.needs.hunger = 0.9;
+        let hungry = map.objects_mut().push_object::<Character>(hungry_character);
+
+        let goal =
+            |map: &Map, id: ObjectId<Character>| map.objects().get_object(id).unwrap().current_goal;
+
+        map.perform_simulation_tick(0.05);
+        assert_eq!(goal(&map, tired), CharacterGoal::Survive(SurviveGoal::Rest));
+        assert_eq!(
+            goal(&map, hungry),
+            CharacterGoal::Survive(SurviveGoal::PreventStarvation)
+        );
+
+        // Resting brings the fatigue down until the character is ready to work again
+        map.step_n(1.0, 60);
+        assert_eq!(goal(&map, tired), CharacterGoal::Survive(SurviveGoal::Rest));
+        map.step_n(1.0, 60);
+        assert_eq!(
+            goal(&map, tired),
+            CharacterGoal::Work(WorkGoal::WorkAtVentilation)
+        );
+
+        // Fed by the game
+        map.objects().get_object_mut(hungry).unwrap().needs.hunger = 0.0;
+        map.perform_simulation_tick(0.05);
+        assert_eq!(
+            goal(&map, hungry),
+            CharacterGoal::Work(WorkGoal::WorkAtVentilation)
+        );
+    }
+
+    #[test]
+    fn characters_pass_through_airlocks() {
+        let mut map = Map::new_default(9, 3);
+        for x in 0..9 {
+            map.tiles[(x, 0)].tile_type = TileType::Wall;
+            map.tiles[(x, 2)].tile_type = TileType::Wall;
+        }
+        for x in [3, 5] {
+            map.tiles[(x, 1)].tile_type = TileType::Door {
+                open: false,
+                air: Default::default(),
+                liquids: Default::default(),
+            };
+        }
+        let airlock = map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(4, 1),
+            facing: Facing::East,
+            building_type: BuildingType::Airlock,
+        });
+        assert_eq!(
+            map.objects().get_object(airlock).unwrap().airlock_doors(),
+            Some([uvec2(5, 1), uvec2(3, 1)])
+        );
+
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(7, 1), Facing::East));
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+
+        let is_open = |map: &Map, x: usize| !map.tiles[(x, 1)].tile_type.is_closed_door();
+        let mut opened = [false; 2];
+
+        for frame in 0..900 {
+            if frame % 3 == 0 {
+                map.perform_simulation_tick(0.05);
+            }
+            map.perform_frame_tick(1.0 / 60.0);
+
+            // The airlock never has both doors open
+            assert!(!(is_open(&map, 3) && is_open(&map, 5)));
+            opened[0] |= is_open(&map, 3);
+            opened[1] |= is_open(&map, 5);
+        }
+
+        assert_eq!(opened, [true, true]);
+        // The doors are closed behind the character
+        assert!(!is_open(&map, 3) && !is_open(&map, 5));
+        assert!(map
+            .objects()
+            .get_object(building)
+            .unwrap()
+            .workspots()
+            .iter()
+            .any(|workspot| workspot.occupation.is_working()));
+    }
+
+    #[test]
+    fn path_around_wall() {
+        // A wall between the start and the target with a gap at the bottom
+        let mut map = Map::new_default(7, 7);
+        for y in 0..5 {
+            map.tiles[(3, y)].tile_type = TileType::Wall;
+        }
+
+        let path = map
+            .find_path(vec2(1.5, 1.5), vec2(5.5, 1.5), true, true)
+            .unwrap();
+
+        assert_eq!(path.points.first(), Some(&vec2(1.5, 1.5)));
+        assert_eq!(path.points.last(), Some(&vec2(5.5, 1.5)));
+        for point in path.points.iter() {
+            assert!(!map.tiles[(point.x as usize, point.y as usize)]
+                .tile_type
+                .is_wall());
+        }
+
+        // The path goes around the bottom corners of the wall, moving straight and diagonally between the nodes
+        let octile_distance = |a: Vec2, b: Vec2| {
+            let delta = (a - b).abs();
+            delta.max_element() + (SQRT_2 - 1.0) * delta.min_element()
+        };
+        let shortest = octile_distance(vec2(1.5, 1.5), vec2(3.0, 5.0))
+            + 1.0
+            + octile_distance(vec2(4.0, 5.0), vec2(5.5, 1.5));
+        assert!(path.total_length() > shortest - 0.1);
+        assert!(path.total_length() < shortest + 0.1);
+
+        // Without the gap there's no way around
+        map.tiles[(3, 5)].tile_type = TileType::Wall;
+        map.tiles[(3, 6)].tile_type = TileType::Wall;
+        assert!(map
+            .find_path(vec2(1.5, 1.5), vec2(5.5, 1.5), true, true)
+            .is_none());
+    }
+
+    #[test]
+    fn path_to_adjacent_tile() {
+        let mut map = Map::new_default(10, 3);
+        map.tiles[(6, 1)].tile_type = TileType::Wall;
+
+        // The wall itself can't be walked to
+        assert!(map
+            .find_path(vec2(0.5, 1.5), vec2(6.5, 1.5), true, true)
+            .is_none());
+
+        let path = map
+            .find_path_adjacent(vec2(0.5, 1.5), uvec2(6, 1), true, true)
+            .unwrap();
+        let end = *path.points.last().unwrap();
+
+        assert_eq!(end, vec2(5.5, 1.5));
+        assert!(map.tiles[(end.x as usize, end.y as usize)]
+            .tile_type
+            .get_liquids()
+            .is_some());
+        assert!(path.total_length() < 6.0);
+
+        // Completely walled in targets can't be reached
+        let mut map = Map::new_default(3, 3);
+        for (x, y) in map.all_tile_coords() {
+            if (x, y) != (0, 0) {
+                map.tiles[(x, y)].tile_type = TileType::Wall;
+            }
+        }
+        assert!(map
+            .find_path_adjacent(vec2(0.5, 0.5), uvec2(2, 2), true, true)
+            .is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        use crate::{liquids::LiquidLeveler, objects::environment_object::EnvironmentObject};
+
+        let mut map = Map::new_default(10, 3);
+        map.objects_mut()
+            .push_object::<EnvironmentObject>(LiquidLeveler {
+                x: 9,
+                y: 2,
+                target: LiquidData::Water { level: 1.0 },
+                enabled: true,
+                solidify: false,
+            });
+        let removed =
+            map.objects_mut()
+                .push_object::<Character>(Character::new(vec2(8.5, 0.5), 1.0, vec![]));
+        map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(3, 1), Facing::East));
+        map.objects_mut().remove_object(removed);
+        map.step_n(1.0 / 60.0, 30);
+
+        let json = serde_json::to_string(&map).unwrap();
+        let mut loaded = serde_json::from_str::<Map>(&json).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+        assert!(loaded.objects().get_object(removed).is_none());
+        assert!(loaded.objects().get_object(building).is_some());
+
+        // Both maps keep on simulating the same way
+        map.step_n(1.0 / 60.0, 30);
+        loaded.step_n(1.0 / 60.0, 30);
+        assert_eq!(
+            serde_json::to_string(&loaded).unwrap(),
+            serde_json::to_string(&map).unwrap()
+        );
+
+        // New objects don't reuse the id of the removed character
+        let new_id = loaded
+            .objects_mut()
+            .push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![]));
+        assert_ne!(new_id, removed);
+
+        // The tiles have to match the size of the map
+        let resized = json.replacen("\"width\":10", "\"width\":11", 1);
+        assert_ne!(resized, json);
+        assert!(serde_json::from_str::<Map>(&resized).is_err());
+    }
+}
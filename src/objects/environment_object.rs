@@ -1,15 +1,47 @@
+use glam::{vec2, Vec2};
+
 use crate::{
     air::{AirLeveler, AirPusher, OxygenUser},
+    heat::{HeatSink, HeatSource},
     liquids::LiquidLeveler,
-    objects::ObjectProperties,
+    objects::{ObjectKind, ObjectProperties},
 };
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EnvironmentObject {
     AirLeveler(AirLeveler<usize>),
     OxygenUser(OxygenUser<usize>),
     AirPusher(AirPusher<usize>),
     LiquidLeveler(LiquidLeveler<usize>),
+    HeatSource(HeatSource<usize>),
+    HeatSink(HeatSink<usize>),
+}
+
+impl EnvironmentObject {
+    /// Returns `true` if the object is enabled
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            EnvironmentObject::AirLeveler(al) => al.enabled,
+            EnvironmentObject::OxygenUser(ou) => ou.enabled,
+            EnvironmentObject::AirPusher(ap) => ap.enabled,
+            EnvironmentObject::LiquidLeveler(ll) => ll.enabled,
+            EnvironmentObject::HeatSource(hs) => hs.enabled,
+            EnvironmentObject::HeatSink(hs) => hs.enabled,
+        }
+    }
+
+    /// Enable or disable the object. A disabled object keeps existing, but doesn't do anything.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        match self {
+            EnvironmentObject::AirLeveler(al) => al.enabled = enabled,
+            EnvironmentObject::OxygenUser(ou) => ou.enabled = enabled,
+            EnvironmentObject::AirPusher(ap) => ap.enabled = enabled,
+            EnvironmentObject::LiquidLeveler(ll) => ll.enabled = enabled,
+            EnvironmentObject::HeatSource(hs) => hs.enabled = enabled,
+            EnvironmentObject::HeatSink(hs) => hs.enabled = enabled,
+        }
+    }
 }
 
 impl From<AirPusher<usize>> for EnvironmentObject {
@@ -36,7 +68,44 @@ impl From<AirLeveler<usize>> for EnvironmentObject {
     }
 }
 
+impl From<HeatSource<usize>> for EnvironmentObject {
+    fn from(v: HeatSource<usize>) -> Self {
+        Self::HeatSource(v)
+    }
+}
+
+impl From<HeatSink<usize>> for EnvironmentObject {
+    fn from(v: HeatSink<usize>) -> Self {
+        Self::HeatSink(v)
+    }
+}
+
 impl ObjectProperties for EnvironmentObject {
+    fn render_kind(&self) -> ObjectKind {
+        match self {
+            EnvironmentObject::AirLeveler(_) => ObjectKind::AirLeveler,
+            EnvironmentObject::OxygenUser(_) => ObjectKind::OxygenUser,
+            EnvironmentObject::AirPusher(_) => ObjectKind::AirPusher,
+            EnvironmentObject::LiquidLeveler(_) => ObjectKind::LiquidLeveler,
+            EnvironmentObject::HeatSource(_) => ObjectKind::HeatSource,
+            EnvironmentObject::HeatSink(_) => ObjectKind::HeatSink,
+        }
+    }
+
+    fn position(&self) -> Option<Vec2> {
+        let (x, y) = match self {
+            EnvironmentObject::AirLeveler(al) => (al.x, al.y),
+            EnvironmentObject::OxygenUser(ou) => (ou.x, ou.y),
+            EnvironmentObject::AirPusher(ap) => (ap.x, ap.y),
+            EnvironmentObject::LiquidLeveler(ll) => (ll.x, ll.y),
+            EnvironmentObject::HeatSource(hs) => (hs.x, hs.y),
+            EnvironmentObject::HeatSink(hs) => (hs.x, hs.y),
+        };
+
+        // The middle of the tile
+        Some(vec2(x as f32 + 0.5, y as f32 + 0.5))
+    }
+
     fn air_levelers(&self) -> Vec<AirLeveler<usize>> {
         match self {
             EnvironmentObject::AirLeveler(al) => vec![*al],
@@ -44,6 +113,14 @@ impl ObjectProperties for EnvironmentObject {
         }
     }
 
+    fn drain_air_reservoir(&mut self, index: usize, amount: f32) {
+        if let (EnvironmentObject::AirLeveler(al), 0) = (self, index) {
+            if let Some(reservoir) = &mut al.reservoir {
+                *reservoir = (*reservoir - amount).max(0.0);
+            }
+        }
+    }
+
     fn oxygen_users(&self) -> Vec<OxygenUser<usize>> {
         match self {
             EnvironmentObject::OxygenUser(ou) => vec![*ou],
@@ -64,4 +141,18 @@ impl ObjectProperties for EnvironmentObject {
             _ => vec![],
         }
     }
+
+    fn heat_sources(&self) -> Vec<HeatSource<usize>> {
+        match self {
+            EnvironmentObject::HeatSource(hs) => vec![*hs],
+            _ => vec![],
+        }
+    }
+
+    fn heat_sinks(&self) -> Vec<HeatSink<usize>> {
+        match self {
+            EnvironmentObject::HeatSink(hs) => vec![*hs],
+            _ => vec![],
+        }
+    }
 }
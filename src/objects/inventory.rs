@@ -0,0 +1,354 @@
+//! The items characters carry with them and the equipment they wear.
+//!
+//! The items in an inventory aren't on the map anymore, this includes the item a character is hauling.
+//! They come back as [Item] objects when they're dropped or when the character is removed.
+
+use super::{
+    characters::Character,
+    item::{Item, ItemKind},
+    ObjectId, Objects,
+};
+use std::fmt::Display;
+
+/// The amount of items a character can carry in its inventory, not counting the equipped item
+pub const INVENTORY_CAPACITY: usize = 8;
+/// How far away an item or another character can be to pick it up or hand it over
+pub const INVENTORY_REACH: f32 = 1.5;
+/// A character with an equipped [ItemKind::OxygenTank] loses its oxygen saturation this much slower
+/// when it can't breathe the air around it
+pub const OXYGEN_TANK_LOSS_FACTOR: f32 = 0.25;
+
+/// An amount of items of the same kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemStack {
+    pub kind: ItemKind,
+    pub count: usize,
+}
+
+/// What a [Character] carries, see [Character::inventory]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Inventory {
+    pub(crate) stacks: Vec<ItemStack>,
+    pub(crate) equipped: Option<ItemKind>,
+}
+
+impl Inventory {
+    /// The stacks of items, in the order their kinds were first picked up
+    pub fn stacks(&self) -> &[ItemStack] {
+        &self.stacks
+    }
+
+    /// The amount of items of the kind, not counting the equipped item
+    pub fn count(&self, kind: ItemKind) -> usize {
+        self.stacks
+            .iter()
+            .find(|stack| stack.kind == kind)
+            .map_or(0, |stack| stack.count)
+    }
+
+    /// The amount of items, not counting the equipped item
+    pub fn len(&self) -> usize {
+        self.stacks.iter().map(|stack| stack.count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stacks.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= INVENTORY_CAPACITY
+    }
+
+    /// The item the character is wearing or holding as a tool
+    pub fn equipped(&self) -> Option<ItemKind> {
+        self.equipped
+    }
+
+    /// Returns false when the inventory is full
+    pub(crate) fn add(&mut self, kind: ItemKind) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        match self.stacks.iter_mut().find(|stack| stack.kind == kind) {
+            Some(stack) => stack.count += 1,
+            None => self.stacks.push(ItemStack { kind, count: 1 }),
+        }
+        true
+    }
+
+    /// Returns false when there's no item of the kind
+    pub(crate) fn remove(&mut self, kind: ItemKind) -> bool {
+        let Some(index) = self.stacks.iter().position(|stack| stack.kind == kind) else {
+            return false;
+        };
+
+        self.stacks[index].count -= 1;
+        if self.stacks[index].count == 0 {
+            self.stacks.remove(index);
+        }
+        true
+    }
+
+    /// All items, including the equipped one
+    pub(crate) fn all_items(&self) -> impl Iterator<Item = ItemKind> + '_ {
+        self.stacks
+            .iter()
+            .flat_map(|stack| std::iter::repeat_n(stack.kind, stack.count))
+            .chain(self.equipped)
+    }
+}
+
+impl Objects {
+    /// Take the item off the map and put it in the inventory of the character
+    pub fn pick_up_item(
+        &mut self,
+        character: ObjectId<Character>,
+        item: ObjectId<Item>,
+    ) -> Result<(), InventoryError> {
+        let (mut character, item_object) = self
+            .get_two_mut(character, item)
+            .ok_or(InventoryError::ObjectNotFound)?;
+        if item_object.hauler.is_some() {
+            return Err(InventoryError::ItemIsHauled);
+        }
+        if character.location.distance(item_object.location) > INVENTORY_REACH {
+            return Err(InventoryError::OutOfReach);
+        }
+        if !character.inventory.add(item_object.kind) {
+            return Err(InventoryError::InventoryFull);
+        }
+
+        drop((character, item_object));
+        self.remove_object(item);
+        Ok(())
+    }
+
+    /// Take an item of the kind out of the inventory of the character and put it on the map where the character stands
+    pub fn drop_item(
+        &mut self,
+        character: ObjectId<Character>,
+        kind: ItemKind,
+    ) -> Result<ObjectId<Item>, InventoryError> {
+        let mut character = self
+            .get_object_mut(character)
+            .ok_or(InventoryError::ObjectNotFound)?;
+        if !character.inventory.remove(kind) {
+            return Err(InventoryError::NotInInventory(kind));
+        }
+
+        let location = character.location;
+        drop(character);
+        Ok(self.push_object(Item::new(kind, location)))
+    }
+
+    /// Hand an item of the kind from the inventory of one character to another character that's close enough
+    pub fn transfer_item(
+        &mut self,
+        from: ObjectId<Character>,
+        to: ObjectId<Character>,
+        kind: ItemKind,
+    ) -> Result<(), InventoryError> {
+        let (mut from, mut to) = self
+            .get_two_mut(from, to)
+            .ok_or(InventoryError::ObjectNotFound)?;
+        if from.location.distance(to.location) > INVENTORY_REACH {
+            return Err(InventoryError::OutOfReach);
+        }
+        if from.inventory.count(kind) == 0 {
+            return Err(InventoryError::NotInInventory(kind));
+        }
+        if !to.inventory.add(kind) {
+            return Err(InventoryError::InventoryFull);
+        }
+
+        from.inventory.remove(kind);
+        Ok(())
+    }
+
+    /// Equip an item of the kind from the inventory of the character.
+    /// The item that was equipped before goes back into the inventory and is returned.
+    pub fn equip_item(
+        &mut self,
+        character: ObjectId<Character>,
+        kind: ItemKind,
+    ) -> Result<Option<ItemKind>, InventoryError> {
+        let mut character = self
+            .get_object_mut(character)
+            .ok_or(InventoryError::ObjectNotFound)?;
+        let inventory = &mut character.inventory;
+        if !inventory.remove(kind) {
+            return Err(InventoryError::NotInInventory(kind));
+        }
+
+        // There's room for the previous item, because the new one just left the inventory
+        let previous = inventory.equipped.replace(kind);
+        if let Some(previous) = previous {
+            inventory.add(previous);
+        }
+        Ok(previous)
+    }
+
+    /// Put the equipped item of the character back into its inventory and return it
+    pub fn unequip_item(
+        &mut self,
+        character: ObjectId<Character>,
+    ) -> Result<Option<ItemKind>, InventoryError> {
+        let mut character = self
+            .get_object_mut(character)
+            .ok_or(InventoryError::ObjectNotFound)?;
+        let inventory = &mut character.inventory;
+        let Some(equipped) = inventory.equipped else {
+            return Ok(None);
+        };
+        if !inventory.add(equipped) {
+            return Err(InventoryError::InventoryFull);
+        }
+
+        inventory.equipped = None;
+        Ok(Some(equipped))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryError {
+    /// The character or item doesn't exist
+    ObjectNotFound,
+    /// A character is already hauling the item
+    ItemIsHauled,
+    /// The item or the other character is further away than [INVENTORY_REACH]
+    OutOfReach,
+    /// The inventory already has [INVENTORY_CAPACITY] items
+    InventoryFull,
+    NotInInventory(ItemKind),
+}
+
+impl Display for InventoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InventoryError::ObjectNotFound => write!(f, "The character or item doesn't exist"),
+            InventoryError::ItemIsHauled => write!(f, "The item is being hauled"),
+            InventoryError::OutOfReach => write!(f, "The item or character is out of reach"),
+            InventoryError::InventoryFull => write!(f, "The inventory is full"),
+            InventoryError::NotInInventory(kind) => {
+                write!(f, "There's no {kind:?} in the inventory")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InventoryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Map;
+    use glam::vec2;
+
+    #[test]
+    fn pick_up_drop_and_transfer() {
+        let map = Map::new_default(4, 4);
+        let mut objects = map.objects_mut();
+        let alice =
+            objects.push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, Vec::new()));
+        let bob = objects.push_object::<Character>(Character::new(vec2(1.5, 0.5), 1.0, Vec::new()));
+        let ore = objects.push_object::<Item>(Item::new(ItemKind::Ore, vec2(1.0, 1.0)));
+        let far_ore = objects.push_object::<Item>(Item::new(ItemKind::Ore, vec2(3.5, 3.5)));
+
+        assert_eq!(
+            objects.pick_up_item(alice, far_ore),
+            Err(InventoryError::OutOfReach)
+        );
+        objects.pick_up_item(alice, ore).unwrap();
+        assert!(objects.get_object(ore).is_none());
+        assert_eq!(
+            objects.pick_up_item(alice, ore),
+            Err(InventoryError::ObjectNotFound)
+        );
+        assert_eq!(
+            objects.get_object(alice).unwrap().inventory().stacks(),
+            [ItemStack {
+                kind: ItemKind::Ore,
+                count: 1
+            }]
+        );
+
+        assert_eq!(
+            objects.transfer_item(alice, bob, ItemKind::Food),
+            Err(InventoryError::NotInInventory(ItemKind::Food))
+        );
+        objects.transfer_item(alice, bob, ItemKind::Ore).unwrap();
+        assert!(objects.get_object(alice).unwrap().inventory().is_empty());
+        assert_eq!(
+            objects
+                .get_object(bob)
+                .unwrap()
+                .inventory()
+                .count(ItemKind::Ore),
+            1
+        );
+
+        let dropped = objects.drop_item(bob, ItemKind::Ore).unwrap();
+        assert_eq!(
+            objects.get_object(dropped).unwrap().location,
+            vec2(1.5, 0.5)
+        );
+        assert!(objects.get_object(bob).unwrap().inventory().is_empty());
+    }
+
+    #[test]
+    fn inventory_capacity_and_equipment() {
+        let map = Map::new_default(4, 4);
+        let mut objects = map.objects_mut();
+        let character =
+            objects.push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, Vec::new()));
+        let mut left_behind = None;
+        for kind in [ItemKind::OxygenTank]
+            .into_iter()
+            .chain([ItemKind::Food; INVENTORY_CAPACITY])
+        {
+            let item = objects.push_object::<Item>(Item::new(kind, vec2(0.5, 0.5)));
+            if let Err(error) = objects.pick_up_item(character, item) {
+                assert_eq!(error, InventoryError::InventoryFull);
+                left_behind = Some(item);
+            }
+        }
+        assert!(objects.get_object(character).unwrap().inventory().is_full());
+
+        assert_eq!(
+            objects.equip_item(character, ItemKind::OxygenTank),
+            Ok(None)
+        );
+        assert_eq!(
+            objects.equip_item(character, ItemKind::Food),
+            Ok(Some(ItemKind::OxygenTank))
+        );
+        assert_eq!(
+            objects
+                .get_object(character)
+                .unwrap()
+                .inventory()
+                .equipped(),
+            Some(ItemKind::Food)
+        );
+
+        // Equipping made room for the food that didn't fit, which leaves no room to take the food off again
+        objects
+            .pick_up_item(character, left_behind.unwrap())
+            .unwrap();
+        assert_eq!(
+            objects.unequip_item(character),
+            Err(InventoryError::InventoryFull)
+        );
+
+        // Everything the character carried ends up on the map again
+        let items_before = objects.get_objects::<Item>().count();
+        objects.remove_object(character);
+        assert_eq!(
+            objects.get_objects::<Item>().count(),
+            items_before + INVENTORY_CAPACITY + 1
+        );
+    }
+}
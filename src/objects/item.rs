@@ -0,0 +1,154 @@
+use glam::Vec2;
+
+use super::{
+    building::{Building, BuildingType},
+    characters::Character,
+    LockedObject, ObjectId, ObjectKind, ObjectProperties, Objects,
+};
+
+/// What an [Item] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ItemKind {
+    Ore,
+    Food,
+    Components,
+    /// Lets the character that has it equipped go longer without breathable air,
+    /// see [OXYGEN_TANK_LOSS_FACTOR](super::inventory::OXYGEN_TANK_LOSS_FACTOR)
+    OxygenTank,
+}
+
+impl ItemKind {
+    pub const ALL: [ItemKind; 4] = [
+        ItemKind::Ore,
+        ItemKind::Food,
+        ItemKind::Components,
+        ItemKind::OxygenTank,
+    ];
+}
+
+/// Something that lies on the map and can be hauled to a [BuildingType::Stockpile]
+/// or the input slots of a building by the characters
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Item {
+    pub kind: ItemKind,
+    /// Where the item lies
+    pub location: Vec2,
+    /// The character that is coming to pick up the item
+    pub(crate) hauler: Option<ObjectId<Character>>,
+}
+
+impl Item {
+    pub fn new(kind: ItemKind, location: Vec2) -> Self {
+        Self {
+            kind,
+            location,
+            hauler: None,
+        }
+    }
+
+    /// The character that is coming to pick up the item
+    pub fn hauler(&self) -> Option<ObjectId<Character>> {
+        self.hauler
+    }
+}
+
+impl ObjectProperties for Item {
+    fn render_kind(&self) -> ObjectKind {
+        ObjectKind::Item
+    }
+
+    fn position(&self) -> Option<Vec2> {
+        Some(self.location)
+    }
+
+    fn on_removed(&self, id: ObjectId<()>, objects: &mut Objects) {
+        // Nobody can pick up or deliver an item that doesn't exist anymore
+        for mut character in objects.get_objects_mut::<Character>() {
+            character.stop_hauling(id.cast());
+        }
+    }
+}
+
+impl Objects {
+    /// Get the item of the kind closest to the given position that passes the filter
+    pub fn nearest_item(
+        &self,
+        position: Vec2,
+        kind: ItemKind,
+        mut filter: impl FnMut(&Item) -> bool,
+    ) -> Option<LockedObject<'_, Item>> {
+        self.nearest(position, |item: &Item| item.kind == kind && filter(item))
+    }
+
+    /// The amount of items of the kind the building can still take, counting the items that are on their way to it.
+    ///
+    /// A [BuildingType::Stockpile] takes items of every kind, other buildings only what fits in their input slots.
+    pub(crate) fn item_room(&self, destination: ObjectId<Building>, kind: ItemKind) -> usize {
+        let Some(destination) = self.get_object(destination) else {
+            return 0;
+        };
+
+        let room = match destination.building_type {
+            BuildingType::Stockpile { capacity } => {
+                let stored = self
+                    .get_objects::<Item>()
+                    .filter(|item| item.hauler.is_none() && lies_on(&destination, item.location))
+                    .count();
+                capacity.saturating_sub(stored)
+            }
+            ref building_type => building_type.input_room(kind),
+        };
+        let incoming = self
+            .get_objects::<Character>()
+            .filter(|character| character.hauling_to() == Some(destination.id()))
+            .count();
+
+        room.saturating_sub(incoming)
+    }
+
+    /// Returns true if the item lies on a stockpile, so it doesn't need to be hauled
+    pub(crate) fn is_stored(&self, item: &Item) -> bool {
+        item.hauler.is_none()
+            && self.get_objects::<Building>().any(|building| {
+                matches!(building.building_type, BuildingType::Stockpile { .. })
+                    && lies_on(&building, item.location)
+            })
+    }
+}
+
+fn lies_on(building: &Building, location: Vec2) -> bool {
+    location.x >= 0.0
+        && location.y >= 0.0
+        && building.is_on_tile(location.x as usize, location.y as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec2;
+
+    #[test]
+    fn nearest_item_of_kind() {
+        let mut objects = Objects::new();
+        let far_ore = objects.push_object::<Item>(Item::new(ItemKind::Ore, vec2(5.5, 0.5)));
+        let near_ore = objects.push_object::<Item>(Item::new(ItemKind::Ore, vec2(1.5, 0.5)));
+        let food = objects.push_object::<Item>(Item::new(ItemKind::Food, vec2(0.5, 0.5)));
+
+        let nearest = |kind, filter: fn(&Item) -> bool| {
+            objects
+                .nearest_item(vec2(0.0, 0.0), kind, filter)
+                .map(|item| item.id())
+        };
+
+        assert_eq!(nearest(ItemKind::Ore, |_| true), Some(near_ore));
+        assert_eq!(nearest(ItemKind::Food, |_| true), Some(food));
+        assert_eq!(nearest(ItemKind::Components, |_| true), None);
+        // Only the items that pass the filter are considered
+        assert_eq!(
+            nearest(ItemKind::Ore, |item| item.location.x > 2.0),
+            Some(far_ore)
+        );
+    }
+}
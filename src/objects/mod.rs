@@ -1,49 +1,95 @@
-use self::{building::Building, characters::Character, environment_object::EnvironmentObject};
+use self::{
+    building::Building, characters::Character, environment_object::EnvironmentObject, item::Item,
+    spatial_index::SpatialIndex,
+};
 use crate::{
     air::{AirLeveler, AirPusher, OxygenUser},
+    events::MapEvent,
+    heat::{HeatSink, HeatSource},
     liquids::LiquidLeveler,
+    tiles::Tile,
+    Map,
 };
+use glam::Vec2;
 use std::{
-    any::{type_name, TypeId},
+    any::{type_name, Any, TypeId},
     cell::UnsafeCell,
-    ops::{Deref, DerefMut},
-    sync::atomic::{AtomicU32, Ordering},
+    fmt::{Debug, Display},
+    mem::size_of,
+    ops::{Deref, DerefMut, Index},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
 };
 
 pub mod building;
 pub mod characters;
 pub mod environment_object;
+pub mod inventory;
+pub mod item;
 mod object_id;
+mod spatial_index;
 
 pub use object_id::ObjectId;
 
+/// An object is never on a tile that is further than this many tiles from the tile its position is in,
+/// so the objects on a tile can be found in the spatial index. See [ObjectProperties::is_on_tile].
+pub const MAX_FOOTPRINT_REACH: usize = 1;
+
 #[derive(Debug)]
 pub struct Objects {
-    next_object_id: u32,
+    /// None when all ids have been handed out
+    next_object_id: Option<u32>,
     object_sync: ObjectSync,
 
-    // These object arrays must be in order of object ID
-    environment_objects: Vec<Object<EnvironmentObject>>,
-    buildings: Vec<Object<Building>>,
-    characters: Vec<Object<Character>>,
+    /// A store for every type of object that has been added, in the order the types were first added.
+    /// Every store is a `Vec<Object<T>>` that must be in order of object ID.
+    stores: Vec<(TypeId, Box<dyn ObjectStore>)>,
+
+    /// The objects by position. Kept up to date by the [LockedObjectMut]s when objects move.
+    spatial_index: Mutex<SpatialIndex>,
+
+    /// Added and removed objects that haven't been sent to the map event listeners yet
+    events: Vec<MapEvent>,
 }
 
 impl Objects {
     pub const fn new() -> Self {
         Self {
-            next_object_id: 0,
+            next_object_id: Some(0),
             object_sync: ObjectSync::new(),
-            environment_objects: Vec::new(),
-            buildings: Vec::new(),
-            characters: Vec::new(),
+            stores: Vec::new(),
+            spatial_index: Mutex::new(SpatialIndex::new()),
+            events: Vec::new(),
         }
     }
 
+    /// Add an object and get its id.
+    ///
+    /// Panics when all object ids have been used. Use [Self::try_push_object] to handle that case.
     pub fn push_object<T: ObjectProperties>(&mut self, object: impl Into<T>) -> ObjectId<T> {
-        let object = object.into();
+        self.try_push_object(object)
+            .expect("All object ids have been used")
+    }
+
+    /// Add an object and get its id.
+    ///
+    /// Any type that implements [ObjectProperties] can be added, including types defined outside of this crate.
+    ///
+    /// Ids are never reused, so an id can't end up pointing at a different object after a removal.
+    /// That means there's a limited amount of objects that can ever be pushed.
+    /// Once all ids have been used, this returns an error.
+    pub fn try_push_object<T: ObjectProperties>(
+        &mut self,
+        object: impl Into<T>,
+    ) -> Result<ObjectId<T>, ObjectIdsExhausted> {
+        let new_object_id = self.next_object_id.ok_or(ObjectIdsExhausted)?;
+        self.next_object_id = new_object_id.checked_add(1);
 
-        let new_object_id = self.next_object_id;
-        self.next_object_id += 1;
+        let object = object.into();
+        let kind = object.render_kind();
+        let position = object.position();
 
         let object = Object {
             id: new_object_id,
@@ -53,21 +99,190 @@ impl Objects {
         self.get_vec_of_type_mut().push(object);
 
         self.object_sync.push_object(object_id.cast());
+        self.spatial_index
+            .get_mut()
+            .unwrap()
+            .insert(object_id.cast(), TypeId::of::<T>(), position);
+
+        self.events.push(MapEvent::ObjectAdded {
+            object: object_id.cast(),
+            kind,
+        });
 
-        object_id
+        Ok(object_id)
     }
 
     pub fn remove_object<T: ObjectProperties>(&mut self, id: ObjectId<T>) {
-        let object_vec = self.get_vec_of_type_mut::<T>();
+        let object_vec = self
+            .get_store_mut::<T>()
+            .expect("There are no objects of this type");
         let index = object_vec
             .iter()
             .enumerate()
             .find_map(|(index, object)| (object.id() == id).then_some(index))
             .unwrap();
 
-        object_vec.remove(index);
+        let removed_object = object_vec.remove(index).object.into_inner();
 
         self.object_sync.remove_object(id.cast());
+        self.spatial_index
+            .get_mut()
+            .unwrap()
+            .remove(id.cast(), removed_object.position());
+
+        self.events.push(MapEvent::ObjectRemoved {
+            object: id.cast(),
+            kind: removed_object.render_kind(),
+        });
+
+        removed_object.on_removed(id.cast(), self);
+    }
+
+    /// Release the memory that is left over after removing objects.
+    ///
+    /// Useful for long running simulations where many objects come and go.
+    pub fn compact(&mut self) {
+        for (_, store) in self.stores.iter_mut() {
+            store.compact();
+        }
+        self.object_sync.compact();
+        self.spatial_index.get_mut().unwrap().compact();
+    }
+
+    pub(crate) fn next_object_id(&self) -> Option<u32> {
+        self.next_object_id
+    }
+
+    pub(crate) fn set_next_object_id(&mut self, next_object_id: Option<u32>) {
+        self.next_object_id = next_object_id;
+    }
+
+    /// Put back an object with the id it had before, like when loading a saved map.
+    ///
+    /// The objects of a type must be restored in the order of their ids and after the next object id is set.
+    pub(crate) fn restore_object<T: ObjectProperties>(
+        &mut self,
+        id: u32,
+        object: T,
+    ) -> Result<(), String> {
+        if self.next_object_id.is_some_and(|next| id >= next) {
+            return Err(format!("object id {id} was never handed out"));
+        }
+        if self.object_sync.find_index(ObjectId::new(id)).is_ok() {
+            return Err(format!("duplicate object id {id}"));
+        }
+
+        let vec = self.get_vec_of_type_mut::<T>();
+        if vec.last().is_some_and(|last| last.id > id) {
+            return Err(format!("object id {id} is out of order"));
+        }
+
+        let position = object.position();
+        vec.push(Object {
+            id,
+            object: UnsafeCell::new(object),
+        });
+        self.object_sync.push_object(ObjectId::new(id));
+        self.spatial_index.get_mut().unwrap().insert(
+            ObjectId::new(id),
+            TypeId::of::<T>(),
+            position,
+        );
+
+        Ok(())
+    }
+
+    /// Put the object with the id back the way it was, or remove it when it's None, like when rolling back the map.
+    ///
+    /// Unlike [Self::remove_object], this has no side effects on the other objects.
+    pub(crate) fn reset_object<T: ObjectProperties>(&mut self, id: u32, object: Option<T>) {
+        let object_id = ObjectId::<T>::new(id);
+        let vec = self.get_vec_of_type_mut::<T>();
+        let index = vec.binary_search_by_key(&id, |object| object.id);
+
+        let removed_object = index
+            .ok()
+            .map(|index| vec.remove(index).object.into_inner());
+        if let Some(removed_object) = &removed_object {
+            self.object_sync.remove_object(object_id.cast());
+            self.spatial_index
+                .get_mut()
+                .unwrap()
+                .remove(object_id.cast(), removed_object.position());
+        }
+
+        match (removed_object, object) {
+            (removed_object, Some(object)) => {
+                if removed_object.is_none() {
+                    self.events.push(MapEvent::ObjectAdded {
+                        object: object_id.cast(),
+                        kind: object.render_kind(),
+                    });
+                }
+
+                let position = object.position();
+                let vec = self.get_vec_of_type_mut::<T>();
+                let index = vec
+                    .binary_search_by_key(&id, |object| object.id)
+                    .unwrap_err();
+                vec.insert(
+                    index,
+                    Object {
+                        id,
+                        object: UnsafeCell::new(object),
+                    },
+                );
+                self.object_sync.push_object(object_id.cast());
+                self.spatial_index.get_mut().unwrap().insert(
+                    object_id.cast(),
+                    TypeId::of::<T>(),
+                    position,
+                );
+            }
+            (Some(removed_object), None) => self.events.push(MapEvent::ObjectRemoved {
+                object: object_id.cast(),
+                kind: removed_object.render_kind(),
+            }),
+            (None, None) => {}
+        }
+    }
+
+    /// Replace the object with the id by what `f` makes of it, keeping its id.
+    /// This is for changes that need to own the object, like moving a value out of it.
+    ///
+    /// Like [Self::reset_object], this has no side effects on the other objects.
+    /// Returns false when there's no object with the id.
+    pub(crate) fn replace_object<T: ObjectProperties>(
+        &mut self,
+        id: ObjectId<T>,
+        f: impl FnOnce(T) -> T,
+    ) -> bool {
+        let vec = self.get_vec_of_type_mut::<T>();
+        let Ok(index) = vec.binary_search_by_key(&id.as_u32(), |object| object.id) else {
+            return false;
+        };
+
+        let old_object = vec.remove(index).object.into_inner();
+        let old_position = old_object.position();
+        let object = f(old_object);
+        let position = object.position();
+        vec.insert(
+            index,
+            Object {
+                id: id.as_u32(),
+                object: UnsafeCell::new(object),
+            },
+        );
+
+        let spatial_index = self.spatial_index.get_mut().unwrap();
+        spatial_index.remove(id.cast(), old_position);
+        spatial_index.insert(id.cast(), TypeId::of::<T>(), position);
+
+        true
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<MapEvent> {
+        std::mem::take(&mut self.events)
     }
 
     pub fn get_object<T: ObjectProperties>(&self, id: ObjectId<T>) -> Option<LockedObject<'_, T>> {
@@ -79,46 +294,62 @@ impl Objects {
     pub fn get_object_mut<T: ObjectProperties>(
         &self,
         id: ObjectId<T>,
-    ) -> Option<LockedObjectMut<T>> {
+    ) -> Option<LockedObjectMut<'_, T>> {
         let vec = self.get_vec_of_type::<T>();
         let object_index = vec.binary_search_by_key(&id, |obj| obj.id()).ok()?;
-        Some(LockedObjectMut::new(&vec[object_index], &self.object_sync))
+        Some(LockedObjectMut::new(
+            &vec[object_index],
+            &self.object_sync,
+            &self.spatial_index,
+        ))
     }
 
-    pub fn get_all_objects(&self) -> impl Iterator<Item = LockedObject<'_, dyn ObjectProperties>> {
-        let eo = self
-            .environment_objects
-            .iter()
-            .map(|val| LockedObject::new_dyn(val, &self.object_sync));
-        let b = self
-            .buildings
-            .iter()
-            .map(|val| LockedObject::new_dyn(val, &self.object_sync));
-        let c = self
-            .characters
-            .iter()
-            .map(|val| LockedObject::new_dyn(val, &self.object_sync));
+    /// Get mutable access to two objects at the same time.
+    ///
+    /// Returns None if either of the objects doesn't exist or if both ids are the same object.
+    pub fn get_two_mut<A: ObjectProperties, B: ObjectProperties>(
+        &self,
+        a: ObjectId<A>,
+        b: ObjectId<B>,
+    ) -> Option<(LockedObjectMut<'_, A>, LockedObjectMut<'_, B>)> {
+        if a.cast() == b.cast() {
+            // Locking the same object twice would never finish
+            return None;
+        }
+
+        let vec_a = self.get_vec_of_type::<A>();
+        let object_a = &vec_a[vec_a.binary_search_by_key(&a, |obj| obj.id()).ok()?];
+        let vec_b = self.get_vec_of_type::<B>();
+        let object_b = &vec_b[vec_b.binary_search_by_key(&b, |obj| obj.id()).ok()?];
+
+        // Always lock in the order of the ids, so two callers locking the same pair can't deadlock each other
+        if a.cast() < b.cast() {
+            let locked_a = LockedObjectMut::new(object_a, &self.object_sync, &self.spatial_index);
+            let locked_b = LockedObjectMut::new(object_b, &self.object_sync, &self.spatial_index);
+            Some((locked_a, locked_b))
+        } else {
+            let locked_b = LockedObjectMut::new(object_b, &self.object_sync, &self.spatial_index);
+            let locked_a = LockedObjectMut::new(object_a, &self.object_sync, &self.spatial_index);
+            Some((locked_a, locked_b))
+        }
+    }
 
-        eo.chain(b).chain(c)
+    /// Get all objects of all types, grouped by type in the order the types were first added
+    pub fn get_all_objects(&self) -> impl Iterator<Item = LockedObject<'_, dyn ObjectProperties>> {
+        self.stores.iter().flat_map(move |(_, store)| {
+            (0..store.len())
+                .map(move |index| LockedObject::new(store.get_dyn(index), &self.object_sync))
+        })
     }
 
     pub fn get_all_objects_mut(
         &self,
     ) -> impl Iterator<Item = LockedObjectMut<'_, dyn ObjectProperties>> {
-        let eo = self
-            .environment_objects
-            .iter()
-            .map(|val| LockedObjectMut::new_dyn(val, &self.object_sync));
-        let b = self
-            .buildings
-            .iter()
-            .map(|val| LockedObjectMut::new_dyn(val, &self.object_sync));
-        let c = self
-            .characters
-            .iter()
-            .map(|val| LockedObjectMut::new_dyn(val, &self.object_sync));
-
-        eo.chain(b).chain(c)
+        self.stores.iter().flat_map(move |(_, store)| {
+            (0..store.len()).map(move |index| {
+                LockedObjectMut::new(store.get_dyn(index), &self.object_sync, &self.spatial_index)
+            })
+        })
     }
 
     pub fn get_objects<T: ObjectProperties>(&self) -> impl Iterator<Item = LockedObject<'_, T>> {
@@ -132,33 +363,394 @@ impl Objects {
     ) -> impl Iterator<Item = LockedObjectMut<'_, T>> {
         self.get_vec_of_type()
             .iter()
-            .map(|obj| LockedObjectMut::new(obj, &self.object_sync))
+            .map(|obj| LockedObjectMut::new(obj, &self.object_sync, &self.spatial_index))
     }
 
-    fn get_vec_of_type<T: ObjectProperties>(&self) -> &Vec<Object<T>> {
-        match TypeId::of::<T>() {
-            o if o == TypeId::of::<EnvironmentObject>() => unsafe {
-                std::mem::transmute(&self.environment_objects)
-            },
-            o if o == TypeId::of::<Building>() => unsafe { std::mem::transmute(&self.buildings) },
-            o if o == TypeId::of::<Character>() => unsafe { std::mem::transmute(&self.characters) },
-            _ => unreachable!(),
+    /// Get all characters that are part of the given group
+    pub fn get_characters_in_group(
+        &self,
+        group: u32,
+    ) -> impl Iterator<Item = LockedObject<'_, Character>> {
+        self.get_objects::<Character>()
+            .filter(move |character| character.group == Some(group))
+    }
+
+    /// Get the ids of all objects with a position within the radius around the given position
+    pub fn objects_in_radius(
+        &self,
+        position: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = ObjectId<()>> {
+        self.spatial_index
+            .lock()
+            .unwrap()
+            .in_radius(position, radius)
+            .into_iter()
+            .map(|object| object.id)
+    }
+
+    /// Get the ids of all objects on the given tile, see [ObjectProperties::is_on_tile]
+    pub fn objects_on_tile(&self, x: usize, y: usize) -> impl Iterator<Item = ObjectId<()>> {
+        // Objects can be on the tiles around their position, like buildings with a bigger footprint
+        let reach = MAX_FOOTPRINT_REACH as f32;
+        let tile = Vec2::new(x as f32, y as f32);
+        let candidates = self
+            .spatial_index
+            .lock()
+            .unwrap()
+            .in_rect(tile - reach, tile + 1.0 + reach);
+
+        let mut ids = candidates
+            .into_iter()
+            .filter(|candidate| {
+                self.get_dyn_object(candidate.id, candidate.type_id)
+                    .is_some_and(|object| object.is_on_tile(x, y))
+            })
+            .map(|candidate| candidate.id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.into_iter()
+    }
+
+    /// Get the object with the given id from the store of the given type
+    fn get_dyn_object(
+        &self,
+        id: ObjectId<()>,
+        type_id: TypeId,
+    ) -> Option<LockedObject<'_, dyn ObjectProperties>> {
+        let (_, store) = self
+            .stores
+            .iter()
+            .find(|(store_type_id, _)| *store_type_id == type_id)?;
+        let object = store.get_dyn(store.find(id)?);
+        Some(LockedObject::new(object, &self.object_sync))
+    }
+
+    /// Get the building closest to the given position that passes the filter
+    pub fn nearest_building(
+        &self,
+        position: Vec2,
+        filter: impl FnMut(&Building) -> bool,
+    ) -> Option<LockedObject<'_, Building>> {
+        self.nearest(position, filter)
+    }
+
+    /// Get the object of the type closest to the given position that passes the filter
+    fn nearest<T: ObjectProperties>(
+        &self,
+        position: Vec2,
+        mut filter: impl FnMut(&T) -> bool,
+    ) -> Option<LockedObject<'_, T>> {
+        let mut nearest: Option<(f32, LockedObject<'_, T>)> = None;
+
+        for (min_distance, objects) in self.objects_by_distance::<T>(position) {
+            if nearest
+                .as_ref()
+                .is_some_and(|(distance, _)| *distance < min_distance)
+            {
+                // The objects that are left are all further away
+                break;
+            }
+
+            for object in objects {
+                let distance = object.position().unwrap().distance(position);
+                let is_nearer = nearest.as_ref().is_none_or(|(nearest_distance, nearest)| {
+                    (distance, object.id()) < (*nearest_distance, nearest.id())
+                });
+
+                if is_nearer && filter(&object) {
+                    nearest = Some((distance, object));
+                }
+            }
         }
+
+        nearest.map(|(_, object)| object)
+    }
+
+    /// Go over the objects of the given type with a position in groups of increasing distance to the position.
+    ///
+    /// Every group comes with the shortest distance any object of it or the groups after it can have.
+    pub(crate) fn objects_by_distance<T: ObjectProperties>(
+        &self,
+        position: Vec2,
+    ) -> impl Iterator<Item = (f32, Vec<LockedObject<'_, T>>)> {
+        (0..)
+            .map_while(move |ring| {
+                // The index must be released before locking the objects
+                let objects = self.spatial_index.lock().unwrap().ring(position, ring)?;
+                Some((ring, objects))
+            })
+            .map(move |(ring, objects)| {
+                let mut objects = objects
+                    .into_iter()
+                    .filter(|object| object.type_id == TypeId::of::<T>())
+                    .filter_map(|object| self.get_object(object.id.cast::<T>()))
+                    .collect::<Vec<_>>();
+                objects.sort_by_key(|object| object.id());
+                (SpatialIndex::ring_min_distance(ring), objects)
+            })
     }
 
+    /// Estimate of the amount of bytes used by the object storage
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + self.stores.capacity() * size_of::<(TypeId, Box<dyn ObjectStore>)>()
+            + self
+                .stores
+                .iter()
+                .map(|(_, store)| store.memory_usage())
+                .sum::<usize>()
+            + self.object_sync.memory_usage()
+            + self.spatial_index.lock().unwrap().memory_usage()
+    }
+
+    fn get_store<T: ObjectProperties>(&self) -> Option<&Vec<Object<T>>> {
+        let (_, store) = self
+            .stores
+            .iter()
+            .find(|(type_id, _)| *type_id == TypeId::of::<T>())?;
+        store.as_any().downcast_ref()
+    }
+
+    fn get_store_mut<T: ObjectProperties>(&mut self) -> Option<&mut Vec<Object<T>>> {
+        let (_, store) = self
+            .stores
+            .iter_mut()
+            .find(|(type_id, _)| *type_id == TypeId::of::<T>())?;
+        store.as_any_mut().downcast_mut()
+    }
+
+    fn get_vec_of_type<T: ObjectProperties>(&self) -> &[Object<T>] {
+        self.get_store::<T>().map_or(&[], |store| store.as_slice())
+    }
+
+    /// Get the store of the type, adding it if this is the first object of the type
     fn get_vec_of_type_mut<T: ObjectProperties>(&mut self) -> &mut Vec<Object<T>> {
-        match TypeId::of::<T>() {
-            o if o == TypeId::of::<EnvironmentObject>() => unsafe {
-                std::mem::transmute(&mut self.environment_objects)
-            },
-            o if o == TypeId::of::<Building>() => unsafe {
-                std::mem::transmute(&mut self.buildings)
-            },
-            o if o == TypeId::of::<Character>() => unsafe {
-                std::mem::transmute(&mut self.characters)
-            },
-            _ => unreachable!("{} is not covered", type_name::<T>()),
+        if self.get_store::<T>().is_none() {
+            self.stores
+                .push((TypeId::of::<T>(), Box::new(Vec::<Object<T>>::new())));
         }
+
+        self.get_store_mut::<T>().unwrap()
+    }
+}
+
+/// The objects of a single type, so objects of any type can be kept together
+trait ObjectStore: Send + Sync {
+    fn len(&self) -> usize;
+    fn get_dyn(&self, index: usize) -> &Object<dyn ObjectProperties>;
+    /// The index of the object with the given id
+    fn find(&self, id: ObjectId<()>) -> Option<usize>;
+    fn memory_usage(&self) -> usize;
+    fn compact(&mut self);
+    fn type_name(&self) -> &'static str;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: ObjectProperties> ObjectStore for Vec<Object<T>> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn get_dyn(&self, index: usize) -> &Object<dyn ObjectProperties> {
+        &self[index]
+    }
+
+    fn find(&self, id: ObjectId<()>) -> Option<usize> {
+        self.binary_search_by_key(&id.cast(), |obj| obj.id()).ok()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.capacity() * size_of::<Object<T>>()
+    }
+
+    fn compact(&mut self) {
+        debug_assert!(self.windows(2).all(|w| w[0].id < w[1].id));
+        self.shrink_to_fit();
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Debug for dyn ObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObjectStore")
+            .field("type", &self.type_name())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Map {
+    /// Get the world position of the object with the given id.
+    ///
+    /// Returns None if the object doesn't exist or if it has no position.
+    pub fn object_position<T: ObjectProperties>(&self, id: ObjectId<T>) -> Option<Vec2> {
+        self.objects().get_object(id)?.position()
+    }
+
+    /// Get the ids of all objects on the given tile.
+    ///
+    /// Buildings are on every tile of their footprint. Other objects are on the tile their position is in.
+    pub fn objects_on_tile(&self, x: usize, y: usize) -> impl Iterator<Item = ObjectId<()>> {
+        self.objects().objects_on_tile(x, y)
+    }
+
+    /// Run the [ObjectProperties::tick] of every object
+    pub(crate) fn tick_objects(&mut self, delta_time: f32) {
+        let ctx = TickContext {
+            tiles: &self.tiles,
+            width: self.tiles.width(),
+            height: self.tiles.height(),
+            current_time: self.current_time,
+        };
+
+        for mut object in self.objects.get_mut().unwrap().get_all_objects_mut() {
+            object.tick(&ctx, delta_time);
+        }
+    }
+
+    /// Get the amount of objects of every kind, taken under a single lock
+    pub fn object_counts(&self) -> ObjectCounts {
+        let objects = self.objects();
+
+        ObjectCounts {
+            environment: objects.get_vec_of_type::<EnvironmentObject>().len(),
+            buildings: objects.get_vec_of_type::<Building>().len(),
+            characters: objects.get_vec_of_type::<Character>().len(),
+            items: objects.get_vec_of_type::<Item>().len(),
+        }
+    }
+}
+
+/// The amount of objects of every kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ObjectCounts {
+    pub environment: usize,
+    pub buildings: usize,
+    pub characters: usize,
+    pub items: usize,
+}
+
+/// All object ids have been used, so no more objects can be added
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectIdsExhausted;
+
+impl Display for ObjectIdsExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "All object ids have been used")
+    }
+}
+
+impl std::error::Error for ObjectIdsExhausted {}
+
+impl Default for Objects {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Objects are stored as lists of `(id, object)` pairs so the ids survive a round trip.
+/// Only the object types of this crate are stored, objects of types defined elsewhere are left out.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Objects {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{SerializeSeq, SerializeStruct};
+
+        struct ObjectList<'a, T: ObjectProperties>(&'a Objects, std::marker::PhantomData<T>);
+
+        impl<'a, T: ObjectProperties + serde::Serialize> serde::Serialize for ObjectList<'a, T> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut seq =
+                    serializer.serialize_seq(Some(self.0.get_vec_of_type::<T>().len()))?;
+                for object in self.0.get_objects::<T>() {
+                    seq.serialize_element(&(object.id(), &*object))?;
+                }
+                seq.end()
+            }
+        }
+
+        let mut state = serializer.serialize_struct("Objects", 7)?;
+        state.serialize_field("next_object_id", &self.next_object_id)?;
+        state.serialize_field(
+            "environment_objects",
+            &ObjectList::<EnvironmentObject>(self, std::marker::PhantomData),
+        )?;
+        state.serialize_field(
+            "buildings",
+            &ObjectList::<Building>(self, std::marker::PhantomData),
+        )?;
+        state.serialize_field(
+            "characters",
+            &ObjectList::<Character>(self, std::marker::PhantomData),
+        )?;
+        state.serialize_field(
+            "pipes",
+            &ObjectList::<crate::pipes::Pipe>(self, std::marker::PhantomData),
+        )?;
+        state.serialize_field(
+            "cables",
+            &ObjectList::<crate::power::Cable>(self, std::marker::PhantomData),
+        )?;
+        state.serialize_field("items", &ObjectList::<Item>(self, std::marker::PhantomData))?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Objects {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "Objects")]
+        struct ObjectsData {
+            next_object_id: Option<u32>,
+            environment_objects: Vec<(u32, EnvironmentObject)>,
+            buildings: Vec<(u32, Building)>,
+            characters: Vec<(u32, Character)>,
+            #[serde(default)]
+            pipes: Vec<(u32, crate::pipes::Pipe)>,
+            #[serde(default)]
+            cables: Vec<(u32, crate::power::Cable)>,
+            #[serde(default)]
+            items: Vec<(u32, Item)>,
+        }
+
+        fn fill<T: ObjectProperties, E: Error>(
+            objects: &mut Objects,
+            list: Vec<(u32, T)>,
+        ) -> Result<(), E> {
+            for (id, object) in list {
+                objects.restore_object(id, object).map_err(E::custom)?;
+            }
+
+            Ok(())
+        }
+
+        let data = ObjectsData::deserialize(deserializer)?;
+
+        let mut objects = Objects::new();
+        objects.next_object_id = data.next_object_id;
+        fill(&mut objects, data.environment_objects)?;
+        fill(&mut objects, data.buildings)?;
+        fill(&mut objects, data.characters)?;
+        fill(&mut objects, data.pipes)?;
+        fill(&mut objects, data.cables)?;
+        fill(&mut objects, data.items)?;
+
+        Ok(objects)
     }
 }
 
@@ -172,6 +764,15 @@ impl ObjectSync {
         Self { states: Vec::new() }
     }
 
+    fn memory_usage(&self) -> usize {
+        self.states.capacity() * size_of::<(ObjectId<()>, SyncState)>()
+    }
+
+    fn compact(&mut self) {
+        debug_assert!(self.states.windows(2).all(|w| w[0].0 < w[1].0));
+        self.states.shrink_to_fit();
+    }
+
     fn find_index(&self, object_id: ObjectId<()>) -> Result<usize, usize> {
         self.states.binary_search_by_key(&object_id, |(id, _)| *id)
     }
@@ -258,7 +859,7 @@ impl SyncState {
 }
 
 #[derive(Debug)]
-pub struct Object<T: ObjectProperties> {
+pub struct Object<T: ObjectProperties + ?Sized> {
     id: u32,
     object: UnsafeCell<T>,
 }
@@ -269,8 +870,8 @@ impl<T: ObjectProperties> Object<T> {
     }
 }
 
-unsafe impl<T: ObjectProperties + Sync> Sync for Object<T> {}
-unsafe impl<T: ObjectProperties + Send> Send for Object<T> {}
+unsafe impl<T: ObjectProperties + Sync + ?Sized> Sync for Object<T> {}
+unsafe impl<T: ObjectProperties + Send + ?Sized> Send for Object<T> {}
 
 #[derive(Debug)]
 pub struct LockedObject<'o, T: ObjectProperties + ?Sized> {
@@ -279,32 +880,27 @@ pub struct LockedObject<'o, T: ObjectProperties + ?Sized> {
     object_sync: &'o ObjectSync,
 }
 
-impl<'o, T: ObjectProperties> LockedObject<'o, T> {
+impl<'o, T: ObjectProperties + ?Sized> LockedObject<'o, T> {
     pub(crate) fn new(object: &'o Object<T>, object_sync: &'o ObjectSync) -> Self {
-        object_sync.take_read_access(object.id().cast());
+        let id = ObjectId::new(object.id);
+        object_sync.take_read_access(id);
         Self {
-            id: object.id().cast(),
+            id,
             object: unsafe { &*object.object.get() },
             object_sync,
         }
     }
+}
 
+impl<'o, T: ObjectProperties> LockedObject<'o, T> {
     pub fn id(&self) -> ObjectId<T> {
         self.id.cast()
     }
 }
 
 impl<'o> LockedObject<'o, dyn ObjectProperties> {
-    pub(crate) fn new_dyn<T: ObjectProperties>(
-        object: &'o Object<T>,
-        object_sync: &'o ObjectSync,
-    ) -> Self {
-        object_sync.take_read_access(object.id().cast());
-        Self {
-            id: object.id().cast(),
-            object: unsafe { &*object.object.get() },
-            object_sync,
-        }
+    pub fn id(&self) -> ObjectId<()> {
+        self.id
     }
 }
 
@@ -329,34 +925,39 @@ pub struct LockedObjectMut<'o, T: ObjectProperties + ?Sized> {
     id: ObjectId<()>,
     object: &'o mut T,
     object_sync: &'o ObjectSync,
+    spatial_index: &'o Mutex<SpatialIndex>,
+    /// The position when the object was locked, so the spatial index can be updated if it moved
+    old_position: Option<Vec2>,
 }
 
-impl<'o, T: ObjectProperties> LockedObjectMut<'o, T> {
-    pub(crate) fn new(object: &'o Object<T>, object_sync: &'o ObjectSync) -> Self {
-        object_sync.take_write_access(object.id().cast());
+impl<'o, T: ObjectProperties + ?Sized> LockedObjectMut<'o, T> {
+    pub(crate) fn new(
+        object: &'o Object<T>,
+        object_sync: &'o ObjectSync,
+        spatial_index: &'o Mutex<SpatialIndex>,
+    ) -> Self {
+        let id = ObjectId::new(object.id);
+        object_sync.take_write_access(id);
+        let object = unsafe { &mut *object.object.get() };
         Self {
-            id: object.id().cast(),
-            object: unsafe { &mut *object.object.get() },
+            id,
+            old_position: object.position(),
+            object,
             object_sync,
+            spatial_index,
         }
     }
+}
 
+impl<'o, T: ObjectProperties> LockedObjectMut<'o, T> {
     pub fn id(&self) -> ObjectId<T> {
         self.id.cast()
     }
 }
 
 impl<'o> LockedObjectMut<'o, dyn ObjectProperties> {
-    pub(crate) fn new_dyn<T: ObjectProperties>(
-        object: &'o Object<T>,
-        object_sync: &'o ObjectSync,
-    ) -> Self {
-        object_sync.take_write_access(object.id().cast());
-        Self {
-            id: object.id().cast(),
-            object: unsafe { &mut *object.object.get() },
-            object_sync,
-        }
+    pub fn id(&self) -> ObjectId<()> {
+        self.id
     }
 }
 
@@ -376,16 +977,45 @@ impl<'o, T: ObjectProperties + ?Sized> DerefMut for LockedObjectMut<'o, T> {
 
 impl<'o, T: ObjectProperties + ?Sized> Drop for LockedObjectMut<'o, T> {
     fn drop(&mut self) {
+        let position = self.object.position();
+        if position != self.old_position {
+            self.spatial_index
+                .lock()
+                .unwrap()
+                .move_object(self.id, self.old_position, position);
+        }
+
         unsafe {
             self.object_sync.free_write_access(self.id);
         }
     }
 }
 
-pub trait ObjectProperties: 'static {
+/// Something that can be added to the [Objects] of a map.
+///
+/// This can be implemented for types outside of this crate to add objects with their own behaviour.
+pub trait ObjectProperties: Send + Sync + 'static {
+    /// The kind of the object, so a renderer can pick what to draw without knowing the concrete type
+    fn render_kind(&self) -> ObjectKind;
+    /// The world position of the object, if it has one
+    fn position(&self) -> Option<Vec2> {
+        None
+    }
+    /// Whether the object is on the given tile. By default that's the tile its position is in.
+    ///
+    /// It can only be on the tiles within [MAX_FOOTPRINT_REACH] of the tile its position is in.
+    fn is_on_tile(&self, x: usize, y: usize) -> bool {
+        self.position().is_some_and(|position| {
+            position.x >= 0.0
+                && position.y >= 0.0
+                && (position.x as usize, position.y as usize) == (x, y)
+        })
+    }
     fn air_levelers(&self) -> Vec<AirLeveler<usize>> {
         Vec::new()
     }
+    /// Take gas out of the reservoir of the air leveler at the given index of [Self::air_levelers]
+    fn drain_air_reservoir(&mut self, _index: usize, _amount: f32) {}
     fn oxygen_users(&self) -> Vec<OxygenUser<usize>> {
         Vec::new()
     }
@@ -395,6 +1025,78 @@ pub trait ObjectProperties: 'static {
     fn air_pushers(&self) -> Vec<AirPusher<usize>> {
         Vec::new()
     }
+    fn heat_sources(&self) -> Vec<HeatSource<usize>> {
+        Vec::new()
+    }
+    fn heat_sinks(&self) -> Vec<HeatSink<usize>> {
+        Vec::new()
+    }
+    /// Called by [Objects::remove_object] after the object has been taken out,
+    /// so the object can clear the references the other objects still have to it
+    fn on_removed(&self, _id: ObjectId<()>, _objects: &mut Objects) {}
+    /// Called once every simulation tick, before the tiles are simulated,
+    /// so the object can update its own state like fuel, cooldowns or production progress
+    fn tick(&mut self, _ctx: &TickContext, _delta_time: f32) {}
+}
+
+/// What an object can see of the map during [ObjectProperties::tick].
+///
+/// The other objects can't be looked at, they might be in the middle of their own tick.
+pub struct TickContext<'m> {
+    tiles: &'m dyn Index<(usize, usize), Output = Tile>,
+    width: usize,
+    height: usize,
+    current_time: f64,
+}
+
+impl TickContext<'_> {
+    /// The tile at the coords, or None if it's outside of the map
+    pub fn tile(&self, x: usize, y: usize) -> Option<&Tile> {
+        (x < self.width && y < self.height).then(|| &self.tiles[(x, y)])
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The simulated time in seconds at the start of the tick
+    pub fn current_time(&self) -> f64 {
+        self.current_time
+    }
+}
+
+/// What an object is, as far as rendering is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    AirLeveler,
+    OxygenUser,
+    AirPusher,
+    LiquidLeveler,
+    HeatSource,
+    HeatSink,
+    HandCrankedVentilator,
+    Airlock,
+    OxygenGenerator,
+    FumeScrubber,
+    MiningJob,
+    /// A building that is [under construction](building::BuildingType::UnderConstruction)
+    ConstructionSite,
+    Pump,
+    Pipe,
+    Generator,
+    PoweredVentilator,
+    Cable,
+    Stockpile,
+    Heater,
+    Cooler,
+    Item,
+    Character,
+    /// An object type defined outside of this crate, with a number of its own choosing
+    Custom(u32),
 }
 
 #[cfg(test)]
@@ -402,6 +1104,10 @@ mod tests {
     use std::{sync::Mutex, time::Duration};
 
     use super::*;
+    use crate::{air::GasMask, Direction8, Facing};
+    use building::{BuildingType, WorkSpotOccupation};
+    use characters::tests::ventilator;
+    use glam::{uvec2, vec2};
 
     #[test]
     fn spinlock() {
@@ -456,4 +1162,459 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn object_position() {
+        let map = Map::new_default(10, 10);
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(3.5, 4.25),
+            1.0,
+            vec![],
+        ));
+
+        assert_eq!(map.object_position(character), Some(vec2(3.5, 4.25)));
+
+        map.objects_mut().remove_object(character);
+        assert_eq!(map.object_position(character), None);
+    }
+
+    #[test]
+    fn objects_on_tile() {
+        let map = Map::new_default(10, 10);
+        let building = map
+            .objects_mut()
+            .push_object::<Building>(ventilator(uvec2(3, 4), Facing::North));
+        let character =
+            map.objects_mut()
+                .push_object::<Character>(Character::new(vec2(3.2, 4.9), 1.0, vec![]));
+        map.objects_mut()
+            .push_object::<Character>(Character::new(vec2(5.5, 4.5), 1.0, vec![]));
+
+        assert_eq!(
+            map.objects_on_tile(3, 4).collect::<Vec<_>>(),
+            vec![building.cast(), character.cast()]
+        );
+        assert_eq!(map.objects_on_tile(4, 4).count(), 0);
+
+        // An airlock is also on the tiles next to its location
+        let airlock = map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(7, 4),
+            facing: Facing::North,
+            building_type: BuildingType::Airlock,
+        });
+        for y in 3..=5 {
+            assert_eq!(
+                map.objects_on_tile(7, y).collect::<Vec<_>>(),
+                vec![airlock.cast()]
+            );
+        }
+        assert_eq!(map.objects_on_tile(7, 6).count(), 0);
+    }
+
+    #[test]
+    fn spatial_queries() {
+        let mut objects = Objects::new();
+        let near = objects.push_object::<Building>(ventilator(uvec2(2, 2), Facing::North));
+        let middle = objects.push_object::<Building>(ventilator(uvec2(20, 2), Facing::North));
+        let far = objects.push_object::<Building>(ventilator(uvec2(30, 30), Facing::North));
+        let character =
+            objects.push_object::<Character>(Character::new(vec2(1.5, 1.5), 1.0, vec![]));
+
+        let in_radius = |objects: &Objects, position, radius| {
+            let mut ids = objects
+                .objects_in_radius(position, radius)
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(
+            in_radius(&objects, vec2(2.5, 2.5), 2.0),
+            vec![near.cast(), character.cast()]
+        );
+
+        let nearest = |objects: &Objects, position, skip: &[ObjectId<Building>]| {
+            objects
+                .nearest_building(position, |building| {
+                    !skip.iter().any(|skipped| {
+                        objects.get_object(*skipped).unwrap().location == building.location
+                    })
+                })
+                .map(|building| building.id())
+        };
+        assert_eq!(nearest(&objects, vec2(25.0, 5.0), &[]), Some(middle));
+        assert_eq!(nearest(&objects, vec2(25.0, 5.0), &[middle]), Some(near));
+
+        // Moved objects are found at their new position
+        objects.get_object_mut(character).unwrap().location = vec2(30.2, 30.2);
+        assert_eq!(
+            in_radius(&objects, vec2(30.5, 30.5), 1.0),
+            vec![far.cast(), character.cast()]
+        );
+        assert_eq!(in_radius(&objects, vec2(2.5, 2.5), 2.0), vec![near.cast()]);
+
+        objects.remove_object(far);
+        assert_eq!(
+            in_radius(&objects, vec2(30.5, 30.5), 1.0),
+            vec![character.cast()]
+        );
+        assert_eq!(nearest(&objects, vec2(25.0, 5.0), &[middle, near]), None);
+    }
+
+    #[test]
+    fn object_counts() {
+        let map = Map::new_default(10, 10);
+        assert_eq!(map.object_counts(), ObjectCounts::default());
+
+        for i in 0..3 {
+            map.objects_mut()
+                .push_object::<EnvironmentObject>(OxygenUser {
+                    x: i,
+                    y: 0,
+                    change_per_sec: 0.0,
+                    enabled: true,
+                });
+        }
+        let character =
+            map.objects_mut()
+                .push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![]));
+        map.objects_mut()
+            .push_object::<Character>(Character::new(vec2(1.5, 0.5), 1.0, vec![]));
+
+        assert_eq!(
+            map.object_counts(),
+            ObjectCounts {
+                environment: 3,
+                buildings: 0,
+                characters: 2,
+                items: 0,
+            }
+        );
+
+        map.objects_mut().remove_object(character);
+        assert_eq!(map.object_counts().characters, 1);
+    }
+
+    #[test]
+    fn get_two_mut() {
+        let mut objects = Objects::new();
+        let building = objects.push_object::<Building>(ventilator(uvec2(2, 0), Facing::North));
+        let character =
+            objects.push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![]));
+
+        {
+            let (mut character, mut building) = objects.get_two_mut(character, building).unwrap();
+            character.location = vec2(1.5, 0.5);
+            building.claim_workspot(0, character.id()).unwrap();
+        }
+
+        // The locks are released again, in both orders
+        {
+            let (building, character) = objects.get_two_mut(building, character).unwrap();
+            assert_eq!(character.location, vec2(1.5, 0.5));
+            assert!(matches!(
+                building.workspots()[0].occupation,
+                WorkSpotOccupation::Claimed(id) if id == character.id()
+            ));
+        }
+
+        assert!(objects.get_two_mut(character, character).is_none());
+
+        objects.remove_object(character);
+        assert!(objects.get_two_mut(character, building).is_none());
+        assert!(objects.get_two_mut(building, character).is_none());
+    }
+
+    #[test]
+    fn characters_in_group() {
+        let mut objects = Objects::new();
+
+        let mut grouped_character = |x: f32, group: Option<u32>| {
+            let mut character = Character::new(vec2(x, 0.5), 1.0, vec![]);
+            character.group = group;
+            objects.push_object::<Character>(character)
+        };
+
+        let first = grouped_character(0.5, Some(1));
+        let second = grouped_character(1.5, Some(2));
+        let third = grouped_character(2.5, Some(1));
+        grouped_character(3.5, None);
+
+        assert_eq!(
+            objects
+                .get_characters_in_group(1)
+                .map(|character| character.id())
+                .collect::<Vec<_>>(),
+            vec![first, third]
+        );
+        assert_eq!(
+            objects
+                .get_characters_in_group(2)
+                .map(|character| character.id())
+                .collect::<Vec<_>>(),
+            vec![second]
+        );
+        assert_eq!(objects.get_characters_in_group(3).count(), 0);
+    }
+
+    #[test]
+    fn object_id_exhaustion() {
+        let mut objects = Objects::new();
+        // Pushing four billion objects takes a while, so we skip ahead to the last ids
+        objects.next_object_id = Some(u32::MAX - 1);
+
+        let second_to_last = objects
+            .try_push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![]))
+            .unwrap();
+        let last = objects
+            .try_push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![]))
+            .unwrap();
+        assert_eq!(last.cast(), ObjectId::<()>::new(u32::MAX));
+
+        // Removing an object doesn't free up its id
+        objects.remove_object(second_to_last);
+        assert_eq!(
+            objects.try_push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![])),
+            Err(ObjectIdsExhausted)
+        );
+        assert_eq!(objects.get_objects::<Character>().count(), 1);
+        assert!(objects.get_object(last).is_some());
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut objects = Objects::new();
+        let mut last_usage = objects.memory_usage();
+
+        for i in 0..100 {
+            objects.push_object::<EnvironmentObject>(OxygenUser {
+                x: i,
+                y: 0,
+                change_per_sec: 0.0,
+                enabled: true,
+            });
+            objects.push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![]));
+
+            let usage = objects.memory_usage();
+            assert!(usage >= last_usage);
+            last_usage = usage;
+        }
+
+        assert!(last_usage > Objects::new().memory_usage());
+    }
+
+    #[test]
+    fn compact() {
+        let mut objects = Objects::new();
+
+        let ids = (0..100)
+            .map(|i| {
+                objects.push_object::<Character>(Character::new(
+                    vec2(i as f32 + 0.5, 0.5),
+                    1.0,
+                    vec![],
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        for (i, id) in ids.iter().enumerate() {
+            if i % 10 != 0 {
+                objects.remove_object(*id);
+            }
+        }
+
+        let usage_before = objects.memory_usage();
+        objects.compact();
+        assert!(objects.memory_usage() < usage_before);
+
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(objects.get_object(*id).is_some(), i % 10 == 0);
+        }
+        assert_eq!(
+            objects.get_object(ids[50]).unwrap().location,
+            vec2(50.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn render_kind() {
+        let mut objects = Objects::new();
+        objects.push_object::<EnvironmentObject>(AirLeveler {
+            x: 0,
+            y: 0,
+            nitrogen: 0.79,
+            oxygen: 0.21,
+            fumes: 0.0,
+            gases: GasMask::ALL,
+            reservoir: None,
+            rate: None,
+            enabled: true,
+        });
+        objects.push_object::<EnvironmentObject>(OxygenUser {
+            x: 0,
+            y: 0,
+            change_per_sec: 0.0,
+            enabled: true,
+        });
+        objects.push_object::<EnvironmentObject>(AirPusher {
+            x: 0,
+            y: 0,
+            direction: Direction8::North,
+            amount: 0.0,
+            enabled: true,
+        });
+        objects.push_object::<EnvironmentObject>(LiquidLeveler {
+            x: 0,
+            y: 0,
+            target: Default::default(),
+            enabled: true,
+            solidify: false,
+        });
+        objects.push_object::<Building>(ventilator(uvec2(0, 0), Facing::North));
+        objects.push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![]));
+
+        assert_eq!(
+            objects
+                .get_all_objects()
+                .map(|object| object.render_kind())
+                .collect::<Vec<_>>(),
+            vec![
+                ObjectKind::AirLeveler,
+                ObjectKind::OxygenUser,
+                ObjectKind::AirPusher,
+                ObjectKind::LiquidLeveler,
+                ObjectKind::HandCrankedVentilator,
+                ObjectKind::Character,
+            ]
+        );
+    }
+
+    #[test]
+    fn objects_tick_every_simulation_tick() {
+        /// Flares up in bursts, with a cooldown in between
+        struct Stove {
+            cooldown: f32,
+            bursts: usize,
+            last_time: f64,
+        }
+
+        impl ObjectProperties for Stove {
+            fn render_kind(&self) -> ObjectKind {
+                ObjectKind::Custom(3)
+            }
+
+            fn tick(&mut self, ctx: &TickContext, delta_time: f32) {
+                assert!(ctx.tile(1, 0).is_some());
+                assert!(ctx.tile(ctx.width(), 0).is_none());
+                self.last_time = ctx.current_time();
+
+                self.cooldown -= delta_time;
+                if self.cooldown < 0.0 {
+                    self.cooldown += 0.5;
+                    self.bursts += 1;
+                }
+            }
+        }
+
+        let mut map = Map::new_default(2, 1);
+        let stove = map.objects_mut().push_object::<Stove>(Stove {
+            cooldown: 0.0,
+            bursts: 0,
+            last_time: -1.0,
+        });
+
+        map.step_n(0.25, 8);
+        let objects = map.objects();
+        let stove = objects.get_object(stove).unwrap();
+        // At 0, 0.5, 1 and 1.5 seconds
+        assert_eq!(stove.bursts, 4);
+        assert_eq!(stove.last_time, 1.75);
+    }
+
+    #[test]
+    fn user_defined_objects() {
+        struct Beacon {
+            location: Vec2,
+            temperature: f32,
+        }
+
+        impl ObjectProperties for Beacon {
+            fn render_kind(&self) -> ObjectKind {
+                ObjectKind::Custom(7)
+            }
+
+            fn position(&self) -> Option<Vec2> {
+                Some(self.location)
+            }
+
+            fn heat_sources(&self) -> Vec<HeatSource<usize>> {
+                vec![HeatSource {
+                    x: self.location.x as usize,
+                    y: self.location.y as usize,
+                    temperature: self.temperature,
+                    change_per_sec: 10.0,
+                    enabled: true,
+                }]
+            }
+        }
+
+        let mut map = Map::new_default(4, 4);
+        assert_eq!(map.objects().get_objects::<Beacon>().count(), 0);
+
+        map.objects_mut()
+            .push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, vec![]));
+        let beacon = map.objects_mut().push_object::<Beacon>(Beacon {
+            location: vec2(2.5, 1.5),
+            temperature: 50.0,
+        });
+
+        assert_eq!(map.objects().get_object(beacon).unwrap().temperature, 50.0);
+        assert_eq!(
+            map.objects_on_tile(2, 1).collect::<Vec<_>>(),
+            vec![beacon.cast()]
+        );
+        assert_eq!(
+            map.objects()
+                .get_all_objects()
+                .map(|object| object.render_kind())
+                .collect::<Vec<_>>(),
+            vec![ObjectKind::Character, ObjectKind::Custom(7)]
+        );
+
+        // The effects of the object take part in the simulation like any other
+        map.perform_simulation_tick(0.5);
+        assert!(map.tiles[(2, 1)].temperature > map.tiles[(0, 0)].temperature);
+
+        map.objects_mut().remove_object(beacon);
+        assert_eq!(map.objects().get_objects::<Beacon>().count(), 0);
+        assert_eq!(map.objects().get_objects::<Character>().count(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn objects_serde_rejects_bad_ids() {
+        let character = |id: u32| {
+            format!(
+                "[{id}, {}]",
+                serde_json::to_string(&Character::new(vec2(0.5, 0.5), 1.0, vec![])).unwrap()
+            )
+        };
+        let objects = |next_object_id: u32, characters: &[u32]| {
+            let characters = characters
+                .iter()
+                .map(|id| character(*id))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!(
+                r#"{{"next_object_id":{next_object_id},"environment_objects":[],"buildings":[],"characters":[{characters}]}}"#
+            )
+        };
+
+        let loaded = serde_json::from_str::<Objects>(&objects(5, &[1, 3])).unwrap();
+        assert_eq!(loaded.get_objects::<Character>().count(), 2);
+        assert!(loaded.get_object(ObjectId::<Character>::new(3)).is_some());
+
+        assert!(serde_json::from_str::<Objects>(&objects(5, &[3, 1])).is_err());
+        assert!(serde_json::from_str::<Objects>(&objects(5, &[1, 1])).is_err());
+        assert!(serde_json::from_str::<Objects>(&objects(3, &[1, 3])).is_err());
+    }
 }
@@ -1,4 +1,5 @@
 use super::ObjectProperties;
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
 use std::{any::type_name, marker::PhantomData};
 
 pub struct ObjectId<T> {
@@ -14,7 +15,7 @@ impl<T> Ord for ObjectId<T> {
 
 impl<T> PartialOrd for ObjectId<T> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.id.partial_cmp(&other.id)
+        Some(self.cmp(other))
     }
 }
 
@@ -50,6 +51,10 @@ impl<T> ObjectId<T> {
             _phantom: PhantomData,
         }
     }
+
+    pub(crate) fn as_u32(self) -> u32 {
+        self.id
+    }
 }
 
 impl<T: ObjectProperties> ObjectId<T> {
@@ -69,3 +74,27 @@ impl ObjectId<()> {
         }
     }
 }
+
+#[cfg(feature = "serde")]
+impl<T> serde::Serialize for ObjectId<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.id.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> serde::Deserialize<'de> for ObjectId<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(Self::new)
+    }
+}
+
+impl<T> Snapshot for ObjectId<T> {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.id);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        reader.read().map(Self::new)
+    }
+}
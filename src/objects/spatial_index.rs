@@ -0,0 +1,245 @@
+use super::ObjectId;
+use glam::{IVec2, Vec2};
+use std::{any::TypeId, collections::BTreeMap, mem::size_of};
+
+/// The width and height in tiles of the square that is covered by one bucket
+const BUCKET_SIZE: f32 = 8.0;
+
+/// The objects bucketed by the square of tiles their position is in,
+/// so the objects near a position can be found without going over all of them
+#[derive(Debug)]
+pub(crate) struct SpatialIndex {
+    buckets: BTreeMap<(i32, i32), Vec<IndexedObject>>,
+    /// The objects that don't have a position
+    unplaced: Vec<(ObjectId<()>, TypeId)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct IndexedObject {
+    pub id: ObjectId<()>,
+    pub type_id: TypeId,
+    pub position: Vec2,
+}
+
+impl SpatialIndex {
+    pub const fn new() -> Self {
+        Self {
+            buckets: BTreeMap::new(),
+            unplaced: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, id: ObjectId<()>, type_id: TypeId, position: Option<Vec2>) {
+        match position {
+            Some(position) => {
+                self.buckets
+                    .entry(bucket_key(position))
+                    .or_default()
+                    .push(IndexedObject {
+                        id,
+                        type_id,
+                        position,
+                    })
+            }
+            None => self.unplaced.push((id, type_id)),
+        }
+    }
+
+    /// Remove the object that was inserted with the given position. Returns the type of the object.
+    pub fn remove(&mut self, id: ObjectId<()>, position: Option<Vec2>) -> TypeId {
+        match position {
+            Some(position) => {
+                let key = bucket_key(position);
+                let bucket = self.buckets.get_mut(&key).unwrap();
+                let index = bucket.iter().position(|object| object.id == id).unwrap();
+                let type_id = bucket.swap_remove(index).type_id;
+                if bucket.is_empty() {
+                    self.buckets.remove(&key);
+                }
+                type_id
+            }
+            None => {
+                let index = self
+                    .unplaced
+                    .iter()
+                    .position(|(unplaced_id, _)| *unplaced_id == id)
+                    .unwrap();
+                self.unplaced.swap_remove(index).1
+            }
+        }
+    }
+
+    pub fn move_object(&mut self, id: ObjectId<()>, old: Option<Vec2>, new: Option<Vec2>) {
+        if let (Some(old), Some(new)) = (old, new) {
+            if bucket_key(old) == bucket_key(new) {
+                let bucket = self.buckets.get_mut(&bucket_key(old)).unwrap();
+                bucket
+                    .iter_mut()
+                    .find(|object| object.id == id)
+                    .unwrap()
+                    .position = new;
+                return;
+            }
+        }
+
+        let type_id = self.remove(id, old);
+        self.insert(id, type_id, new);
+    }
+
+    /// All objects with a position within the radius around the given position
+    pub fn in_radius(&self, position: Vec2, radius: f32) -> Vec<IndexedObject> {
+        let (min_x, min_y) = bucket_key(position - radius);
+        let (max_x, max_y) = bucket_key(position + radius);
+
+        (min_x..=max_x)
+            .flat_map(|x| self.buckets.range((x, min_y)..=(x, max_y)))
+            .flat_map(|(_, bucket)| bucket)
+            .filter(|object| object.position.distance(position) <= radius)
+            .copied()
+            .collect()
+    }
+
+    /// All objects with a position within the rect from `min` to `max`
+    pub fn in_rect(&self, min: Vec2, max: Vec2) -> Vec<IndexedObject> {
+        let (min_x, min_y) = bucket_key(min);
+        let (max_x, max_y) = bucket_key(max);
+
+        (min_x..=max_x)
+            .flat_map(|x| self.buckets.range((x, min_y)..=(x, max_y)))
+            .flat_map(|(_, bucket)| bucket)
+            .filter(|object| object.position.cmpge(min).all() && object.position.cmplt(max).all())
+            .copied()
+            .collect()
+    }
+
+    /// The objects in the ring of buckets at the given distance in buckets around the bucket of the position.
+    ///
+    /// Returns None when there are no buckets at or beyond the ring.
+    pub fn ring(&self, position: Vec2, ring: u32) -> Option<Vec<IndexedObject>> {
+        let center = IVec2::from(bucket_key(position));
+        let ring = ring as i32;
+
+        let max_ring = self
+            .buckets
+            .keys()
+            .map(|key| (IVec2::from(*key) - center).abs().max_element())
+            .max()?;
+        if ring > max_ring {
+            return None;
+        }
+
+        let (min, max) = (center - ring, center + ring);
+        Some(
+            (min.x..=max.x)
+                .flat_map(|x| self.buckets.range((x, min.y)..=(x, max.y)))
+                .filter(|(key, _)| (IVec2::from(**key) - center).abs().max_element() == ring)
+                .flat_map(|(_, bucket)| bucket)
+                .copied()
+                .collect(),
+        )
+    }
+
+    /// The shortest distance an object in the given ring can be from the position
+    pub fn ring_min_distance(ring: u32) -> f32 {
+        ring.saturating_sub(1) as f32 * BUCKET_SIZE
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.buckets.len() * size_of::<((i32, i32), Vec<IndexedObject>)>()
+            + self
+                .buckets
+                .values()
+                .map(|bucket| bucket.capacity() * size_of::<IndexedObject>())
+                .sum::<usize>()
+            + self.unplaced.capacity() * size_of::<(ObjectId<()>, TypeId)>()
+    }
+
+    pub fn compact(&mut self) {
+        for bucket in self.buckets.values_mut() {
+            bucket.shrink_to_fit();
+        }
+        self.unplaced.shrink_to_fit();
+    }
+}
+
+fn bucket_key(position: Vec2) -> (i32, i32) {
+    (position / BUCKET_SIZE).floor().as_ivec2().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec2;
+
+    #[test]
+    fn rings_and_radius() {
+        let mut index = SpatialIndex::new();
+        let type_id = TypeId::of::<()>();
+        index.insert(ObjectId::new(0), type_id, Some(vec2(1.0, 1.0)));
+        index.insert(ObjectId::new(1), type_id, Some(vec2(12.0, 3.0)));
+        index.insert(ObjectId::new(2), type_id, Some(vec2(40.0, 1.0)));
+        index.insert(ObjectId::new(3), type_id, None);
+
+        let ids = |objects: Vec<IndexedObject>| {
+            let mut ids = objects.iter().map(|object| object.id).collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(
+            ids(index.in_radius(vec2(5.0, 2.0), 8.0)),
+            vec![ObjectId::new(0), ObjectId::new(1)]
+        );
+        assert_eq!(
+            ids(index.in_radius(vec2(5.0, 2.0), 5.0)),
+            vec![ObjectId::new(0)]
+        );
+
+        assert_eq!(
+            ids(index.in_rect(vec2(0.0, 0.0), vec2(13.0, 4.0))),
+            vec![ObjectId::new(0), ObjectId::new(1)]
+        );
+        assert_eq!(
+            ids(index.in_rect(vec2(0.0, 0.0), vec2(12.0, 4.0))),
+            vec![ObjectId::new(0)]
+        );
+
+        assert_eq!(
+            ids(index.ring(vec2(1.0, 1.0), 0).unwrap()),
+            vec![ObjectId::new(0)]
+        );
+        assert_eq!(
+            ids(index.ring(vec2(1.0, 1.0), 1).unwrap()),
+            vec![ObjectId::new(1)]
+        );
+        assert_eq!(ids(index.ring(vec2(1.0, 1.0), 3).unwrap()), vec![]);
+        assert_eq!(
+            ids(index.ring(vec2(1.0, 1.0), 5).unwrap()),
+            vec![ObjectId::new(2)]
+        );
+        assert!(index.ring(vec2(1.0, 1.0), 6).is_none());
+
+        // Moving within a bucket and to another bucket
+        index.move_object(ObjectId::new(0), Some(vec2(1.0, 1.0)), Some(vec2(2.0, 1.0)));
+        index.move_object(
+            ObjectId::new(1),
+            Some(vec2(12.0, 3.0)),
+            Some(vec2(39.0, 3.0)),
+        );
+        index.move_object(ObjectId::new(3), None, Some(vec2(3.0, 1.0)));
+        assert_eq!(
+            ids(index.in_radius(vec2(2.0, 1.0), 1.0)),
+            vec![ObjectId::new(0), ObjectId::new(3)]
+        );
+        assert_eq!(
+            ids(index.in_radius(vec2(40.0, 2.0), 2.0)),
+            vec![ObjectId::new(1), ObjectId::new(2)]
+        );
+
+        index.remove(ObjectId::new(2), Some(vec2(40.0, 1.0)));
+        assert_eq!(
+            ids(index.in_radius(vec2(40.0, 2.0), 2.0)),
+            vec![ObjectId::new(1)]
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! The paths the characters searched, kept so they don't need to be searched again every tick.
+//!
+//! A path is stored for its start tile, its target tile and the hazards it avoids. It's searched between the
+//! centres of those tiles, so it doesn't matter where on the tile the character that searched it stood.
+//! When a tile becomes walkable or unwalkable, like when a wall is built or a tile floods, the paths that go through it
+//! are thrown away, together with the searches that didn't find a path. The other paths are kept,
+//! even when the tile opens up a shorter way.
+//! The liquid and closed door penalties only make one path preferable over another, so they don't throw paths away.
+//!
+//! Because of that, the paths in the cache influence the paths the characters take.
+//! So when [rollback](crate::rollback) is enabled, the changes to the cache are recorded so they can be undone too.
+
+use crate::{grid::Grid, objects::characters::Character, Map};
+use glam::{uvec2, vec2, UVec2, Vec2};
+use std::{
+    collections::{HashMap, HashSet},
+    mem::size_of,
+};
+
+/// The `(avoid_lava, avoid_drowning)` constraints a path can be searched with
+const PATH_CONSTRAINTS: [(bool, bool); 4] =
+    [(false, false), (false, true), (true, false), (true, true)];
+
+/// How well the path cache is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PathCacheStats {
+    /// The paths that were taken from the cache
+    pub hits: u64,
+    /// The paths that had to be searched
+    pub misses: u64,
+    /// The times paths were thrown away because the walkability of a tile changed
+    pub invalidations: u64,
+    /// The amount of paths in the cache, including the searches that didn't find a path
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct PathKey {
+    pub from: UVec2,
+    pub to: UVec2,
+    pub avoid_lava: bool,
+    pub avoid_drowning: bool,
+}
+
+impl PathKey {
+    pub(crate) fn new(from: Vec2, to: Vec2, avoid_lava: bool, avoid_drowning: bool) -> Self {
+        Self {
+            from: from.as_uvec2(),
+            to: to.as_uvec2(),
+            avoid_lava,
+            avoid_drowning,
+        }
+    }
+}
+
+pub(crate) struct PathCache {
+    /// The points of the paths between the tile centres. None when no path was found.
+    paths: HashMap<PathKey, Option<Vec<Vec2>>>,
+    /// For every tile, whether it was walkable with each of the [PATH_CONSTRAINTS] when the paths were searched.
+    /// None until it's first refreshed.
+    walkability: Option<Grid<[bool; 4]>>,
+    hits: u64,
+    misses: u64,
+    invalidations: u64,
+    /// The changes since the journal was last taken. None when they aren't recorded.
+    journal: Option<Vec<PathCacheChange>>,
+}
+
+/// A change to the [PathCache] that can be undone
+pub(crate) enum PathCacheChange {
+    /// A path that wasn't in the cache was added
+    Inserted(PathKey),
+    /// The walkability changed. Has the paths that were thrown away because of it.
+    Refreshed {
+        walkability: Option<Grid<[bool; 4]>>,
+        removed_paths: Vec<(PathKey, Option<Vec<Vec2>>)>,
+    },
+}
+
+// The paths can be searched again, so only the state of the map is interesting
+impl std::fmt::Debug for PathCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PathCache").finish_non_exhaustive()
+    }
+}
+
+impl PathCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            paths: HashMap::new(),
+            walkability: None,
+            hits: 0,
+            misses: 0,
+            invalidations: 0,
+            journal: None,
+        }
+    }
+
+    /// Get the points of the cached path. The outer option is None when the path isn't in the cache.
+    pub(crate) fn get(&mut self, key: &PathKey) -> Option<Option<Vec<Vec2>>> {
+        let points = self.paths.get(key).cloned();
+
+        match points {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+
+        points
+    }
+
+    pub(crate) fn insert(&mut self, key: PathKey, points: Option<Vec<Vec2>>) {
+        let existed = self.paths.insert(key, points).is_some();
+        if let Some(journal) = self.journal.as_mut().filter(|_| !existed) {
+            journal.push(PathCacheChange::Inserted(key));
+        }
+    }
+
+    /// Start or stop recording the changes to the cache
+    pub(crate) fn set_journaling(&mut self, journaling: bool) {
+        self.journal = journaling.then(Vec::new);
+    }
+
+    /// Take the changes that were recorded since the last time
+    pub(crate) fn take_journal(&mut self) -> Vec<PathCacheChange> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Undo the changes, which must be the last changes made to the cache. The statistics aren't rewound.
+    pub(crate) fn undo(&mut self, changes: Vec<PathCacheChange>) {
+        for change in changes.into_iter().rev() {
+            match change {
+                PathCacheChange::Inserted(key) => {
+                    self.paths.remove(&key);
+                }
+                PathCacheChange::Refreshed {
+                    walkability,
+                    removed_paths,
+                } => {
+                    self.walkability = walkability;
+                    self.paths.extend(removed_paths);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn stats(&self) -> PathCacheStats {
+        PathCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            invalidations: self.invalidations,
+            entries: self.paths.len(),
+        }
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.paths.capacity() * size_of::<(PathKey, Option<Vec<Vec2>>)>()
+            + self
+                .paths
+                .values()
+                .flatten()
+                .map(|points| points.capacity() * size_of::<Vec2>())
+                .sum::<usize>()
+            + self.walkability.as_ref().map_or(0, Grid::memory_usage)
+    }
+}
+
+impl PathCacheChange {
+    pub(crate) fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                PathCacheChange::Inserted(_) => 0,
+                PathCacheChange::Refreshed {
+                    walkability,
+                    removed_paths,
+                } => {
+                    walkability.as_ref().map_or(0, Grid::memory_usage)
+                        + removed_paths.capacity() * size_of::<(PathKey, Option<Vec<Vec2>>)>()
+                        + removed_paths
+                            .iter()
+                            .filter_map(|(_, points)| points.as_ref())
+                            .map(|points| points.capacity() * size_of::<Vec2>())
+                            .sum::<usize>()
+                }
+            }
+    }
+}
+
+impl Map {
+    /// Throw away the cached paths that go through tiles of which the walkability changed since they were searched,
+    /// and the searches that didn't find a path.
+    ///
+    /// The tiles can be changed directly, so this looks at all of them. Without characters nobody uses the paths,
+    /// so then nothing is done until there are characters again.
+    pub(crate) fn refresh_path_cache(&self) {
+        if self.objects().get_objects::<Character>().next().is_none() {
+            return;
+        }
+
+        let mut path_cache = self.path_cache.lock().unwrap();
+        let known = path_cache.walkability.is_some();
+        let old_walkability = path_cache
+            .journal
+            .is_some()
+            .then(|| path_cache.walkability.clone());
+        let walkability = path_cache
+            .walkability
+            .get_or_insert_with(|| Grid::new(self.width(), self.height(), [false; 4]));
+
+        // The tiles of which the walkability changed, for each of the constraints
+        let mut changed_tiles: [HashSet<UVec2>; 4] = Default::default();
+        for (x, y) in self.all_tile_coords() {
+            let centre = vec2(x as f32, y as f32) + vec2(0.5, 0.5);
+            let tile_walkability = PATH_CONSTRAINTS.map(|(avoid_lava, avoid_drowning)| {
+                self.position_penalty(centre, avoid_lava, avoid_drowning)
+                    .is_some()
+            });
+
+            for (constraint, changed_tiles) in changed_tiles.iter_mut().enumerate() {
+                if known && walkability[(x, y)][constraint] != tile_walkability[constraint] {
+                    changed_tiles.insert(uvec2(x as u32, y as u32));
+                }
+            }
+            walkability[(x, y)] = tile_walkability;
+        }
+
+        if known && changed_tiles.iter().all(HashSet::is_empty) {
+            return;
+        }
+
+        // The paths searched before the walkability was known are kept
+        let removed_paths = if known {
+            path_cache.invalidations += 1;
+            let removed_keys = path_cache
+                .paths
+                .iter()
+                .filter(|(key, points)| {
+                    let constraint = PATH_CONSTRAINTS
+                        .iter()
+                        .position(|constraint| *constraint == (key.avoid_lava, key.avoid_drowning))
+                        .unwrap();
+                    let changed_tiles = &changed_tiles[constraint];
+
+                    match points {
+                        // Any change might have opened up a way
+                        None => !changed_tiles.is_empty(),
+                        Some(points) => points
+                            .iter()
+                            .any(|point| changed_tiles.contains(&point.as_uvec2())),
+                    }
+                })
+                .map(|(key, _)| *key)
+                .collect::<Vec<_>>();
+
+            removed_keys
+                .into_iter()
+                .map(|key| (key, path_cache.paths.remove(&key).unwrap()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        if let (Some(journal), Some(walkability)) = (path_cache.journal.as_mut(), old_walkability) {
+            journal.push(PathCacheChange::Refreshed {
+                walkability,
+                removed_paths,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{liquids::LiquidData, tiles::TileType};
+
+    #[test]
+    fn paths_are_kept_until_walkability_changes() {
+        let mut map = Map::new_default(7, 3);
+        // The cache is only looked after while there's someone to use the paths
+        map.objects_mut()
+            .push_object::<Character>(Character::new(vec2(0.5, 0.5), 1.0, Vec::new()));
+        map.refresh_path_cache();
+
+        let path = map
+            .find_cached_path(vec2(0.2, 1.5), vec2(6.5, 1.5), true, true)
+            .unwrap();
+        assert!((path.total_length() - 6.3).abs() < 0.1);
+
+        // Other positions on the same tiles use the same search
+        let path = map
+            .find_cached_path(vec2(0.8, 1.5), vec2(6.5, 1.5), true, true)
+            .unwrap();
+        assert!((path.total_length() - 5.7).abs() < 0.1);
+        assert_eq!(
+            map.path_cache_stats(),
+            PathCacheStats {
+                hits: 1,
+                misses: 1,
+                invalidations: 0,
+                entries: 1,
+            }
+        );
+        assert!(map
+            .find_cached_path(vec2(0.5, 0.5), vec2(2.5, 0.5), true, true)
+            .is_some());
+
+        // Shallow water only makes the path more expensive
+        *map.tiles[(3, 1)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water { level: 0.1 };
+        map.refresh_path_cache();
+        assert_eq!(map.path_cache_stats().invalidations, 0);
+
+        // A wall cuts the path off
+        for y in 0..3 {
+            map.tiles[(3, y)].tile_type = TileType::Wall;
+        }
+        map.refresh_path_cache();
+        assert!(map
+            .find_cached_path(vec2(0.2, 1.5), vec2(6.5, 1.5), true, true)
+            .is_none());
+        // The paths that don't go through the wall are kept
+        assert!(map
+            .find_cached_path(vec2(0.5, 0.5), vec2(2.5, 0.5), true, true)
+            .is_some());
+        assert_eq!(
+            map.path_cache_stats(),
+            PathCacheStats {
+                hits: 2,
+                misses: 3,
+                invalidations: 1,
+                entries: 2,
+            }
+        );
+    }
+}
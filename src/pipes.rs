@@ -0,0 +1,270 @@
+//! Pumps and the pipes they push liquid through.
+//!
+//! A [BuildingType::Pump] takes the liquid on its own tile, the intake, and pushes it into the pipe on the tile it faces.
+//! Pipes on tiles that are directly next to each other are connected and form a network.
+//! The liquid comes out at the [Pipe::outlet] pipes of the network, split evenly between them.
+//! The liquid moves through the network in the same tick, so pipes don't hold any liquid themselves.
+
+use crate::{
+    air::{AirDiff, STEAM_PER_WATER_LEVEL},
+    grid::Grid,
+    liquids::{AnyLiquid, Lava, LiquidData, LiquidDiff, LiquidKind, Water},
+    networks::TileNetworks,
+    objects::{
+        building::{Building, BuildingType},
+        ObjectKind, ObjectProperties,
+    },
+    Map,
+};
+use glam::{vec2, UVec2, Vec2};
+
+/// A pipe on a tile. It connects to the pipes on the tiles directly next to it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pipe {
+    pub location: UVec2,
+    /// The liquid that is pumped into the network comes out of the outlets onto their tile
+    pub outlet: bool,
+}
+
+impl ObjectProperties for Pipe {
+    fn render_kind(&self) -> ObjectKind {
+        ObjectKind::Pipe
+    }
+
+    fn position(&self) -> Option<Vec2> {
+        Some(self.location.as_vec2() + vec2(0.5, 0.5))
+    }
+}
+
+impl Map {
+    /// Search the pipe networks and return the outlets of every network
+    fn pipe_networks(&self, networks: &mut TileNetworks) -> Vec<Vec<(usize, usize)>> {
+        let objects = self.objects();
+        let pipes = objects
+            .get_objects::<Pipe>()
+            .map(|pipe| {
+                (
+                    (pipe.location.x as usize, pipe.location.y as usize),
+                    pipe.outlet,
+                )
+            })
+            .collect::<Vec<_>>();
+        drop(objects);
+
+        let network_count = networks.search(pipes.iter().map(|(tile, _)| *tile)).len();
+        let mut outlets = vec![Vec::new(); network_count];
+        for (tile, _) in pipes.into_iter().filter(|(_, outlet)| *outlet) {
+            if let Some(network) = networks.network(tile.0, tile.1) {
+                outlets[network].push(tile);
+            }
+        }
+        // Sorted so the result doesn't depend on the order of the pipes
+        for network_outlets in outlets.iter_mut() {
+            network_outlets.sort_unstable();
+            network_outlets.dedup();
+        }
+
+        outlets
+    }
+
+    /// Add the liquid the pumps move through the pipe networks this tick to the liquid diffs.
+    ///
+    /// A pump never takes more than what is left on its intake after the other flows of the tick
+    /// and never fills an outlet above [LiquidData::MAX_LEVEL], so no liquid is lost or created.
+    pub(crate) fn add_pump_flows(
+        &self,
+        water_diff: &mut Grid<LiquidDiff>,
+        lava_diff: &mut Grid<LiquidDiff>,
+        air_diff: &Grid<AirDiff>,
+        networks: &mut TileNetworks,
+        delta_time: f32,
+    ) {
+        let objects = self.objects();
+        let pumps = objects
+            .get_objects::<Building>()
+            .filter_map(|building| match building.building_type {
+                BuildingType::Pump {
+                    rate,
+                    enabled: true,
+                } if rate > 0.0 => Some((building.location, building.facing, rate)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        drop(objects);
+
+        if pumps.is_empty() {
+            return;
+        }
+
+        let outlets = self.pipe_networks(networks);
+
+        for (location, facing, rate) in pumps {
+            let intake = (location.x as usize, location.y as usize);
+            let Some(network) = facing
+                .move_coords_in_direction(intake.0, intake.1, self.width(), self.height())
+                .and_then(|(x, y)| networks.network(x, y))
+            else {
+                continue;
+            };
+            let Some(liquids) = self.tiles[intake].tile_type.get_liquids() else {
+                continue;
+            };
+            let Some(kind) = liquids.kind() else {
+                continue;
+            };
+
+            // What is left of the liquid on the intake after the other flows of this tick
+            let available = match kind {
+                LiquidKind::Water => {
+                    liquids.get_level::<Water>() + water_diff[intake].level
+                        - air_diff[intake].evaporated / STEAM_PER_WATER_LEVEL
+                }
+                LiquidKind::Lava => liquids.get_level::<Lava>() + lava_diff[intake].level,
+            }
+            .max(0.0);
+
+            let open_outlets = outlets[network]
+                .iter()
+                .copied()
+                .filter(|outlet| self.tiles[*outlet].tile_type.get_liquids().is_some())
+                .collect::<Vec<_>>();
+            if open_outlets.is_empty() {
+                continue;
+            }
+
+            let share = rate * delta_time / open_outlets.len() as f32;
+            let amounts = open_outlets
+                .iter()
+                .map(|outlet| {
+                    let level = self.tiles[*outlet]
+                        .tile_type
+                        .get_liquids()
+                        .unwrap()
+                        .get_level::<AnyLiquid>();
+                    let room = LiquidData::MAX_LEVEL
+                        - (level + water_diff[*outlet].level + lava_diff[*outlet].level);
+                    share.min(room.max(0.0))
+                })
+                .collect::<Vec<_>>();
+
+            let total = amounts.iter().sum::<f32>();
+            if total <= 0.0 {
+                continue;
+            }
+            let scale = (available / total).min(1.0);
+
+            let diff = match kind {
+                LiquidKind::Water => &mut *water_diff,
+                LiquidKind::Lava => &mut *lava_diff,
+            };
+            diff[intake].level -= total * scale;
+            for (outlet, amount) in open_outlets.into_iter().zip(amounts) {
+                diff[outlet].level += amount * scale;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tiles::TileType, Facing};
+    use approx::assert_relative_eq;
+    use glam::uvec2;
+
+    fn total_liquid(map: &Map) -> f32 {
+        map.all_tile_coords()
+            .filter_map(|coords| map.tiles[coords].tile_type.get_liquids())
+            .map(|liquids| liquids.get_level::<AnyLiquid>())
+            .sum()
+    }
+
+    #[test]
+    fn pump_moves_water_over_wall() {
+        let mut map = Map::new_default(5, 1);
+        map.tiles[(2, 0)].tile_type = TileType::Wall;
+        *map.tiles[(0, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water { level: 1.0 };
+
+        map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(0, 0),
+            facing: Facing::East,
+            building_type: BuildingType::Pump {
+                rate: 0.5,
+                enabled: true,
+            },
+        });
+        // The pipes run through the wall
+        for (x, outlet) in [(1, false), (2, false), (3, true)] {
+            map.objects_mut().push_object::<Pipe>(Pipe {
+                location: uvec2(x, 0),
+                outlet,
+            });
+        }
+
+        map.step_n(0.1, 10);
+
+        let water_level = |map: &Map, x| {
+            map.tiles[(x, 0)]
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<AnyLiquid>()
+        };
+        assert!(water_level(&map, 0) < 0.9);
+        assert!(water_level(&map, 3) + water_level(&map, 4) > 0.4);
+        assert_relative_eq!(total_liquid(&map), 1.0, epsilon = 1e-4);
+
+        // Nothing is left to pump, but the pump doesn't make water out of nothing
+        map.step_n(0.1, 100);
+        assert_relative_eq!(water_level(&map, 0), 0.0);
+        assert_relative_eq!(total_liquid(&map), 1.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn pump_needs_a_connected_outlet() {
+        let mut map = Map::new_default(5, 1);
+        *map.tiles[(0, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Lava { level: 0.05 };
+        for x in 1..5 {
+            map.tiles[(x, 0)].ground_level = 1.0;
+        }
+
+        map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(0, 0),
+            facing: Facing::East,
+            building_type: BuildingType::Pump {
+                rate: 1.0,
+                enabled: true,
+            },
+        });
+        map.objects_mut().push_object::<Pipe>(Pipe {
+            location: uvec2(1, 0),
+            outlet: false,
+        });
+        // Not connected, there's a gap at x = 2
+        let outlet = map.objects_mut().push_object::<Pipe>(Pipe {
+            location: uvec2(3, 0),
+            outlet: true,
+        });
+
+        map.step_n(0.1, 5);
+        assert!(map.tiles[(3, 0)]
+            .tile_type
+            .get_liquids()
+            .unwrap()
+            .kind()
+            .is_none());
+
+        map.objects_mut().remove_object(outlet);
+        map.objects_mut().push_object::<Pipe>(Pipe {
+            location: uvec2(2, 0),
+            outlet: true,
+        });
+
+        map.step_n(0.1, 1);
+        assert_eq!(
+            map.tiles[(2, 0)].tile_type.get_liquids().unwrap().kind(),
+            Some(LiquidKind::Lava)
+        );
+    }
+}
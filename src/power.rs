@@ -0,0 +1,348 @@
+//! Generators and the cables they send power through to the buildings that need it.
+//!
+//! Cables on tiles that are directly next to each other are connected and form a network.
+//! A building is on a network when a cable runs under any tile of its footprint.
+//! Every simulation tick, the power the [generators](crate::objects::building::BuildingType::Generator)
+//! of a network make is shared by the buildings on it. When there's less power than they need,
+//! every building gets the same fraction of what it needs and its effects are scaled down by that fraction.
+//! Generators that burn fuel only make power while the ore the characters brought them lasts.
+
+use crate::{
+    networks::TileNetworks,
+    objects::{building::Building, ObjectKind, ObjectProperties},
+    Map,
+};
+use glam::{uvec2, vec2, UVec2, Vec2};
+
+/// A cable on a tile. It connects to the cables on the tiles directly next to it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cable {
+    pub location: UVec2,
+}
+
+impl ObjectProperties for Cable {
+    fn render_kind(&self) -> ObjectKind {
+        ObjectKind::Cable
+    }
+
+    fn position(&self) -> Option<Vec2> {
+        Some(self.location.as_vec2() + vec2(0.5, 0.5))
+    }
+}
+
+/// Connected cables and the power that goes through them, see [Map::power_networks]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerNetwork {
+    /// The tiles of the cables of the network
+    pub cables: Vec<UVec2>,
+    /// The power the generators on the network make
+    pub supply: f32,
+    /// The power the buildings on the network need
+    pub demand: f32,
+}
+
+impl PowerNetwork {
+    /// The fraction of the demand that is supplied, from 0 to 1.
+    /// A network that doesn't need any power is fully supplied.
+    pub fn satisfaction(&self) -> f32 {
+        if self.demand <= 0.0 {
+            1.0
+        } else {
+            (self.supply / self.demand).min(1.0)
+        }
+    }
+}
+
+impl Map {
+    /// All cable networks on the map with their supply and demand
+    pub fn power_networks(&self) -> Vec<PowerNetwork> {
+        self.search_power_networks(&mut TileNetworks::new(self.width(), self.height()))
+    }
+
+    /// Search the cable networks and sum up their supply and demand
+    fn search_power_networks(&self, networks: &mut TileNetworks) -> Vec<PowerNetwork> {
+        let objects = self.objects();
+
+        let mut power_networks = networks
+            .search(
+                objects
+                    .get_objects::<Cable>()
+                    .map(|cable| (cable.location.x as usize, cable.location.y as usize)),
+            )
+            .into_iter()
+            .map(|tiles| PowerNetwork {
+                cables: tiles
+                    .into_iter()
+                    .map(|(x, y)| uvec2(x as u32, y as u32))
+                    .collect(),
+                supply: 0.0,
+                demand: 0.0,
+            })
+            .collect::<Vec<_>>();
+
+        for building in objects.get_objects::<Building>() {
+            let Some(network) = building_network(&building, networks) else {
+                continue;
+            };
+
+            power_networks[network].supply += building.building_type.power_supply();
+            power_networks[network].demand += building.building_type.power_demand();
+        }
+
+        power_networks
+    }
+
+    /// Share the power of every network between the buildings on it
+    pub(crate) fn update_power_grid(&mut self, networks: &mut TileNetworks) {
+        let power_networks = self.search_power_networks(networks);
+
+        for mut building in self
+            .objects
+            .get_mut()
+            .unwrap()
+            .get_objects_mut::<Building>()
+        {
+            if building.building_type.power_demand() <= 0.0 {
+                continue;
+            }
+
+            let power = building_network(&building, networks)
+                .map_or(0.0, |network| power_networks[network].satisfaction());
+            building.building_type.set_power(power);
+        }
+    }
+}
+
+/// The network of the first cable under the footprint of the building
+fn building_network(building: &Building, networks: &TileNetworks) -> Option<usize> {
+    building
+        .footprint_tiles()
+        .into_iter()
+        .find_map(|tile| networks.network(tile.x as usize, tile.y as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::{
+            building::{temperature_control_workspots, BuildingType, WorkSpot, WorkSpotOccupation},
+            item::ItemKind,
+            ObjectId,
+        },
+        tiles::{Tile, TileType},
+        Facing,
+    };
+    use approx::assert_relative_eq;
+
+    fn ventilator_power(map: &Map, id: ObjectId<Building>) -> f32 {
+        match map.objects().get_object(id).unwrap().building_type {
+            BuildingType::PoweredVentilator { power } => power,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn generators_power_their_network() {
+        let mut map = Map::new_default(6, 1);
+        let mut objects = map.objects_mut();
+        objects.push_object::<Building>(Building {
+            location: uvec2(0, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Generator {
+                output: 1.5,
+                enabled: true,
+                fuel: None,
+            },
+        });
+        let ventilator = |x| Building {
+            location: uvec2(x, 0),
+            facing: Facing::East,
+            building_type: BuildingType::PoweredVentilator { power: 0.0 },
+        };
+        let first = objects.push_object::<Building>(ventilator(1));
+        let second = objects.push_object::<Building>(ventilator(2));
+        // Not connected, there's a gap at x = 4
+        let unconnected = objects.push_object::<Building>(ventilator(5));
+        for x in [0, 1, 2, 3, 5] {
+            objects.push_object::<Cable>(Cable {
+                location: uvec2(x, 0),
+            });
+        }
+        drop(objects);
+
+        let networks = map.power_networks();
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks[0].cables.len(), 4);
+        assert_eq!(networks[0].supply, 1.5);
+        assert_eq!(networks[0].demand, 2.0);
+        assert_eq!(networks[0].satisfaction(), 0.75);
+        assert_eq!(networks[1].satisfaction(), 0.0);
+
+        map.perform_simulation_tick(0.1);
+        assert_eq!(ventilator_power(&map, first), 0.75);
+        assert_eq!(ventilator_power(&map, second), 0.75);
+        assert_eq!(ventilator_power(&map, unconnected), 0.0);
+
+        // The effect of a building scales with its power
+        let objects = map.objects();
+        let pushers = objects.get_object(first).unwrap().air_pushers();
+        assert_eq!(pushers[0].amount, 0.75 * 0.5);
+    }
+
+    #[test]
+    fn generators_burn_their_fuel() {
+        let mut map = Map::new_default(1, 1);
+        let generator = map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(0, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Generator {
+                output: 1.0,
+                enabled: true,
+                fuel: Some(0.15),
+            },
+        });
+        map.objects_mut().push_object::<Cable>(Cable {
+            location: uvec2(0, 0),
+        });
+
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.power_networks()[0].supply, 1.0);
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.power_networks()[0].supply, 0.0);
+
+        // Ore adds fuel, as long as there's room for it
+        let objects = map.objects_mut();
+        let mut generator = objects.get_object_mut(generator).unwrap();
+        assert_eq!(generator.building_type.input_room(ItemKind::Ore), 3);
+        assert_eq!(generator.building_type.input_room(ItemKind::Food), 0);
+        assert!(generator.building_type.put_input(ItemKind::Ore));
+        assert!(!generator.building_type.put_input(ItemKind::Food));
+        assert_eq!(generator.building_type.input_room(ItemKind::Ore), 2);
+        assert_eq!(generator.building_type.power_supply(), 1.0);
+    }
+
+    #[test]
+    fn powered_ventilator_pushes_air() {
+        let mut map = Map::new_default(3, 1);
+        map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(0, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Generator {
+                output: 1.0,
+                enabled: true,
+                fuel: None,
+            },
+        });
+        map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(1, 0),
+            facing: Facing::East,
+            building_type: BuildingType::PoweredVentilator { power: 0.0 },
+        });
+        for x in 0..2 {
+            map.objects_mut().push_object::<Cable>(Cable {
+                location: uvec2(x, 0),
+            });
+        }
+
+        map.step_n(0.1, 10);
+        let pressure = |map: &Map, x| {
+            map.tiles[(x, 0)]
+                .tile_type
+                .get_air()
+                .unwrap()
+                .air_pressure(0.0)
+        };
+        assert!(pressure(&map, 2) > pressure(&map, 0));
+    }
+
+    #[test]
+    fn heaters_and_coolers_run_on_power_and_workers() {
+        let mut map = Map::new_default(5, 1);
+        let manned = || {
+            temperature_control_workspots().map(|workspot| WorkSpot {
+                occupation: WorkSpotOccupation::Working(ObjectId::new(0)),
+                ..workspot
+            })
+        };
+        // Walls keep the heater and cooler from evening out each other's tiles
+        map.tiles[(1, 0)].tile_type = TileType::Wall;
+        map.tiles[(3, 0)].tile_type = TileType::Wall;
+        let generator = map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(2, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Generator {
+                output: 2.0,
+                enabled: true,
+                fuel: None,
+            },
+        });
+        let heater = map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(0, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Heater {
+                target: 25.0,
+                power: 0.0,
+                workspots: manned(),
+            },
+        });
+        map.objects_mut().push_object::<Building>(Building {
+            location: uvec2(4, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Cooler {
+                target: 15.0,
+                power: 0.0,
+                workspots: manned(),
+            },
+        });
+        for x in 0..5 {
+            map.objects_mut().push_object::<Cable>(Cable {
+                location: uvec2(x, 0),
+            });
+        }
+
+        map.perform_simulation_tick(0.1);
+        assert!(map.tiles[(0, 0)].temperature > Tile::DEFAULT_TEMPERATURE);
+        assert!(map.tiles[(4, 0)].temperature < Tile::DEFAULT_TEMPERATURE);
+
+        // They stop at their target
+        map.step_n(0.1, 100);
+        assert_relative_eq!(map.tiles[(0, 0)].temperature, 25.0, epsilon = 0.01);
+        assert_relative_eq!(map.tiles[(4, 0)].temperature, 15.0, epsilon = 0.01);
+
+        // Without power they don't do anything
+        map.objects_mut()
+            .get_object_mut(generator)
+            .unwrap()
+            .building_type = BuildingType::Generator {
+            output: 2.0,
+            enabled: false,
+            fuel: None,
+        };
+        map.tiles[(0, 0)].temperature = Tile::DEFAULT_TEMPERATURE;
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.tiles[(0, 0)].temperature, Tile::DEFAULT_TEMPERATURE);
+        assert_eq!(map.tiles[(4, 0)].temperature, 15.0);
+
+        // Nor without anyone working at them
+        map.objects_mut()
+            .get_object_mut(generator)
+            .unwrap()
+            .building_type = BuildingType::Generator {
+            output: 2.0,
+            enabled: true,
+            fuel: None,
+        };
+        map.objects_mut()
+            .get_object_mut(heater)
+            .unwrap()
+            .building_type = BuildingType::Heater {
+            target: 25.0,
+            power: 0.0,
+            workspots: temperature_control_workspots(),
+        };
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.tiles[(0, 0)].temperature, Tile::DEFAULT_TEMPERATURE);
+    }
+}
@@ -0,0 +1,520 @@
+//! Rewinding the map by a few simulation ticks, like for client-side prediction.
+//!
+//! When rollback is enabled with [Map::enable_rollback], the state of the map is recorded at the start of every
+//! simulation tick. Only the latest state is kept in full. For the ticks before it, only what changed is kept,
+//! so the recording doesn't take much more memory than the map itself when little is going on.
+//!
+//! The recording covers the tiles, the objects of the types that are saved in a [snapshot](crate::snapshot),
+//! the current time and the state that influences how the next ticks play out, like the settled tiles, the
+//! [liquid flow](Map::liquid_flow) and the cached paths. Rolling back and simulating the same ticks again gives the same map, as long as the
+//! map is [deterministic](Map::set_deterministic) and the same changes are made to it between the ticks.
+//!
+//! Objects of types defined outside of this crate aren't rolled back. The event listeners get the events
+//! of the objects that come back or disappear, and they get the events of the ticks again when they're simulated again.
+
+use crate::{
+    grid::Grid,
+    path_cache::PathCacheChange,
+    snapshot::{object_records, reset_object_record, ObjectRecords},
+    tiles::Tile,
+    Map, TileCoordIter,
+};
+use glam::Vec2;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    mem::size_of,
+};
+
+/// The recorded ticks of a map
+pub(crate) struct Rollback {
+    /// The maximum amount of ticks that can be rolled back
+    max_ticks: usize,
+    /// The state of the map at the last recording or rollback. None until the first tick.
+    latest: Option<TickState>,
+    /// True when the latest state was restored by a rollback, so it's the start of a tick that hasn't run yet
+    restored: bool,
+    /// For every tick before the latest, the changes that turn the state at the start of the next tick
+    /// back into the state at its start. The oldest tick comes first.
+    undos: VecDeque<TickUndo>,
+}
+
+// The recordings can be big, so only the amount of ticks is interesting
+impl std::fmt::Debug for Rollback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Rollback")
+            .field("max_ticks", &self.max_ticks)
+            .field("ticks", &self.ticks())
+            .finish_non_exhaustive()
+    }
+}
+
+struct TickState {
+    tiles: Grid<Tile>,
+    settled: Grid<bool>,
+    settled_baseline: Option<Grid<Tile>>,
+    liquid_flow: Grid<Vec2>,
+    objects: ObjectRecords,
+    next_object_id: Option<u32>,
+    current_time: f64,
+}
+
+struct TickUndo {
+    tiles: HashMap<(usize, usize), Tile>,
+    settled: HashMap<(usize, usize), bool>,
+    settled_baseline: BaselineUndo,
+    liquid_flow: HashMap<(usize, usize), Vec2>,
+    /// The records of the objects that changed. None for the objects that didn't exist yet.
+    objects: HashMap<(u8, u32), Option<Vec<u8>>>,
+    next_object_id: Option<u32>,
+    current_time: f64,
+    /// The changes to the path cache, in the order they were made
+    path_cache: Vec<PathCacheChange>,
+}
+
+enum BaselineUndo {
+    /// The tiles of the baseline that changed
+    Changed(HashMap<(usize, usize), Tile>),
+    /// The baseline was created or thrown away
+    Replaced(Option<Grid<Tile>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackError {
+    /// Rollback hasn't been enabled with [Map::enable_rollback]
+    NotEnabled,
+    /// Fewer ticks are recorded than were asked to be rolled back
+    NotEnoughTicks { requested: usize, available: usize },
+}
+
+impl Display for RollbackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RollbackError::NotEnabled => write!(f, "Rollback is not enabled"),
+            RollbackError::NotEnoughTicks {
+                requested,
+                available,
+            } => write!(
+                f,
+                "Can't roll back {requested} ticks, only {available} ticks are recorded"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RollbackError {}
+
+impl Rollback {
+    /// The amount of ticks that can be rolled back
+    fn ticks(&self) -> usize {
+        match self.latest {
+            None => 0,
+            Some(_) if self.restored => self.undos.len(),
+            Some(_) => self.undos.len() + 1,
+        }
+    }
+
+    /// Forget the oldest ticks that don't fit anymore
+    fn trim(&mut self) {
+        while self.undos.len() >= self.max_ticks {
+            self.undos.pop_front();
+        }
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.latest.as_ref().map_or(0, TickState::memory_usage)
+            + self.undos.capacity() * size_of::<TickUndo>()
+            + self.undos.iter().map(TickUndo::memory_usage).sum::<usize>()
+    }
+}
+
+impl TickState {
+    fn new(map: &Map) -> Self {
+        let objects = map.objects();
+        Self {
+            tiles: map.tiles.clone(),
+            settled: map.settled.clone(),
+            settled_baseline: map.settled_baseline.clone(),
+            liquid_flow: map.liquid_flow.clone(),
+            objects: object_records(&objects),
+            next_object_id: objects.next_object_id(),
+            current_time: map.current_time,
+        }
+    }
+
+    /// Change the state to the one of the map and return the changes that undo that
+    fn update(&mut self, map: &Map) -> TickUndo {
+        let settled_baseline = match (&mut self.settled_baseline, &map.settled_baseline) {
+            (Some(old), Some(new)) => BaselineUndo::Changed(update_grid(old, new)),
+            (old, new) => BaselineUndo::Replaced(std::mem::replace(old, new.clone())),
+        };
+
+        let objects = map.objects();
+        let mut records = object_records(&objects);
+        let mut object_changes = HashMap::new();
+        // The objects that changed or were removed
+        self.objects
+            .retain(|key, record| match records.remove(key) {
+                Some(new_record) => {
+                    if new_record != *record {
+                        object_changes.insert(*key, Some(std::mem::replace(record, new_record)));
+                    }
+                    true
+                }
+                None => {
+                    object_changes.insert(*key, Some(std::mem::take(record)));
+                    false
+                }
+            });
+        // The objects that were added
+        for (key, record) in records {
+            object_changes.insert(key, None);
+            self.objects.insert(key, record);
+        }
+
+        TickUndo {
+            tiles: update_grid(&mut self.tiles, &map.tiles),
+            settled: update_grid(&mut self.settled, &map.settled),
+            settled_baseline,
+            liquid_flow: update_grid(&mut self.liquid_flow, &map.liquid_flow),
+            objects: object_changes,
+            next_object_id: std::mem::replace(&mut self.next_object_id, objects.next_object_id()),
+            current_time: std::mem::replace(&mut self.current_time, map.current_time),
+            path_cache: Vec::new(),
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.tiles.memory_usage()
+            + self.settled.memory_usage()
+            + self.settled_baseline.as_ref().map_or(0, Grid::memory_usage)
+            + self.liquid_flow.memory_usage()
+            + self
+                .objects
+                .values()
+                .map(|record| size_of::<((u8, u32), Vec<u8>)>() + record.capacity())
+                .sum::<usize>()
+    }
+}
+
+impl TickUndo {
+    /// Undo the changes to the state. Returns the changes to the path cache that need to be undone.
+    fn apply(self, state: &mut TickState) -> Vec<PathCacheChange> {
+        for (tile, value) in self.tiles {
+            state.tiles[tile] = value;
+        }
+        for (tile, value) in self.settled {
+            state.settled[tile] = value;
+        }
+        for (tile, value) in self.liquid_flow {
+            state.liquid_flow[tile] = value;
+        }
+        match self.settled_baseline {
+            BaselineUndo::Changed(tiles) => {
+                let baseline = state
+                    .settled_baseline
+                    .as_mut()
+                    .expect("Only an existing baseline can change");
+                for (tile, value) in tiles {
+                    baseline[tile] = value;
+                }
+            }
+            BaselineUndo::Replaced(baseline) => state.settled_baseline = baseline,
+        }
+        for (key, record) in self.objects {
+            match record {
+                Some(record) => state.objects.insert(key, record),
+                None => state.objects.remove(&key),
+            };
+        }
+        state.next_object_id = self.next_object_id;
+        state.current_time = self.current_time;
+
+        self.path_cache
+    }
+
+    /// Add the changes that undo the changes made after this undo, so this undoes both
+    fn merge_newer(&mut self, newer: Self) {
+        // The values in this undo are the oldest, so they win
+        for (tile, value) in newer.tiles {
+            self.tiles.entry(tile).or_insert(value);
+        }
+        for (tile, value) in newer.settled {
+            self.settled.entry(tile).or_insert(value);
+        }
+        for (tile, value) in newer.liquid_flow {
+            self.liquid_flow.entry(tile).or_insert(value);
+        }
+        for (key, record) in newer.objects {
+            self.objects.entry(key).or_insert(record);
+        }
+
+        let settled_baseline =
+            std::mem::replace(&mut self.settled_baseline, BaselineUndo::Replaced(None));
+        self.settled_baseline = match (settled_baseline, newer.settled_baseline) {
+            (BaselineUndo::Replaced(baseline), _) => BaselineUndo::Replaced(baseline),
+            (BaselineUndo::Changed(tiles), BaselineUndo::Changed(mut newer_tiles)) => {
+                newer_tiles.extend(tiles);
+                BaselineUndo::Changed(newer_tiles)
+            }
+            (BaselineUndo::Changed(tiles), BaselineUndo::Replaced(baseline)) => {
+                BaselineUndo::Replaced(baseline.map(|mut baseline| {
+                    for (tile, value) in tiles {
+                        baseline[tile] = value;
+                    }
+                    baseline
+                }))
+            }
+        };
+
+        // The newest changes are undone first
+        self.path_cache.extend(newer.path_cache);
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.tiles.capacity() * size_of::<((usize, usize), Tile)>()
+            + self.settled.capacity() * size_of::<((usize, usize), bool)>()
+            + self.liquid_flow.capacity() * size_of::<((usize, usize), Vec2)>()
+            + match &self.settled_baseline {
+                BaselineUndo::Changed(tiles) => {
+                    tiles.capacity() * size_of::<((usize, usize), Tile)>()
+                }
+                BaselineUndo::Replaced(baseline) => baseline.as_ref().map_or(0, Grid::memory_usage),
+            }
+            + self
+                .objects
+                .values()
+                .map(|record| {
+                    size_of::<((u8, u32), Option<Vec<u8>>)>()
+                        + record.as_ref().map_or(0, Vec::capacity)
+                })
+                .sum::<usize>()
+            + self
+                .path_cache
+                .iter()
+                .map(PathCacheChange::memory_usage)
+                .sum::<usize>()
+    }
+}
+
+/// Change the old grid into the new one and return the old values of the tiles that changed
+fn update_grid<T: Copy + PartialEq>(
+    old: &mut Grid<T>,
+    new: &Grid<T>,
+) -> HashMap<(usize, usize), T> {
+    let mut old_values = HashMap::new();
+    for tile in TileCoordIter::new(new.width(), new.height()) {
+        if old[tile] != new[tile] {
+            old_values.insert(tile, std::mem::replace(&mut old[tile], new[tile]));
+        }
+    }
+    old_values
+}
+
+impl Map {
+    /// Record the simulation ticks, so up to `max_ticks` of them can be undone with [Self::rollback].
+    ///
+    /// The recording starts at the next tick. Calling this again changes the amount of ticks that are kept.
+    /// Zero turns rollback off and throws the recording away.
+    pub fn enable_rollback(&mut self, max_ticks: usize) {
+        if max_ticks == 0 {
+            self.rollback = None;
+            self.path_cache.get_mut().unwrap().set_journaling(false);
+            return;
+        }
+
+        match &mut self.rollback {
+            Some(rollback) => {
+                rollback.max_ticks = max_ticks;
+                rollback.trim();
+            }
+            None => {
+                self.rollback = Some(Rollback {
+                    max_ticks,
+                    latest: None,
+                    restored: false,
+                    undos: VecDeque::new(),
+                });
+                self.path_cache.get_mut().unwrap().set_journaling(true);
+            }
+        }
+    }
+
+    /// The amount of simulation ticks that can be rolled back right now
+    pub fn rollback_ticks(&self) -> usize {
+        self.rollback.as_ref().map_or(0, Rollback::ticks)
+    }
+
+    /// Put the map back the way it was at the start of the simulation tick the given amount of ticks ago.
+    ///
+    /// Rolling back one tick undoes the last tick and every change made to the map after it.
+    /// The ticks that are rolled back are forgotten, so they can be simulated again.
+    pub fn rollback(&mut self, ticks: usize) -> Result<(), RollbackError> {
+        let rollback = self.rollback.as_mut().ok_or(RollbackError::NotEnabled)?;
+        let available = rollback.ticks();
+        if ticks > available {
+            return Err(RollbackError::NotEnoughTicks {
+                requested: ticks,
+                available,
+            });
+        }
+        if ticks == 0 {
+            return Ok(());
+        }
+
+        // Go back to the latest state first and from there to the state of the tick
+        let path_cache = self.path_cache.get_mut().unwrap();
+        let changes = path_cache.take_journal();
+        path_cache.undo(changes);
+        let state = rollback.latest.as_mut().unwrap();
+        let undos = if rollback.restored { ticks } else { ticks - 1 };
+        for _ in 0..undos {
+            let undo = rollback.undos.pop_back().unwrap();
+            path_cache.undo(undo.apply(state));
+        }
+        rollback.restored = true;
+
+        for tile in TileCoordIter::new(self.tiles.width(), self.tiles.height()) {
+            if self.tiles[tile] != state.tiles[tile] {
+                self.tiles[tile] = state.tiles[tile];
+                self.render_dirty[tile] = true;
+            }
+        }
+        self.settled = state.settled.clone();
+        self.settled_baseline = state.settled_baseline.clone();
+        self.liquid_flow = state.liquid_flow.clone();
+        self.current_time = state.current_time;
+
+        let objects = self.objects.get_mut().unwrap();
+        let mut records = object_records(objects);
+        for (key, record) in state.objects.iter() {
+            if records.remove(key).as_ref() != Some(record) {
+                reset_object_record(objects, *key, Some(record));
+            }
+        }
+        // The objects that didn't exist yet
+        for key in records.into_keys() {
+            reset_object_record(objects, key, None);
+        }
+        objects.set_next_object_id(state.next_object_id);
+
+        self.update_rooms(TileCoordIter::new(self.width(), self.height()));
+        self.publish_events();
+
+        Ok(())
+    }
+
+    /// Record the state of the map at the start of a simulation tick, when rollback is enabled
+    pub(crate) fn record_rollback_tick(&mut self) {
+        let Some(mut rollback) = self.rollback.take() else {
+            return;
+        };
+
+        let path_cache_changes = self.path_cache.get_mut().unwrap().take_journal();
+        match &mut rollback.latest {
+            None => rollback.latest = Some(TickState::new(self)),
+            Some(latest) => {
+                let mut undo = latest.update(self);
+                undo.path_cache = path_cache_changes;
+
+                if !rollback.restored {
+                    rollback.undos.push_back(undo);
+                    rollback.trim();
+                } else if let Some(last) = rollback.undos.back_mut() {
+                    // The tick of the latest state hasn't run yet, so the changes made to the map
+                    // after the rollback are part of it
+                    last.merge_newer(undo);
+                }
+            }
+        }
+        rollback.restored = false;
+
+        self.rollback = Some(rollback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        liquids::LiquidData,
+        objects::{
+            building::{Building, BuildingType},
+            characters::{Character, WorkGoal},
+            item::{Item, ItemKind},
+        },
+        Facing,
+    };
+    use glam::{uvec2, vec2};
+
+    fn state(map: &Map) -> (Grid<Tile>, ObjectRecords, f64) {
+        (
+            map.tiles.clone(),
+            object_records(&map.objects()),
+            map.current_time,
+        )
+    }
+
+    #[test]
+    fn rolled_back_ticks_play_out_the_same() {
+        let mut map = Map::new_default(6, 1);
+        map.set_deterministic(true);
+        map.enable_rollback(100);
+        *map.tiles[(3, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water { level: 0.2 };
+        let mut objects = map.objects_mut();
+        objects.push_object::<Building>(Building {
+            location: uvec2(5, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Generator {
+                output: 1.0,
+                enabled: true,
+                fuel: Some(0.0),
+            },
+        });
+        let ore = objects.push_object::<Item>(Item::new(ItemKind::Ore, vec2(0.5, 0.5)));
+        objects.push_object::<Character>(Character::new(
+            vec2(2.5, 0.5),
+            1.0,
+            vec![WorkGoal::HaulItems],
+        ));
+        drop(objects);
+
+        map.step_n(0.1, 5);
+        let before = state(&map);
+        map.step_n(0.1, 80);
+        let after = state(&map);
+        // The generator burned the ore
+        assert!(map.objects().get_object(ore).is_none());
+        assert_eq!(map.rollback_ticks(), 85);
+
+        assert_eq!(
+            map.rollback(86),
+            Err(RollbackError::NotEnoughTicks {
+                requested: 86,
+                available: 85
+            })
+        );
+        map.rollback(80).unwrap();
+        assert!(map.objects().get_object(ore).is_some());
+        assert!(state(&map) == before);
+        assert_eq!(map.rollback_ticks(), 5);
+
+        map.step_n(0.1, 80);
+        assert!(state(&map) == after);
+        assert_eq!(map.rollback_ticks(), 85);
+    }
+
+    #[test]
+    fn rollback_needs_to_be_enabled() {
+        let mut map = Map::new_default(2, 2);
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.rollback(1), Err(RollbackError::NotEnabled));
+
+        map.enable_rollback(2);
+        assert_eq!(map.rollback_ticks(), 0);
+        map.step_n(0.1, 3);
+        assert_eq!(map.rollback_ticks(), 2);
+        map.enable_rollback(0);
+        assert_eq!(map.rollback(1), Err(RollbackError::NotEnabled));
+    }
+}
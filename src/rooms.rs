@@ -0,0 +1,307 @@
+//! The enclosed spaces of the map.
+//!
+//! A room is a group of tiles with air that are directly next to each other. Walls and doors bound the rooms,
+//! so an open door still separates the rooms on both sides of it.
+//!
+//! The rooms are updated at the start of every simulation tick. Only the rooms next to tiles that became
+//! or stopped being part of a room are searched again, the other rooms keep their [RoomId].
+
+use crate::{
+    air::AirData,
+    grid::Grid,
+    liquids::AnyLiquid,
+    tiles::{Tile, TileType},
+    Facing, Map, TileCoordIter,
+};
+use glam::{uvec2, UVec2};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    mem::size_of,
+};
+
+/// Identifies a room as long as its tiles don't change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RoomId(u32);
+
+/// Statistics of the air of a room, see [Map::room_stats]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoomStats {
+    /// The amount of tiles of the room
+    pub tiles: usize,
+    /// The total air of the room, like [Map::air_mass]
+    pub air: AirData,
+    /// The average air pressure of the tiles
+    pub pressure: f32,
+    /// The average temperature of the tiles in degrees Celsius
+    pub temperature: f32,
+}
+
+#[derive(Debug)]
+pub(crate) struct Rooms {
+    /// The room of every tile. None for walls and doors.
+    tile_rooms: Grid<Option<RoomId>>,
+    /// The tiles of every room
+    rooms: BTreeMap<RoomId, Vec<UVec2>>,
+    next_id: u32,
+    /// Scratch space of the room search, all false in between updates
+    searched: Grid<bool>,
+}
+
+impl Rooms {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            tile_rooms: Grid::new(width, height, None),
+            rooms: BTreeMap::new(),
+            next_id: 0,
+            searched: Grid::new(width, height, false),
+        }
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.tile_rooms.memory_usage()
+            + self.searched.memory_usage()
+            + self
+                .rooms
+                .values()
+                .map(|tiles| {
+                    size_of::<(RoomId, Vec<UVec2>)>() + tiles.capacity() * size_of::<UVec2>()
+                })
+                .sum::<usize>()
+    }
+}
+
+/// Returns true if the tile is part of a room
+fn is_room_tile(tile: &Tile) -> bool {
+    tile.tile_type.get_air().is_some() && !matches!(tile.tile_type, TileType::Door { .. })
+}
+
+impl Map {
+    /// Search all rooms of the map again.
+    ///
+    /// This gives every room a new [RoomId]. The rooms are kept up to date by the simulation ticks,
+    /// so this is only needed to see the changes made to the tiles since the last tick right away.
+    pub fn compute_rooms(&mut self) {
+        self.rooms = Rooms::new(self.width(), self.height());
+        self.update_rooms(TileCoordIter::new(self.width(), self.height()));
+    }
+
+    /// The room the tile is part of, or None for walls and doors and for tiles outside of the map
+    pub fn room_at(&self, x: usize, y: usize) -> Option<RoomId> {
+        self.rooms.tile_rooms.get(x, y).copied().flatten()
+    }
+
+    /// All rooms with their tiles
+    pub fn rooms(&self) -> impl Iterator<Item = (RoomId, &[UVec2])> {
+        self.rooms
+            .rooms
+            .iter()
+            .map(|(room, tiles)| (*room, tiles.as_slice()))
+    }
+
+    /// The tiles of the room, or None when the room doesn't exist (anymore)
+    pub fn room_tiles(&self, room: RoomId) -> Option<&[UVec2]> {
+        self.rooms.rooms.get(&room).map(Vec::as_slice)
+    }
+
+    /// The statistics of the air of the room, or None when the room doesn't exist (anymore)
+    pub fn room_stats(&self, room: RoomId) -> Option<RoomStats> {
+        let room_tiles = self.room_tiles(room)?;
+
+        let mut air = AirData {
+            nitrogen: 0.0,
+            oxygen: 0.0,
+            fumes: 0.0,
+            steam: 0.0,
+            carbon_dioxide: 0.0,
+            temperature: Tile::DEFAULT_TEMPERATURE,
+        };
+        let mut pressure = 0.0;
+        let mut temperature = 0.0;
+        for tile in room_tiles {
+            let tile = &self.tiles[(tile.x as usize, tile.y as usize)];
+            let (tile_air, liquids) = tile.tile_type.get_ground().unwrap();
+
+            air.mix_in(tile_air.total(), tile_air.total() * tile_air.temperature);
+            air.nitrogen += tile_air.nitrogen;
+            air.oxygen += tile_air.oxygen;
+            air.fumes += tile_air.fumes;
+            air.steam += tile_air.steam;
+            air.carbon_dioxide += tile_air.carbon_dioxide;
+            pressure += tile_air.air_pressure(liquids.get_level::<AnyLiquid>());
+            temperature += tile.temperature;
+        }
+
+        let count = room_tiles.len() as f32;
+        Some(RoomStats {
+            tiles: room_tiles.len(),
+            air,
+            pressure: pressure / count,
+            temperature: temperature / count,
+        })
+    }
+
+    /// Search the rooms next to the tiles that became or stopped being part of a room since the last update.
+    ///
+    /// Only the given tiles are checked, so they must include every tile of which the type changed since then.
+    pub(crate) fn update_rooms(&mut self, tiles: impl IntoIterator<Item = (usize, usize)>) {
+        let changed_tiles = tiles
+            .into_iter()
+            .filter(|(x, y)| {
+                is_room_tile(&self.tiles[(*x, *y)]) != self.rooms.tile_rooms[(*x, *y)].is_some()
+            })
+            .collect::<Vec<_>>();
+        if changed_tiles.is_empty() {
+            return;
+        }
+
+        let (width, height) = (self.width(), self.height());
+        let rooms = &mut self.rooms;
+
+        // The rooms that might have been split, merged, grown or shrunk
+        let mut affected = HashSet::new();
+        for (x, y) in changed_tiles.iter().copied() {
+            affected.extend(rooms.tile_rooms[(x, y)]);
+            affected.extend(
+                Facing::side_neighbours(x, y, width, height)
+                    .filter_map(|tile| rooms.tile_rooms[tile]),
+            );
+        }
+
+        let mut seeds = changed_tiles;
+        for room in affected.iter() {
+            let tiles = rooms.rooms.remove(room).unwrap_or_default();
+            seeds.extend(tiles.iter().map(|tile| (tile.x as usize, tile.y as usize)));
+        }
+        for (x, y) in seeds.iter().copied() {
+            if !is_room_tile(&self.tiles[(x, y)]) {
+                rooms.tile_rooms[(x, y)] = None;
+            }
+        }
+
+        // Sorted so the new rooms don't depend on the order of the changes
+        seeds.sort_unstable_by_key(|(x, y)| (*y, *x));
+        let searched = &mut rooms.searched;
+        // The tiles of every new room and how many of them were part of every old room
+        let mut new_rooms = Vec::<(Vec<UVec2>, HashMap<RoomId, usize>)>::new();
+        for (x, y) in seeds {
+            if searched[(x, y)] || !is_room_tile(&self.tiles[(x, y)]) {
+                continue;
+            }
+
+            let mut room_tiles = Vec::new();
+            let mut old_rooms = HashMap::new();
+            let mut to_visit = vec![(x, y)];
+            searched[(x, y)] = true;
+            while let Some((x, y)) = to_visit.pop() {
+                room_tiles.push(uvec2(x as u32, y as u32));
+                if let Some(old_room) = rooms.tile_rooms[(x, y)] {
+                    *old_rooms.entry(old_room).or_default() += 1;
+                }
+
+                for (nx, ny) in Facing::side_neighbours(x, y, width, height) {
+                    if !searched[(nx, ny)] && is_room_tile(&self.tiles[(nx, ny)]) {
+                        searched[(nx, ny)] = true;
+                        to_visit.push((nx, ny));
+                    }
+                }
+            }
+
+            room_tiles.sort_unstable_by_key(|tile| (tile.y, tile.x));
+            new_rooms.push((room_tiles, old_rooms));
+        }
+        for tile in new_rooms.iter().flat_map(|(room_tiles, _)| room_tiles) {
+            searched[(tile.x as usize, tile.y as usize)] = false;
+        }
+
+        // The biggest rooms go first, so when a room is split the biggest part keeps its id
+        new_rooms.sort_by_key(|(room_tiles, _)| Reverse(room_tiles.len()));
+        for (room_tiles, old_rooms) in new_rooms {
+            // The id of the old room the room has most tiles of, as long as another room didn't take it yet
+            let id = old_rooms
+                .into_iter()
+                .filter(|(old_room, _)| affected.contains(old_room))
+                .max_by_key(|(old_room, count)| (*count, Reverse(*old_room)))
+                .map(|(old_room, _)| old_room)
+                .unwrap_or_else(|| {
+                    rooms.next_id += 1;
+                    RoomId(rooms.next_id - 1)
+                });
+            affected.remove(&id);
+
+            for tile in room_tiles.iter() {
+                rooms.tile_rooms[(tile.x as usize, tile.y as usize)] = Some(id);
+            }
+            rooms.rooms.insert(id, room_tiles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::liquids::LiquidData;
+
+    #[test]
+    fn walls_and_doors_split_rooms() {
+        // ..#..
+        // ..D..
+        // ..#..
+        let mut map = Map::new_default(5, 3);
+        map.tiles[(2, 0)].tile_type = TileType::Wall;
+        map.tiles[(2, 2)].tile_type = TileType::Wall;
+        map.tiles[(2, 1)].tile_type = TileType::Door {
+            open: true,
+            air: AirData::new_default(),
+            liquids: LiquidData::default(),
+        };
+        map.compute_rooms();
+
+        let left = map.room_at(0, 0).unwrap();
+        let right = map.room_at(4, 2).unwrap();
+        assert_ne!(left, right);
+        assert_eq!(map.room_at(1, 2), Some(left));
+        assert_eq!(map.room_at(2, 1), None);
+        assert_eq!(map.room_at(5, 0), None);
+        assert_eq!(map.rooms().count(), 2);
+        assert_eq!(map.room_tiles(left).unwrap().len(), 6);
+
+        let stats = map.room_stats(left).unwrap();
+        assert_eq!(stats.tiles, 6);
+        assert_eq!(stats.temperature, Tile::DEFAULT_TEMPERATURE);
+        assert_eq!(
+            stats.air.oxygen,
+            AirData::new_default().oxygen * stats.tiles as f32
+        );
+    }
+
+    #[test]
+    fn rooms_follow_the_walls() {
+        let mut map = Map::new_default(5, 1);
+        map.tiles[(2, 0)].tile_type = TileType::Wall;
+        map.perform_simulation_tick(0.1);
+        let left = map.room_at(0, 0).unwrap();
+        let right = map.room_at(4, 0).unwrap();
+
+        // Building a wall only changes the room it's built in
+        map.tiles[(4, 0)].tile_type = TileType::Wall;
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.room_at(0, 0), Some(left));
+        assert_eq!(map.room_at(3, 0), Some(right));
+        assert_eq!(map.room_tiles(right).unwrap(), &[uvec2(3, 0)]);
+
+        // Digging out the wall in between merges the rooms into the biggest one
+        map.tiles[(2, 0)].tile_type = TileType::new_default();
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.room_at(3, 0), Some(left));
+        assert_eq!(map.room_tiles(right), None);
+        assert_eq!(map.rooms().count(), 1);
+
+        // Splitting it again keeps the id for the biggest part
+        map.tiles[(1, 0)].tile_type = TileType::Wall;
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.room_at(3, 0), Some(left));
+        assert_ne!(map.room_at(0, 0), Some(left));
+        assert_eq!(map.rooms().count(), 2);
+    }
+}
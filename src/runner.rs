@@ -0,0 +1,364 @@
+//! Running the simulation on a thread of its own.
+//!
+//! A [SimulationRunner] owns the map and ticks it at a fixed rate. Every tick is a simulation tick followed by a frame tick,
+//! so the characters move at the same rate. After every tick it publishes a [SimulationFrame]
+//! with copies of the layers the game asked for and the [FrameEvent]s of the characters. Reading the latest frame never waits on the simulation,
+//! so a render thread can draw at its own pace while the simulation catches up.
+//!
+//! Changes to the map, like adding objects, are sent to the simulation thread as commands.
+//! They are done between two ticks.
+
+use crate::{grid::Grid, objects::characters::FrameEvent, Map, MapLayer, TickResult};
+use arc_swap::ArcSwap;
+use std::{
+    fmt::Display,
+    sync::{
+        mpsc::{self, RecvTimeoutError, TryRecvError},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+type Command = Box<dyn FnOnce(&mut Map) + Send>;
+
+/// The state of the map after a simulation tick, as published by a [SimulationRunner]
+#[derive(Debug, Clone)]
+pub struct SimulationFrame {
+    tick: u64,
+    current_time: f64,
+    tick_result: TickResult,
+    frame_events: Vec<FrameEvent>,
+    layers: Vec<(MapLayer, Grid<f32>)>,
+}
+
+impl SimulationFrame {
+    fn new(
+        map: &Map,
+        layers: &[MapLayer],
+        tick: u64,
+        tick_result: TickResult,
+        frame_events: Vec<FrameEvent>,
+    ) -> Self {
+        Self {
+            tick,
+            current_time: map.current_time,
+            tick_result,
+            frame_events,
+            layers: layers
+                .iter()
+                .map(|layer| (*layer, map.sample_layer(*layer)))
+                .collect(),
+        }
+    }
+
+    /// The amount of ticks that were done before this frame. The first frame, of the map as it was given, is 0.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The simulation time of the map in this frame
+    pub fn current_time(&self) -> f64 {
+        self.current_time
+    }
+
+    /// What happened in the tick that made this frame
+    pub fn tick_result(&self) -> &TickResult {
+        &self.tick_result
+    }
+
+    /// What happened to the characters in the frame tick that made this frame.
+    /// Frames that are skipped by the reader also skip their events.
+    pub fn frame_events(&self) -> &[FrameEvent] {
+        &self.frame_events
+    }
+
+    /// The copy of the layer, or None if the runner wasn't asked to copy it
+    pub fn layer(&self, layer: MapLayer) -> Option<&Grid<f32>> {
+        self.layers
+            .iter()
+            .find(|(frame_layer, _)| *frame_layer == layer)
+            .map(|(_, values)| values)
+    }
+}
+
+/// Reads the latest frame of a [SimulationRunner]. It can be cloned and sent to other threads.
+#[derive(Debug, Clone)]
+pub struct SimulationFrames {
+    latest: Arc<ArcSwap<SimulationFrame>>,
+}
+
+impl SimulationFrames {
+    /// The frame of the last tick. This doesn't block, the frame is kept alive for as long as it's held.
+    pub fn latest(&self) -> Arc<SimulationFrame> {
+        self.latest.load_full()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunnerError {
+    /// The delta time isn't a positive number that fits in a [Duration]
+    InvalidDeltaTime(f32),
+}
+
+impl Display for RunnerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunnerError::InvalidDeltaTime(delta_time) => write!(
+                f,
+                "The delta time of the simulation must be a positive amount of seconds, but is {delta_time}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RunnerError {}
+
+/// Owns a map and runs its simulation on a background thread, see the [module docs](self)
+pub struct SimulationRunner {
+    commands: mpsc::Sender<Command>,
+    frames: SimulationFrames,
+    thread: Option<JoinHandle<Map>>,
+}
+
+impl SimulationRunner {
+    /// Start ticking the map with the delta time, once every `delta_time` seconds of real time.
+    /// After every tick the given layers are copied into the new frame.
+    ///
+    /// When a tick takes longer than the delta time the simulation runs slower than real time.
+    /// It doesn't try to catch up later.
+    ///
+    /// Fails when the delta time isn't positive or is too big to wait for.
+    pub fn start(map: Map, delta_time: f32, layers: Vec<MapLayer>) -> Result<Self, RunnerError> {
+        let interval = Duration::try_from_secs_f32(delta_time)
+            .ok()
+            .filter(|_| delta_time > 0.0)
+            .ok_or(RunnerError::InvalidDeltaTime(delta_time))?;
+
+        let frames = SimulationFrames {
+            latest: Arc::new(ArcSwap::from_pointee(SimulationFrame::new(
+                &map,
+                &layers,
+                0,
+                TickResult::default(),
+                Vec::new(),
+            ))),
+        };
+        let (commands, receiver) = mpsc::channel();
+
+        let latest = frames.latest.clone();
+        let thread = std::thread::Builder::new()
+            .name("aci-map simulation".into())
+            .spawn(move || run(map, delta_time, interval, &layers, &receiver, &latest))
+            .expect("Could not spawn the simulation thread");
+
+        Ok(Self {
+            commands,
+            frames,
+            thread: Some(thread),
+        })
+    }
+
+    /// The frame of the last tick
+    pub fn latest_frame(&self) -> Arc<SimulationFrame> {
+        self.frames.latest()
+    }
+
+    /// A reader of the frames that can be handed to other threads
+    pub fn frames(&self) -> SimulationFrames {
+        self.frames.clone()
+    }
+
+    /// Change the map on the simulation thread before the next tick.
+    /// Commands are done in the order they were sent.
+    ///
+    /// Use a channel of your own to get something back from the map.
+    pub fn execute(&self, command: impl FnOnce(&mut Map) + Send + 'static) {
+        // The thread only stops when it's told to by us or when it panicked, which is found out when joining it
+        let _ = self.commands.send(Box::new(command));
+    }
+
+    /// Stop the simulation after the tick it's working on and get the map back
+    ///
+    /// # Panics
+    ///
+    /// When the simulation thread panicked
+    pub fn stop(mut self) -> Map {
+        self.join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    fn join(&mut self) -> std::thread::Result<Map> {
+        // Closing the channel tells the thread to stop
+        let (closed, _) = mpsc::channel();
+        drop(std::mem::replace(&mut self.commands, closed));
+
+        self.thread
+            .take()
+            .expect("The simulation thread is only joined once")
+            .join()
+    }
+}
+
+impl Drop for SimulationRunner {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            let _ = self.join();
+        }
+    }
+}
+
+fn run(
+    mut map: Map,
+    delta_time: f32,
+    interval: Duration,
+    layers: &[MapLayer],
+    commands: &mpsc::Receiver<Command>,
+    latest: &ArcSwap<SimulationFrame>,
+) -> Map {
+    let mut next_tick = Instant::now() + interval;
+    let mut tick = 0;
+
+    loop {
+        // The commands that came in are done before every tick, also when the ticks are running late
+        loop {
+            match commands.try_recv() {
+                Ok(command) => command(&mut map),
+                Err(TryRecvError::Disconnected) => return map,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        let now = Instant::now();
+        if now < next_tick {
+            match commands.recv_timeout(next_tick - now) {
+                Ok(command) => command(&mut map),
+                Err(RecvTimeoutError::Disconnected) => return map,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            continue;
+        }
+
+        let tick_result = map.perform_simulation_tick(delta_time);
+        let frame_events = map.perform_frame_tick(delta_time);
+        tick += 1;
+        latest.store(Arc::new(SimulationFrame::new(
+            &map,
+            layers,
+            tick,
+            tick_result,
+            frame_events,
+        )));
+
+        next_tick = (next_tick + interval).max(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        facing::Facing,
+        liquids::LiquidData,
+        objects::{
+            building::Building,
+            characters::{tests::ventilator, Character, WorkGoal},
+        },
+    };
+    use glam::{uvec2, vec2};
+
+    #[test]
+    fn runner_ticks_in_the_background() {
+        let mut map = Map::new_default(8, 8);
+        *map.tiles[(0, 0)].tile_type.get_liquids_mut().unwrap() = LiquidData::Water { level: 1.0 };
+
+        let runner = SimulationRunner::start(map, 0.001, vec![MapLayer::Water]).unwrap();
+        let frames = runner.frames();
+        let first_frame = frames.latest();
+        assert_eq!(first_frame.layer(MapLayer::Water).unwrap()[(0, 0)], 1.0);
+        assert!(first_frame.layer(MapLayer::Lava).is_none());
+
+        let (sender, receiver) = mpsc::channel();
+        runner.execute(move |map| {
+            *map.tile_mut(7, 7).tile_type.get_liquids_mut().unwrap() =
+                LiquidData::Water { level: 1.0 };
+            sender.send(()).unwrap();
+        });
+        receiver.recv().unwrap();
+
+        let reader = std::thread::spawn(move || {
+            while frames.latest().tick() < 10 {
+                std::thread::yield_now();
+            }
+            frames.latest()
+        });
+        let frame = reader.join().unwrap();
+        assert!(frame.current_time() > 0.0);
+        assert!(frame.layer(MapLayer::Water).unwrap()[(0, 0)] < 1.0);
+        // Frames that are still held don't change
+        assert_eq!(first_frame.tick(), 0);
+
+        let map = runner.stop();
+        assert!(map.current_time >= frame.current_time());
+        assert!(
+            map.tile(7, 7)
+                .tile_type
+                .get_liquids()
+                .unwrap()
+                .get_level::<crate::liquids::Water>()
+                > 0.0
+        );
+    }
+
+    #[test]
+    fn commands_are_done_when_ticks_run_late() {
+        // Every tick takes longer than this, so the runner never waits for the next one
+        let runner = SimulationRunner::start(Map::new_default(32, 32), 1e-9, Vec::new()).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..3 {
+            let sender = sender.clone();
+            runner.execute(move |_| sender.send(i).unwrap());
+        }
+        assert_eq!(receiver.iter().take(3).collect::<Vec<_>>(), [0, 1, 2]);
+
+        runner.stop();
+    }
+
+    #[test]
+    fn characters_move_in_the_background() {
+        let map = Map::new_default(10, 3);
+        let character = map.objects_mut().push_object::<Character>(Character::new(
+            vec2(0.5, 1.5),
+            1.0,
+            vec![WorkGoal::WorkAtVentilation],
+        ));
+        map.objects_mut()
+            .push_object::<Building>(ventilator(uvec2(8, 1), Facing::East));
+
+        let runner = SimulationRunner::start(map, 0.01, Vec::new()).unwrap();
+        loop {
+            let (sender, receiver) = mpsc::channel();
+            runner.execute(move |map| {
+                sender
+                    .send(map.objects().get_object(character).unwrap().location)
+                    .unwrap()
+            });
+            if receiver.recv().unwrap().x > 1.0 {
+                break;
+            }
+        }
+
+        runner.stop();
+    }
+
+    #[test]
+    fn invalid_delta_time() {
+        for delta_time in [f32::NAN, f32::INFINITY, -1.0, 0.0, f32::MAX] {
+            assert!(matches!(
+                SimulationRunner::start(Map::new_default(4, 4), delta_time, Vec::new()),
+                Err(RunnerError::InvalidDeltaTime(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,223 @@
+/// The constants that tune how fast the air and liquids spread and the characters walk.
+///
+/// The rates are in fraction per second and the walk speed in tiles per second, so they don't depend on the tick rate.
+/// Pick one of the presets if you don't want to tune every value yourself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SimulationParams {
+    /// How fast air moves from high to low pressure
+    pub air_pressure_spread_rate: f32,
+    /// How fast the gasses in the air mix with the air of the neighbours
+    pub air_diffusion_spread_rate: f32,
+    /// How fast water flows to lower tiles
+    pub water_spread_rate: f32,
+    /// How fast lava flows to lower tiles
+    pub lava_spread_rate: f32,
+    /// The fraction of the temperature difference between neighbouring tiles that evens out per second
+    pub heat_spread_rate: f32,
+    /// The fraction of the temperature difference between the air and its tile that evens out per second
+    pub air_heat_exchange_rate: f32,
+    /// The fraction of the intensity of a fire that jumps over to every neighbour that can burn per second
+    pub fire_spread_rate: f32,
+    /// How fast characters walk in tiles per second
+    pub character_walk_speed: f32,
+    /// How the liquid flows of a tick are calculated
+    pub liquid_solver: LiquidSolver,
+    /// When a tile and its neighbours change less than this in a tick, the air and liquid calculations
+    /// skip the tile until something around it changes again. With 0.0 only tiles that didn't change at all are skipped.
+    pub settled_epsilon: f32,
+}
+
+/// The way the liquid flows are solved every tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LiquidSolver {
+    /// All flows are calculated from the levels at the start of the tick and then applied at once.
+    /// The result doesn't depend on the order in which the tiles are visited.
+    #[default]
+    Jacobi,
+    /// The levels are updated while scanning over the tiles, so every tile sees the flows of the tiles before it.
+    /// This converges faster and sloshes less, but the result depends on the scan order.
+    GaussSeidel,
+    /// Every connected body of liquid moves towards a single surface level, like communicating vessels.
+    /// Full tiles pass the pressure on, so liquid can rise up out of a U-bend,
+    /// where the other solvers only look at the surfaces of neighbouring tiles.
+    Hydraulic,
+}
+
+/// The way the air calculations of a tick are run.
+///
+/// The backends calculate the same flows, but the results can differ in the last bits because
+/// the flows are summed in another order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationBackend {
+    /// Go over the tiles one at a time
+    Scalar,
+    /// Process a column of tiles at once in vector lanes. Only available with the `simd` feature.
+    #[cfg(feature = "simd")]
+    Simd,
+}
+
+impl SimulationBackend {
+    /// The fastest backend that is available. This is what a new map uses.
+    pub const fn auto() -> Self {
+        #[cfg(feature = "simd")]
+        return Self::Simd;
+        #[cfg(not(feature = "simd"))]
+        return Self::Scalar;
+    }
+}
+
+impl Default for SimulationBackend {
+    fn default() -> Self {
+        Self::auto()
+    }
+}
+
+impl SimulationParams {
+    /// Slow and steady. Water is ten times as runny as lava. This is the default.
+    pub const fn realistic() -> Self {
+        Self {
+            air_pressure_spread_rate: 0.01,
+            air_diffusion_spread_rate: 0.05,
+            water_spread_rate: 0.01,
+            lava_spread_rate: 0.001,
+            heat_spread_rate: 0.02,
+            air_heat_exchange_rate: 0.1,
+            fire_spread_rate: 0.1,
+            character_walk_speed: 1.2,
+            liquid_solver: LiquidSolver::Jacobi,
+            settled_epsilon: 0.000_01,
+        }
+    }
+
+    /// Everything moves quicker so changes are visible right away, while lava is still clearly slower than water
+    pub const fn arcade() -> Self {
+        Self {
+            air_pressure_spread_rate: 0.03,
+            air_diffusion_spread_rate: 0.25,
+            water_spread_rate: 0.05,
+            lava_spread_rate: 0.01,
+            heat_spread_rate: 0.1,
+            air_heat_exchange_rate: 0.5,
+            fire_spread_rate: 0.3,
+            character_walk_speed: 1.2,
+            liquid_solver: LiquidSolver::Jacobi,
+            settled_epsilon: 0.000_01,
+        }
+    }
+
+    /// Useful to quickly get a map into a steady state, for example with [crate::Map::settle].
+    ///
+    /// The air mixes as fast as it can with ticks of up to a second.
+    /// The pressure and liquid flows are kept low enough that they don't slosh back and forth.
+    pub const fn fast_settle() -> Self {
+        Self {
+            air_pressure_spread_rate: 0.05,
+            air_diffusion_spread_rate: 1.0,
+            water_spread_rate: 0.1,
+            lava_spread_rate: 0.02,
+            heat_spread_rate: 0.1,
+            air_heat_exchange_rate: 0.5,
+            fire_spread_rate: 0.3,
+            character_walk_speed: 1.2,
+            liquid_solver: LiquidSolver::Jacobi,
+            settled_epsilon: 0.000_01,
+        }
+    }
+}
+
+impl Default for SimulationParams {
+    fn default() -> Self {
+        Self::realistic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{liquids::LiquidData, tiles::TileType, Map};
+
+    #[test]
+    fn presets_differ() {
+        assert_eq!(SimulationParams::default(), SimulationParams::realistic());
+        assert_ne!(SimulationParams::realistic(), SimulationParams::arcade());
+        assert_ne!(
+            SimulationParams::realistic(),
+            SimulationParams::fast_settle()
+        );
+        assert_ne!(SimulationParams::arcade(), SimulationParams::fast_settle());
+    }
+
+    #[test]
+    fn fast_settle_settles_faster() {
+        let ticks_to_settle = |params: SimulationParams| {
+            let mut map = Map::new_default(5, 5);
+            map.set_simulation_params(params);
+            map.tiles[(0, 0)].tile_type.get_air_mut().unwrap().fumes = 0.5;
+            map.tiles[(4, 4)].tile_type = TileType::Ground {
+                air: Default::default(),
+                liquids: LiquidData::Water { level: 1.0 },
+            };
+
+            map.settle(0.1, 0.0001, 10_000).unwrap()
+        };
+
+        let realistic = ticks_to_settle(SimulationParams::realistic());
+        let fast_settle = ticks_to_settle(SimulationParams::fast_settle());
+
+        assert!(fast_settle < realistic);
+    }
+
+    #[test]
+    fn gauss_seidel_settles_faster() {
+        let ticks_to_settle = |liquid_solver: LiquidSolver| {
+            // A walled basin with a column of water in one corner
+            let mut map = Map::new_default(9, 9);
+            map.set_simulation_params(SimulationParams {
+                liquid_solver,
+                ..SimulationParams::fast_settle()
+            });
+            for (x, y) in map.all_tile_coords() {
+                if x == 0 || y == 0 || x == 8 || y == 8 {
+                    map.tiles[(x, y)].tile_type = TileType::Wall;
+                }
+            }
+            map.tiles[(1, 1)].tile_type = TileType::Ground {
+                air: Default::default(),
+                liquids: LiquidData::Water { level: 3.0 },
+            };
+
+            map.settle(0.1, 0.0001, 10_000).unwrap()
+        };
+
+        let jacobi = ticks_to_settle(LiquidSolver::Jacobi);
+        let gauss_seidel = ticks_to_settle(LiquidSolver::GaussSeidel);
+
+        assert!(gauss_seidel < jacobi);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn missing_params_are_defaulted() {
+        // The fields the params had before the heat simulation was added
+        let json = r#"{
+            "air_pressure_spread_rate": 0.5,
+            "air_diffusion_spread_rate": 0.1,
+            "water_spread_rate": 0.25,
+            "lava_spread_rate": 0.025
+        }"#;
+        let params = serde_json::from_str::<SimulationParams>(json).unwrap();
+
+        assert_eq!(params.air_pressure_spread_rate, 0.5);
+        assert_eq!(
+            params.character_walk_speed,
+            SimulationParams::default().character_walk_speed
+        );
+        assert_eq!(
+            params.settled_epsilon,
+            SimulationParams::default().settled_epsilon
+        );
+    }
+}
@@ -0,0 +1,1649 @@
+//! A compact binary format to save and load a map without needing serde.
+//!
+//! A snapshot starts with a header ([MAGIC], the format version that wrote it and
+//! the oldest format version that can read it), followed by sections.
+//! Every section is a section id and a length prefixed payload and the list of sections ends with section id 0.
+//! Readers skip the sections they don't know.
+//!
+//! Every tile and object is written as a length prefixed record.
+//! New fields are only ever added to the end of a record, so older readers can skip them.
+//! Changes that older readers can't skip over must bump [MIN_READER_VERSION].
+
+use crate::{
+    air::{AirData, AirLeveler, AirPusher, GasMask, OxygenUser},
+    heat::{HeatSink, HeatSource},
+    liquids::{LiquidData, LiquidLeveler},
+    objects::{
+        building::{Building, BuildingType, WorkSpot, WorkSpotOccupation},
+        characters::{
+            Character, CharacterEvent, CharacterGoal, CharacterTask, Needs, SurviveGoal, WorkGoal,
+        },
+        environment_object::EnvironmentObject,
+        inventory::{Inventory, ItemStack},
+        item::{Item, ItemKind},
+        ObjectProperties, Objects,
+    },
+    pipes::Pipe,
+    power::Cable,
+    tiles::{Tile, TileType},
+    Direction8, Facing, Map,
+};
+use glam::{UVec2, Vec2};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+    io::{Read, Write},
+    sync::RwLock,
+};
+
+/// The bytes every snapshot starts with
+pub const MAGIC: [u8; 4] = *b"ACIM";
+/// The version of the format this crate writes.
+///
+/// - 1: The first version
+pub const FORMAT_VERSION: u16 = 1;
+/// The oldest format version that can read the snapshots this crate writes.
+/// It's the last version in the list of [FORMAT_VERSION] that older readers can't skip over.
+pub const MIN_READER_VERSION: u16 = 1;
+
+mod section {
+    pub const END: u8 = 0;
+    /// The size of the map and the current time
+    pub const MAP: u8 = 1;
+    /// All tiles, column by column
+    pub const TILES: u8 = 2;
+    pub const NEXT_OBJECT_ID: u8 = 3;
+    pub const ENVIRONMENT_OBJECTS: u8 = 4;
+    pub const BUILDINGS: u8 = 5;
+    pub const CHARACTERS: u8 = 6;
+    pub const PIPES: u8 = 7;
+    pub const CABLES: u8 = 8;
+    pub const ITEMS: u8 = 9;
+}
+
+impl Map {
+    /// Save the tiles, objects and current time of the map in the binary snapshot format.
+    ///
+    /// Settings like the simulation parameters aren't part of the snapshot.
+    /// Objects of types defined outside of this crate aren't saved either.
+    pub fn save_snapshot(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let mut snapshot = SnapshotWriter::new();
+        snapshot.bytes.extend_from_slice(&MAGIC);
+        snapshot
+            .bytes
+            .extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        snapshot
+            .bytes
+            .extend_from_slice(&MIN_READER_VERSION.to_le_bytes());
+
+        snapshot.write_section(section::MAP, |writer| {
+            writer.write(&self.width());
+            writer.write(&self.height());
+            writer.write(&self.current_time);
+        });
+        snapshot.write_section(section::TILES, |writer| {
+            for x in 0..self.width() {
+                for y in 0..self.height() {
+                    writer.write_record(|writer| writer.write(&self.tiles[(x, y)]));
+                }
+            }
+        });
+
+        let objects = self.objects();
+        snapshot.write_section(section::NEXT_OBJECT_ID, |writer| {
+            writer.write(&objects.next_object_id())
+        });
+        snapshot.write_section(section::ENVIRONMENT_OBJECTS, |writer| {
+            write_objects::<EnvironmentObject>(writer, &objects)
+        });
+        snapshot.write_section(section::BUILDINGS, |writer| {
+            write_objects::<Building>(writer, &objects)
+        });
+        snapshot.write_section(section::CHARACTERS, |writer| {
+            write_objects::<Character>(writer, &objects)
+        });
+        snapshot.write_section(section::PIPES, |writer| {
+            write_objects::<Pipe>(writer, &objects)
+        });
+        snapshot.write_section(section::CABLES, |writer| {
+            write_objects::<Cable>(writer, &objects)
+        });
+        snapshot.write_section(section::ITEMS, |writer| {
+            write_objects::<Item>(writer, &objects)
+        });
+        snapshot.write_u8(section::END);
+
+        writer.write_all(&snapshot.bytes)
+    }
+
+    /// Load a map from a snapshot made with [Self::save_snapshot].
+    ///
+    /// Snapshots made by newer versions of this crate can be loaded as long as their format allows it.
+    /// The data they have that this version doesn't know about is skipped.
+    pub fn load_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let mut reader = SnapshotReader { bytes: &bytes };
+        if reader.read_bytes(MAGIC.len()).ok() != Some(&MAGIC) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let _version = reader.read_u16()?;
+        let min_reader_version = reader.read_u16()?;
+        if min_reader_version > FORMAT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion { min_reader_version });
+        }
+
+        let mut sections = Vec::new();
+        loop {
+            let id = reader.read_u8()?;
+            if id == section::END {
+                break;
+            }
+            let len = reader.read_len()?;
+            let payload = reader.read_bytes(len)?;
+            if sections.iter().any(|(known_id, _)| *known_id == id) {
+                return Err(SnapshotError::Corrupt(format!(
+                    "section {id} is there twice"
+                )));
+            }
+            sections.push((id, payload));
+        }
+        let section = |id: u8| {
+            sections
+                .iter()
+                .find(|(known_id, _)| *known_id == id)
+                .map(|(_, payload)| SnapshotReader { bytes: payload })
+        };
+        let required_section = |id: u8| {
+            section(id).ok_or_else(|| SnapshotError::Corrupt(format!("section {id} is missing")))
+        };
+
+        let mut reader = required_section(section::MAP)?;
+        let (width, height) = (reader.read::<usize>()?, reader.read::<usize>()?);
+        let current_time = reader.read()?;
+
+        let mut reader = required_section(section::TILES)?;
+        // Every tile takes at least a byte, so a size that's too big for the section isn't allocated
+        let fits = width
+            .checked_mul(height)
+            .is_some_and(|tiles| tiles > 0 && tiles <= reader.bytes.len());
+        if !fits {
+            return Err(SnapshotError::Corrupt(format!(
+                "the tiles don't fit a {width}x{height} map"
+            )));
+        }
+
+        let mut map = Self::new_default(width, height);
+        map.current_time = current_time;
+        for x in 0..width {
+            for y in 0..height {
+                map.tiles[(x, y)] = reader.read_record(|reader| reader.read())?;
+            }
+        }
+
+        let mut objects = Objects::new();
+        objects.set_next_object_id(required_section(section::NEXT_OBJECT_ID)?.read()?);
+        if let Some(mut reader) = section(section::ENVIRONMENT_OBJECTS) {
+            read_objects::<EnvironmentObject>(&mut reader, &mut objects)?;
+        }
+        if let Some(mut reader) = section(section::BUILDINGS) {
+            read_objects::<Building>(&mut reader, &mut objects)?;
+        }
+        if let Some(mut reader) = section(section::CHARACTERS) {
+            read_objects::<Character>(&mut reader, &mut objects)?;
+        }
+        if let Some(mut reader) = section(section::PIPES) {
+            read_objects::<Pipe>(&mut reader, &mut objects)?;
+        }
+        if let Some(mut reader) = section(section::CABLES) {
+            read_objects::<Cable>(&mut reader, &mut objects)?;
+        }
+        if let Some(mut reader) = section(section::ITEMS) {
+            read_objects::<Item>(&mut reader, &mut objects)?;
+        }
+        map.objects = RwLock::new(objects);
+
+        // Nothing has been rendered of the loaded map yet
+        map.render_dirty.fill(true);
+
+        Ok(map)
+    }
+}
+
+fn write_objects<T: ObjectProperties + Snapshot>(writer: &mut SnapshotWriter, objects: &Objects) {
+    let objects = objects.get_objects::<T>().collect::<Vec<_>>();
+    writer.write(&objects.len());
+    for object in objects {
+        writer.write(&object.id());
+        writer.write_record(|writer| writer.write(&*object));
+    }
+}
+
+fn read_objects<T: ObjectProperties + Snapshot>(
+    reader: &mut SnapshotReader,
+    objects: &mut Objects,
+) -> Result<(), SnapshotError> {
+    for _ in 0..reader.read_len()? {
+        let id = reader.read::<u32>()?;
+        let object = reader.read_record(|reader| reader.read::<T>())?;
+        objects
+            .restore_object(id, object)
+            .map_err(SnapshotError::Corrupt)?;
+    }
+
+    Ok(())
+}
+
+/// The objects of the types that are saved in a snapshot, as a record for every object by section and id
+pub(crate) type ObjectRecords = BTreeMap<(u8, u32), Vec<u8>>;
+
+/// Write every object of the types that are saved in a snapshot to a separate record
+pub(crate) fn object_records(objects: &Objects) -> ObjectRecords {
+    let mut records = ObjectRecords::new();
+    insert_object_records::<EnvironmentObject>(&mut records, section::ENVIRONMENT_OBJECTS, objects);
+    insert_object_records::<Building>(&mut records, section::BUILDINGS, objects);
+    insert_object_records::<Character>(&mut records, section::CHARACTERS, objects);
+    insert_object_records::<Pipe>(&mut records, section::PIPES, objects);
+    insert_object_records::<Cable>(&mut records, section::CABLES, objects);
+    insert_object_records::<Item>(&mut records, section::ITEMS, objects);
+    records
+}
+
+fn insert_object_records<T: ObjectProperties + Snapshot>(
+    records: &mut ObjectRecords,
+    section: u8,
+    objects: &Objects,
+) {
+    for object in objects.get_objects::<T>() {
+        let mut writer = SnapshotWriter::new();
+        writer.write(&*object);
+        records.insert((section, object.id().as_u32()), writer.bytes);
+    }
+}
+
+/// Put an object back the way it was written by [object_records], or remove it when the record is None
+pub(crate) fn reset_object_record(objects: &mut Objects, key: (u8, u32), record: Option<&[u8]>) {
+    match key.0 {
+        section::ENVIRONMENT_OBJECTS => reset_object::<EnvironmentObject>(objects, key.1, record),
+        section::BUILDINGS => reset_object::<Building>(objects, key.1, record),
+        section::CHARACTERS => reset_object::<Character>(objects, key.1, record),
+        section::PIPES => reset_object::<Pipe>(objects, key.1, record),
+        section::CABLES => reset_object::<Cable>(objects, key.1, record),
+        section::ITEMS => reset_object::<Item>(objects, key.1, record),
+        section => unreachable!("objects aren't written to section {section}"),
+    }
+}
+
+fn reset_object<T: ObjectProperties + Snapshot>(
+    objects: &mut Objects,
+    id: u32,
+    record: Option<&[u8]>,
+) {
+    let object = record.map(|bytes| {
+        SnapshotReader { bytes }
+            .read::<T>()
+            .expect("The records of the objects can be read back")
+    });
+    objects.reset_object(id, object);
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    /// The data doesn't start like a snapshot does
+    NotASnapshot,
+    /// The snapshot can only be read by a newer version of this crate
+    UnsupportedVersion {
+        min_reader_version: u16,
+    },
+    /// The snapshot ends too early or contains data that can't be right
+    Corrupt(String),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "Could not read the snapshot: {error}"),
+            SnapshotError::NotASnapshot => write!(f, "The data is not a map snapshot"),
+            SnapshotError::UnsupportedVersion { min_reader_version } => write!(
+                f,
+                "The snapshot needs format version {min_reader_version}, but only version {FORMAT_VERSION} is supported"
+            ),
+            SnapshotError::Corrupt(reason) => write!(f, "The snapshot is corrupt: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+fn corrupt<T>(reason: &str) -> Result<T, SnapshotError> {
+    Err(SnapshotError::Corrupt(reason.into()))
+}
+
+/// A value that can be written to and read from a snapshot
+pub(crate) trait Snapshot: Sized {
+    fn write(&self, writer: &mut SnapshotWriter);
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError>;
+}
+
+pub(crate) struct SnapshotWriter {
+    bytes: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    pub fn write<T: Snapshot>(&mut self, value: &T) {
+        value.write(self);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    /// Write an unsigned number in as few bytes as needed, 7 bits per byte
+    pub fn write_varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    /// Write what the closure writes prefixed with its length, so readers can skip what they don't know at the end of it
+    pub fn write_record(&mut self, write: impl FnOnce(&mut SnapshotWriter)) {
+        let mut record = SnapshotWriter::new();
+        write(&mut record);
+        self.write_varint(record.bytes.len() as u64);
+        self.bytes.extend_from_slice(&record.bytes);
+    }
+
+    fn write_section(&mut self, id: u8, write: impl FnOnce(&mut SnapshotWriter)) {
+        self.write_u8(id);
+        self.write_record(write);
+    }
+}
+
+pub(crate) struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub fn read<T: Snapshot>(&mut self) -> Result<T, SnapshotError> {
+        T::read(self)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if len > self.bytes.len() {
+            return corrupt("the data ends too early");
+        }
+        let (read, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(read)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        corrupt("a number is too long")
+    }
+
+    /// Read the length of a record or list.
+    /// Every element takes at least a byte, so a length longer than the remaining data can't be right.
+    pub fn read_len(&mut self) -> Result<usize, SnapshotError> {
+        match usize::try_from(self.read_varint()?) {
+            Ok(len) if len <= self.bytes.len() => Ok(len),
+            _ => corrupt("a length is longer than the data"),
+        }
+    }
+
+    /// Read a record written by [SnapshotWriter::write_record].
+    /// Whatever the closure doesn't read of the record is skipped.
+    pub fn read_record<T>(
+        &mut self,
+        read: impl FnOnce(&mut SnapshotReader<'a>) -> Result<T, SnapshotError>,
+    ) -> Result<T, SnapshotError> {
+        let len = self.read_len()?;
+        let mut record = SnapshotReader {
+            bytes: self.read_bytes(len)?,
+        };
+        read(&mut record)
+    }
+}
+
+impl Snapshot for u8 {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(*self);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        reader.read_u8()
+    }
+}
+
+impl Snapshot for u32 {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write_varint(*self as u64);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        u32::try_from(reader.read_varint()?).or_else(|_| corrupt("a number is out of range"))
+    }
+}
+
+impl Snapshot for usize {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write_varint(*self as u64);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        usize::try_from(reader.read_varint()?).or_else(|_| corrupt("a number is out of range"))
+    }
+}
+
+impl Snapshot for f32 {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.bytes.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(f32::from_le_bytes(
+            reader.read_bytes(4)?.try_into().unwrap(),
+        ))
+    }
+}
+
+impl Snapshot for f64 {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.bytes.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(f64::from_le_bytes(
+            reader.read_bytes(8)?.try_into().unwrap(),
+        ))
+    }
+}
+
+impl Snapshot for bool {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(*self as u8);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => corrupt("a bool is not 0 or 1"),
+        }
+    }
+}
+
+impl<T: Snapshot> Snapshot for Option<T> {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        match self {
+            None => writer.write_u8(0),
+            Some(value) => {
+                writer.write_u8(1);
+                writer.write(value);
+            }
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(reader.read()?)),
+            _ => corrupt("unknown option variant"),
+        }
+    }
+}
+
+impl<T: Snapshot> Snapshot for Vec<T> {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.len());
+        for value in self {
+            writer.write(value);
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        (0..reader.read_len()?).map(|_| reader.read()).collect()
+    }
+}
+
+impl<T: Snapshot> Snapshot for VecDeque<T> {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.len());
+        for value in self {
+            writer.write(value);
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        (0..reader.read_len()?).map(|_| reader.read()).collect()
+    }
+}
+
+impl<T: Snapshot, const N: usize> Snapshot for [T; N] {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        for value in self {
+            writer.write(value);
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let values = (0..N)
+            .map(|_| reader.read())
+            .collect::<Result<Vec<T>, _>>()?;
+        Ok(values.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+}
+
+impl Snapshot for Vec2 {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.x);
+        writer.write(&self.y);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Vec2::new(reader.read()?, reader.read()?))
+    }
+}
+
+impl Snapshot for UVec2 {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.x);
+        writer.write(&self.y);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(UVec2::new(reader.read()?, reader.read()?))
+    }
+}
+
+impl Snapshot for Facing {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(*self as u8);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(Facing::North),
+            1 => Ok(Facing::East),
+            2 => Ok(Facing::South),
+            3 => Ok(Facing::West),
+            _ => corrupt("unknown facing"),
+        }
+    }
+}
+
+impl Snapshot for Tile {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.ground_level);
+        writer.write(&self.tile_type);
+        writer.write(&self.sealed);
+        writer.write(&self.temperature);
+        writer.write(&self.fire);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Tile {
+            ground_level: reader.read()?,
+            tile_type: reader.read()?,
+            sealed: reader.read()?,
+            temperature: reader.read()?,
+            fire: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for TileType {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        match self {
+            TileType::Wall => writer.write_u8(0),
+            TileType::Ground { air, liquids } => {
+                writer.write_u8(1);
+                writer.write(air);
+                writer.write(liquids);
+            }
+            TileType::LowWall {
+                height,
+                air,
+                liquids,
+            } => {
+                writer.write_u8(2);
+                writer.write(height);
+                writer.write(air);
+                writer.write(liquids);
+            }
+            TileType::Door { open, air, liquids } => {
+                writer.write_u8(3);
+                writer.write(open);
+                writer.write(air);
+                writer.write(liquids);
+            }
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(TileType::Wall),
+            1 => Ok(TileType::Ground {
+                air: reader.read()?,
+                liquids: reader.read()?,
+            }),
+            2 => Ok(TileType::LowWall {
+                height: reader.read()?,
+                air: reader.read()?,
+                liquids: reader.read()?,
+            }),
+            3 => Ok(TileType::Door {
+                open: reader.read()?,
+                air: reader.read()?,
+                liquids: reader.read()?,
+            }),
+            _ => corrupt("unknown tile type"),
+        }
+    }
+}
+
+impl Snapshot for AirData {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.nitrogen);
+        writer.write(&self.oxygen);
+        writer.write(&self.fumes);
+        writer.write(&self.steam);
+        writer.write(&self.carbon_dioxide);
+        writer.write(&self.temperature);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(AirData {
+            nitrogen: reader.read()?,
+            oxygen: reader.read()?,
+            fumes: reader.read()?,
+            steam: reader.read()?,
+            carbon_dioxide: reader.read()?,
+            temperature: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for LiquidData {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        match self {
+            LiquidData::None => writer.write_u8(0),
+            LiquidData::Water { level } => {
+                writer.write_u8(1);
+                writer.write(level);
+            }
+            LiquidData::Lava { level } => {
+                writer.write_u8(2);
+                writer.write(level);
+            }
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(LiquidData::None),
+            1 => Ok(LiquidData::Water {
+                level: reader.read()?,
+            }),
+            2 => Ok(LiquidData::Lava {
+                level: reader.read()?,
+            }),
+            _ => corrupt("unknown liquid"),
+        }
+    }
+}
+
+impl Snapshot for EnvironmentObject {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        match self {
+            EnvironmentObject::AirLeveler(object) => {
+                writer.write_u8(0);
+                writer.write(&object.x);
+                writer.write(&object.y);
+                // The gases that aren't leveled are written as None
+                writer.write(&object.gases.nitrogen.then_some(object.nitrogen));
+                writer.write(&object.gases.oxygen.then_some(object.oxygen));
+                writer.write(&object.gases.fumes.then_some(object.fumes));
+                writer.write(&object.reservoir);
+                writer.write(&object.rate);
+                writer.write(&object.enabled);
+            }
+            EnvironmentObject::OxygenUser(object) => {
+                writer.write_u8(1);
+                writer.write(&object.x);
+                writer.write(&object.y);
+                writer.write(&object.change_per_sec);
+                writer.write(&object.enabled);
+            }
+            EnvironmentObject::AirPusher(object) => {
+                writer.write_u8(2);
+                writer.write(&object.x);
+                writer.write(&object.y);
+                writer.write(&object.direction.facing());
+                writer.write(&object.amount);
+                writer.write(&object.enabled);
+                writer.write(&object.direction.is_diagonal());
+            }
+            EnvironmentObject::LiquidLeveler(object) => {
+                writer.write_u8(3);
+                writer.write(&object.x);
+                writer.write(&object.y);
+                writer.write(&object.target);
+                writer.write(&object.enabled);
+                writer.write(&object.solidify);
+            }
+            EnvironmentObject::HeatSource(object) => {
+                writer.write_u8(4);
+                writer.write(&object.x);
+                writer.write(&object.y);
+                writer.write(&object.temperature);
+                writer.write(&object.change_per_sec);
+                writer.write(&object.enabled);
+            }
+            EnvironmentObject::HeatSink(object) => {
+                writer.write_u8(5);
+                writer.write(&object.x);
+                writer.write(&object.y);
+                writer.write(&object.temperature);
+                writer.write(&object.change_per_sec);
+                writer.write(&object.enabled);
+            }
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => {
+                let x = reader.read()?;
+                let y = reader.read()?;
+                let nitrogen: Option<f32> = reader.read()?;
+                let oxygen: Option<f32> = reader.read()?;
+                let fumes: Option<f32> = reader.read()?;
+                Ok(EnvironmentObject::AirLeveler(AirLeveler {
+                    x,
+                    y,
+                    nitrogen: nitrogen.unwrap_or_default(),
+                    oxygen: oxygen.unwrap_or_default(),
+                    fumes: fumes.unwrap_or_default(),
+                    gases: GasMask {
+                        nitrogen: nitrogen.is_some(),
+                        oxygen: oxygen.is_some(),
+                        fumes: fumes.is_some(),
+                    },
+                    reservoir: reader.read()?,
+                    rate: reader.read()?,
+                    enabled: reader.read()?,
+                }))
+            }
+            1 => Ok(EnvironmentObject::OxygenUser(OxygenUser {
+                x: reader.read()?,
+                y: reader.read()?,
+                change_per_sec: reader.read()?,
+                enabled: reader.read()?,
+            })),
+            2 => {
+                let x = reader.read()?;
+                let y = reader.read()?;
+                let facing = reader.read::<Facing>()?;
+                let amount = reader.read()?;
+                let enabled = reader.read()?;
+                let diagonal = reader.read::<bool>()?;
+                Ok(EnvironmentObject::AirPusher(AirPusher {
+                    x,
+                    y,
+                    direction: Direction8::from(facing).rotate_eighths(diagonal as u8),
+                    amount,
+                    enabled,
+                }))
+            }
+            3 => Ok(EnvironmentObject::LiquidLeveler(LiquidLeveler {
+                x: reader.read()?,
+                y: reader.read()?,
+                target: reader.read()?,
+                enabled: reader.read()?,
+                solidify: reader.read()?,
+            })),
+            4 => Ok(EnvironmentObject::HeatSource(HeatSource {
+                x: reader.read()?,
+                y: reader.read()?,
+                temperature: reader.read()?,
+                change_per_sec: reader.read()?,
+                enabled: reader.read()?,
+            })),
+            5 => Ok(EnvironmentObject::HeatSink(HeatSink {
+                x: reader.read()?,
+                y: reader.read()?,
+                temperature: reader.read()?,
+                change_per_sec: reader.read()?,
+                enabled: reader.read()?,
+            })),
+            _ => corrupt("unknown environment object"),
+        }
+    }
+}
+
+impl Snapshot for Building {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.location);
+        writer.write(&self.facing);
+        writer.write(&self.building_type);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Building {
+            location: reader.read()?,
+            facing: reader.read()?,
+            building_type: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for BuildingType {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        match self {
+            BuildingType::HandCrankedVentilator { workspots } => {
+                writer.write_u8(0);
+                writer.write(workspots);
+            }
+            BuildingType::Airlock => writer.write_u8(1),
+            BuildingType::OxygenGenerator { workspots } => {
+                writer.write_u8(2);
+                writer.write(workspots);
+            }
+            BuildingType::FumeScrubber { workspots } => {
+                writer.write_u8(3);
+                writer.write(workspots);
+            }
+            BuildingType::MiningJob {
+                workspots,
+                progress,
+                rate,
+                fumes_per_sec,
+            } => {
+                writer.write_u8(4);
+                writer.write(workspots);
+                writer.write(progress);
+                writer.write(rate);
+                writer.write(fumes_per_sec);
+            }
+            BuildingType::Pump { rate, enabled } => {
+                writer.write_u8(5);
+                writer.write(rate);
+                writer.write(enabled);
+            }
+            BuildingType::Generator {
+                output,
+                enabled,
+                fuel,
+            } => {
+                writer.write_u8(6);
+                writer.write(output);
+                writer.write(enabled);
+                writer.write(fuel);
+            }
+            BuildingType::PoweredVentilator { power } => {
+                writer.write_u8(7);
+                writer.write(power);
+            }
+            BuildingType::Stockpile { capacity } => {
+                writer.write_u8(8);
+                writer.write(capacity);
+            }
+            BuildingType::Heater {
+                target,
+                power,
+                workspots,
+            } => {
+                writer.write_u8(9);
+                writer.write(target);
+                writer.write(power);
+                writer.write(workspots);
+            }
+            BuildingType::Cooler {
+                target,
+                power,
+                workspots,
+            } => {
+                writer.write_u8(10);
+                writer.write(target);
+                writer.write(power);
+                writer.write(workspots);
+            }
+            BuildingType::UnderConstruction {
+                building_type,
+                workspots,
+                progress,
+                required_work,
+            } => {
+                writer.write_u8(11);
+                writer.write(workspots);
+                writer.write(progress);
+                writer.write(required_work);
+                writer.write(building_type.as_ref());
+            }
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(BuildingType::HandCrankedVentilator {
+                workspots: reader.read()?,
+            }),
+            1 => Ok(BuildingType::Airlock),
+            2 => Ok(BuildingType::OxygenGenerator {
+                workspots: reader.read()?,
+            }),
+            3 => Ok(BuildingType::FumeScrubber {
+                workspots: reader.read()?,
+            }),
+            4 => Ok(BuildingType::MiningJob {
+                workspots: reader.read()?,
+                progress: reader.read()?,
+                rate: reader.read()?,
+                fumes_per_sec: reader.read()?,
+            }),
+            5 => Ok(BuildingType::Pump {
+                rate: reader.read()?,
+                enabled: reader.read()?,
+            }),
+            6 => Ok(BuildingType::Generator {
+                output: reader.read()?,
+                enabled: reader.read()?,
+                fuel: reader.read()?,
+            }),
+            7 => Ok(BuildingType::PoweredVentilator {
+                power: reader.read()?,
+            }),
+            8 => Ok(BuildingType::Stockpile {
+                capacity: reader.read()?,
+            }),
+            9 => Ok(BuildingType::Heater {
+                target: reader.read()?,
+                power: reader.read()?,
+                workspots: reader.read()?,
+            }),
+            10 => Ok(BuildingType::Cooler {
+                target: reader.read()?,
+                power: reader.read()?,
+                workspots: reader.read()?,
+            }),
+            11 => Ok(BuildingType::UnderConstruction {
+                workspots: reader.read()?,
+                progress: reader.read()?,
+                required_work: reader.read()?,
+                building_type: Box::new(reader.read()?),
+            }),
+            _ => corrupt("unknown building type"),
+        }
+    }
+}
+
+impl Snapshot for Pipe {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.location);
+        writer.write(&self.outlet);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Pipe {
+            location: reader.read()?,
+            outlet: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for Cable {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.location);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Cable {
+            location: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for Item {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.kind);
+        writer.write(&self.location);
+        writer.write(&self.hauler);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Item {
+            kind: reader.read()?,
+            location: reader.read()?,
+            hauler: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for ItemKind {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(match self {
+            ItemKind::Ore => 0,
+            ItemKind::Food => 1,
+            ItemKind::Components => 2,
+            ItemKind::OxygenTank => 3,
+        });
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(ItemKind::Ore),
+            1 => Ok(ItemKind::Food),
+            2 => Ok(ItemKind::Components),
+            3 => Ok(ItemKind::OxygenTank),
+            _ => corrupt("unknown item kind"),
+        }
+    }
+}
+
+impl Snapshot for ItemStack {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.kind);
+        writer.write(&self.count);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(ItemStack {
+            kind: reader.read()?,
+            count: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for Inventory {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.stacks);
+        writer.write(&self.equipped);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Inventory {
+            stacks: reader.read()?,
+            equipped: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for WorkSpot {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.location);
+        match self.occupation {
+            WorkSpotOccupation::Open => writer.write_u8(0),
+            WorkSpotOccupation::Claimed(character) => {
+                writer.write_u8(1);
+                writer.write(&character);
+            }
+            WorkSpotOccupation::Working(character) => {
+                writer.write_u8(2);
+                writer.write(&character);
+            }
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let location = reader.read()?;
+        let occupation = match reader.read_u8()? {
+            0 => WorkSpotOccupation::Open,
+            1 => WorkSpotOccupation::Claimed(reader.read()?),
+            2 => WorkSpotOccupation::Working(reader.read()?),
+            _ => return corrupt("unknown workspot occupation"),
+        };
+        Ok(WorkSpot {
+            location,
+            occupation,
+        })
+    }
+}
+
+impl Snapshot for Needs {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.hunger);
+        writer.write(&self.fatigue);
+        writer.write(&self.oxygen_saturation);
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Needs {
+            hunger: reader.read()?,
+            fatigue: reader.read()?,
+            oxygen_saturation: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for WorkGoal {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(match self {
+            WorkGoal::WorkAtVentilation => 0,
+            WorkGoal::WorkAtLifeSupport => 1,
+            WorkGoal::Mine => 2,
+            WorkGoal::HaulItems => 3,
+            WorkGoal::Construct => 4,
+        });
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(WorkGoal::WorkAtVentilation),
+            1 => Ok(WorkGoal::WorkAtLifeSupport),
+            2 => Ok(WorkGoal::Mine),
+            3 => Ok(WorkGoal::HaulItems),
+            4 => Ok(WorkGoal::Construct),
+            _ => corrupt("unknown work goal"),
+        }
+    }
+}
+
+impl Snapshot for SurviveGoal {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(match self {
+            SurviveGoal::RunFromDanger => 0,
+            SurviveGoal::FindAir => 1,
+            SurviveGoal::PreventStarvation => 2,
+            SurviveGoal::Rest => 3,
+        });
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(SurviveGoal::RunFromDanger),
+            1 => Ok(SurviveGoal::FindAir),
+            2 => Ok(SurviveGoal::PreventStarvation),
+            3 => Ok(SurviveGoal::Rest),
+            _ => corrupt("unknown survive goal"),
+        }
+    }
+}
+
+impl Snapshot for CharacterGoal {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        match self {
+            CharacterGoal::Survive(goal) => {
+                writer.write_u8(0);
+                writer.write(goal);
+            }
+            CharacterGoal::Work(goal) => {
+                writer.write_u8(1);
+                writer.write(goal);
+            }
+            CharacterGoal::Idle => writer.write_u8(2),
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(CharacterGoal::Survive(reader.read()?)),
+            1 => Ok(CharacterGoal::Work(reader.read()?)),
+            2 => Ok(CharacterGoal::Idle),
+            _ => corrupt("unknown character goal"),
+        }
+    }
+}
+
+impl Snapshot for CharacterTask {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        match self {
+            CharacterTask::PanicRun { target } => {
+                writer.write_u8(0);
+                writer.write(target);
+            }
+            CharacterTask::WorkAtSpot {
+                building,
+                workspot_index,
+            } => {
+                writer.write_u8(1);
+                writer.write(building);
+                writer.write(workspot_index);
+            }
+            CharacterTask::MoveTo => writer.write_u8(2),
+            CharacterTask::Rest => writer.write_u8(3),
+            CharacterTask::Idle => writer.write_u8(4),
+            CharacterTask::PickUp { item, destination } => {
+                writer.write_u8(5);
+                writer.write(item);
+                writer.write(destination);
+            }
+            CharacterTask::Deliver { kind, destination } => {
+                writer.write_u8(6);
+                writer.write(kind);
+                writer.write(destination);
+            }
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(CharacterTask::PanicRun {
+                target: reader.read()?,
+            }),
+            1 => Ok(CharacterTask::WorkAtSpot {
+                building: reader.read()?,
+                workspot_index: reader.read()?,
+            }),
+            2 => Ok(CharacterTask::MoveTo),
+            3 => Ok(CharacterTask::Rest),
+            4 => Ok(CharacterTask::Idle),
+            5 => Ok(CharacterTask::PickUp {
+                item: reader.read()?,
+                destination: reader.read()?,
+            }),
+            6 => Ok(CharacterTask::Deliver {
+                kind: reader.read()?,
+                destination: reader.read()?,
+            }),
+            _ => corrupt("unknown character task"),
+        }
+    }
+}
+
+impl Snapshot for CharacterEvent {
+    fn write(&self, writer: &mut SnapshotWriter) {
+        match self {
+            CharacterEvent::ClaimedWorkspot {
+                building,
+                workspot_index,
+            } => {
+                writer.write_u8(0);
+                writer.write(building);
+                writer.write(workspot_index);
+            }
+            CharacterEvent::ReleasedWorkspot {
+                building,
+                workspot_index,
+            } => {
+                writer.write_u8(1);
+                writer.write(building);
+                writer.write(workspot_index);
+            }
+            CharacterEvent::StartedWorking {
+                building,
+                workspot_index,
+            } => {
+                writer.write_u8(2);
+                writer.write(building);
+                writer.write(workspot_index);
+            }
+            CharacterEvent::PathBlocked => writer.write_u8(3),
+            CharacterEvent::BuildingRemoved { building } => {
+                writer.write_u8(4);
+                writer.write(building);
+            }
+            CharacterEvent::ConstructionFinished { building } => {
+                writer.write_u8(5);
+                writer.write(building);
+            }
+        }
+    }
+
+    fn read(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_u8()? {
+            0 => Ok(CharacterEvent::ClaimedWorkspot {
+                building: reader.read()?,
+                workspot_index: reader.read()?,
+            }),
+            1 => Ok(CharacterEvent::ReleasedWorkspot {
+                building: reader.read()?,
+                workspot_index: reader.read()?,
+            }),
+            2 => Ok(CharacterEvent::StartedWorking {
+                building: reader.read()?,
+                workspot_index: reader.read()?,
+            }),
+            3 => Ok(CharacterEvent::PathBlocked),
+            4 => Ok(CharacterEvent::BuildingRemoved {
+                building: reader.read()?,
+            }),
+            5 => Ok(CharacterEvent::ConstructionFinished {
+                building: reader.read()?,
+            }),
+            _ => corrupt("unknown character event"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::building::temperature_control_workspots;
+    use glam::{uvec2, vec2};
+    use std::fmt::Debug;
+
+    fn test_map() -> Map {
+        let mut map = Map::new_default(8, 4);
+        map.tiles[(0, 0)].tile_type = TileType::Wall;
+        map.tiles[(1, 0)].tile_type = TileType::LowWall {
+            height: 0.3,
+            air: AirData::new_default(),
+            liquids: LiquidData::Water { level: 0.5 },
+        };
+        map.tiles[(2, 0)].tile_type = TileType::Door {
+            open: false,
+            air: AirData::new_default(),
+            liquids: LiquidData::None,
+        };
+        map.tiles[(7, 3)].tile_type = TileType::Ground {
+            air: AirData::new_default(),
+            liquids: LiquidData::Lava { level: 0.2 },
+        };
+        map.tiles[(3, 2)].sealed = true;
+        map.tiles[(4, 2)].temperature = 80.0;
+
+        let mut objects = map.objects_mut();
+        let removed = objects.push_object::<EnvironmentObject>(HeatSink {
+            x: 1,
+            y: 1,
+            temperature: 5.0,
+            change_per_sec: 1.0,
+            enabled: true,
+        });
+        objects.push_object::<EnvironmentObject>(AirLeveler {
+            x: 5,
+            y: 1,
+            nitrogen: 0.0,
+            oxygen: 0.3,
+            fumes: 0.0,
+            gases: GasMask {
+                nitrogen: false,
+                oxygen: true,
+                fumes: true,
+            },
+            reservoir: Some(10.0),
+            rate: Some(0.5),
+            enabled: true,
+        });
+        objects.push_object::<EnvironmentObject>(AirPusher {
+            x: 6,
+            y: 1,
+            direction: Direction8::NorthWest,
+            amount: 0.1,
+            enabled: false,
+        });
+        objects.push_object::<EnvironmentObject>(LiquidLeveler {
+            x: 7,
+            y: 2,
+            target: LiquidData::Water { level: 0.1 },
+            enabled: true,
+            solidify: false,
+        });
+        objects.remove_object(removed);
+        objects.push_object::<Building>(Building {
+            location: uvec2(4, 1),
+            facing: Facing::South,
+            building_type: BuildingType::OxygenGenerator {
+                workspots: [
+                    WorkSpot {
+                        location: vec2(0.2, 0.5),
+                        occupation: WorkSpotOccupation::Open,
+                    },
+                    WorkSpot {
+                        location: vec2(0.8, 0.5),
+                        occupation: WorkSpotOccupation::Open,
+                    },
+                ],
+            },
+        });
+        objects.push_object::<Building>(Building {
+            location: uvec2(0, 3),
+            facing: Facing::East,
+            building_type: BuildingType::Pump {
+                rate: 0.2,
+                enabled: false,
+            },
+        });
+        objects.push_object::<Pipe>(Pipe {
+            location: uvec2(1, 3),
+            outlet: true,
+        });
+        objects.push_object::<Building>(Building {
+            location: uvec2(2, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Generator {
+                output: 2.0,
+                enabled: true,
+                fuel: Some(30.0),
+            },
+        });
+        objects.push_object::<Building>(Building {
+            location: uvec2(3, 0),
+            facing: Facing::East,
+            building_type: BuildingType::PoweredVentilator { power: 0.0 },
+        });
+        objects.push_object::<Building>(Building {
+            location: uvec2(6, 3),
+            facing: Facing::West,
+            building_type: BuildingType::UnderConstruction {
+                building_type: Box::new(BuildingType::Generator {
+                    output: 1.0,
+                    enabled: true,
+                    fuel: None,
+                }),
+                workspots: [
+                    WorkSpot {
+                        location: vec2(0.2, 0.5),
+                        occupation: WorkSpotOccupation::Open,
+                    },
+                    WorkSpot {
+                        location: vec2(0.8, 0.5),
+                        occupation: WorkSpotOccupation::Open,
+                    },
+                ],
+                progress: 0.25,
+                required_work: 2.0,
+            },
+        });
+        objects.push_object::<Building>(Building {
+            location: uvec2(4, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Heater {
+                target: 30.0,
+                power: 0.5,
+                workspots: temperature_control_workspots(),
+            },
+        });
+        for x in 2..5 {
+            objects.push_object::<Cable>(Cable {
+                location: uvec2(x, 0),
+            });
+        }
+        objects.push_object::<Building>(Building {
+            location: uvec2(7, 0),
+            facing: Facing::North,
+            building_type: BuildingType::Stockpile { capacity: 4 },
+        });
+        objects.push_object::<Item>(Item::new(ItemKind::Components, vec2(5.2, 3.7)));
+        let mut character = Character::new(vec2(6.5, 2.5), 0.8, vec![WorkGoal::WorkAtLifeSupport]);
+        character.inventory = Inventory {
+            stacks: vec![ItemStack {
+                kind: ItemKind::Ore,
+                count: 2,
+            }],
+            equipped: Some(ItemKind::OxygenTank),
+        };
+        objects.push_object::<Character>(character);
+        drop(objects);
+
+        // Let the character claim a workspot and walk a bit, so it has a goal, task and path
+        map.step_n(0.1, 3);
+        assert!(map
+            .objects()
+            .get_objects::<Character>()
+            .all(|character| character.current_path.is_some()));
+        map
+    }
+
+    fn objects_debug<T: ObjectProperties + Debug>(map: &Map) -> Vec<String> {
+        map.objects()
+            .get_objects::<T>()
+            .map(|object| format!("{:?} {:?}", object.id(), *object))
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let map = test_map();
+
+        let mut bytes = Vec::new();
+        map.save_snapshot(&mut bytes).unwrap();
+        let mut loaded = Map::load_snapshot(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(format!("{:?}", loaded.tiles), format!("{:?}", map.tiles));
+        assert_eq!(loaded.current_time, map.current_time);
+        assert_eq!(
+            objects_debug::<EnvironmentObject>(&loaded),
+            objects_debug::<EnvironmentObject>(&map)
+        );
+        assert_eq!(
+            objects_debug::<Building>(&loaded),
+            objects_debug::<Building>(&map)
+        );
+        assert_eq!(
+            objects_debug::<Character>(&loaded),
+            objects_debug::<Character>(&map)
+        );
+        assert_eq!(objects_debug::<Pipe>(&loaded), objects_debug::<Pipe>(&map));
+        assert_eq!(
+            objects_debug::<Cable>(&loaded),
+            objects_debug::<Cable>(&map)
+        );
+        assert_eq!(objects_debug::<Item>(&loaded), objects_debug::<Item>(&map));
+        assert_eq!(loaded.take_render_dirty().len(), 8 * 4);
+
+        // Ids carry on where the saved map left off
+        let next = map
+            .objects_mut()
+            .push_object::<EnvironmentObject>(HeatSource {
+                x: 0,
+                y: 1,
+                temperature: 50.0,
+                change_per_sec: 1.0,
+                enabled: true,
+            });
+        let loaded_next = loaded
+            .objects_mut()
+            .push_object::<EnvironmentObject>(HeatSource {
+                x: 0,
+                y: 1,
+                temperature: 50.0,
+                change_per_sec: 1.0,
+                enabled: true,
+            });
+        assert_eq!(next, loaded_next);
+    }
+
+    #[test]
+    fn snapshot_skips_unknown_data() {
+        let map = test_map();
+
+        // A snapshot as a newer version could write it, with an extra section and extra tile fields
+        let mut snapshot = SnapshotWriter::new();
+        snapshot.bytes.extend_from_slice(&MAGIC);
+        snapshot
+            .bytes
+            .extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        snapshot
+            .bytes
+            .extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        snapshot.write_section(200, |writer| writer.write(&1234.5f32));
+        snapshot.write_section(section::MAP, |writer| {
+            writer.write(&8usize);
+            writer.write(&4usize);
+            writer.write(&map.current_time);
+        });
+        snapshot.write_section(section::TILES, |writer| {
+            for x in 0..8 {
+                for y in 0..4 {
+                    writer.write_record(|writer| {
+                        writer.write(&map.tiles[(x, y)]);
+                        writer.write(&u32::MAX);
+                    });
+                }
+            }
+        });
+        snapshot.write_section(section::NEXT_OBJECT_ID, |writer| writer.write(&Some(0u32)));
+        snapshot.write_u8(section::END);
+
+        let loaded = Map::load_snapshot(&mut snapshot.bytes.as_slice()).unwrap();
+        assert_eq!(format!("{:?}", loaded.tiles), format!("{:?}", map.tiles));
+        assert_eq!(loaded.objects().get_objects::<Building>().count(), 0);
+    }
+
+    #[test]
+    fn snapshot_rejects_bad_data() {
+        let mut bytes = Vec::new();
+        test_map().save_snapshot(&mut bytes).unwrap();
+
+        assert!(matches!(
+            Map::load_snapshot(&mut &b"not a snapshot"[..]),
+            Err(SnapshotError::NotASnapshot)
+        ));
+
+        let mut newer = bytes.clone();
+        newer[6..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Map::load_snapshot(&mut newer.as_slice()),
+            Err(SnapshotError::UnsupportedVersion { min_reader_version }) if min_reader_version == FORMAT_VERSION + 1
+        ));
+
+        // The map section starts right after the header with its id and length, then the width
+        assert_eq!(bytes[8], section::MAP);
+        assert_eq!(bytes[10], 8);
+        let mut empty = bytes.clone();
+        empty[10] = 0;
+        assert!(matches!(
+            Map::load_snapshot(&mut empty.as_slice()),
+            Err(SnapshotError::Corrupt(_))
+        ));
+
+        for len in [10, bytes.len() / 2, bytes.len() - 1] {
+            assert!(matches!(
+                Map::load_snapshot(&mut &bytes[..len]),
+                Err(SnapshotError::Corrupt(_))
+            ));
+        }
+    }
+}
@@ -1,18 +1,45 @@
-use crate::{air::AirData, liquids::LiquidData};
+use crate::{
+    air::AirData,
+    liquids::{AnyLiquid, Lava, LiquidData, Water},
+    TileCoordIter,
+};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tile {
     pub ground_level: f32,
     pub tile_type: TileType,
+    /// A sealed tile doesn't take part in the air simulation, as if it's a wall for air.
+    /// It can still be walked on and hold liquids.
+    pub sealed: bool,
+    /// The temperature of the tile in degrees Celsius. Walls don't take part in the heat simulation, so it's ignored for them.
+    #[cfg_attr(
+        feature = "serde",
+        serde(alias = "air_temperature", default = "default_temperature")
+    )]
+    pub temperature: f32,
+    /// How fiercely the tile is burning, from 0 for not burning to 1. See [crate::fire].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fire: f32,
+}
+
+#[cfg(feature = "serde")]
+fn default_temperature() -> f32 {
+    Tile::DEFAULT_TEMPERATURE
 }
 
 impl Tile {
     pub const TUNNEL_HEIGHT: f32 = 3.0;
+    /// The temperature of new tiles in degrees Celsius
+    pub const DEFAULT_TEMPERATURE: f32 = 20.0;
 
     pub fn new(ground_level: f32, tile_type: TileType) -> Self {
         Self {
             ground_level,
             tile_type,
+            sealed: false,
+            temperature: Self::DEFAULT_TEMPERATURE,
+            fire: 0.0,
         }
     }
 
@@ -20,6 +47,67 @@ impl Tile {
         Self {
             ground_level: 0.0,
             tile_type: TileType::new_default(),
+            sealed: false,
+            temperature: Self::DEFAULT_TEMPERATURE,
+            fire: 0.0,
+        }
+    }
+
+    /// The temperature of the tile in degrees Celsius. Walls don't have a temperature.
+    pub fn temperature(&self) -> Option<f32> {
+        self.tile_type.get_air().map(|_| self.temperature)
+    }
+
+    /// The level of the top of the ground or liquid in this tile. Liquid rests on the [Self::liquid_floor_level].
+    pub fn surface_level(&self) -> f32 {
+        self.tile_type
+            .get_liquids()
+            .map(|liquids| self.liquid_floor_level() + liquids.get_level::<AnyLiquid>())
+            .unwrap_or(self.ground_level)
+    }
+
+    /// Get the air of the tile if it takes part in the air simulation
+    pub fn get_simulated_air(&self) -> Option<(&AirData, &LiquidData)> {
+        if self.sealed || self.tile_type.blocks_flow() {
+            None
+        } else {
+            self.tile_type.get_ground()
+        }
+    }
+
+    /// The level liquids in this tile rest on
+    pub fn liquid_floor_level(&self) -> f32 {
+        self.ground_level + self.tile_type.liquid_barrier_height()
+    }
+
+    /// The biggest change in ground level, air, liquid or fire between the two tiles.
+    /// Tiles of a different type, or a door that opened or closed, are infinitely different.
+    pub(crate) fn max_difference(&self, other: &Tile) -> f32 {
+        if std::mem::discriminant(&self.tile_type) != std::mem::discriminant(&other.tile_type)
+            || self.tile_type.is_closed_door() != other.tile_type.is_closed_door()
+        {
+            return f32::INFINITY;
+        }
+
+        let ground_difference = (self.ground_level - other.ground_level).abs();
+
+        match (self.tile_type.get_ground(), other.tile_type.get_ground()) {
+            (None, None) => ground_difference,
+            (Some((air, liquids)), Some((other_air, other_liquids))) => [
+                ground_difference,
+                (self.fire - other.fire).abs(),
+                (air.nitrogen - other_air.nitrogen).abs(),
+                (air.oxygen - other_air.oxygen).abs(),
+                (air.fumes - other_air.fumes).abs(),
+                (air.steam - other_air.steam).abs(),
+                (air.carbon_dioxide - other_air.carbon_dioxide).abs(),
+                (air.temperature - other_air.temperature).abs(),
+                (liquids.get_level::<Water>() - other_liquids.get_level::<Water>()).abs(),
+                (liquids.get_level::<Lava>() - other_liquids.get_level::<Lava>()).abs(),
+            ]
+            .into_iter()
+            .fold(0.0, f32::max),
+            _ => f32::INFINITY,
         }
     }
 }
@@ -30,10 +118,69 @@ impl Default for Tile {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// The kind of surface something can land on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceKind {
+    Wall,
+    Ground,
+    Water,
+    Lava,
+}
+
+/// A rectangle of tiles
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl TileRect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns `true` if the rect contains the given tile coords
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    /// Iterate over the coords of all tiles in the rect
+    pub fn coords(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (x, y) = (self.x, self.y);
+        TileCoordIter::new(self.width, self.height)
+            .map(move |(local_x, local_y)| (x + local_x, y + local_y))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TileType {
     Wall,
-    Ground { air: AirData, liquids: LiquidData },
+    Ground {
+        air: AirData,
+        liquids: LiquidData,
+    },
+    /// A wall that blocks liquids up to its height, but lets air flow over it and can be walked on.
+    ///
+    /// Any liquid in this tile rests on top of the wall.
+    LowWall {
+        height: f32,
+        air: AirData,
+        liquids: LiquidData,
+    },
+    /// A door that blocks air and liquids when closed. Characters open it to walk through.
+    Door {
+        open: bool,
+        air: AirData,
+        liquids: LiquidData,
+    },
 }
 
 impl TileType {
@@ -45,7 +192,10 @@ impl TileType {
     }
 
     pub fn get_ground(&self) -> Option<(&AirData, &LiquidData)> {
-        if let Self::Ground { air, liquids } = self {
+        if let Self::Ground { air, liquids }
+        | Self::LowWall { air, liquids, .. }
+        | Self::Door { air, liquids, .. } = self
+        {
             Some((air, liquids))
         } else {
             None
@@ -53,7 +203,10 @@ impl TileType {
     }
 
     pub(crate) fn get_ground_mut(&mut self) -> Option<(&mut AirData, &mut LiquidData)> {
-        if let Self::Ground { air, liquids } = self {
+        if let Self::Ground { air, liquids }
+        | Self::LowWall { air, liquids, .. }
+        | Self::Door { air, liquids, .. } = self
+        {
             Some((air, liquids))
         } else {
             None
@@ -61,7 +214,8 @@ impl TileType {
     }
 
     pub fn get_air(&self) -> Option<&AirData> {
-        if let Self::Ground { air, .. } = self {
+        if let Self::Ground { air, .. } | Self::LowWall { air, .. } | Self::Door { air, .. } = self
+        {
             Some(air)
         } else {
             None
@@ -69,7 +223,8 @@ impl TileType {
     }
 
     pub(crate) fn get_air_mut(&mut self) -> Option<&mut AirData> {
-        if let Self::Ground { air, .. } = self {
+        if let Self::Ground { air, .. } | Self::LowWall { air, .. } | Self::Door { air, .. } = self
+        {
             Some(air)
         } else {
             None
@@ -77,7 +232,10 @@ impl TileType {
     }
 
     pub fn get_liquids(&self) -> Option<&LiquidData> {
-        if let Self::Ground { liquids, .. } = self {
+        if let Self::Ground { liquids, .. }
+        | Self::LowWall { liquids, .. }
+        | Self::Door { liquids, .. } = self
+        {
             Some(liquids)
         } else {
             None
@@ -85,13 +243,24 @@ impl TileType {
     }
 
     pub(crate) fn get_liquids_mut(&mut self) -> Option<&mut LiquidData> {
-        if let Self::Ground { liquids, .. } = self {
+        if let Self::Ground { liquids, .. }
+        | Self::LowWall { liquids, .. }
+        | Self::Door { liquids, .. } = self
+        {
             Some(liquids)
         } else {
             None
         }
     }
 
+    /// The height up to which liquids are blocked from flowing into this tile
+    pub fn liquid_barrier_height(&self) -> f32 {
+        match self {
+            Self::LowWall { height, .. } => *height,
+            _ => 0.0,
+        }
+    }
+
     /// Returns `true` if the tile type is [`Wall`].
     ///
     /// [`Wall`]: TileType::Wall
@@ -99,6 +268,20 @@ impl TileType {
     pub fn is_wall(&self) -> bool {
         matches!(self, Self::Wall)
     }
+
+    /// Returns `true` if the tile type is a [`Door`] that is closed.
+    ///
+    /// [`Door`]: TileType::Door
+    #[must_use]
+    pub fn is_closed_door(&self) -> bool {
+        matches!(self, Self::Door { open: false, .. })
+    }
+
+    /// Returns `true` if air and liquids can't flow into or out of this tile
+    #[must_use]
+    pub fn blocks_flow(&self) -> bool {
+        self.is_wall() || self.is_closed_door()
+    }
 }
 
 impl Default for TileType {
@@ -106,3 +289,24 @@ impl Default for TileType {
         Self::new_default()
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_old_temperature() {
+        let tile = Tile::new(1.0, TileType::new_default());
+        let json = serde_json::to_string(&tile).unwrap();
+
+        let renamed = json.replace(r#""temperature":"#, r#""air_temperature":"#);
+        assert_eq!(serde_json::from_str::<Tile>(&renamed).unwrap(), tile);
+
+        let mut hot = tile;
+        hot.temperature = 80.0;
+        let missing = serde_json::to_string(&hot)
+            .unwrap()
+            .replace(r#""temperature":80.0,"#, "");
+        assert_eq!(serde_json::from_str::<Tile>(&missing).unwrap(), tile);
+    }
+}
@@ -0,0 +1,164 @@
+//! Per-tile data of the game itself, kept alongside the simulation.
+//!
+//! A game can add a layer of any type, like the biome or the owner of every tile,
+//! instead of keeping a second grid of its own next to the map. There is at most one layer per type.
+//! The simulation never looks at these layers and they aren't part of [snapshots](crate::snapshot)
+//! or [rollback](crate::rollback).
+
+use crate::{grid::Grid, Map};
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+};
+
+/// The layers by the type of their grid
+#[derive(Default)]
+pub(crate) struct UserLayers {
+    layers: HashMap<TypeId, Box<dyn UserLayer>>,
+}
+
+impl UserLayers {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.layers.values().map(|layer| layer.memory_usage()).sum()
+    }
+
+    fn get<T: 'static>(&self) -> Option<&T> {
+        self.layers.get(&TypeId::of::<T>())?.as_any().downcast_ref()
+    }
+
+    fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.layers
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut()
+    }
+}
+
+impl std::fmt::Debug for UserLayers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.layers.values().map(|layer| layer.type_name()))
+            .finish()
+    }
+}
+
+/// A grid of user data with its type erased
+trait UserLayer: Send + Sync {
+    fn type_name(&self) -> &'static str;
+    fn memory_usage(&self) -> usize;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Send + Sync + 'static> UserLayer for Grid<T> {
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn memory_usage(&self) -> usize {
+        Grid::memory_usage(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Map {
+    /// Add a layer of user data of the type, with the given value on every tile.
+    /// Returns the layer of the type that was already there.
+    pub fn insert_user_layer<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        value: T,
+    ) -> Option<Grid<T>> {
+        let old_layer = self.remove_user_layer::<T>();
+        self.user_layers.layers.insert(
+            TypeId::of::<Grid<T>>(),
+            Box::new(Grid::new(self.width(), self.height(), value)),
+        );
+        old_layer
+    }
+
+    /// Take the layer of user data of the type out of the map
+    pub fn remove_user_layer<T: Send + Sync + 'static>(&mut self) -> Option<Grid<T>> {
+        let layer = self.user_layers.layers.remove(&TypeId::of::<Grid<T>>())?;
+        layer.into_any().downcast().ok().map(|layer| *layer)
+    }
+
+    /// The layer of user data of the type, or None if it hasn't been added
+    pub fn user_layer<T: Send + Sync + 'static>(&self) -> Option<&Grid<T>> {
+        self.user_layers.get()
+    }
+
+    pub fn user_layer_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut Grid<T>> {
+        self.user_layers.get_mut()
+    }
+
+    /// The user data of the type on the tile.
+    /// None when the layer hasn't been added or the tile is outside of the map.
+    pub fn tile_user_data<T: Send + Sync + 'static>(&self, x: usize, y: usize) -> Option<&T> {
+        self.user_layer::<T>()?.get(x, y)
+    }
+
+    pub fn tile_user_data_mut<T: Send + Sync + 'static>(
+        &mut self,
+        x: usize,
+        y: usize,
+    ) -> Option<&mut T> {
+        self.user_layer_mut::<T>()?.get_mut(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Biome {
+        Cave,
+        Crystals,
+    }
+
+    #[test]
+    fn user_layers_per_type() {
+        let mut map = Map::new_default(4, 3);
+        assert_eq!(map.tile_user_data::<Biome>(0, 0), None);
+
+        assert!(map.insert_user_layer(Biome::Cave).is_none());
+        map.insert_user_layer::<Option<u32>>(None);
+        *map.tile_user_data_mut(2, 1).unwrap() = Biome::Crystals;
+        *map.tile_user_data_mut::<Option<u32>>(2, 1).unwrap() = Some(7);
+
+        assert_eq!(map.tile_user_data(2, 1), Some(&Biome::Crystals));
+        assert_eq!(map.tile_user_data(1, 1), Some(&Biome::Cave));
+        assert_eq!(map.tile_user_data::<Biome>(4, 1), None);
+        assert_eq!(map.tile_user_data::<Option<u32>>(2, 1), Some(&Some(7)));
+        assert!(map.memory_usage() > Map::new_default(4, 3).memory_usage());
+
+        // The simulation leaves the layers alone
+        map.perform_simulation_tick(0.1);
+        assert_eq!(map.tile_user_data(2, 1), Some(&Biome::Crystals));
+
+        let biomes = map.insert_user_layer(Biome::Cave).unwrap();
+        assert_eq!(biomes[(2, 1)], Biome::Crystals);
+        assert_eq!(map.tile_user_data(2, 1), Some(&Biome::Cave));
+
+        let owners = map.remove_user_layer::<Option<u32>>().unwrap();
+        assert_eq!(owners[(2, 1)], Some(7));
+        assert!(map.user_layer::<Option<u32>>().is_none());
+        assert!(map.user_layer::<Biome>().is_some());
+    }
+}